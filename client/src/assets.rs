@@ -0,0 +1,359 @@
+use crate::state;
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, DirBuilder, File},
+    io::ErrorKind,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    sync::{Condvar, LazyLock, Mutex},
+    time::{Duration, SystemTime},
+};
+use ureq::serde_json;
+
+/// The directory that holds downloaded file contents, each named after its
+/// SHA-256 digest.
+static ASSET_DIR: LazyLock<String> = LazyLock::new(|| state::path("assets"));
+
+/// Maps the source of every file resource, i.e. its path on pullconfd, to
+/// the digest of the content that was downloaded most recently.
+static INDEX_FILE: LazyLock<String> = LazyLock::new(|| state::path("assets/index.json"));
+
+/// Downloads that were interrupted, named after the digest of their source,
+/// along with the etag of the content they belong to.
+static PARTIAL_DIR: LazyLock<String> = LazyLock::new(|| state::path("assets/partial"));
+
+/// Serializes changes to the index, as resources may be applied
+/// concurrently.
+static INDEX: Mutex<()> = Mutex::new(());
+
+/// The sources that are currently downloaded. Resources with the same
+/// source wait for each other, and then find the content in the cache.
+static DOWNLOADS: (Mutex<Vec<PathBuf>>, Condvar) = (Mutex::new(Vec::new()), Condvar::new());
+
+/// Limits of the asset cache, which are enforced after every run.
+#[derive(Clone, Copy, Debug)]
+pub struct AssetCache {
+    /// The total size of cached assets in bytes, where zero means no limit.
+    /// The least recently used assets are evicted first.
+    pub max_size: u64,
+    /// The time after which an asset that has not been used is evicted,
+    /// where zero means no limit.
+    pub max_age: Duration,
+}
+
+impl AssetCache {
+    /// Select the assets to evict, given their paths, sizes and the time
+    /// since they were used last.
+    fn select(&self, mut assets: Vec<(PathBuf, u64, Duration)>) -> Vec<(PathBuf, u64)> {
+        // The most recently used assets are kept first.
+        assets.sort_by_key(|(_, _, age)| *age);
+
+        let mut total = 0;
+        let mut evicted = vec![];
+
+        for (path, size, age) in assets {
+            let expired = !self.max_age.is_zero() && age > self.max_age;
+            let exceeds = self.max_size > 0 && total + size > self.max_size;
+
+            if expired || exceeds {
+                evicted.push((path, size));
+            } else {
+                total += size;
+            }
+        }
+
+        evicted
+    }
+}
+
+/// Return the content that was most recently downloaded from the given
+/// source along with its digest. Cached content that does not match its
+/// digest is removed.
+pub fn lookup(source: &Path) -> Option<(String, Vec<u8>)> {
+    let digest = {
+        let _guard = INDEX.lock().unwrap_or_else(|error| error.into_inner());
+        read_index().remove(&source.display().to_string())?
+    };
+
+    let path = Path::new(&*ASSET_DIR).join(&digest);
+    let bytes = fs::read(&path).ok()?;
+
+    if format!("{:x}", Sha256::digest(&bytes)) != digest {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+
+    // The modification time tells when an asset was used last.
+    let _ = File::options()
+        .write(true)
+        .open(&path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+
+    Some((digest, bytes))
+}
+
+/// Save content that was downloaded from the given source and return its
+/// digest. Identical content is only stored once.
+pub fn store(source: &Path, bytes: &[u8]) -> Result<String, std::io::Error> {
+    let digest = format!("{:x}", Sha256::digest(bytes));
+    let path = Path::new(&*ASSET_DIR).join(&digest);
+
+    // Resources with the same source must not write the same asset at once.
+    let _guard = INDEX.lock().unwrap_or_else(|error| error.into_inner());
+
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&*ASSET_DIR)?;
+
+    if !path.exists() {
+        let temporary = path.with_extension("tmp");
+
+        fs::write(&temporary, bytes).and_then(|_| fs::rename(&temporary, &path))?;
+    }
+
+    let mut index = read_index();
+
+    if index.get(&source.display().to_string()) != Some(&digest) {
+        index.insert(source.display().to_string(), digest.clone());
+        write_index(&index)?;
+    }
+
+    Ok(digest)
+}
+
+/// A download from a source that is written to disk as it is received, so
+/// that an interrupted download can be resumed by the next attempt instead
+/// of starting from zero.
+#[derive(Debug)]
+pub struct Download {
+    source: PathBuf,
+    path: PathBuf,
+    etag_path: PathBuf,
+}
+
+impl Download {
+    /// Prepare a download from the given source, which blocks while another
+    /// download from the same source is in progress.
+    pub fn new(source: &Path) -> Result<Self, std::io::Error> {
+        let (downloads, finished) = &DOWNLOADS;
+
+        let mut active = downloads.lock().unwrap_or_else(|error| error.into_inner());
+
+        while active.iter().any(|active| active == source) {
+            active = finished
+                .wait(active)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+
+        active.push(source.to_path_buf());
+        drop(active);
+
+        let name = format!("{:x}", Sha256::digest(source.display().to_string()));
+
+        // The source is released on drop, even if this fails.
+        let download = Self {
+            source: source.to_path_buf(),
+            path: Path::new(&*PARTIAL_DIR).join(format!("{}.part", name)),
+            etag_path: Path::new(&*PARTIAL_DIR).join(format!("{}.etag", name)),
+        };
+
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&*PARTIAL_DIR)?;
+
+        Ok(download)
+    }
+
+    /// Return the number of bytes that were received by an interrupted
+    /// download, along with the etag of the content they belong to.
+    pub fn partial(&self) -> Option<(u64, String)> {
+        let etag = fs::read_to_string(&self.etag_path).ok()?;
+        let size = fs::metadata(&self.path).ok()?.len();
+
+        (size > 0 && !etag.is_empty()).then_some((size, etag))
+    }
+
+    /// Start the download over. Without an etag the download cannot be
+    /// resumed, as there is no way to tell whether the content has changed
+    /// in the meantime.
+    pub fn restart(&self, etag: Option<&str>) -> Result<File, std::io::Error> {
+        match etag {
+            Some(etag) => fs::write(&self.etag_path, etag)?,
+            None => match fs::remove_file(&self.etag_path) {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+                _ => (),
+            },
+        }
+
+        File::create(&self.path)
+    }
+
+    /// Continue an interrupted download.
+    pub fn resume(&self) -> Result<File, std::io::Error> {
+        File::options().append(true).open(&self.path)
+    }
+
+    /// Return the downloaded content and remove it from disk.
+    pub fn finish(self) -> Result<Vec<u8>, std::io::Error> {
+        let bytes = fs::read(&self.path)?;
+        self.discard();
+        Ok(bytes)
+    }
+
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.path);
+        let _ = fs::remove_file(&self.etag_path);
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        let (downloads, finished) = &DOWNLOADS;
+
+        downloads
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .retain(|active| *active != self.source);
+
+        finished.notify_all();
+    }
+}
+
+/// Remove assets that have not been used within the maximum age and, if
+/// the cache is still too large, the least recently used assets. Failures
+/// are logged, as they do not affect the run.
+pub fn evict(pid: u32, cache: &AssetCache) {
+    let scope = "assets";
+
+    let entries = match fs::read_dir(&*ASSET_DIR) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == ErrorKind::NotFound => return,
+        Err(error) => {
+            warn!(scope, pid; "failed to read directory {}: {}", *ASSET_DIR, error);
+            return;
+        }
+    };
+
+    let now = SystemTime::now();
+
+    // Interrupted downloads that were not resumed within the maximum age
+    // are abandoned.
+    if !cache.max_age.is_zero() {
+        for entry in fs::read_dir(&*PARTIAL_DIR).into_iter().flatten().flatten() {
+            if entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified).unwrap_or_default() > cache.max_age
+                })
+            {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    let assets = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_digest(&entry.file_name().to_string_lossy()))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let age = now
+                .duration_since(metadata.modified().ok()?)
+                .unwrap_or_default();
+
+            Some((entry.path(), metadata.len(), age))
+        })
+        .collect::<Vec<(PathBuf, u64, Duration)>>();
+
+    let mut total = assets.iter().map(|(_, size, _)| size).sum::<u64>();
+    let mut evicted = 0;
+
+    for (path, size) in cache.select(assets) {
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                debug!(scope, pid; "evicted asset {}", path.display());
+                total -= size;
+                evicted += 1;
+            }
+            Err(error) => {
+                warn!(scope, pid; "failed to evict asset {}: {}", path.display(), error)
+            }
+        }
+    }
+
+    if evicted == 0 {
+        return;
+    }
+
+    info!(scope, pid; "evicted {} assets from the cache, {} bytes remain", evicted, total);
+
+    // Sources whose content has been evicted are downloaded again.
+    let _guard = INDEX.lock().unwrap_or_else(|error| error.into_inner());
+
+    let mut index = read_index();
+
+    index.retain(|_, digest| Path::new(&*ASSET_DIR).join(digest).exists());
+
+    if let Err(error) = write_index(&index) {
+        warn!(scope, pid; "failed to save {}: {}", *INDEX_FILE, error);
+    }
+}
+
+fn is_digest(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Read the index, which is treated as empty if it is missing or cannot be
+/// parsed. The caller must hold the index lock.
+fn read_index() -> HashMap<String, String> {
+    fs::read(&*INDEX_FILE)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Replace the index atomically. The caller must hold the index lock.
+fn write_index(index: &HashMap<String, String>) -> Result<(), std::io::Error> {
+    let temporary = Path::new(&*INDEX_FILE).with_extension("tmp");
+
+    fs::write(&temporary, serde_json::to_vec(index)?)
+        .and_then(|_| fs::rename(&temporary, &*INDEX_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_assets_to_evict() {
+        let hours = |hours: u64| Duration::from_secs(hours * 3600);
+
+        let assets = vec![
+            (PathBuf::from("old"), 10, hours(48)),
+            (PathBuf::from("recent"), 60, hours(1)),
+            (PathBuf::from("large"), 50, hours(2)),
+            (PathBuf::from("small"), 20, hours(3)),
+        ];
+
+        let cache = AssetCache {
+            max_size: 100,
+            max_age: hours(24),
+        };
+
+        assert_eq!(
+            cache.select(assets.clone()),
+            vec![(PathBuf::from("large"), 50), (PathBuf::from("old"), 10)]
+        );
+
+        let unlimited = AssetCache {
+            max_size: 0,
+            max_age: Duration::ZERO,
+        };
+
+        assert!(unlimited.select(assets).is_empty());
+    }
+}
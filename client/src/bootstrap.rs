@@ -0,0 +1,353 @@
+use crate::{
+    configuration::{Configuration, Overrides},
+    platform::{self, Platform},
+    resources, state,
+};
+use common::error::Terminate;
+use log::{error, info};
+use std::{
+    fs::{self, DirBuilder, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::Path,
+    process::Command,
+};
+
+const ENVIRONMENT_FILE: &str = "/etc/pullconf/environment";
+const CRON_FILE: &str = "/etc/cron.d/pullconf";
+const SYSTEMD_DIR: &str = "/etc/systemd/system";
+const INIT_SCRIPT: &str = "/etc/init.d/pullconf";
+const OPENRC_RUN: &str = "/sbin/openrc-run";
+const RC_SCRIPT: &str = "/usr/local/etc/rc.d/pullconf";
+
+/// Directories in which the Debian package installs its units.
+const UNIT_DIRS: [&str; 3] = [
+    "/lib/systemd/system",
+    "/usr/lib/systemd/system",
+    SYSTEMD_DIR,
+];
+
+const SERVICE_UNIT: &str = include_str!("../systemd/pullconf.service");
+const TIMER_UNIT: &str = include_str!("../systemd/pullconf.timer");
+const OPENRC_SCRIPT: &str = include_str!("../openrc/pullconf");
+const RC_SCRIPT_CONTENT: &str = include_str!("../rc.d/pullconf");
+
+/// Runs pullconf every five minutes on systems without systemd, like the
+/// timer unit does.
+const CRON_JOB: &str = "# Installed by `pullconf bootstrap`.
+*/5 * * * * root /bin/sh -c 'set -a; . /etc/pullconf/environment; exec /usr/bin/pullconf' >> /var/log/pullconf/pullconf.log 2>&1
+";
+
+/// Prepare a new system for pullconf: verify that pullconfd can be reached
+/// with the given API key, save the server and the API key to the
+/// environment file and schedule regular runs. The first run is left to the
+/// caller.
+pub fn bootstrap(
+    pid: u32,
+    overrides: &Overrides,
+    api_key: Option<String>,
+) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    let Some(server) = overrides.server.as_deref() else {
+        error!(scope, pid; "bootstrap requires the server to be passed with --server");
+        return Err(Terminate);
+    };
+
+    let api_key = match (api_key, &overrides.api_key_file) {
+        (Some(api_key), _) => api_key,
+        (None, Some(path)) => match fs::read_to_string(path) {
+            Ok(api_key) => api_key.trim().to_string(),
+            Err(error) => {
+                error!(scope, pid; "failed to read API key from file {}: {}", path.display(), error);
+                return Err(Terminate);
+            }
+        },
+        (None, None) => {
+            error!(scope, pid; "bootstrap requires the API key to be passed with --api-key or --api-key-file");
+            return Err(Terminate);
+        }
+    };
+
+    for (name, value) in [("server", server), ("API key", api_key.as_str())] {
+        if value.is_empty()
+            || !value
+                .chars()
+                .all(|c| c.is_ascii_graphic() && !matches!(c, '"' | '\'' | '\\'))
+        {
+            error!(scope, pid; "{} must be non-empty and must not contain whitespace or quotes", name);
+            return Err(Terminate);
+        }
+    }
+
+    // Fetching the resource catalog proves that pullconfd is reachable, that
+    // its certificate is trusted and that it accepts the API key. Runs of
+    // pullconf read both values from the environment, so does this one.
+    std::env::set_var("PULLCONF_SERVER", server);
+    std::env::set_var("PULLCONF_API_KEY", &api_key);
+
+    let overrides = Overrides {
+        hostname: overrides.hostname.clone(),
+        allow_rollback: overrides.allow_rollback,
+        ..Default::default()
+    };
+
+    Configuration::get(pid, &overrides, false)?;
+
+    info!(scope, pid; "verified connection to {} and API key", server);
+
+    for (directory, mode) in [
+        ("/etc/pullconf", 0o755),
+        (state::directory(), 0o750),
+        ("/var/log/pullconf", 0o755),
+    ] {
+        if let Err(error) = DirBuilder::new()
+            .recursive(true)
+            .mode(mode)
+            .create(directory)
+        {
+            error!(scope, pid; "failed to create directory {}: {}", directory, error);
+            return Err(Terminate);
+        }
+    }
+
+    write_environment(pid, server, &api_key)?;
+
+    if platform::get() == Platform::FreeBsd {
+        install_rc_script(pid)
+    } else if Path::new("/run/systemd/system").exists() {
+        install_timer(pid)
+    } else if platform::get() == Platform::Alpine || Path::new(OPENRC_RUN).exists() {
+        install_init_script(pid)
+    } else {
+        install_cron_job(pid)
+    }
+}
+
+/// Save the server and the API key to the environment file, keeping any
+/// other variables in it. The file is only readable by root, since it
+/// contains the API key.
+fn write_environment(pid: u32, server: &str, api_key: &str) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    let current = match fs::read_to_string(ENVIRONMENT_FILE) {
+        Ok(current) => current,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            error!(scope, pid; "failed to read {}: {}", ENVIRONMENT_FILE, error);
+            return Err(Terminate);
+        }
+    };
+
+    let environment = merge(
+        &current,
+        &[("PULLCONF_SERVER", server), ("PULLCONF_API_KEY", api_key)],
+    );
+
+    let result = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(ENVIRONMENT_FILE)
+        .and_then(|mut file| {
+            // The mode only applies to new files.
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+            file.write_all(environment.as_bytes())
+        });
+
+    match result {
+        Ok(()) => {
+            info!(scope, pid; "saved server and API key to {}", ENVIRONMENT_FILE);
+            Ok(())
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to write {}: {}", ENVIRONMENT_FILE, error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Install the service and timer units unless they are already installed,
+/// e.g. by the Debian package, and enable the timer.
+fn install_timer(pid: u32) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    for (name, content) in [
+        ("pullconf.service", SERVICE_UNIT),
+        ("pullconf.timer", TIMER_UNIT),
+    ] {
+        if UNIT_DIRS
+            .iter()
+            .any(|directory| Path::new(directory).join(name).exists())
+        {
+            continue;
+        }
+
+        let path = Path::new(SYSTEMD_DIR).join(name);
+
+        if let Err(error) = fs::write(&path, content) {
+            error!(scope, pid; "failed to write {}: {}", path.display(), error);
+            return Err(Terminate);
+        }
+
+        info!(scope, pid; "installed {}", path.display());
+    }
+
+    for args in [
+        &["daemon-reload"][..],
+        &["enable", "--now", "pullconf.timer"][..],
+    ] {
+        if let Err(error) = resources::run(Command::new("systemctl").args(args)) {
+            error!(scope, pid; "failed to enable pullconf.timer: {:#}", error);
+            return Err(Terminate);
+        }
+    }
+
+    info!(scope, pid; "enabled pullconf.timer");
+
+    Ok(())
+}
+
+/// Run pullconf in daemon mode as an OpenRC service, e.g. on Alpine, which
+/// supervises it and restarts it should it exit. An existing init script is
+/// kept, but the service is always added to the default runlevel.
+fn install_init_script(pid: u32) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    if Path::new(INIT_SCRIPT).exists() {
+        info!(scope, pid; "keeping existing init script {}", INIT_SCRIPT);
+    } else {
+        let result = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o755)
+            .open(INIT_SCRIPT)
+            .and_then(|mut file| file.write_all(OPENRC_SCRIPT.as_bytes()));
+
+        if let Err(error) = result {
+            error!(scope, pid; "failed to write {}: {}", INIT_SCRIPT, error);
+            return Err(Terminate);
+        }
+
+        info!(scope, pid; "installed init script {}", INIT_SCRIPT);
+    }
+
+    for (program, args) in [
+        ("rc-update", &["add", "pullconf", "default"][..]),
+        ("rc-service", &["pullconf", "start"][..]),
+    ] {
+        if let Err(error) = resources::run(Command::new(program).args(args)) {
+            error!(scope, pid; "failed to start the pullconf service: {:#}", error);
+            return Err(Terminate);
+        }
+    }
+
+    info!(scope, pid; "started the pullconf service in the default runlevel");
+
+    Ok(())
+}
+
+/// Run pullconf in daemon mode as an rc.d service on FreeBSD, supervised by
+/// daemon(8), and enable it in rc.conf(5). An existing rc.d script is kept.
+fn install_rc_script(pid: u32) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    if Path::new(RC_SCRIPT).exists() {
+        info!(scope, pid; "keeping existing rc.d script {}", RC_SCRIPT);
+    } else {
+        let result = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o755)
+            .open(RC_SCRIPT)
+            .and_then(|mut file| file.write_all(RC_SCRIPT_CONTENT.as_bytes()));
+
+        if let Err(error) = result {
+            error!(scope, pid; "failed to write {}: {}", RC_SCRIPT, error);
+            return Err(Terminate);
+        }
+
+        info!(scope, pid; "installed rc.d script {}", RC_SCRIPT);
+    }
+
+    for (program, args) in [
+        ("sysrc", &["pullconf_enable=YES"][..]),
+        ("service", &["pullconf", "start"][..]),
+    ] {
+        if let Err(error) = resources::run(Command::new(program).args(args)) {
+            error!(scope, pid; "failed to start the pullconf service: {:#}", error);
+            return Err(Terminate);
+        }
+    }
+
+    info!(scope, pid; "enabled the pullconf service in /etc/rc.conf and started it");
+
+    Ok(())
+}
+
+/// Schedule regular runs via cron on systems that do not run systemd.
+fn install_cron_job(pid: u32) -> Result<(), Terminate> {
+    let scope = "bootstrap";
+
+    if Path::new(CRON_FILE).exists() {
+        info!(scope, pid; "keeping existing cron job {}", CRON_FILE);
+        return Ok(());
+    }
+
+    match fs::write(CRON_FILE, CRON_JOB) {
+        Ok(()) => {
+            info!(scope, pid; "installed cron job {}", CRON_FILE);
+            Ok(())
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to write {}: {}", CRON_FILE, error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Set variables in the contents of an environment file. Existing
+/// assignments of these variables are replaced, new ones are appended.
+fn merge(current: &str, variables: &[(&str, &str)]) -> String {
+    let mut lines = current
+        .lines()
+        .filter(|line| {
+            !variables.iter().any(|(name, _)| {
+                line.trim_start()
+                    .strip_prefix(name)
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            })
+        })
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+
+    for (name, value) in variables {
+        lines.push(format!("{}={}", name, value));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_environment() {
+        assert_eq!(
+            merge("", &[("PULLCONF_SERVER", "pullconf.example.com")]),
+            "PULLCONF_SERVER=pullconf.example.com\n"
+        );
+        assert_eq!(
+            merge(
+                "# comment\nPULLCONF_SERVER=old.example.com\nPULLCONF_SERVER_NAME=x\nLOG_LEVEL=debug\n",
+                &[
+                    ("PULLCONF_SERVER", "new.example.com"),
+                    ("PULLCONF_API_KEY", "secret")
+                ]
+            ),
+            "# comment\nPULLCONF_SERVER_NAME=x\nLOG_LEVEL=debug\nPULLCONF_SERVER=new.example.com\nPULLCONF_API_KEY=secret\n"
+        );
+    }
+}
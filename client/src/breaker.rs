@@ -0,0 +1,135 @@
+use crate::state;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, sync::LazyLock, time::Duration};
+use time::OffsetDateTime;
+use ureq::serde_json;
+
+static BREAKER_FILE: LazyLock<String> = LazyLock::new(|| state::path("breaker.json"));
+
+/// Consecutive failed runs, persisted between runs. A run has failed if it
+/// did not get to apply the resource catalog at all or if no resource
+/// could be applied, i.e. every resource either failed or was skipped.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Failures {
+    pub count: u32,
+    /// The end of the most recent failed run as UNIX timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last: Option<i64>,
+}
+
+impl Failures {
+    /// Read the consecutive failures from disk. A missing or unreadable
+    /// file is treated as if no run had failed.
+    pub fn load() -> Self {
+        fs::read(&*BREAKER_FILE)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Record whether a run has failed. A successful run resets the count.
+/// Failures to persist the count are only logged.
+pub fn record(pid: u32, failed: bool) {
+    let mut failures = Failures::load();
+
+    if failed {
+        failures.count = failures.count.saturating_add(1);
+        failures.last = Some(OffsetDateTime::now_utc().unix_timestamp());
+    } else if failures.count == 0 {
+        return;
+    } else {
+        failures = Failures::default();
+    }
+
+    let mut temporary = Path::new(&*BREAKER_FILE).to_path_buf();
+    temporary.set_extension("tmp");
+
+    let result = serde_json::to_vec(&failures)
+        .map_err(|error| error.to_string())
+        .and_then(|bytes| fs::write(&temporary, bytes).map_err(|error| error.to_string()))
+        .and_then(|_| fs::rename(&temporary, &*BREAKER_FILE).map_err(|error| error.to_string()));
+
+    if let Err(error) = result {
+        warn!(scope = "breaker", pid; "failed to save failed runs to {}: {}", *BREAKER_FILE, error);
+    }
+}
+
+/// After a number of consecutive failed runs pullconf backs off instead of
+/// hammering a broken system every few minutes: scheduled runs are skipped
+/// until the backoff has elapsed since the last failed run, and the daemon
+/// waits for at least the backoff between runs.
+#[derive(Clone, Copy, Debug)]
+pub struct Breaker {
+    /// The number of consecutive failed runs after which pullconf backs
+    /// off, where zero disables the breaker.
+    pub threshold: u32,
+    pub backoff: Duration,
+}
+
+impl Breaker {
+    /// Check whether pullconf backs off after the given failures.
+    pub fn is_open(&self, failures: &Failures) -> bool {
+        self.threshold > 0 && failures.count >= self.threshold
+    }
+
+    /// Return the time until the next run may be attempted, or `None` if
+    /// runs are not held back.
+    pub fn remaining(&self, failures: &Failures, now: OffsetDateTime) -> Option<Duration> {
+        if !self.is_open(failures) {
+            return None;
+        }
+
+        let elapsed = now.unix_timestamp() - failures.last?;
+
+        self.backoff
+            .checked_sub(Duration::from_secs(elapsed.max(0) as u64))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn back_off_after_threshold() {
+        let breaker = Breaker {
+            threshold: 3,
+            backoff: Duration::from_secs(3600),
+        };
+
+        let now = OffsetDateTime::now_utc();
+
+        let failures = Failures {
+            count: 2,
+            last: Some(now.unix_timestamp()),
+        };
+
+        assert!(!breaker.is_open(&failures));
+        assert_eq!(breaker.remaining(&failures, now), None);
+
+        let failures = Failures {
+            count: 3,
+            last: Some(now.unix_timestamp() - 600),
+        };
+
+        assert!(breaker.is_open(&failures));
+        assert_eq!(
+            breaker.remaining(&failures, now),
+            Some(Duration::from_secs(3000))
+        );
+        assert_eq!(
+            breaker.remaining(&failures, now + Duration::from_secs(3000)),
+            None
+        );
+
+        let disabled = Breaker {
+            threshold: 0,
+            ..breaker
+        };
+
+        assert!(!disabled.is_open(&failures));
+    }
+}
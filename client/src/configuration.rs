@@ -1,24 +1,290 @@
-use crate::resources::{
-    Resource, {Error, Resources},
+use crate::{
+    breaker,
+    endpoints::{self, Health},
+    history, interactive, local, metrics,
+    overrides::{LocalOverrides, Override},
+    priority, privileges,
+    resources::{cron, Action, Resource, Resources},
+    schema, state, systemd,
+    update::{self, Release},
 };
-use common::{error::Terminate, Hostname};
-use log::{debug, error, info};
+use anyhow::Context;
+use common::{
+    error::{ErrorCode, Terminate},
+    problem::{self, Problem},
+    report::{ResourceResult, Run},
+    Hostname, Priority, Purge,
+};
+use log::{debug, error, info, warn};
+use rand::Rng;
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     env,
     error::Error as StdError,
-    fs,
-    io::{BufReader, ErrorKind},
-    path::PathBuf,
-    process::Command,
+    fmt, fs,
+    io::{BufReader, ErrorKind, Read},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
     str::FromStr,
-    time::Instant,
+    sync::{Condvar, LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
 };
-use ureq::{serde_json, Agent, AgentBuilder};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use ureq::{Agent, AgentBuilder, Request, Response};
 use url::Url;
+use uuid::Uuid;
+
+static ETAG_FILE: LazyLock<String> = LazyLock::new(|| state::path("etag"));
+static CATALOG_FILE: LazyLock<String> = LazyLock::new(|| state::path("catalog"));
+/// The SHA-256 digest of the saved resource catalog in the format of
+/// `sha256sum`, which is verified before the saved catalog is applied.
+static CHECKSUM_FILE: LazyLock<String> = LazyLock::new(|| state::path("catalog.sha256"));
+/// The serial of the last resource catalog that was fetched from pullconfd,
+/// see `check_serial`.
+static SERIAL_FILE: LazyLock<String> = LazyLock::new(|| state::path("serial"));
+
+/// The upper bound of the delay between two attempts to query pullconfd.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// The bounds of the polling interval that pullconfd may ask the daemon
+/// for, so that a typo on the server neither floods it with requests nor
+/// stops clients from polling at all.
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The number of resources that are logged as the slowest of a run.
+const SLOWEST_RESOURCES: usize = 5;
+
+/// Timeouts of requests to pullconfd in seconds, where zero means no timeout.
+/// The connect and read timeouts apply to every request, whereas the overall
+/// timeout differs between the resource catalog and file contents, as large
+/// files may take much longer to download.
+#[derive(Clone, Copy, Debug)]
+struct Timeouts {
+    connect: u64,
+    read: u64,
+    catalog: u64,
+    assets: u64,
+}
+
+impl Timeouts {
+    fn from_env(pid: u32) -> Result<Self, Terminate> {
+        Ok(Self {
+            connect: parse_number(pid, "PULLCONF_TIMEOUT_CONNECT", 10)?,
+            read: parse_number(pid, "PULLCONF_TIMEOUT_READ", 30)?,
+            catalog: parse_number(pid, "PULLCONF_TIMEOUT", 60)?,
+            assets: parse_number(pid, "PULLCONF_TIMEOUT_ASSETS", 600)?,
+        })
+    }
+
+    /// Apply the timeouts to the agent. The overall timeout of the agent is
+    /// the one for file contents, the resource catalog request overrides it.
+    fn configure(&self, mut builder: AgentBuilder) -> AgentBuilder {
+        if self.connect > 0 {
+            builder = builder.timeout_connect(Duration::from_secs(self.connect));
+        }
+
+        if self.read > 0 {
+            builder = builder.timeout_read(Duration::from_secs(self.read));
+        }
+
+        if self.assets > 0 {
+            builder = builder.timeout(Duration::from_secs(self.assets));
+        }
+
+        builder
+    }
+}
+
+/// Settings that are passed on the command line and take precedence over
+/// the environment and the system's hostname.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// Replaces `PULLCONF_SERVER`.
+    pub server: Option<String>,
+    /// A file whose content replaces `PULLCONF_API_KEY`, so that the key
+    /// does not appear in the process list or the shell history.
+    pub api_key_file: Option<PathBuf>,
+    /// Replaces the output of `hostname --fqdn`.
+    pub hostname: Option<String>,
+    /// Permits a resource catalog that is older than the one that was
+    /// fetched before, see `check_serial`.
+    pub allow_rollback: bool,
+}
+
+/// How the resources of the catalog are scheduled when it is applied.
+#[derive(Clone, Copy, Debug)]
+pub struct Scheduling {
+    /// The maximum number of resources that are applied concurrently.
+    pub parallelism: usize,
+    /// The CPU and IO priority of resources that do not set their own.
+    pub priority: Priority,
+    /// The time after which no further resources are started. Resources
+    /// that are pending by then are deferred to the next run.
+    pub budget: Option<Duration>,
+}
+
+/// Tags and resources that are passed on the command line to apply only a
+/// subset of the resource catalog.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    /// Only the resources that are named by their type and primary
+    /// parameter, e.g. `file:/etc/ssh/sshd_config`, are applied, unless no
+    /// resources are given.
+    pub only: Vec<String>,
+    /// Only resources with at least one of these tags are applied, unless
+    /// no tags are given.
+    pub tags: Vec<String>,
+    /// Resources with any of these tags are not applied.
+    pub skip_tags: Vec<String>,
+    /// Resources of these types are not applied, e.g. when the package
+    /// mirror is down. Unlike tags, resources that depend on them are
+    /// skipped as well.
+    pub skip_types: Vec<String>,
+}
+
+impl Selection {
+    /// Check whether neither resources nor tags are selected. Skipped types
+    /// do not narrow the selection, as they are handled while applying.
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.tags.is_empty() && self.skip_tags.is_empty()
+    }
+
+    fn matches(&self, resource: &Resource) -> bool {
+        let tags = resource.tags();
+
+        (self.only.is_empty() || self.only.contains(&name(resource)))
+            && (self.tags.is_empty() || tags.iter().any(|tag| self.tags.contains(tag)))
+            && !tags.iter().any(|tag| self.skip_tags.contains(tag))
+    }
+
+    /// Return the IDs of the resources that match the tags and of every
+    /// resource that they depend on, directly or transitively. Dependencies
+    /// are included even if they have a tag that is skipped, as the selected
+    /// resources could not be applied otherwise.
+    /// Naming a resource that is not part of the catalog is an error, as it
+    /// is most likely a typo.
+    fn select(&self, resources: &VecDeque<Resource>) -> Result<HashSet<Uuid>, String> {
+        if let Some(missing) = self
+            .only
+            .iter()
+            .find(|item| !resources.iter().any(|resource| name(resource) == **item))
+        {
+            return Err(format!(
+                "resource `{}` is not part of the resource catalog, expected <type>:<primary parameter>",
+                missing
+            ));
+        }
 
-const ETAG_FILE: &str = "/var/lib/pullconf/etag";
-const CATALOG_FILE: &str = "/var/lib/pullconf/catalog";
+        let resources = resources
+            .iter()
+            .map(|resource| (resource.id(), resource))
+            .collect::<HashMap<_, _>>();
+
+        let mut pending = resources
+            .values()
+            .filter(|resource| self.matches(resource))
+            .map(|resource| resource.id())
+            .collect::<Vec<_>>();
+
+        let mut selected = HashSet::new();
+
+        while let Some(id) = pending.pop() {
+            if selected.insert(id) {
+                if let Some(resource) = resources.get(&id) {
+                    pending.extend(
+                        resource
+                            .dependencies()
+                            .iter()
+                            .map(|dependency| dependency.id),
+                    );
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+}
+
+/// Return the name by which a resource is selected on the command line,
+/// i.e. its type and primary parameter, e.g. `file:/etc/ssh/sshd_config`.
+pub fn name(resource: &Resource) -> String {
+    format!("{}:{}", resource.kind(), resource.display())
+}
+
+/// How often and after which delay failed requests to pullconfd are retried.
+#[derive(Clone, Copy, Debug)]
+struct Retry {
+    attempts: u32,
+    delay: Duration,
+}
+
+impl Retry {
+    /// Read the number of retries and the initial delay from the environment.
+    fn from_env(pid: u32) -> Result<Self, Terminate> {
+        Ok(Self {
+            attempts: parse_number(pid, "PULLCONF_RETRIES", 3)?
+                .try_into()
+                .unwrap_or(u32::MAX),
+            delay: Duration::from_secs(parse_number(pid, "PULLCONF_RETRY_DELAY", 1)?),
+        })
+    }
+
+    /// Return the delay before the given (zero-based) retry. The initial delay
+    /// is doubled with every retry up to an upper bound. Then a random jitter of
+    /// up to half of the delay is subtracted, so that clients which failed at
+    /// the same time do not retry at the same time.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(MAX_RETRY_DELAY);
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// The overall result of applying a resource catalog, which determines the
+/// exit code of the program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    /// Every resource was already in its desired state.
+    Unchanged,
+    /// At least one resource was (or in noop mode would be) modified.
+    Changed,
+    /// At least one resource failed to apply.
+    Failed,
+    /// The run was skipped as pullconf has been disabled.
+    Disabled,
+    /// The run was skipped as previous runs failed repeatedly, see
+    /// `breaker::Breaker`.
+    Quarantined,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unchanged => f.write_str("unchanged"),
+            Self::Changed => f.write_str("changed"),
+            Self::Failed => f.write_str("failed"),
+            Self::Disabled => f.write_str("disabled"),
+            Self::Quarantined => f.write_str("quarantined"),
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Unchanged => Self::SUCCESS,
+            Outcome::Changed => Self::from(2),
+            Outcome::Disabled | Outcome::Quarantined => Self::from(3),
+            Outcome::Failed => Self::from(4),
+        }
+    }
+}
 
 /// This struct contains every piece of information that is needed to retrieve
 /// this system's configuration (resource catalog) from pullconfd and apply it.
@@ -27,19 +293,71 @@ pub struct Configuration {
     agent: Agent,
     base_url: Url,
     api_key: String,
+    /// The etag of the resource catalog, if pullconfd sent one.
+    etag: Option<String>,
+    /// The version of pullconf that pullconfd advertises, if any.
+    release: Option<Release>,
+    /// The interval in which pullconfd asks the daemon to poll, if any.
+    interval: Option<Duration>,
+    /// Resources that are skipped or forced into noop mode on this system,
+    /// see `LocalOverrides`.
+    local_overrides: LocalOverrides,
+    /// Whether the resource catalog was read from a local file instead of
+    /// pullconfd, see `Configuration::local`.
+    local: bool,
     resources: VecDeque<Resource>,
+    /// Namespaces in which pullconf removes what it created earlier, but
+    /// which is no longer part of the resource catalog.
+    purge: Purge,
 }
 
 impl Configuration {
     /// Retrieve this system's configuration from pullconfd.
     /// Depending on pullconfd's answer, either the payload or the cached resource
     /// catalog are parsed from JSON and then returned.
-    pub fn get(pid: u32) -> Result<Self, Terminate> {
+    /// In offline mode pullconfd is not queried at all and the saved resource
+    /// catalog is returned instead.
+    pub fn get(pid: u32, overrides: &Overrides, offline: bool) -> Result<Self, Terminate> {
+        Self::retrieve(pid, overrides, offline, true)
+    }
+
+    /// Retrieve this system's configuration from pullconfd without using or
+    /// replacing the saved resource catalog, e.g. to compare both.
+    pub fn fetch(pid: u32, overrides: &Overrides) -> Result<Self, Terminate> {
+        Self::retrieve(pid, overrides, false, false)
+    }
+
+    /// Return the saved resource catalog, which is empty if none has been
+    /// saved yet.
+    pub fn saved(pid: u32) -> Result<VecDeque<Resource>, Terminate> {
+        if !Path::new(&*CATALOG_FILE).exists() {
+            return Ok(VecDeque::new());
+        }
+
+        let etag = get_etag(pid)?;
+
+        Ok(get_saved_resource_catalog(pid, etag.as_deref())?.data)
+    }
+
+    fn retrieve(
+        pid: u32,
+        overrides: &Overrides,
+        offline: bool,
+        cache: bool,
+    ) -> Result<Self, Terminate> {
         let scope = "configuration";
 
         // Retrieve the system's (fully-qualified) hostname. The hostname is used
         // to query pullconfd for this system's configuration.
-        let hostname = {
+        let hostname = if let Some(hostname) = &overrides.hostname {
+            match Hostname::from_str(hostname) {
+                Ok(hostname) => hostname,
+                Err(error) => {
+                    error!(scope, pid; "failed to parse --hostname: {}", error);
+                    return Err(Terminate);
+                }
+            }
+        } else {
             let mut command = Command::new("hostname");
             command.arg("--fqdn");
 
@@ -78,11 +396,15 @@ impl Configuration {
             }
         };
 
-        let base_url = {
-            let address = {
+        // One or more servers that are tried in order, see
+        // `endpoints::resolve`.
+        let endpoints = {
+            let servers = if let Some(server) = &overrides.server {
+                server.clone()
+            } else {
                 let v = "PULLCONF_SERVER";
                 match env::var(v) {
-                    Ok(value) => format!("https://{}", value),
+                    Ok(value) => value,
                     Err(error) => {
                         error!(scope, pid; "failed to read environment variable {}: {}", v, error);
                         return Err(Terminate);
@@ -90,17 +412,19 @@ impl Configuration {
                 }
             };
 
-            match Url::parse(&address) {
-                Ok(url) => url,
+            endpoints::resolve(pid, &servers)?
+        };
+
+        // The API key that is defined in the TOML configuration file on the server.
+        let api_key = if let Some(path) = &overrides.api_key_file {
+            match fs::read_to_string(path) {
+                Ok(value) => value.trim().to_string(),
                 Err(error) => {
-                    error!(scope, pid; "failed to parse {} as URL: {}", address, error);
+                    error!(scope, pid; "failed to read API key from file {}: {}", path.display(), error);
                     return Err(Terminate);
                 }
             }
-        };
-
-        // The API key that is defined in the TOML configuration file on the server.
-        let api_key = {
+        } else {
             let v = "PULLCONF_API_KEY";
             match env::var(v) {
                 Ok(value) => value,
@@ -160,56 +484,207 @@ impl Configuration {
         }
 
         // Build a custom TLS configuration from the truststore that was created earlier.
-        let tls_config = rustls::ClientConfig::builder()
-            .with_root_certificates(roots)
-            .with_no_client_auth();
-
-        // Initialize the agent used to communicate with pullconfd.
-        let agent = AgentBuilder::new()
-            .https_only(true)
-            .tls_config(std::sync::Arc::new(tls_config))
-            .build();
-
-        // Both successful and erroneous responses from pullconfd are JSON. Except when
-        // the response comes from an intermediary (e.g. a reverse proxy).
+        let tls_config = std::sync::Arc::new(
+            rustls::ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        );
+
+        let timeouts = Timeouts::from_env(pid)?;
+
+        // Initialize the agent used to communicate with a server. The proxy
+        // may differ between servers, so every server gets its own agent.
+        let build_agent = |base_url: &Url| -> Result<Agent, Terminate> {
+            let mut builder = AgentBuilder::new()
+                .https_only(true)
+                .user_agent(&format!("{}/{}", crate::APPLICATION, crate::VERSION))
+                .tls_config(tls_config.clone())
+                .resolver(endpoints::HappyEyeballs {
+                    timeout: Duration::from_secs(timeouts.connect),
+                });
+
+            builder = timeouts.configure(builder);
+
+            if let Some(proxy) = proxy(pid, base_url.host_str().unwrap_or_default())? {
+                builder = builder.proxy(proxy);
+            }
+
+            Ok(builder.build())
+        };
+
+        // The agent is still needed in offline mode, since file contents are
+        // downloaded when the resources are applied.
+        if offline {
+            info!(scope, pid; "running in offline mode, reading saved resource catalog from disk");
+
+            let base_url = endpoints[0].clone();
+            let etag = get_etag(pid).unwrap_or_default();
+            let catalog = get_saved_resource_catalog(pid, etag.as_deref())?;
+
+            return Ok(Self {
+                agent: build_agent(&base_url)?,
+                base_url,
+                api_key,
+                etag,
+                release: None,
+                interval: None,
+                local_overrides: LocalOverrides::load(pid)?,
+                local: false,
+                resources: catalog.data,
+                purge: catalog.purge,
+            });
+        }
+
+        let retry = Retry::from_env(pid)?;
+
+        // Successful responses from pullconfd are JSON, erroneous responses are
+        // problem details (RFC 7807). Except when the response comes from an
+        // intermediary (e.g. a reverse proxy).
         let content_type = "application/json";
 
         let scope = "request";
 
-        // Query pullconfd for this system's configuration and parse the result.
-        let url = base_url
-            .join(&format!("/api/clients/{}/resources", hostname))
-            .unwrap();
+        // The saved resource catalog belongs to the regular server and hostname,
+        // so it is neither used nor replaced if either of them is overridden.
+        let cache = cache && overrides.server.is_none() && overrides.hostname.is_none();
 
-        let mut request = agent
-            .get(url.as_str())
-            .set("accept", content_type)
-            .set("x-api-key", &api_key);
+        let saved_etag = if cache {
+            debug!(scope, pid; "checking if a file with an etag of a saved resource catalog exists");
 
-        debug!(scope, pid, url:%; "checking if a file with an etag of a saved resource catalog exists");
+            get_etag(pid)?
+        } else {
+            None
+        };
 
-        if let Some(etag) = get_etag(pid)? {
-            debug!(scope, pid, url:%; "adding etag of saved resource catalog to request");
-            request = request.set("if-none-match", &etag);
-        }
+        // Servers that failed recently are tried last. With a single server
+        // there is nothing to choose from.
+        let mut health = if endpoints.len() > 1 {
+            Some(Health::load())
+        } else {
+            None
+        };
 
-        let _timer = Instant::now();
+        let endpoints = match &health {
+            Some(health) => health.order(endpoints, OffsetDateTime::now_utc()),
+            None => endpoints,
+        };
+
+        let last = endpoints.len() - 1;
 
-        let resources = match request.call().inspect(|response| {
-            if let Some(content_length) = response.header("content-length") {
-                debug!(scope, pid, url:%; "received {} bytes", content_length);
+        let mut attempts = endpoints.into_iter().enumerate();
+
+        // Query the servers for this system's configuration until one of
+        // them responds. Only unavailable servers are failed over, whereas
+        // any response from a server is final.
+        let (agent, base_url, url, result) = loop {
+            let Some((index, base_url)) = attempts.next() else {
+                unreachable!("the last server is never failed over");
+            };
+
+            let agent = build_agent(&base_url)?;
+
+            // Send this system's facts ahead of the catalog request. Failures are
+            // logged, but do not prevent the catalog from being fetched.
+            send_facts(pid, &agent, &base_url, &hostname, &api_key, timeouts);
+
+            // Query pullconfd for this system's configuration and parse the result.
+            let url = base_url
+                .join(&format!("/api/clients/{}/resources", hostname))
+                .unwrap();
+
+            let mut request = agent
+                .get(url.as_str())
+                .set(
+                    "accept",
+                    &format!("{}, {}", content_type, problem::CONTENT_TYPE),
+                )
+                .set("x-api-key", &api_key)
+                .set("x-pullconf-target", &update::target());
+
+            if timeouts.catalog > 0 {
+                request = request.timeout(Duration::from_secs(timeouts.catalog));
             }
 
-            debug!(scope, pid, url:%;
-                   "finished request in {} ms",
-                   (_timer.elapsed().as_millis() as f64) / 1000.0
-            )
-        }) {
+            if let Some(etag) = &saved_etag {
+                debug!(scope, pid, url:%; "adding etag of saved resource catalog to request");
+                request = request.set("if-none-match", etag);
+            }
+
+            // Retrying a server only delays the failover, so only the last
+            // server is retried.
+            let retry = if index == last {
+                retry
+            } else {
+                Retry {
+                    attempts: 0,
+                    ..retry
+                }
+            };
+
+            let _timer = Instant::now();
+
+            let result = call(pid, request, retry).inspect(|response| {
+                if let Some(content_length) = response.header("content-length") {
+                    debug!(scope, pid, url:%; "received {} bytes", content_length);
+                }
+
+                debug!(scope, pid, url:%;
+                       "finished request in {} ms",
+                       (_timer.elapsed().as_millis() as f64) / 1000.0
+                )
+            });
+
+            let available = !matches!(&result, Err(error) if is_transient(error));
+
+            if let Some(health) = &mut health {
+                health.record(&base_url, available, OffsetDateTime::now_utc());
+            }
+
+            if !available && index < last {
+                warn!(
+                    scope,
+                    pid,
+                    url:%;
+                    "server is unavailable ({}), failing over to the next server",
+                    result.as_ref().err().map(|error| describe(error)).unwrap_or_default()
+                );
+
+                continue;
+            }
+
+            break (agent, base_url, url, result);
+        };
+
+        if let Some(health) = &health {
+            health.save(pid);
+        }
+
+        let (etag, catalog, release, interval) = match result {
+            // If pullconfd remains unreachable, apply the saved resource catalog
+            // instead of not applying anything at all.
+            Err(error) if cache && is_transient(&error) && Path::new(&*CATALOG_FILE).exists() => {
+                warn!(
+                    scope,
+                    pid,
+                    url:%;
+                    "giving up on request ({}), reading saved resource catalog from disk",
+                    describe(&error)
+                );
+
+                let catalog = get_saved_resource_catalog(pid, saved_etag.as_deref())?;
+
+                (saved_etag, catalog, None, None)
+            }
             Ok(response) => {
+                let release = Release::from_response(&response);
+                let interval = polling_interval(&response);
+
                 if response.status() == 304 {
                     debug!(scope, pid, url:%; "server returned 304, ignoring the request body and reading saved resource catalog from disk");
 
-                    get_saved_resource_catalog(pid)?.data
+                    let catalog = get_saved_resource_catalog(pid, saved_etag.as_deref())?;
+
+                    (saved_etag, catalog, release, interval)
                 } else {
                     // If the response is successful according to the status code, but the
                     // content type hints at a non-JSON body, log a generic error including
@@ -242,34 +717,41 @@ impl Configuration {
                             }
                         };
 
-                        if let Some(etag) = etag {
+                        // Validate the whole resource catalog first, so that a
+                        // catalog that cannot be applied is neither applied in
+                        // part nor saved to disk for later runs.
+                        let catalog = validate(pid, url.as_str(), payload.as_bytes())?;
+
+                        check_serial(
+                            pid,
+                            url.as_str(),
+                            catalog.serial,
+                            overrides.allow_rollback,
+                            cache,
+                        )?;
+
+                        if let Some(etag) = etag.as_ref().filter(|_| cache) {
                             debug!(scope, pid, url:%; "saving resource catalog data to disk");
 
-                            save_resource_catalog(pid, &etag, &payload)?;
+                            save_resource_catalog(pid, etag, &payload)?;
                         }
 
-                        match serde_json::from_str::<Resources>(&payload) {
-                            Ok(catalog) => catalog.data,
-                            Err(error) => {
-                                error!(scope, pid, url:%; "failed to deserialize resource catalog : {}", error);
-                                return Err(Terminate);
-                            }
-                        }
+                        (etag, catalog, release, interval)
                     }
                 }
             }
-            Err(error) => match error {
+            Err(error) => match *error {
                 ureq::Error::Status(_, response) => {
                     // If the response is erroneous according to the status code, but the
                     // content type hints at a non-JSON body, log a generic error including
                     // relevant information for debugging and terminate the program.
-                    if response.content_type() != content_type {
+                    if response.content_type() != problem::CONTENT_TYPE {
                         error!(
                             scope,
                             pid,
                             url:%;
                             "unexpected API response content type, expected {}, got {} and status {} {} from {}",
-                            content_type,
+                            problem::CONTENT_TYPE,
                             response.content_type(),
                             response.status(),
                             response.status_text(),
@@ -277,11 +759,11 @@ impl Configuration {
                         );
                         return Err(Terminate);
                     } else {
-                        debug!(scope, pid, url:%; "content type is {}, deserializing error message", content_type);
+                        debug!(scope, pid, url:%; "content type is {}, deserializing error message", problem::CONTENT_TYPE);
 
-                        // Otherwise parse the well-known API error format from JSON and log
+                        // Otherwise parse the problem details and log
                         // the error appropiately. Then terminate the program.
-                        let error = match response.into_json::<Error>() {
+                        let error = match response.into_json::<Problem>() {
                             Ok(error) => error,
                             Err(error) => {
                                 error!(scope, pid, url:%; "failed to deserialize error response: {}", error);
@@ -292,13 +774,37 @@ impl Configuration {
                         error!(
                             scope,
                             pid,
-                            url:%
+                            url:%,
+                            category:% = error.error_code()
                             ;
-                            "pullconfd failed to process the request: ({}) {}",
+                            "pullconfd failed to process the request: ({}, {}) {}",
+                            error.code,
                             error.title,
                             error.detail
                         );
 
+                        // Point out how to resolve errors that recur on every
+                        // run until they are fixed.
+                        match error.error_code() {
+                            ErrorCode::Authentication => error!(
+                                scope,
+                                pid;
+                                "the API key is not accepted, run `pullconf bootstrap` to register this system again or check PULLCONF_API_KEY"
+                            ),
+                            ErrorCode::Authorization => error!(
+                                scope,
+                                pid;
+                                "the API key is not permitted to fetch the resource catalog of {}, check the hostname of this system or --hostname",
+                                hostname
+                            ),
+                            ErrorCode::Validation | ErrorCode::NotFound => error!(
+                                scope,
+                                pid;
+                                "the request is rejected by pullconfd and fails again unless the configuration of pullconf is changed"
+                            ),
+                            _ => (),
+                        }
+
                         return Err(Terminate);
                     }
                 }
@@ -314,12 +820,97 @@ impl Configuration {
             agent,
             base_url,
             api_key,
-            resources,
+            etag,
+            release,
+            interval,
+            local_overrides: LocalOverrides::load(pid)?,
+            local: false,
+            resources: catalog.data,
+            purge: catalog.purge,
         };
 
         Ok(configuration)
     }
 
+    /// Read this system's configuration from a local file without contacting
+    /// pullconfd at all, see `local::load`. File contents are never downloaded
+    /// in this case, so the agent and the base URL remain unused.
+    pub fn local(pid: u32, path: &Path) -> Result<Self, Terminate> {
+        Ok(Self {
+            agent: AgentBuilder::new().build(),
+            base_url: Url::parse("http://localhost/").expect("URL is valid"),
+            api_key: String::new(),
+            etag: None,
+            release: None,
+            interval: None,
+            local_overrides: LocalOverrides::load(pid)?,
+            local: true,
+            resources: local::load(pid, path)?,
+            purge: Purge::default(),
+        })
+    }
+
+    /// The resource catalog in the order in which it was received.
+    pub fn resources(&self) -> &VecDeque<Resource> {
+        &self.resources
+    }
+
+    /// Return the paths of the selected file, directory and symlink resources
+    /// along with their names, e.g. to watch them for changes.
+    pub fn watched(&self, selection: &Selection) -> Vec<(PathBuf, String)> {
+        let selected = if selection.is_empty() {
+            None
+        } else {
+            selection.select(&self.resources).ok()
+        };
+
+        self.resources
+            .iter()
+            .filter(|resource| matches!(resource.kind(), "file" | "directory" | "symlink"))
+            .filter(|resource| {
+                selected
+                    .as_ref()
+                    .is_none_or(|selected| selected.contains(&resource.id()))
+            })
+            .map(|resource| (PathBuf::from(resource.display()), name(resource)))
+            .collect()
+    }
+
+    /// The version of pullconf that pullconfd advertises, if any.
+    pub fn release(&self) -> Option<&Release> {
+        self.release.as_ref()
+    }
+
+    /// The interval in which pullconfd asks the daemon to poll, if any.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Download the client binary that pullconfd serves for self-updates.
+    pub fn download_agent(&self, pid: u32) -> Result<Vec<u8>, anyhow::Error> {
+        let url = self.base_url.join("/api/agent")?;
+
+        debug!(scope = "update", pid, url:%; "downloading client binary");
+
+        let response = self
+            .agent
+            .get(url.as_str())
+            .set("accept", "application/octet-stream")
+            .set("x-api-key", &self.api_key)
+            .set("x-pullconf-target", &update::target())
+            .call()
+            .context("failed to download client binary")?;
+
+        let mut bytes = vec![];
+
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .context("failed to read client binary")?;
+
+        Ok(bytes)
+    }
+
     /// Apply every resource that is part of this system's configuration.
     /// Resources are applied in no particular order. Every resource
     /// checks if it has any dependencies and if those were alreay applied.
@@ -328,36 +919,774 @@ impl Configuration {
     /// Since there are always resources that have no dependencies, those are
     /// applied first and then everything else, until every resource has been
     /// applied.
-    pub fn apply(mut self, pid: u32) {
+    /// In noop mode the current state of every resource is evaluated, but
+    /// nothing is modified. Instead the action that would be taken is
+    /// reported.
+    /// If tags are selected, resources that neither match them nor are
+    /// needed by a resource that does are skipped.
+    /// The result of every resource is recorded in the history of runs.
+    /// Once the run budget, if any, is exhausted, resources that have not
+    /// been started yet are deferred to the next run.
+    pub fn apply(
+        mut self,
+        pid: u32,
+        noop: bool,
+        audit: bool,
+        selection: &Selection,
+        scheduling: &Scheduling,
+        metrics: Option<&Path>,
+    ) -> Result<Outcome, Terminate> {
         let _timer = Instant::now();
+        let now = OffsetDateTime::now_utc();
+        let timestamp = now
+            .replace_nanosecond(0)
+            .unwrap_or(now)
+            .format(&Rfc3339)
+            .unwrap_or_default();
+
+        let selected = if selection.is_empty() {
+            None
+        } else {
+            match selection.select(&self.resources) {
+                Ok(selected) => Some(selected),
+                Err(error) => {
+                    error!(pid; "{}", error);
+                    return Err(Terminate);
+                }
+            }
+        };
+
+        if let Some(selected) = &selected {
+            info!(
+                pid;
+                "applying {} of {} resources that are selected or required by selected resources",
+                selected.len(),
+                self.resources.len()
+            );
+        }
+
+        if !self.local_overrides.is_empty() {
+            let names = self.resources.iter().map(name).collect::<Vec<String>>();
+
+            for resource in self.local_overrides.unmatched(&names) {
+                warn!(
+                    scope = "overrides",
+                    pid;
+                    "overridden resource `{}` is not part of the resource catalog, expected <type>:<primary parameter>",
+                    resource
+                );
+            }
+        }
+
+        if scheduling.parallelism > 1 {
+            debug!(pid; "applying up to {} resources concurrently", scheduling.parallelism);
+        }
+
+        let total = self.resources.len();
+
+        // Namespaces are only purged if the whole catalog is applied, as
+        // what resources outside of the selection manage would be removed
+        // otherwise.
+        let purge_cron_jobs = self.purge.cron_jobs
+            && selection.is_empty()
+            && !selection.skip_types.iter().any(|kind| kind == "cron::job");
+
+        if self.purge.cron_jobs && !purge_cron_jobs {
+            info!(pid; "not purging cron jobs, as only a subset of the resource catalog is applied");
+        }
+
+        let cron_jobs = self
+            .resources
+            .iter()
+            .filter_map(|resource| match resource {
+                Resource::CronJob(job) => Some(job.parameters.target.clone()),
+                _ => None,
+            })
+            .collect::<Vec<PathBuf>>();
+
+        let state = (
+            Mutex::new(Schedule {
+                pending: std::mem::take(&mut self.resources),
+                applied: HashMap::with_capacity(total),
+                results: Vec::with_capacity(total),
+                locks: HashSet::new(),
+                running: 0,
+                deadline: scheduling.budget.map(|budget| _timer + budget),
+                deferred: 0,
+            }),
+            Condvar::new(),
+        );
+
+        thread::scope(|scope| {
+            for _ in 0..scheduling.parallelism.max(1) {
+                scope.spawn(|| {
+                    self.work(
+                        pid,
+                        noop,
+                        selection,
+                        selected.as_ref(),
+                        scheduling.priority,
+                        &state,
+                    )
+                });
+            }
+        });
+
+        let Schedule {
+            applied: applied_resources,
+            mut results,
+            deferred,
+            ..
+        } = state.0.into_inner().unwrap();
+
+        let purged = if purge_cron_jobs {
+            cron::job::purge(
+                pid,
+                noop,
+                &cron_jobs
+                    .iter()
+                    .map(PathBuf::as_path)
+                    .collect::<Vec<&Path>>(),
+            )
+        } else {
+            vec![]
+        };
+
+        let _elapsed = (_timer.elapsed().as_millis() as f64) / 1000.0;
+
+        let changes = applied_resources
+            .values()
+            .map(Resource::action)
+            .chain(purged.iter().map(|result| &result.result))
+            .filter(|action| action.is_change())
+            .count();
+
+        let failures = applied_resources
+            .values()
+            .map(Resource::action)
+            .chain(purged.iter().map(|result| &result.result))
+            .filter(|action| **action == Action::Failed)
+            .count();
+
+        results.extend(purged);
+
+        // Deferred resources are skipped as well, but reported separately.
+        let skipped = applied_resources
+            .values()
+            .filter(|resource| resource.is_skipped())
+            .count()
+            - deferred;
+
+        if noop {
+            info!(
+                pid;
+                "evaluated resource catalog in {:.3} seconds in noop mode, {} of {} resources would change",
+                _elapsed,
+                changes,
+                applied_resources.len()
+            );
+        } else {
+            info!(pid; "applied resource catalog in {:.3} seconds", _elapsed);
+        }
+
+        if skipped > 0 {
+            info!(
+                pid;
+                "{} of {} resources were skipped",
+                skipped,
+                applied_resources.len()
+            );
+        }
+
+        if deferred > 0 {
+            warn!(
+                pid;
+                "{} of {} resources were deferred to the next run as the run budget of {} seconds was exhausted",
+                deferred,
+                applied_resources.len(),
+                scheduling.budget.unwrap_or_default().as_secs()
+            );
+        }
+
+        let mut slowest = results
+            .iter()
+            .filter(|result| result.duration > 0.0)
+            .collect::<Vec<&ResourceResult>>();
+        slowest.sort_by(|a, b| b.duration.total_cmp(&a.duration));
 
-        let mut applied_resources = HashMap::with_capacity(self.resources.len());
+        if !slowest.is_empty() {
+            debug!(
+                pid;
+                "slowest resources: {}",
+                slowest
+                    .iter()
+                    .take(SLOWEST_RESOURCES)
+                    .map(|result| format!("{} ({:.3} seconds)", result.resource, result.duration))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+
+        let outcome = if failures > 0 {
+            error!(
+                pid;
+                "{} of {} resources failed to apply",
+                failures,
+                applied_resources.len()
+            );
+
+            Outcome::Failed
+        } else if changes > 0 {
+            Outcome::Changed
+        } else {
+            Outcome::Unchanged
+        };
+
+        if interactive::enabled() {
+            interactive::print_summary(&results, _timer.elapsed().as_secs_f64());
+        }
 
-        while let Some(mut resource) = self.resources.pop_front() {
-            if !resource.is_ready(&applied_resources) {
-                self.resources.push_back(resource);
+        let run = Run {
+            timestamp,
+            noop,
+            etag: self.etag,
+            outcome: outcome.to_string(),
+            duration: _timer.elapsed().as_secs_f64(),
+            deferred,
+            resources: results,
+        };
+
+        if audit {
+            history::record_drift(pid, &run);
+        }
+
+        history::record(pid, &run);
+
+        if let Some(directory) = metrics {
+            metrics::record(pid, directory, &run);
+        }
+
+        // Only enforcing runs count towards the circuit breaker, so that
+        // audits of a broken system do not hold back the next enforcing run.
+        // The same applies to runs of a local resource catalog.
+        if !noop && !self.local {
+            breaker::record(
+                pid,
+                failures > 0 && failures + skipped + deferred == applied_resources.len(),
+            );
+        }
+
+        Ok(outcome)
+    }
+
+    /// Apply resources from the shared schedule until none are left. A
+    /// worker picks the first pending resource whose dependencies have been
+    /// applied and whose lock, if any, is not held by another worker.
+    fn work(
+        &self,
+        pid: u32,
+        noop: bool,
+        selection: &Selection,
+        selected: Option<&HashSet<Uuid>>,
+        priority: Priority,
+        (schedule, wakeup): &(Mutex<Schedule>, Condvar),
+    ) {
+        let mut state = schedule.lock().unwrap();
+
+        loop {
+            if !state.pending.is_empty()
+                && state
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                state.defer(pid);
+                wakeup.notify_all();
+                return;
+            }
+
+            let position = state.pending.iter().position(|resource| {
+                resource.is_ready(&state.applied)
+                    && resource
+                        .exclusive()
+                        .is_none_or(|lock| !state.locks.contains(lock))
+            });
+
+            let Some(mut resource) = position.and_then(|index| state.pending.remove(index)) else {
+                if state.pending.is_empty() {
+                    return;
+                }
+
+                if state.running == 0 {
+                    // No resource is being applied and none is ready, so the
+                    // remaining resources would wait forever.
+                    state.fail_unsatisfied(pid);
+                    wakeup.notify_all();
+                    return;
+                }
+
+                state = wakeup.wait(state).unwrap();
                 continue;
+            };
+
+            let lock = resource.exclusive().map(str::to_string);
+
+            if let Some(lock) = &lock {
+                state.locks.insert(lock.clone());
+            }
+
+            // Resources only inspect the dependencies that they declare, so
+            // a copy of those suffices while the schedule is unlocked.
+            let dependencies: HashMap<Uuid, Resource> = resource
+                .dependencies()
+                .iter()
+                .filter_map(|dependency| {
+                    state
+                        .applied
+                        .get(&dependency.id)
+                        .map(|applied| (dependency.id, applied.clone()))
+                })
+                .collect();
+
+            state.running += 1;
+            drop(state);
+
+            let started = Instant::now();
+
+            let priority = resource.priority().unwrap_or(priority);
+            let lowered = priority::lower(pid, priority);
+
+            if lowered.is_some() {
+                debug!(pid; "applying {} at {} priority", resource.repr(), priority);
+            }
+
+            self.process(pid, noop, selection, selected, &mut resource, &dependencies);
+
+            drop(lowered);
+
+            let duration = started.elapsed().as_secs_f64();
+
+            // Dependents may act on how long and with which result this
+            // resource was applied, see `ResourceTrait::applied_dependencies`.
+            resource.set_duration(duration);
+
+            debug!(
+                pid,
+                resource = resource.kind(),
+                name = resource.display(),
+                duration;
+                "{} took {:.3} seconds",
+                resource.repr(),
+                duration
+            );
+
+            let result = ResourceResult {
+                id: resource.id(),
+                resource: resource.repr(),
+                result: *resource.action(),
+                duration,
+                failure: resource.failure().map(str::to_string),
+            };
+
+            // Progress is reported after every resource, so that the
+            // watchdog only fires if a single resource hangs.
+            systemd::keepalive(pid);
+
+            state = schedule.lock().unwrap();
+            state.running -= 1;
+
+            if let Some(lock) = &lock {
+                state.locks.remove(lock);
+            }
+
+            state.results.push(result);
+            state.applied.insert(resource.id(), resource);
+
+            wakeup.notify_all();
+        }
+    }
+
+    /// Apply a single resource, unless it is skipped by type, because it
+    /// is not selected or because it is skipped locally. In least-privilege
+    /// mode resources that require privileges that have not been granted
+    /// fail without being applied.
+    fn process(
+        &self,
+        pid: u32,
+        noop: bool,
+        selection: &Selection,
+        selected: Option<&HashSet<Uuid>>,
+        resource: &mut Resource,
+        applied_resources: &HashMap<Uuid, Resource>,
+    ) {
+        let local_override = self.local_overrides.find(&name(resource));
+
+        if let Some(Override::Skip(reason)) = local_override {
+            let action = Action::Skipped;
+
+            warn!(
+                pid,
+                result:% = action;
+                "skipping {} as it is skipped locally in /etc/pullconf/overrides.toml{}",
+                resource.repr(),
+                reason.map(|reason| format!(": {}", reason)).unwrap_or_default()
+            );
+
+            resource.set_action(action);
+        } else if selection
+            .skip_types
+            .iter()
+            .any(|kind| kind == resource.kind())
+        {
+            let action = Action::Skipped;
+
+            warn!(
+                pid,
+                result:% = action;
+                "skipping {} as resources of type `{}` are skipped",
+                resource.repr(),
+                resource.kind()
+            );
+
+            resource.set_action(action);
+        } else if selected.is_some_and(|selected| !selected.contains(&resource.id())) {
+            let action = Action::Skipped;
+
+            debug!(
+                pid,
+                result:% = action;
+                "skipping {} as it is not selected",
+                resource.repr()
+            );
+
+            resource.set_action(action);
+        } else if let Err(reason) = privileges::check(&resource.privileges()) {
+            error!(
+                pid,
+                result:% = Action::Failed;
+                "cannot apply {} as {}",
+                resource.repr(),
+                reason
+            );
+
+            resource.fail(reason);
+        } else {
+            if let Some(Override::Noop(reason)) = local_override.as_ref().filter(|_| !noop) {
+                warn!(
+                    pid;
+                    "applying {} in noop mode as it is overridden locally in /etc/pullconf/overrides.toml{}",
+                    resource.repr(),
+                    reason.map(|reason| format!(": {}", reason)).unwrap_or_default()
+                );
             }
 
+            let noop = noop || matches!(local_override, Some(Override::Noop(_)));
+
             resource.apply(
                 pid,
+                noop,
                 &self.agent,
                 &self.base_url,
                 &self.api_key,
-                &applied_resources,
+                applied_resources,
+            );
+        }
+    }
+}
+
+/// The state that the workers applying the resource catalog share.
+struct Schedule {
+    /// Resources that have not been picked up by a worker yet.
+    pending: VecDeque<Resource>,
+    applied: HashMap<Uuid, Resource>,
+    results: Vec<ResourceResult>,
+    /// Locks that are held by workers, see `Resource::exclusive`.
+    locks: HashSet<String>,
+    /// The number of resources that are being applied right now.
+    running: usize,
+    /// The time after which no further resources are started, see
+    /// `Scheduling::budget`.
+    deadline: Option<Instant>,
+    /// The number of resources that were deferred to the next run.
+    deferred: usize,
+}
+
+impl Schedule {
+    /// Mark all pending resources as failed. This is called when no
+    /// progress can be made, i.e. when a dependency is missing from the
+    /// resource catalog or the dependencies form a cycle.
+    fn fail_unsatisfied(&mut self, pid: u32) {
+        let reasons: Vec<String> = self
+            .pending
+            .iter()
+            .map(|resource| {
+                self.unsatisfied(resource)
+                    .unwrap_or_else(|| "an unsatisfied dependency".to_string())
+            })
+            .collect();
+
+        for (mut resource, reason) in std::mem::take(&mut self.pending).into_iter().zip(reasons) {
+            let action = Action::Failed;
+
+            error!(
+                pid,
+                result:% = action;
+                "cannot apply {} due to {}",
+                resource.repr(),
+                reason
             );
 
-            applied_resources.insert(resource.id(), resource);
+            resource.set_action(action);
+
+            self.results.push(ResourceResult {
+                id: resource.id(),
+                resource: resource.repr(),
+                result: *resource.action(),
+                duration: 0.0,
+                failure: Some(reason),
+            });
+
+            self.applied.insert(resource.id(), resource);
         }
+    }
 
-        let _elapsed = (_timer.elapsed().as_millis() as f64) / 1000.0;
+    /// Skip all pending resources, as the run budget is exhausted. Resources
+    /// that are being applied right now are not interrupted.
+    fn defer(&mut self, pid: u32) {
+        for mut resource in std::mem::take(&mut self.pending) {
+            let action = Action::Skipped;
+
+            warn!(
+                pid,
+                resource = resource.kind(),
+                name = resource.display(),
+                result:% = action;
+                "deferring {} to the next run as the run budget is exhausted",
+                resource.repr()
+            );
 
-        info!(pid; "applied resource catalog in {:.3} seconds", _elapsed);
+            resource.set_action(action);
+
+            self.results.push(ResourceResult {
+                id: resource.id(),
+                resource: resource.repr(),
+                result: *resource.action(),
+                duration: 0.0,
+                failure: None,
+            });
+
+            self.applied.insert(resource.id(), resource);
+            self.deferred += 1;
+        }
+    }
+
+    /// Describe the first dependency of a resource that has not been
+    /// applied, if any.
+    fn unsatisfied(&self, resource: &Resource) -> Option<String> {
+        let dependency = resource
+            .dependencies()
+            .iter()
+            .find(|dependency| !self.applied.contains_key(&dependency.id))?;
+
+        let description = match self
+            .pending
+            .iter()
+            .find(|pending| pending.id() == dependency.id)
+        {
+            Some(pending) => format!(
+                "an unsatisfied dependency on {}, which is part of a dependency cycle or depends on a missing resource",
+                pending.repr()
+            ),
+            None => format!(
+                "an unsatisfied dependency on {} `{}`, which is missing from the resource catalog",
+                dependency.kind, dependency.id
+            ),
+        };
+
+        Some(description)
+    }
+}
+
+/// Upload the facts of this system to pullconfd.
+fn send_facts(
+    pid: u32,
+    agent: &Agent,
+    base_url: &Url,
+    hostname: &Hostname,
+    api_key: &str,
+    timeouts: Timeouts,
+) {
+    let scope = "request";
+
+    let url = match base_url.join(&format!("/api/clients/{}/facts", hostname)) {
+        Ok(url) => url,
+        Err(error) => {
+            warn!(scope, pid; "failed to build URL to send facts to: {}", error);
+            return;
+        }
+    };
+
+    let facts = crate::facts::collect(pid);
+
+    let mut request = agent
+        .put(url.as_str())
+        .set("accept", problem::CONTENT_TYPE)
+        .set("x-api-key", api_key);
+
+    if timeouts.catalog > 0 {
+        request = request.timeout(Duration::from_secs(timeouts.catalog));
+    }
+
+    match request.send_json(&facts) {
+        Ok(_) => debug!(scope, pid, url:%; "sent facts"),
+        Err(error) => warn!(scope, pid, url:%; "failed to send facts ({})", describe(&error)),
+    }
+}
+
+/// Send a request to pullconfd and retry transient failures with exponential
+/// backoff, see `Retry`.
+fn call(pid: u32, request: Request, retry: Retry) -> Result<Response, Box<ureq::Error>> {
+    let mut attempt = 0;
+
+    loop {
+        match request.clone().call() {
+            Err(error) if attempt < retry.attempts && is_transient(&error) => {
+                let delay = retry.backoff(attempt);
+
+                attempt += 1;
+
+                warn!(
+                    scope = "request",
+                    pid,
+                    url = request.url();
+                    "request failed ({}), retrying in {:.3} seconds (retry {} of {})",
+                    describe(&error),
+                    delay.as_secs_f64(),
+                    attempt,
+                    retry.attempts
+                );
+
+                systemd::sleep(pid, delay);
+            }
+            result => return result.map_err(Box::new),
+        }
+    }
+}
+
+/// Check whether a failed request may succeed when it is retried, i.e. the
+/// server could not be reached, the connection timed out or the server
+/// responded with a server error.
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => (500..600).contains(status),
+        ureq::Error::Transport(transport) => matches!(
+            transport.kind(),
+            ureq::ErrorKind::Dns
+                | ureq::ErrorKind::ConnectionFailed
+                | ureq::ErrorKind::Io
+                | ureq::ErrorKind::ProxyConnect
+        ),
+    }
+}
+
+fn describe(error: &ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(status, response) => {
+            format!("status {} {}", status, response.status_text())
+        }
+        ureq::Error::Transport(transport) => match transport.source() {
+            Some(source) => format!("{}: {}", transport.kind(), source),
+            None => transport.kind().to_string(),
+        },
     }
 }
 
+/// Determine the proxy for requests to pullconfd, which applies to both
+/// resource catalog and asset requests. `PULLCONF_PROXY` takes precedence
+/// over `HTTPS_PROXY`, which in turn is ignored if the server matches an
+/// entry of `NO_PROXY`.
+fn proxy(pid: u32, host: &str) -> Result<Option<ureq::Proxy>, Terminate> {
+    let scope = "configuration";
+
+    let var = |names: &[&'static str]| {
+        names.iter().find_map(|name| {
+            env::var(name)
+                .ok()
+                .filter(|value| !value.is_empty())
+                .map(|value| (*name, value))
+        })
+    };
+
+    let (variable, address) = match var(&["PULLCONF_PROXY"]) {
+        Some(proxy) => proxy,
+        None => match var(&["HTTPS_PROXY", "https_proxy"]) {
+            Some(proxy) => {
+                if var(&["NO_PROXY", "no_proxy"]).is_some_and(|(_, list)| is_excluded(host, &list))
+                {
+                    debug!(scope, pid; "not using a proxy as {} matches NO_PROXY", host);
+                    return Ok(None);
+                }
+
+                proxy
+            }
+            None => return Ok(None),
+        },
+    };
+
+    match ureq::Proxy::new(&address) {
+        Ok(proxy) => {
+            // The address may contain credentials, so it is not logged.
+            debug!(scope, pid; "using proxy from environment variable {}", variable);
+            Ok(Some(proxy))
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to parse proxy address from environment variable {}: {}", variable, error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Check whether a host matches an entry of a comma-separated `NO_PROXY`
+/// list, i.e. it is equal to the entry or a subdomain of it. Ports are
+/// ignored and `*` matches every host.
+fn is_excluded(host: &str, list: &str) -> bool {
+    let host = host.to_lowercase();
+
+    list.split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let entry = entry
+                .rsplit_once(':')
+                .map_or(entry.as_str(), |(name, _)| name);
+            let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+
+            entry == "*" || host == entry || host.ends_with(&format!(".{}", entry))
+        })
+}
+
+/// Parse a non-negative number from an environment variable, or return the
+/// default if the variable is not set.
+fn parse_number(pid: u32, variable: &str, default: u64) -> Result<u64, Terminate> {
+    match env::var(variable) {
+        Ok(value) => value.parse::<u64>().map_err(|error| {
+            error!(scope = "configuration", pid; "failed to parse environment variable {}: {}", variable, error);
+            Terminate
+        }),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Read the etag of the saved resource catalog. Without a checksum the
+/// saved catalog cannot be verified, e.g. if it was saved by an older
+/// version of pullconf, so the etag is ignored and pullconfd sends the
+/// whole catalog again.
 fn get_etag(pid: u32) -> Result<Option<String>, Terminate> {
-    match fs::read_to_string(ETAG_FILE) {
+    if !Path::new(&*CHECKSUM_FILE).exists() {
+        debug!(scope = "request", pid; "checksum file of saved resource catalog does not exist");
+        return Ok(None);
+    }
+
+    match fs::read_to_string(&*ETAG_FILE) {
         Ok(etag) => {
             if etag.is_empty() {
                 Ok(None)
@@ -370,50 +1699,486 @@ fn get_etag(pid: u32) -> Result<Option<String>, Terminate> {
             Ok(None)
         }
         Err(error) => {
-            error!(scope = "request", pid; "failed to read etag file {}: {}", ETAG_FILE, error);
+            error!(scope = "request", pid; "failed to read etag file {}: {}", *ETAG_FILE, error);
             Err(Terminate)
         }
     }
 }
 
-fn get_saved_resource_catalog(pid: u32) -> Result<Resources, Terminate> {
-    match fs::read_to_string(CATALOG_FILE) {
-        Ok(s) => match serde_json::from_str::<Resources>(&s) {
-            Ok(resources) => Ok(resources),
-            Err(error) => {
-                error!(scope = "request", pid; "failed to deserialize resource catalog from file: {}", error);
-                Err(Terminate)
-            }
-        },
+/// Read the saved resource catalog and verify it against its checksum and
+/// the etag it was saved with, so that a truncated or tampered catalog is
+/// never applied. Catalogs of an older version are migrated, see
+/// `schema::validate`. If the verification fails, the etag is removed so
+/// that the next run fetches the whole catalog from pullconfd again.
+fn get_saved_resource_catalog(pid: u32, etag: Option<&str>) -> Result<Resources, Terminate> {
+    let scope = "request";
+
+    let catalog = match fs::read(&*CATALOG_FILE) {
+        Ok(catalog) => catalog,
         Err(error) => {
-            error!(scope = "request", pid; "failed to read resource catalog file: {}", error);
-            Err(Terminate)
+            error!(scope, pid; "failed to read resource catalog file: {}", error);
+            return Err(Terminate);
         }
-    }
-}
+    };
 
-fn save_resource_catalog(pid: u32, etag: &str, catalog: &str) -> Result<(), Terminate> {
-    if let Err(error) = fs::write(ETAG_FILE, etag) {
+    let verified = fs::read_to_string(&*CHECKSUM_FILE)
+        .map_err(|error| format!("failed to read {}: {}", *CHECKSUM_FILE, error))
+        .and_then(|checksum| verify(&catalog, &checksum, etag));
+
+    if let Err(reason) = verified {
         error!(
-            scope = "request",
+            scope,
             pid;
-            "failed to save latest resource catalog etag to file: {}",
-            error
+            "refusing to apply saved resource catalog {}, it may be truncated or tampered with: {}",
+            *CATALOG_FILE,
+            reason
         );
 
+        discard_etag(pid);
+
         return Err(Terminate);
     }
 
-    if let Err(error) = fs::write(CATALOG_FILE, catalog) {
+    // A catalog that this version of pullconf cannot apply, e.g. one that
+    // was saved by a newer version before a downgrade, is fetched again.
+    validate(pid, &CATALOG_FILE, &catalog).inspect_err(|_| discard_etag(pid))
+}
+
+/// Remove the etag of the saved resource catalog, as pullconfd never
+/// answers with 304 Not Modified without it, so the next run fetches the
+/// whole catalog again.
+fn discard_etag(pid: u32) {
+    match fs::remove_file(&*ETAG_FILE) {
+        Ok(()) => (),
+        Err(error) if error.kind() == ErrorKind::NotFound => (),
+        Err(error) => {
+            error!(scope = "request", pid; "failed to remove etag file {}: {}", *ETAG_FILE, error)
+        }
+    }
+}
+
+/// Validate a resource catalog before any of its resources is applied, see
+/// `schema::validate`, and log every problem that is found.
+fn validate(pid: u32, source: &str, catalog: &[u8]) -> Result<Resources, Terminate> {
+    let scope = "schema";
+
+    schema::validate(catalog).map_err(|errors| {
+        for error in &errors {
+            error!(scope, pid; "{}", error);
+        }
+
         error!(
-            scope = "request",
+            scope,
+            pid;
+            "refusing to apply resource catalog from {}, as it has {} problems",
+            source,
+            errors.len()
+        );
+
+        Terminate
+    })
+}
+
+/// Refuse a resource catalog whose serial is lower than the serial of the
+/// catalog that was fetched before, or that lacks a serial, unless
+/// rollbacks are permitted, so that an older catalog, e.g. from a stale
+/// cache, a standby server with an outdated state or a replayed response,
+/// is never applied by accident.
+/// Otherwise its serial is saved for later runs if `save` is set. Catalogs
+/// that are fetched with `--server` or `--hostname` are compared, but their
+/// serial is not saved, just like the catalog itself, so that e.g. a test
+/// server cannot cause the regular server to be refused afterwards.
+fn check_serial(
+    pid: u32,
+    source: &str,
+    serial: Option<u64>,
+    allow_rollback: bool,
+    save: bool,
+) -> Result<(), Terminate> {
+    let scope = "request";
+
+    let previous = match fs::read_to_string(&*SERIAL_FILE) {
+        Ok(previous) => match previous.trim().parse::<u64>() {
+            Ok(previous) => Some(previous),
+            Err(error) => {
+                error!(scope, pid; "failed to parse serial file {}: {}", *SERIAL_FILE, error);
+                return Err(Terminate);
+            }
+        },
+        Err(error) if error.kind() == ErrorKind::NotFound => None,
+        Err(error) => {
+            error!(scope, pid; "failed to read serial file {}: {}", *SERIAL_FILE, error);
+            return Err(Terminate);
+        }
+    };
+
+    if let Some(previous) = previous.filter(|previous| is_rollback(*previous, serial)) {
+        let serial = serial.map_or("no serial".to_string(), |serial| {
+            format!("serial {}", serial)
+        });
+
+        if !allow_rollback {
+            error!(
+                scope,
+                pid;
+                "refusing to apply resource catalog from {}, it has {}, but a catalog with serial {} was fetched before, pass --allow-rollback to apply it anyway",
+                source,
+                serial,
+                previous
+            );
+
+            return Err(Terminate);
+        }
+
+        warn!(
+            scope,
             pid;
-            "failed to save latest resource catalog to file: {}",
-            error
+            "applying resource catalog from {} with {}, although a catalog with serial {} was fetched before",
+            source,
+            serial,
+            previous
         );
+    }
+
+    // A catalog without a serial, which is only applied if no catalog had
+    // one before or rollbacks are permitted, does not replace the serial of
+    // the last catalog that had one.
+    let Some(serial) = serial else {
+        debug!(scope, pid; "resource catalog from {} has no serial", source);
+        return Ok(());
+    };
+
+    if !save {
+        return Ok(());
+    }
+
+    if let Err(error) = write_atomically(&SERIAL_FILE, serial.to_string().as_bytes()) {
+        error!(scope, pid; "failed to save serial of resource catalog to file: {}", error);
+        return Err(Terminate);
+    }
+
+    Ok(())
+}
+
+/// Whether a resource catalog with the given serial is older than the one
+/// with the previous serial. Once a catalog had a serial, a catalog without
+/// one, e.g. from a pullconfd that predates serials or a replayed response
+/// of one, is considered older as well.
+fn is_rollback(previous: u64, serial: Option<u64>) -> bool {
+    serial.is_none_or(|serial| serial < previous)
+}
+
+/// Verify the saved resource catalog against the content of the checksum
+/// file. pullconfd computes etags as the SHA-256 digest of the catalog, so
+/// an etag in that format must match as well.
+fn verify(catalog: &[u8], checksum: &str, etag: Option<&str>) -> Result<(), String> {
+    let digest = format!("{:x}", Sha256::digest(catalog));
+
+    let expected = checksum.split_whitespace().next().unwrap_or_default();
+
+    if !expected.eq_ignore_ascii_case(&digest) {
+        return Err(format!(
+            "SHA-256 digest is {}, but {} was saved",
+            digest,
+            if expected.is_empty() {
+                "none"
+            } else {
+                expected
+            }
+        ));
+    }
+
+    // Intermediaries may turn the etag into a weak one, e.g. when they
+    // compress the response.
+    let etag = etag.map(|etag| etag.trim().trim_start_matches("W/").trim_matches('"'));
+
+    match etag {
+        Some(etag)
+            if etag.len() == 64
+                && etag.chars().all(|c| c.is_ascii_hexdigit())
+                && !etag.eq_ignore_ascii_case(&digest) =>
+        {
+            Err(format!(
+                "SHA-256 digest is {}, but the etag is {}",
+                digest, etag
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Save the resource catalog along with its checksum and etag. The etag is
+/// removed first and written last, so that a run that is interrupted in
+/// between does not leave behind an etag that refers to a different
+/// catalog.
+fn save_resource_catalog(pid: u32, etag: &str, catalog: &str) -> Result<(), Terminate> {
+    let scope = "request";
+
+    let checksum = format!("{:x}  catalog\n", Sha256::digest(catalog.as_bytes()));
 
+    let result = match fs::remove_file(&*ETAG_FILE) {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+    .and_then(|_| write_atomically(&CATALOG_FILE, catalog.as_bytes()))
+    .and_then(|_| write_atomically(&CHECKSUM_FILE, checksum.as_bytes()))
+    .and_then(|_| write_atomically(&ETAG_FILE, etag.as_bytes()));
+
+    if let Err(error) = result {
+        error!(scope, pid; "failed to save latest resource catalog to file: {}", error);
         return Err(Terminate);
     }
 
     Ok(())
 }
+
+/// Write a file next to its target and rename it afterwards, so that the
+/// target is never truncated.
+fn write_atomically(path: &str, content: &[u8]) -> Result<(), std::io::Error> {
+    let temporary = format!("{}.tmp", path);
+
+    fs::write(&temporary, content).and_then(|_| fs::rename(&temporary, path))
+}
+
+/// Read the polling interval from the headers of a catalog response, see
+/// `parse_interval`.
+fn polling_interval(response: &Response) -> Option<Duration> {
+    parse_interval(
+        response.header("x-pullconf-interval"),
+        response.header("cache-control"),
+    )
+}
+
+/// Parse the polling interval that pullconfd sends as `X-Pullconf-Interval`
+/// and as the `max-age` directive of `Cache-Control`, preferring the former.
+/// The interval is clamped to `MIN_INTERVAL` and `MAX_INTERVAL`.
+fn parse_interval(interval: Option<&str>, cache_control: Option<&str>) -> Option<Duration> {
+    let max_age = || {
+        cache_control?
+            .split(',')
+            .find_map(|directive| directive.trim().strip_prefix("max-age="))
+    };
+
+    let seconds = interval
+        .and_then(|interval| interval.trim().parse::<u64>().ok())
+        .or_else(|| max_age()?.trim_matches('"').parse::<u64>().ok())?;
+
+    Some(Duration::from_secs(seconds).clamp(MIN_INTERVAL, MAX_INTERVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ureq::serde_json;
+
+    #[test]
+    fn parse_polling_intervals() {
+        assert_eq!(
+            parse_interval(Some("600"), Some("private, max-age=300")),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(
+            parse_interval(None, Some("private, max-age=300")),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(
+            parse_interval(Some("soon"), Some("max-age=\"120\"")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_interval(Some("1"), None), Some(MIN_INTERVAL));
+        assert_eq!(parse_interval(Some("31536000"), None), Some(MAX_INTERVAL));
+        assert_eq!(parse_interval(None, Some("no-cache")), None);
+        assert_eq!(parse_interval(None, None), None);
+    }
+
+    #[test]
+    fn select_resources_by_tags() {
+        let id = |name: &str| {
+            Uuid::from_u128(match name {
+                "base" => 1,
+                "admins" => 2,
+                "web" => 3,
+                _ => 4,
+            })
+        };
+
+        let group = |name: &str, tags: &[&str], requires: &[&str]| {
+            serde_json::json!({
+                "type": "group",
+                "id": id(name),
+                "parameters": { "ensure": "present", "name": name, "system": false },
+                "relationships": {
+                    "requires": requires
+                        .iter()
+                        .map(|name| serde_json::json!({
+                            "type": "group",
+                            "id": id(name)
+                        }))
+                        .collect::<Vec<_>>()
+                },
+                "tags": tags,
+            })
+        };
+
+        let resources: VecDeque<Resource> = serde_json::from_value(serde_json::json!([
+            group("base", &["security"], &[]),
+            group("admins", &["bootstrap"], &["base"]),
+            group("web", &["app"], &["admins"]),
+            group("cache", &["app", "slow"], &[]),
+        ]))
+        .unwrap();
+
+        let selection = Selection {
+            tags: vec!["bootstrap".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            selection.select(&resources),
+            Ok(HashSet::from([id("admins"), id("base")]))
+        );
+
+        let selection = Selection {
+            skip_tags: vec!["slow".to_string(), "security".to_string()],
+            ..Default::default()
+        };
+
+        // `base` has a skipped tag, but is required by `admins`.
+        assert_eq!(
+            selection.select(&resources),
+            Ok(HashSet::from([id("admins"), id("base"), id("web")]))
+        );
+
+        let selection = Selection {
+            only: vec!["group:web".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            selection.select(&resources),
+            Ok(HashSet::from([id("admins"), id("base"), id("web")]))
+        );
+
+        let selection = Selection {
+            only: vec!["group:missing".to_string()],
+            ..Default::default()
+        };
+
+        assert!(selection.select(&resources).is_err());
+    }
+
+    #[test]
+    fn verify_saved_catalog() {
+        let catalog = br#"{"data":[]}"#;
+        let digest = format!("{:x}", Sha256::digest(catalog));
+        let checksum = format!("{}  catalog\n", digest);
+
+        assert_eq!(verify(catalog, &checksum, None), Ok(()));
+        assert_eq!(
+            verify(catalog, &checksum, Some(&format!("W/\"{}\"", digest))),
+            Ok(())
+        );
+        assert_eq!(verify(catalog, &checksum, Some("\"v1\"")), Ok(()));
+
+        // Truncated catalog.
+        assert!(verify(&catalog[..5], &checksum, None).is_err());
+        // Missing checksum.
+        assert!(verify(catalog, "", None).is_err());
+        // Catalog that does not belong to the etag.
+        assert!(verify(catalog, &checksum, Some(&"0".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn detect_rollbacks() {
+        assert!(!is_rollback(3, Some(3)));
+        assert!(!is_rollback(3, Some(4)));
+        assert!(is_rollback(3, Some(2)));
+        // Catalog without serial after one with a serial.
+        assert!(is_rollback(3, None));
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let retry = Retry {
+            attempts: 10,
+            delay: Duration::from_secs(2),
+        };
+
+        for (attempt, max) in [(0, 2), (1, 4), (2, 8), (8, 60)] {
+            let delay = retry.backoff(attempt);
+            let max = Duration::from_secs(max);
+
+            assert!(delay >= max / 2 && delay <= max, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn no_proxy() {
+        assert!(is_excluded(
+            "pullconf.example.com",
+            "localhost, example.com"
+        ));
+        assert!(is_excluded("pullconf.example.com", ".example.com:443"));
+        assert!(is_excluded("pullconf.example.com", "*"));
+        assert!(!is_excluded("pullconf.example.com", "other.example.com"));
+        assert!(!is_excluded("pullconf.example.com", "ample.com"));
+    }
+
+    #[test]
+    fn fail_unsatisfied_dependencies() {
+        let group = |id: u128, name: &str, requires: &[u128]| {
+            serde_json::json!({
+                "type": "group",
+                "id": Uuid::from_u128(id),
+                "parameters": { "ensure": "present", "name": name, "system": false },
+                "relationships": {
+                    "requires": requires
+                        .iter()
+                        .map(|id| serde_json::json!({
+                            "type": "group",
+                            "id": Uuid::from_u128(*id)
+                        }))
+                        .collect::<Vec<_>>()
+                },
+            })
+        };
+
+        // `first` and `second` depend on each other, `third` depends on a
+        // resource that is not part of the catalog.
+        let mut schedule = Schedule {
+            pending: serde_json::from_value(serde_json::json!([
+                group(1, "first", &[2]),
+                group(2, "second", &[1]),
+                group(3, "third", &[4]),
+            ]))
+            .unwrap(),
+            applied: HashMap::new(),
+            results: vec![],
+            locks: HashSet::new(),
+            running: 0,
+            deadline: None,
+            deferred: 0,
+        };
+
+        assert!(schedule
+            .pending
+            .iter()
+            .all(|resource| !resource.is_ready(&schedule.applied)));
+        assert!(schedule
+            .unsatisfied(&schedule.pending[0])
+            .is_some_and(|reason| reason.contains("group `second`")));
+        assert!(schedule
+            .unsatisfied(&schedule.pending[2])
+            .is_some_and(|reason| reason.contains("missing from the resource catalog")));
+
+        schedule.fail_unsatisfied(std::process::id());
+
+        assert!(schedule.pending.is_empty());
+        assert_eq!(schedule.applied.len(), 3);
+        assert!(schedule.applied.values().all(Resource::is_failed));
+        assert!(schedule
+            .results
+            .iter()
+            .all(|result| result.result == Action::Failed));
+    }
+}
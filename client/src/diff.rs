@@ -0,0 +1,257 @@
+use crate::{configuration::name, resources::Resource};
+use common::error::Terminate;
+use log::error;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use ureq::serde_json::{self, Value};
+
+/// The value that replaces the content of sensitive files.
+const REDACTED: &str = "[redacted]";
+
+/// How the resource catalog from pullconfd differs from the saved one, i.e.
+/// what the next run changes compared to the last one. Resources are named
+/// by their type and primary parameter, e.g. `file:/etc/motd`, and listed in
+/// sorted order.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct CatalogDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<Change>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub resource: String,
+    pub parameters: Vec<Parameter>,
+}
+
+/// A parameter that differs, where tags and dependencies count as
+/// parameters as well. A missing value is `null`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Parameter {
+    pub name: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl CatalogDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare two resource catalogs.
+pub fn compare(
+    old: &VecDeque<Resource>,
+    new: &VecDeque<Resource>,
+) -> Result<CatalogDiff, serde_json::Error> {
+    let old = flatten(old)?;
+    let new = flatten(new)?;
+
+    let mut diff = CatalogDiff::default();
+
+    for (resource, parameters) in &new {
+        let Some(previous) = old.get(resource) else {
+            diff.added.push(resource.clone());
+            continue;
+        };
+
+        let mut names = previous.keys().chain(parameters.keys()).collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        let changed = names
+            .into_iter()
+            .filter_map(|name| {
+                let old = previous.get(name).cloned().unwrap_or_default();
+                let new = parameters.get(name).cloned().unwrap_or_default();
+
+                (old != new).then(|| Parameter {
+                    name: name.clone(),
+                    old,
+                    new,
+                })
+            })
+            .collect::<Vec<Parameter>>();
+
+        if !changed.is_empty() {
+            diff.changed.push(Change {
+                resource: resource.clone(),
+                parameters: changed,
+            });
+        }
+    }
+
+    diff.removed = old
+        .keys()
+        .filter(|resource| !new.contains_key(*resource))
+        .cloned()
+        .collect();
+
+    Ok(diff)
+}
+
+/// Map every resource to its parameters, tags and the names of the
+/// resources it depends on, as IDs are meaningless to a reader.
+fn flatten(
+    resources: &VecDeque<Resource>,
+) -> Result<BTreeMap<String, BTreeMap<String, Value>>, serde_json::Error> {
+    let mut flattened = BTreeMap::new();
+
+    for resource in resources {
+        let mut parameters = match resource.parameters()? {
+            Value::Object(parameters) => parameters.into_iter().collect::<BTreeMap<_, _>>(),
+            _ => BTreeMap::new(),
+        };
+
+        if parameters.get("sensitive") == Some(&Value::Bool(true)) {
+            if let Some(content) = parameters
+                .get_mut("content")
+                .filter(|value| !value.is_null())
+            {
+                *content = Value::from(REDACTED);
+            }
+        }
+
+        if !resource.tags().is_empty() {
+            parameters.insert("tags".to_string(), Value::from(resource.tags().to_vec()));
+        }
+
+        let mut requires = resource
+            .dependencies()
+            .iter()
+            .map(|dependency| {
+                resources
+                    .iter()
+                    .find(|resource| resource.id() == dependency.id)
+                    .map(name)
+                    .unwrap_or_else(|| dependency.id.to_string())
+            })
+            .collect::<Vec<String>>();
+
+        if !requires.is_empty() {
+            requires.sort();
+            parameters.insert("requires".to_string(), Value::from(requires));
+        }
+
+        flattened.insert(name(resource), parameters);
+    }
+
+    Ok(flattened)
+}
+
+/// Print the differences between the saved resource catalog and the one
+/// from pullconfd.
+pub fn print(pid: u32, diff: &CatalogDiff, json: bool) -> Result<(), Terminate> {
+    if json {
+        return match serde_json::to_string_pretty(diff) {
+            Ok(json) => {
+                println!("{}", json);
+                Ok(())
+            }
+            Err(error) => {
+                error!(scope = "diff", pid; "failed to serialize differences: {}", error);
+                Err(Terminate)
+            }
+        };
+    }
+
+    if diff.is_empty() {
+        println!("the resource catalog has not changed since the last run");
+        return Ok(());
+    }
+
+    for resource in &diff.added {
+        println!("+ {}", resource);
+    }
+
+    for resource in &diff.removed {
+        println!("- {}", resource);
+    }
+
+    for change in &diff.changed {
+        println!("~ {}", change.resource);
+
+        for parameter in &change.parameters {
+            println!(
+                "    {}: {} -> {}",
+                parameter.name,
+                summarize(&parameter.old),
+                summarize(&parameter.new)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Shorten values that do not fit on a line, e.g. file contents.
+fn summarize(value: &Value) -> String {
+    match value {
+        Value::String(value) if value.contains('\n') || value.len() > 60 => {
+            format!("({} bytes)", value.len())
+        }
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_catalogs() {
+        let group = |id: u128, name: &str, system: bool, requires: &[u128]| {
+            serde_json::json!({
+                "type": "group",
+                "id": uuid::Uuid::from_u128(id),
+                "parameters": { "ensure": "present", "name": name, "system": system },
+                "relationships": {
+                    "requires": requires
+                        .iter()
+                        .map(|id| serde_json::json!({ "type": "group", "id": uuid::Uuid::from_u128(*id) }))
+                        .collect::<Vec<_>>()
+                },
+            })
+        };
+
+        let old: VecDeque<Resource> = serde_json::from_value(serde_json::json!([
+            group(1, "admins", false, &[]),
+            group(2, "web", false, &[]),
+            group(3, "legacy", false, &[]),
+        ]))
+        .unwrap();
+
+        let new: VecDeque<Resource> = serde_json::from_value(serde_json::json!([
+            group(1, "admins", false, &[]),
+            group(2, "web", true, &[1]),
+            group(4, "cache", false, &[]),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            compare(&old, &new).unwrap(),
+            CatalogDiff {
+                added: vec!["group:cache".to_string()],
+                removed: vec!["group:legacy".to_string()],
+                changed: vec![Change {
+                    resource: "group:web".to_string(),
+                    parameters: vec![
+                        Parameter {
+                            name: "requires".to_string(),
+                            old: Value::Null,
+                            new: serde_json::json!(["group:admins"]),
+                        },
+                        Parameter {
+                            name: "system".to_string(),
+                            old: Value::Bool(false),
+                            new: Value::Bool(true),
+                        },
+                    ],
+                }],
+            }
+        );
+
+        assert!(compare(&new, &new).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use crate::state;
+use common::error::Terminate;
+use log::{error, info};
+use std::{fs, io::ErrorKind, sync::LazyLock};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+static DISABLE_FILE: LazyLock<String> = LazyLock::new(|| state::path("disabled"));
+
+/// Disable subsequent runs of pullconf, e.g. during a change freeze, until
+/// `enable` is called. The file contains the reason, which is repeated in
+/// the log of every run that is skipped.
+pub fn disable(pid: u32, reason: Option<&str>) -> Result<(), Terminate> {
+    let scope = "disable";
+
+    let reason = reason.unwrap_or_default().trim();
+
+    if let Err(error) = fs::write(&*DISABLE_FILE, reason) {
+        error!(scope, pid; "failed to write {}: {}", *DISABLE_FILE, error);
+        return Err(Terminate);
+    }
+
+    if reason.is_empty() {
+        info!(scope, pid; "disabled pullconf");
+    } else {
+        info!(scope, pid; "disabled pullconf: {}", reason);
+    }
+
+    Ok(())
+}
+
+/// Allow pullconf to run again. Enabling pullconf when it is not disabled
+/// is not an error.
+pub fn enable(pid: u32) -> Result<(), Terminate> {
+    let scope = "disable";
+
+    match fs::remove_file(&*DISABLE_FILE) {
+        Ok(()) => {
+            info!(scope, pid; "enabled pullconf");
+            Ok(())
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            info!(scope, pid; "pullconf is not disabled");
+            Ok(())
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to remove {}: {}", *DISABLE_FILE, error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Return a description of why and since when pullconf is disabled, or
+/// `None` if it is enabled.
+pub fn disabled() -> Option<String> {
+    let reason = fs::read_to_string(&*DISABLE_FILE).ok()?;
+
+    let since = fs::metadata(&*DISABLE_FILE)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|mtime| OffsetDateTime::from(mtime).format(&Rfc3339).ok())
+        .unwrap_or_else(|| "an unknown time".to_string());
+
+    match reason.trim() {
+        "" => Some(format!("since {}", since)),
+        reason => Some(format!("since {}: {}", since, reason)),
+    }
+}
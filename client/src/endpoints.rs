@@ -0,0 +1,610 @@
+use crate::state;
+use common::error::Terminate;
+use log::{debug, error, warn};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, LazyLock,
+    },
+    thread,
+    time::Duration,
+};
+use time::OffsetDateTime;
+use ureq::serde_json;
+use url::Url;
+
+/// Servers that recently failed to respond, along with the time of the
+/// failure as UNIX timestamp.
+static HEALTH_FILE: LazyLock<String> = LazyLock::new(|| state::path("endpoints.json"));
+
+/// A server that failed to respond is tried after every other server for
+/// this period.
+const COOLDOWN: Duration = Duration::from_secs(600);
+
+/// The port of DNS servers.
+const DNS_PORT: u16 = 53;
+
+/// The time to wait for the response of a DNS server.
+const DNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The type of SRV records.
+const TYPE_SRV: u16 = 33;
+
+/// The delay between connection attempts to the addresses of a server, see
+/// RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Resolve `PULLCONF_SERVER` into the base URLs of the servers that are
+/// tried in order. The value is a comma-separated list of hosts, each with
+/// an optional port, e.g. `pullconf1.example.com,pullconf2.example.com:8443`,
+/// see `parse`. Names that start with an underscore, e.g.
+/// `_pullconf._tcp.example.com`, are resolved via DNS SRV records instead.
+pub fn resolve(pid: u32, servers: &str) -> Result<Vec<Url>, Terminate> {
+    let scope = "configuration";
+
+    let mut endpoints: Vec<Url> = vec![];
+
+    for server in servers
+        .split(',')
+        .map(str::trim)
+        .filter(|server| !server.is_empty())
+    {
+        let addresses = if server.starts_with('_') {
+            match lookup_srv(server) {
+                Ok(targets) => {
+                    debug!(scope, pid; "resolved SRV record {} to {}", server, targets.join(", "));
+                    targets
+                }
+                Err(error) => {
+                    warn!(scope, pid; "failed to resolve SRV record {}: {}", server, error);
+                    continue;
+                }
+            }
+        } else {
+            vec![server.to_string()]
+        };
+
+        for address in addresses {
+            let url = match parse(&address) {
+                Ok(url) => url,
+                Err(error) => {
+                    error!(scope, pid; "failed to parse server {}: {}", address, error);
+                    return Err(Terminate);
+                }
+            };
+
+            if !endpoints.contains(&url) {
+                endpoints.push(url);
+            }
+        }
+    }
+
+    if endpoints.is_empty() {
+        error!(scope, pid; "no server to connect to, PULLCONF_SERVER resolved to an empty list");
+        return Err(Terminate);
+    }
+
+    Ok(endpoints)
+}
+
+/// Turn a server into a base URL. A server is a hostname, an IPv4 address or
+/// an IPv6 address, each with an optional port, e.g. `pullconf.example.com`,
+/// `192.0.2.1:8443`, `2001:db8::1` or `[2001:db8::1]:8443`. IPv6 addresses
+/// must be enclosed in brackets if a port is given. An `https://` prefix and
+/// a trailing slash are ignored.
+fn parse(server: &str) -> Result<Url, String> {
+    let address = server.strip_prefix("https://").unwrap_or(server);
+    let address = address.strip_suffix('/').unwrap_or(address);
+
+    if address.contains("://") {
+        return Err("only https is supported".to_string());
+    }
+
+    let url = match address.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => Url::parse(&format!("https://[{}]/", ip)),
+        Ok(IpAddr::V4(ip)) => Url::parse(&format!("https://{}/", ip)),
+        Err(_) if !address.starts_with('[') && address.matches(':').count() > 1 => {
+            return Err(
+                "an IPv6 address must be enclosed in brackets if a port is given, e.g. [2001:db8::1]:8443"
+                    .to_string(),
+            )
+        }
+        Err(_) => Url::parse(&format!("https://{}/", address)),
+    }
+    .map_err(|error| error.to_string())?;
+
+    if url.host().is_none()
+        || url.path() != "/"
+        || url.query().is_some()
+        || url.fragment().is_some()
+        || !url.username().is_empty()
+    {
+        return Err("expected a host with an optional port".to_string());
+    }
+
+    Ok(url)
+}
+
+/// Resolves the names of servers for the agent. If a name resolves to both
+/// IPv4 and IPv6 addresses, connections to them are attempted concurrently,
+/// staggered by a short delay like with Happy Eyeballs (RFC 8305), and the
+/// address that connects first is tried first by the agent. This way an
+/// address family that is not routed, e.g. IPv4 on an IPv6-only management
+/// network, only costs a short delay instead of the connect timeout.
+#[derive(Debug)]
+pub struct HappyEyeballs {
+    /// The connect timeout, where zero means no timeout.
+    pub timeout: Duration,
+}
+
+impl ureq::Resolver for HappyEyeballs {
+    fn resolve(&self, netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let mut addresses = interleave(netloc.to_socket_addrs()?.collect());
+
+        let families = (
+            addresses.iter().any(SocketAddr::is_ipv4),
+            addresses.iter().any(SocketAddr::is_ipv6),
+        );
+
+        if families == (true, true) {
+            if let Some(index) = race(&addresses, self.timeout)
+                .and_then(|winner| addresses.iter().position(|address| *address == winner))
+            {
+                let winner = addresses.remove(index);
+                addresses.insert(0, winner);
+            }
+        }
+
+        Ok(addresses)
+    }
+}
+
+/// Alternate between address families, starting with the family of the
+/// first address, while keeping the order within each family.
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first().map(SocketAddr::is_ipv6) else {
+        return addresses;
+    };
+
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv6() == first);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+
+    preferred.reverse();
+    other.reverse();
+
+    while !preferred.is_empty() || !other.is_empty() {
+        interleaved.extend(preferred.pop());
+        interleaved.extend(other.pop());
+    }
+
+    interleaved
+}
+
+/// Connect to the given addresses, starting one attempt after another with
+/// a short delay, and return the address that connects first. The
+/// connection itself is closed right away.
+fn race(addresses: &[SocketAddr], timeout: Duration) -> Option<SocketAddr> {
+    let (sender, receiver) = mpsc::channel();
+    let finished = Arc::new(AtomicBool::new(false));
+
+    for (index, address) in addresses.iter().copied().enumerate() {
+        let sender = sender.clone();
+        let finished = Arc::clone(&finished);
+
+        thread::spawn(move || {
+            thread::sleep(CONNECTION_ATTEMPT_DELAY * index as u32);
+
+            if finished.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let connected = if timeout.is_zero() {
+                TcpStream::connect(address)
+            } else {
+                TcpStream::connect_timeout(&address, timeout)
+            };
+
+            let _ = sender.send((address, connected.is_ok()));
+        });
+    }
+
+    drop(sender);
+
+    let winner = receiver
+        .iter()
+        .find(|(_, connected)| *connected)
+        .map(|(address, _)| address);
+
+    finished.store(true, Ordering::Relaxed);
+
+    winner
+}
+
+/// Recent failures of servers, which are persisted between runs, so that a
+/// server that is down for maintenance is not tried first on every run.
+#[derive(Debug, Default)]
+pub struct Health {
+    failures: HashMap<String, i64>,
+}
+
+impl Health {
+    /// Read recent failures from disk. A missing or unreadable file is
+    /// treated as if every server was healthy.
+    pub fn load() -> Self {
+        let failures = fs::read(&*HEALTH_FILE)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self { failures }
+    }
+
+    /// Order the servers so that those which failed within the cooldown
+    /// period are tried last. Otherwise the configured order is kept.
+    pub fn order(&self, endpoints: Vec<Url>, now: OffsetDateTime) -> Vec<Url> {
+        let (healthy, unhealthy): (Vec<Url>, Vec<Url>) =
+            endpoints.into_iter().partition(|endpoint| {
+                self.failures
+                    .get(endpoint.as_str())
+                    .filter(|timestamp| {
+                        now.unix_timestamp() - **timestamp < COOLDOWN.as_secs() as i64
+                    })
+                    .is_none()
+            });
+
+        healthy.into_iter().chain(unhealthy).collect()
+    }
+
+    /// Record whether a server responded.
+    pub fn record(&mut self, endpoint: &Url, healthy: bool, now: OffsetDateTime) {
+        if healthy {
+            self.failures.remove(endpoint.as_str());
+        } else {
+            self.failures
+                .insert(endpoint.to_string(), now.unix_timestamp());
+        }
+    }
+
+    /// Write recent failures to disk. Failures to do so are only logged, as
+    /// they merely affect the order in which servers are tried next time.
+    pub fn save(&self, pid: u32) {
+        let result = serde_json::to_vec(&self.failures)
+            .map_err(|error| error.to_string())
+            .and_then(|bytes| fs::write(&*HEALTH_FILE, bytes).map_err(|error| error.to_string()));
+
+        if let Err(error) = result {
+            warn!(scope = "configuration", pid; "failed to write {}: {}", *HEALTH_FILE, error);
+        }
+    }
+}
+
+/// Query the DNS servers from `/etc/resolv.conf` for the SRV records of a
+/// name and return their targets as `host:port`, ordered by priority and
+/// then by weight.
+fn lookup_srv(name: &str) -> Result<Vec<String>, String> {
+    let nameservers = fs::read_to_string("/etc/resolv.conf")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some("nameserver"), Some(address)) => address.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .collect::<Vec<IpAddr>>();
+
+    let nameservers = if nameservers.is_empty() {
+        vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]
+    } else {
+        nameservers
+    };
+
+    let id = rand::thread_rng().gen::<u16>();
+    let query = encode_query(id, name)?;
+
+    let mut last_error = String::new();
+
+    for nameserver in nameservers {
+        match exchange(SocketAddr::new(nameserver, DNS_PORT), &query) {
+            Ok(response) => {
+                let mut records = parse_srv_response(id, &response)?;
+
+                if records.is_empty() {
+                    return Err("no SRV records found".to_string());
+                }
+
+                records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+
+                return Ok(records
+                    .into_iter()
+                    .map(|record| format!("{}:{}", record.target, record.port))
+                    .collect());
+            }
+            Err(error) => last_error = format!("{}: {}", nameserver, error),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Send a query to a DNS server via UDP and wait for the response.
+fn exchange(nameserver: SocketAddr, query: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let local: SocketAddr = match nameserver {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(local)?;
+    socket.set_read_timeout(Some(DNS_TIMEOUT))?;
+    socket.connect(nameserver)?;
+    socket.send(query)?;
+
+    let mut buffer = vec![0; 4096];
+    let length = socket.recv(&mut buffer)?;
+    buffer.truncate(length);
+
+    Ok(buffer)
+}
+
+#[derive(Debug, PartialEq)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+/// Encode a recursive query for the SRV records of a name.
+fn encode_query(id: u16, name: &str) -> Result<Vec<u8>, String> {
+    let mut query = Vec::with_capacity(name.len() + 18);
+
+    query.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired, one question.
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("{} is not a valid domain name", name));
+        }
+
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+
+    query.push(0);
+    query.extend_from_slice(&TYPE_SRV.to_be_bytes());
+    // Class IN.
+    query.extend_from_slice(&[0x00, 0x01]);
+
+    Ok(query)
+}
+
+/// Parse the SRV records from the answer section of a DNS response.
+fn parse_srv_response(id: u16, response: &[u8]) -> Result<Vec<SrvRecord>, String> {
+    let malformed = || "malformed DNS response".to_string();
+
+    let u16_at = |offset: usize| -> Result<u16, String> {
+        response
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .ok_or_else(malformed)
+    };
+
+    if u16_at(0)? != id {
+        return Err("DNS response does not match the query".to_string());
+    }
+
+    let flags = u16_at(2)?;
+
+    if flags & 0x0200 != 0 {
+        return Err("DNS response is truncated".to_string());
+    }
+
+    match flags & 0x000f {
+        0 => (),
+        3 => return Ok(vec![]),
+        rcode => return Err(format!("DNS server responded with code {}", rcode)),
+    }
+
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+
+    let mut offset = 12;
+
+    for _ in 0..questions {
+        offset = parse_name(response, offset)?.1 + 4;
+    }
+
+    let mut records = vec![];
+
+    for _ in 0..answers {
+        offset = parse_name(response, offset)?.1;
+
+        let kind = u16_at(offset)?;
+        let length = u16_at(offset + 8)? as usize;
+        let data = offset + 10;
+
+        if data + length > response.len() {
+            return Err(malformed());
+        }
+
+        if kind == TYPE_SRV {
+            records.push(SrvRecord {
+                priority: u16_at(data)?,
+                weight: u16_at(data + 2)?,
+                port: u16_at(data + 4)?,
+                target: parse_name(response, data + 6)?.0,
+            });
+        }
+
+        offset = data + length;
+    }
+
+    Ok(records)
+}
+
+/// Parse a possibly compressed domain name at the given offset. Returns the
+/// name and the offset right after it.
+fn parse_name(message: &[u8], mut offset: usize) -> Result<(String, usize), String> {
+    let mut labels: Vec<String> = vec![];
+    let mut end = None;
+
+    // Every pointer must point backwards, so this bounds the number of
+    // labels and pointers that are followed.
+    for _ in 0..message.len() {
+        let length = *message
+            .get(offset)
+            .ok_or_else(|| "malformed DNS name".to_string())? as usize;
+
+        match length {
+            0 => {
+                return Ok((labels.join("."), end.unwrap_or(offset + 1)));
+            }
+            length if length & 0xc0 == 0xc0 => {
+                let low = *message
+                    .get(offset + 1)
+                    .ok_or_else(|| "malformed DNS name".to_string())?
+                    as usize;
+
+                end.get_or_insert(offset + 2);
+                offset = ((length & 0x3f) << 8) | low;
+            }
+            length => {
+                let label = message
+                    .get(offset + 1..offset + 1 + length)
+                    .ok_or_else(|| "malformed DNS name".to_string())?;
+
+                labels.push(String::from_utf8_lossy(label).to_string());
+                offset += 1 + length;
+            }
+        }
+    }
+
+    Err("malformed DNS name".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_srv_records() {
+        let mut response = encode_query(0x1234, "_pullconf._tcp.example.com").unwrap();
+
+        // Turn the query into a response with two answers.
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2;
+
+        // The first answer refers to the name of the question (offset 12),
+        // its target is spelled out.
+        response.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 60, 0, 27]);
+        response.extend_from_slice(&[0, 20, 0, 10, 0x20, 0xfb]);
+        response.extend_from_slice(b"\x07standby\x07example\x03com\x00");
+
+        // The second answer points to "example.com" within the question.
+        response.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 60, 0, 12]);
+        response.extend_from_slice(&[0, 10, 0, 5, 0x01, 0xbb]);
+        response.extend_from_slice(b"\x03one\xc0\x1b");
+
+        assert_eq!(
+            parse_srv_response(0x1234, &response).unwrap(),
+            vec![
+                SrvRecord {
+                    priority: 20,
+                    weight: 10,
+                    port: 8443,
+                    target: "standby.example.com".to_string(),
+                },
+                SrvRecord {
+                    priority: 10,
+                    weight: 5,
+                    port: 443,
+                    target: "one.example.com".to_string(),
+                }
+            ]
+        );
+
+        assert!(parse_srv_response(0x4321, &response).is_err());
+    }
+
+    #[test]
+    fn parse_servers() {
+        let parse = |server: &str| parse(server).map(|url| url.to_string());
+
+        assert_eq!(
+            parse("pullconf.example.com"),
+            Ok("https://pullconf.example.com/".to_string())
+        );
+        assert_eq!(
+            parse("https://pullconf.example.com:8443/"),
+            Ok("https://pullconf.example.com:8443/".to_string())
+        );
+        assert_eq!(
+            parse("192.0.2.1:8443"),
+            Ok("https://192.0.2.1:8443/".to_string())
+        );
+        assert_eq!(
+            parse("2001:db8::1"),
+            Ok("https://[2001:db8::1]/".to_string())
+        );
+        assert_eq!(
+            parse("[2001:db8::1]:8443"),
+            Ok("https://[2001:db8::1]:8443/".to_string())
+        );
+        assert!(parse("2001:db8::1:8443:x").is_err());
+        assert!(parse("http://pullconf.example.com").is_err());
+        assert!(parse("pullconf.example.com/api").is_err());
+        assert!(parse("pullconf.example.com:99999").is_err());
+    }
+
+    #[test]
+    fn interleave_address_families() {
+        let v4 = |last: u8| SocketAddr::from((Ipv4Addr::new(192, 0, 2, last), 443));
+        let v6 =
+            |last: u16| SocketAddr::from((Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last), 443));
+
+        assert_eq!(
+            interleave(vec![v6(1), v6(2), v6(3), v4(1)]),
+            vec![v6(1), v4(1), v6(2), v6(3)]
+        );
+        assert_eq!(
+            interleave(vec![v4(1), v4(2), v6(1), v6(2)]),
+            vec![v4(1), v6(1), v4(2), v6(2)]
+        );
+        assert!(interleave(vec![]).is_empty());
+    }
+
+    #[test]
+    fn order_endpoints_by_health() {
+        let primary = Url::parse("https://pullconf1.example.com").unwrap();
+        let standby = Url::parse("https://pullconf2.example.com").unwrap();
+        let endpoints = vec![primary.clone(), standby.clone()];
+
+        let now = OffsetDateTime::now_utc();
+        let mut health = Health::default();
+
+        health.record(&primary, false, now);
+
+        assert_eq!(
+            health.order(endpoints.clone(), now),
+            vec![standby.clone(), primary.clone()]
+        );
+        assert_eq!(
+            health.order(endpoints.clone(), now + COOLDOWN),
+            vec![primary.clone(), standby.clone()]
+        );
+
+        health.record(&primary, true, now);
+
+        assert_eq!(health.order(endpoints, now), vec![primary, standby]);
+    }
+}
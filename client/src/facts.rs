@@ -0,0 +1,556 @@
+use crate::{
+    platform::{self, Platform},
+    root,
+};
+use common::facts::{
+    BlockDevice, Facts, Interface, Kernel, Memory, OperatingSystem, Package, Processors,
+};
+use log::{debug, warn};
+use nix::ifaddrs::getifaddrs;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    io::Read,
+    net::IpAddr,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use ureq::serde_json::{self, Value};
+
+/// Directory with static fact files and executables that provide
+/// site-specific facts.
+const EXTERNAL_FACTS_DIR: &str = "/etc/pullconf/facts.d";
+
+/// Executables that do not exit within this period are killed.
+const EXTERNAL_FACTS_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DPKG_QUERY: &str = "/usr/bin/dpkg-query";
+const PKG: &str = "/usr/sbin/pkg";
+
+/// The database of the packages that apk installed on Alpine.
+pub const APK_DATABASE: &str = "/lib/apk/db/installed";
+
+/// Gather facts about this system. Facts that cannot be determined are left
+/// empty, so collecting facts never fails.
+pub fn collect(pid: u32) -> Facts {
+    debug!(scope = "facts", pid; "collecting facts");
+
+    Facts {
+        os: os_release(),
+        kernel: Kernel {
+            release: read_trimmed("/proc/sys/kernel/osrelease"),
+            architecture: Some(std::env::consts::ARCH.to_string()),
+        },
+        processors: processors(),
+        memory: memory(),
+        block_devices: block_devices(),
+        interfaces: interfaces(pid),
+        virtualization: virtualization(),
+        external: external(pid, Path::new(EXTERNAL_FACTS_DIR)),
+        packages: if packages_enabled() {
+            packages(pid)
+        } else {
+            vec![]
+        },
+    }
+}
+
+/// The list of installed packages is sizeable and thus only collected if
+/// enabled, e.g. to find the systems that run a vulnerable version of a
+/// package via pullconfd.
+fn packages_enabled() -> bool {
+    env::var("PULLCONF_FACTS_PACKAGES").is_ok_and(|value| value == "true" || value == "1")
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_string())
+        .filter(|contents| !contents.is_empty())
+}
+
+fn os_release() -> OperatingSystem {
+    let contents = fs::read_to_string("/etc/os-release")
+        .or_else(|_| fs::read_to_string("/usr/lib/os-release"))
+        .unwrap_or_default();
+
+    let mut fields = parse_os_release(&contents);
+
+    OperatingSystem {
+        id: fields.remove("ID"),
+        name: fields.remove("NAME"),
+        version: fields.remove("VERSION"),
+        version_id: fields.remove("VERSION_ID"),
+        version_codename: fields.remove("VERSION_CODENAME"),
+    }
+}
+
+/// Parse the `KEY=value` lines of os-release(5), where values may be quoted.
+pub fn parse_os_release(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .or_else(|| {
+                    value
+                        .strip_prefix('\'')
+                        .and_then(|value| value.strip_suffix('\''))
+                })
+                .unwrap_or(value);
+
+            (key.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+fn processors() -> Processors {
+    let contents = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+
+    let values = |name: &str| {
+        contents
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    Processors {
+        count: values("processor").len(),
+        model: values("model name").into_iter().next(),
+    }
+}
+
+fn memory() -> Memory {
+    let contents = fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    let fields = parse_meminfo(&contents);
+
+    Memory {
+        total: fields.get("MemTotal").copied().unwrap_or_default(),
+        swap: fields.get("SwapTotal").copied().unwrap_or_default(),
+    }
+}
+
+/// Parse the lines of `/proc/meminfo`, e.g. `MemTotal: 16303460 kB`, into
+/// sizes in bytes.
+fn parse_meminfo(contents: &str) -> HashMap<String, u64> {
+    contents
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(key, value)| {
+            let mut parts = value.split_whitespace();
+            let size = parts.next()?.parse::<u64>().ok()?;
+
+            let size = match parts.next() {
+                Some("kB") => size * 1024,
+                _ => size,
+            };
+
+            Some((key.to_string(), size))
+        })
+        .collect()
+}
+
+/// List block devices from sysfs, excluding loop devices and RAM disks.
+fn block_devices() -> Vec<BlockDevice> {
+    let entries = match fs::read_dir("/sys/block") {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut devices = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !name.starts_with("loop") && !name.starts_with("ram"))
+        .map(|name| {
+            let path = Path::new("/sys/block").join(&name);
+            let flag = |file: &str| read_trimmed(path.join(file)).is_some_and(|v| v == "1");
+
+            BlockDevice {
+                // The size is given in 512-byte sectors regardless of the
+                // actual sector size of the device.
+                size: read_trimmed(path.join("size"))
+                    .and_then(|sectors| sectors.parse::<u64>().ok())
+                    .unwrap_or_default()
+                    * 512,
+                rotational: flag("queue/rotational"),
+                removable: flag("removable"),
+                name,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+
+    devices
+}
+
+/// List network interfaces and their IP addresses, excluding the loopback
+/// interface.
+fn interfaces(pid: u32) -> Vec<Interface> {
+    let mut interfaces = BTreeMap::<String, Vec<IpAddr>>::new();
+
+    match getifaddrs() {
+        Ok(addresses) => {
+            for address in addresses {
+                let addresses = interfaces.entry(address.interface_name).or_default();
+
+                if let Some(storage) = address.address {
+                    if let Some(ipv4) = storage.as_sockaddr_in() {
+                        addresses.push(IpAddr::V4(ipv4.ip()));
+                    } else if let Some(ipv6) = storage.as_sockaddr_in6() {
+                        addresses.push(IpAddr::V6(ipv6.ip()));
+                    }
+                }
+            }
+        }
+        Err(error) => {
+            debug!(scope = "facts", pid; "failed to query network interfaces: {}", error);
+        }
+    }
+
+    interfaces
+        .into_iter()
+        .filter(|(name, _)| name != "lo" && name != "lo0")
+        .map(|(name, addresses)| Interface {
+            mac: read_trimmed(Path::new("/sys/class/net").join(&name).join("address")),
+            name,
+            addresses,
+        })
+        .collect()
+}
+
+fn virtualization() -> Option<String> {
+    // systemd-detect-virt prints `none` and exits with a non-zero code on
+    // bare metal, so only its output is evaluated.
+    let output = Command::new("systemd-detect-virt").output().ok()?;
+
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|stdout| stdout.trim().to_string())
+        .filter(|stdout| !stdout.is_empty())
+}
+
+/// List the installed packages via dpkg-query, pkg or from the database of
+/// apk, sorted by name.
+fn packages(pid: u32) -> Vec<Package> {
+    match platform::get() {
+        Platform::Debian => query_packages(
+            pid,
+            Command::new(DPKG_QUERY).args([
+                "-W",
+                "-f",
+                "${db:Status-Abbrev}\t${Package}\t${Version}\t${Architecture}\n",
+            ]),
+        )
+        .map(|output| parse_packages(&output))
+        .unwrap_or_default(),
+        Platform::Alpine => {
+            match root::path(Path::new(APK_DATABASE)).and_then(fs::read_to_string) {
+                Ok(contents) => parse_apk_database(&contents),
+                Err(error) => {
+                    warn!(scope = "facts", pid; "failed to list installed packages from {}: {}", APK_DATABASE, error);
+                    vec![]
+                }
+            }
+        }
+        Platform::FreeBsd => query_packages(pid, Command::new(PKG).args(["query", "%n\t%v\t%q"]))
+            .map(|output| parse_pkg_query(&output))
+            .unwrap_or_default(),
+    }
+}
+
+/// Execute the command that lists the installed packages and return its
+/// output.
+fn query_packages(pid: u32, command: &mut Command) -> Option<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    match command.stdin(Stdio::null()).stderr(Stdio::null()).output() {
+        Ok(output) if output.status.success() => {
+            Some(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        Ok(output) => {
+            warn!(scope = "facts", pid; "failed to list installed packages, {} exited with {}", program, output.status);
+            None
+        }
+        Err(error) => {
+            debug!(scope = "facts", pid; "failed to list installed packages: {}", error);
+            None
+        }
+    }
+}
+
+/// Parse the output of dpkg-query, keeping only packages that are
+/// installed, i.e. whose current status (the second character of the
+/// abbreviated status, e.g. `ii `) is `i`.
+fn parse_packages(output: &str) -> Vec<Package> {
+    let mut packages = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            let status = fields.next()?;
+            let name = fields.next()?;
+            let version = fields.next()?;
+            let architecture = fields
+                .next()
+                .filter(|architecture| !architecture.is_empty());
+
+            (status.chars().nth(1) == Some('i')).then(|| Package {
+                name: name.to_string(),
+                version: version.to_string(),
+                architecture: architecture.map(str::to_string),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    packages.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then(a.architecture.cmp(&b.architecture))
+    });
+
+    packages
+}
+
+/// Parse the output of `pkg query '%n\t%v\t%q'`, i.e. the name, version
+/// and ABI, e.g. `FreeBSD:14:amd64`, of every installed package.
+fn parse_pkg_query(output: &str) -> Vec<Package> {
+    let mut packages = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+
+            Some(Package {
+                name: fields.next()?.to_string(),
+                version: fields.next()?.to_string(),
+                architecture: fields
+                    .next()
+                    .filter(|architecture| !architecture.is_empty())
+                    .map(str::to_string),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    packages
+}
+
+/// Parse the database of apk, whose records are separated by empty lines and
+/// whose fields are prefixed with their key, e.g. `P:` for the name and `V:`
+/// for the version of a package.
+pub fn parse_apk_database(contents: &str) -> Vec<Package> {
+    let mut packages = contents
+        .split("\n\n")
+        .filter_map(|record| {
+            let field = |key: &str| {
+                record
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .map(str::to_string)
+            };
+
+            Some(Package {
+                name: field("P:")?,
+                version: field("V:")?,
+                architecture: field("A:"),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    packages
+}
+
+/// Merge the facts from every file in a directory in the order of their
+/// names, so that later files override top-level keys of earlier ones.
+/// Executable files are run and must print a JSON object, other files must
+/// contain a JSON object (`.json`) or a TOML table (`.toml`). Files that
+/// fail to provide facts are skipped.
+fn external(pid: u32, directory: &Path) -> BTreeMap<String, Value> {
+    let scope = "facts";
+
+    let mut paths = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>(),
+        Err(_) => return BTreeMap::new(),
+    };
+
+    paths.sort();
+
+    let mut facts = BTreeMap::new();
+
+    for path in paths {
+        let executable =
+            fs::metadata(&path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0);
+
+        let result = if executable {
+            run_executable(&path)
+        } else {
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => fs::read_to_string(&path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|contents| {
+                        serde_json::from_str::<Value>(&contents).map_err(|error| error.to_string())
+                    }),
+                Some("toml") => fs::read_to_string(&path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|contents| {
+                        toml::from_str::<toml::Table>(&contents).map_err(|error| error.to_string())
+                    })
+                    .and_then(|table| {
+                        serde_json::to_value(table).map_err(|error| error.to_string())
+                    }),
+                _ => {
+                    debug!(scope, pid, path:% = path.display(); "ignoring file without .json or .toml extension");
+                    continue;
+                }
+            }
+        };
+
+        match result {
+            Ok(Value::Object(map)) => facts.extend(map),
+            Ok(_) => {
+                warn!(scope, pid, path:% = path.display(); "ignoring external facts that are not an object");
+            }
+            Err(error) => {
+                warn!(scope, pid, path:% = path.display(); "failed to read external facts: {}", error);
+            }
+        }
+    }
+
+    facts
+}
+
+/// Run an executable and parse its output as JSON.
+fn run_executable(path: &Path) -> Result<Value, String> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|error| error.to_string())?;
+
+    // Read the output on a separate thread, so that an executable that
+    // writes more than the pipe buffer can hold does not block.
+    let mut stdout = child.stdout.take().ok_or("failed to capture stdout")?;
+
+    let reader = thread::spawn(move || {
+        let mut output = vec![];
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    let start = Instant::now();
+
+    let status = loop {
+        match child.try_wait().map_err(|error| error.to_string())? {
+            Some(status) => break status,
+            None if start.elapsed() > EXTERNAL_FACTS_TIMEOUT => {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(format!(
+                    "executable did not exit within {} seconds",
+                    EXTERNAL_FACTS_TIMEOUT.as_secs()
+                ));
+            }
+            None => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+
+    if !status.success() {
+        return Err(format!("executable exited with {}", status));
+    }
+
+    let output = reader
+        .join()
+        .map_err(|_| "failed to read stdout".to_string())?
+        .map_err(|error| error.to_string())?;
+
+    serde_json::from_slice(&output).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_system_files() {
+        let os_release = parse_os_release(
+            "PRETTY_NAME=\"Debian GNU/Linux 12 (bookworm)\"\nNAME='Debian GNU/Linux'\nVERSION_ID=\"12\"\nID=debian\n",
+        );
+
+        assert_eq!(os_release["NAME"], "Debian GNU/Linux");
+        assert_eq!(os_release["VERSION_ID"], "12");
+        assert_eq!(os_release["ID"], "debian");
+
+        let meminfo = parse_meminfo("MemTotal:       16303460 kB\nHugePages_Total:       0\n");
+
+        assert_eq!(meminfo["MemTotal"], 16303460 * 1024);
+        assert_eq!(meminfo["HugePages_Total"], 0);
+
+        let packages = parse_packages(
+            "ii \topenssl\t3.0.11-1~deb12u2\tamd64\nrc \told-tool\t1.0-1\tamd64\nii \tlibc6\t2.36-9\tamd64\n",
+        );
+
+        assert_eq!(
+            packages
+                .iter()
+                .map(|package| (package.name.as_str(), package.version.as_str()))
+                .collect::<Vec<_>>(),
+            [("libc6", "2.36-9"), ("openssl", "3.0.11-1~deb12u2")]
+        );
+
+        let packages = parse_apk_database(
+            "C:Q1abc=\nP:musl\nV:1.2.4_git20230717-r4\nA:x86_64\n\nP:busybox\nV:1.36.1-r15\nA:x86_64\n",
+        );
+
+        assert_eq!(packages[0].name, "busybox");
+        assert_eq!(packages[1].version, "1.2.4_git20230717-r4");
+
+        let packages =
+            parse_pkg_query("zfs-stats\t1.3.2\tFreeBSD:*:*\nsudo\t1.9.15p5_4\tFreeBSD:14:amd64\n");
+
+        assert_eq!(packages[0].name, "sudo");
+        assert_eq!(
+            packages[0].architecture.as_deref(),
+            Some("FreeBSD:14:amd64")
+        );
+    }
+
+    #[test]
+    fn merge_external_facts() {
+        let directory = std::env::temp_dir().join(format!("pullconf-facts-{}", std::process::id()));
+
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(
+            directory.join("10-site.toml"),
+            "rack = \"a1\"\nrole = \"web\"\n",
+        )
+        .unwrap();
+        fs::write(directory.join("20-role.json"), r#"{"role": "db"}"#).unwrap();
+        fs::write(directory.join("30-broken.json"), "[1, 2]").unwrap();
+        fs::write(directory.join("README"), "ignored").unwrap();
+
+        let facts = external(0, &directory);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(facts.len(), 2);
+        assert_eq!(facts["rack"], "a1");
+        assert_eq!(facts["role"], "db");
+    }
+}
@@ -0,0 +1,289 @@
+use crate::state;
+use common::{
+    error::Terminate,
+    report::{Action, ResourceResult, Run, Summary},
+};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{fs, io::ErrorKind, path::Path, sync::LazyLock};
+use ureq::serde_json;
+
+static LAST_RUN_FILE: LazyLock<String> = LazyLock::new(|| state::path("last_run.json"));
+static HISTORY_FILE: LazyLock<String> = LazyLock::new(|| state::path("history.json"));
+static DRIFT_FILE: LazyLock<String> = LazyLock::new(|| state::path("drift.json"));
+
+/// The number of runs that are kept in the history.
+const HISTORY_LIMIT: usize = 100;
+
+/// The report of an audit run, which lists the resources that are not in
+/// their desired state.
+#[derive(Debug, Serialize)]
+struct Drift<'a> {
+    timestamp: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<&'a str>,
+    total: usize,
+    drifted: usize,
+    /// Resources that would be created, changed or deleted, as well as
+    /// resources whose state could not be determined.
+    resources: Vec<&'a ResourceResult>,
+}
+
+impl<'a> From<&'a Run> for Drift<'a> {
+    fn from(run: &'a Run) -> Self {
+        let resources = run
+            .resources
+            .iter()
+            .filter(|resource| resource.result.is_change() || resource.result == Action::Failed)
+            .collect::<Vec<_>>();
+
+        Self {
+            timestamp: &run.timestamp,
+            etag: run.etag.as_deref(),
+            total: run.resources.len(),
+            drifted: resources.len(),
+            resources,
+        }
+    }
+}
+
+/// Save the report of a run as the last run and append its summary to the
+/// history, dropping the oldest entries beyond the limit. Failures are
+/// logged, but do not affect the outcome of the run.
+pub fn record(pid: u32, run: &Run) {
+    let scope = "history";
+
+    if let Err(error) = write(&LAST_RUN_FILE, run) {
+        warn!(scope, pid; "failed to save report of this run to {}: {}", *LAST_RUN_FILE, error);
+    }
+
+    let mut history = match read::<Vec<Summary>>(&HISTORY_FILE) {
+        Ok(history) => history.unwrap_or_default(),
+        Err(error) => {
+            warn!(scope, pid; "discarding unreadable history {}: {}", *HISTORY_FILE, error);
+            vec![]
+        }
+    };
+
+    history.push(Summary::from(run));
+
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+    }
+
+    match write(&HISTORY_FILE, &history) {
+        Ok(()) => debug!(scope, pid; "recorded run in {}", *HISTORY_FILE),
+        Err(error) => warn!(scope, pid; "failed to save history to {}: {}", *HISTORY_FILE, error),
+    }
+}
+
+/// Save the drift report of an audit run. A failure is logged, but does not
+/// affect the outcome of the run.
+pub fn record_drift(pid: u32, run: &Run) {
+    let scope = "history";
+    let drift = Drift::from(run);
+
+    match write(&DRIFT_FILE, &drift) {
+        Ok(()) => info!(
+            scope,
+            pid;
+            "{} of {} resources are not in their desired state, saved drift report to {}",
+            drift.drifted,
+            drift.total,
+            *DRIFT_FILE
+        ),
+        Err(error) => {
+            warn!(scope, pid; "failed to save drift report to {}: {}", *DRIFT_FILE, error)
+        }
+    }
+}
+
+/// Print the report of the last run, either as JSON or in a human-readable
+/// form that lists the result of every resource.
+pub fn print_last(pid: u32, json: bool) -> Result<(), Terminate> {
+    let run = match read::<Run>(&LAST_RUN_FILE) {
+        Ok(Some(run)) => run,
+        Ok(None) => {
+            println!("pullconf has not completed a run yet");
+            return Ok(());
+        }
+        Err(error) => {
+            error!(scope = "history", pid; "failed to read {}: {}", *LAST_RUN_FILE, error);
+            return Err(Terminate);
+        }
+    };
+
+    if json {
+        return print_json(pid, &run);
+    }
+
+    let summary = Summary::from(&run);
+
+    println!("timestamp: {}", run.timestamp);
+    println!(
+        "outcome:   {}{}",
+        run.outcome,
+        if run.noop { " (noop)" } else { "" }
+    );
+    println!("etag:      {}", run.etag.as_deref().unwrap_or("-"));
+    println!("duration:  {:.3} seconds", run.duration);
+    println!(
+        "resources: {} total, {} changed, {} failed{}",
+        summary.total,
+        summary.changed,
+        summary.failed,
+        if summary.deferred > 0 {
+            format!(", {} deferred", summary.deferred)
+        } else {
+            String::new()
+        }
+    );
+
+    if let Some(slowest) = &summary.slowest {
+        println!(
+            "slowest:   {} ({:.3} seconds)",
+            slowest.resource, slowest.duration
+        );
+    }
+
+    if !run.resources.is_empty() {
+        println!();
+    }
+
+    for resource in &run.resources {
+        println!(
+            "{:<10} {:>9.3}s  {}",
+            resource.result, resource.duration, resource.resource
+        );
+
+        if let Some(failure) = &resource.failure {
+            println!("{:>23}{}", "", failure);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the summaries of previous runs, the most recent run last.
+pub fn print_history(pid: u32, json: bool) -> Result<(), Terminate> {
+    let history = match read::<Vec<Summary>>(&HISTORY_FILE) {
+        Ok(history) => history.unwrap_or_default(),
+        Err(error) => {
+            error!(scope = "history", pid; "failed to read {}: {}", *HISTORY_FILE, error);
+            return Err(Terminate);
+        }
+    };
+
+    if json {
+        return print_json(pid, &history);
+    }
+
+    println!(
+        "{:<25} {:<10} {:>10} {:>7} {:>7} {:>7}  ETAG",
+        "TIMESTAMP", "OUTCOME", "DURATION", "TOTAL", "CHANGED", "FAILED"
+    );
+
+    for run in &history {
+        let outcome = if run.noop {
+            format!("{}*", run.outcome)
+        } else {
+            run.outcome.clone()
+        };
+
+        println!(
+            "{:<25} {:<10} {:>9.3}s {:>7} {:>7} {:>7}  {}",
+            run.timestamp,
+            outcome,
+            run.duration,
+            run.total,
+            run.changed,
+            run.failed,
+            run.etag.as_deref().unwrap_or("-")
+        );
+    }
+
+    if history.iter().any(|run| run.noop) {
+        println!("\n* run in noop mode");
+    }
+
+    Ok(())
+}
+
+fn print_json<T: Serialize>(pid: u32, value: &T) -> Result<(), Terminate> {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{}", json);
+            Ok(())
+        }
+        Err(error) => {
+            error!(scope = "history", pid; "failed to serialize history: {}", error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Read and deserialize a file, which results in `None` if it does not exist.
+fn read<T: for<'de> Deserialize<'de>>(path: &str) -> Result<Option<T>, anyhow::Error> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Write a file atomically, so that readers never see a partial file.
+fn write<T: Serialize>(path: &str, value: &T) -> Result<(), anyhow::Error> {
+    let mut temporary = Path::new(path).to_path_buf();
+    temporary.set_extension("tmp");
+
+    fs::write(&temporary, serde_json::to_vec(value)?)?;
+    fs::rename(&temporary, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn drift_report() {
+        let resource = |id: u128, result: Action| ResourceResult {
+            id: Uuid::from_u128(id),
+            resource: format!("file `/etc/{}`", id),
+            result,
+            duration: 0.0,
+            failure: None,
+        };
+
+        let run = Run {
+            timestamp: "2024-09-01T12:00:00Z".to_string(),
+            noop: true,
+            etag: None,
+            outcome: "changed".to_string(),
+            duration: 1.0,
+            deferred: 0,
+            resources: vec![
+                resource(1, Action::Unchanged),
+                resource(2, Action::Changed),
+                resource(3, Action::Skipped),
+                resource(4, Action::Created),
+                resource(5, Action::Failed),
+            ],
+        };
+
+        let drift = Drift::from(&run);
+
+        assert_eq!(drift.total, 5);
+        assert_eq!(drift.drifted, 3);
+        assert_eq!(
+            drift
+                .resources
+                .iter()
+                .map(|resource| resource.id)
+                .collect::<Vec<_>>(),
+            [2, 4, 5].map(Uuid::from_u128)
+        );
+    }
+}
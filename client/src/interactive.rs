@@ -0,0 +1,247 @@
+use common::report::{Action, ResourceResult};
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use std::{
+    io::{IsTerminal, Write},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+
+/// Whether the interactive logger is installed, in which case a summary is
+/// printed at the end of a run.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A logger for manual runs on a terminal. Instead of structured logs it
+/// prints one line per resource with its result and otherwise only the
+/// messages of log entries.
+pub struct Interactive {
+    level: LevelFilter,
+    color: bool,
+}
+
+impl Interactive {
+    /// Install the interactive logger as the global logger. The level is
+    /// read from `LOG_LEVEL` like the default logger does, colors are
+    /// disabled if `NO_COLOR` is set.
+    pub fn init() -> Result<(), String> {
+        let level = std::env::var("LOG_LEVEL")
+            .ok()
+            .and_then(|level| LevelFilter::from_str(&level).ok())
+            .unwrap_or(LevelFilter::Info);
+
+        let color = std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal();
+
+        log::set_boxed_logger(Box::new(Self { level, color }))
+            .map_err(|error| error.to_string())?;
+        log::set_max_level(level);
+
+        ENABLED.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Format a log record as a single line, e.g. "changed   file
+    /// `/etc/motd`" for a resource.
+    fn format(&self, record: &Record) -> String {
+        let mut fields = Fields::default();
+        let _ = record.key_values().visit(&mut fields);
+
+        match fields.result {
+            Some(result) => {
+                let color = match result.as_str() {
+                    "created" | "changed" | "deleted" => GREEN,
+                    "skipped" => YELLOW,
+                    "failed" => RED,
+                    _ => DIM,
+                };
+
+                let label = self.paint(color, &format!("{:<9}", result));
+
+                match (fields.resource, fields.name) {
+                    // Failures and skipped resources also need the reason.
+                    (Some(resource), Some(name)) if record.level() <= Level::Warn => format!(
+                        "{} {} `{}`\n{:>9} {}",
+                        label,
+                        resource,
+                        name,
+                        "",
+                        self.paint(DIM, &record.args().to_string())
+                    ),
+                    (Some(resource), Some(name)) => format!("{} {} `{}`", label, resource, name),
+                    _ => format!("{} {}", label, record.args()),
+                }
+            }
+            None => match record.level() {
+                Level::Error => format!("{} {}", self.paint(RED, "error:"), record.args()),
+                Level::Warn => format!("{} {}", self.paint(YELLOW, "warning:"), record.args()),
+                Level::Info => record.args().to_string(),
+                Level::Debug | Level::Trace => self.paint(DIM, &record.args().to_string()),
+            },
+        }
+    }
+
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+impl Log for Interactive {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let _ = writeln!(std::io::stderr(), "{}", self.format(record));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// The key-value pairs of a log record that describe a resource.
+#[derive(Default)]
+struct Fields {
+    result: Option<String>,
+    resource: Option<String>,
+    name: Option<String>,
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        match key.as_str() {
+            "result" => self.result = Some(value.to_string()),
+            "resource" => self.resource = Some(value.to_string()),
+            "name" => self.name = Some(value.to_string()),
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether the interactive logger is installed.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Print a table with the number of resources per result, the total
+/// duration of the run and the resource that took the longest.
+pub fn print_summary(results: &[ResourceResult], duration: f64) {
+    let color = std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal();
+    let mut stderr = std::io::stderr().lock();
+
+    let bold = |text: &str| {
+        if color {
+            format!("{}{}{}", BOLD, text, RESET)
+        } else {
+            text.to_string()
+        }
+    };
+
+    let _ = writeln!(stderr);
+    let _ = writeln!(
+        stderr,
+        "{}",
+        bold(&format!("{:<10} {:>6}", "RESULT", "COUNT"))
+    );
+
+    for result in Action::ALL {
+        let count = results
+            .iter()
+            .filter(|resource| resource.result == result)
+            .count();
+
+        if count > 0 {
+            let _ = writeln!(stderr, "{:<10} {:>6}", result, count);
+        }
+    }
+
+    let _ = writeln!(
+        stderr,
+        "{}",
+        bold(&format!("{:<10} {:>6}", "total", results.len()))
+    );
+    let _ = writeln!(stderr, "\nfinished in {:.3} seconds", duration);
+
+    if let Some(slowest) = results
+        .iter()
+        .max_by(|a, b| a.duration.total_cmp(&b.duration))
+        .filter(|resource| resource.duration > 0.0)
+    {
+        let _ = writeln!(
+            stderr,
+            "slowest resource: {} ({:.3} seconds)",
+            slowest.resource, slowest.duration
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_resource_lines() {
+        let logger = Interactive {
+            level: LevelFilter::Info,
+            color: false,
+        };
+
+        let format = |level: Level, kvs: &[(&str, &str)], message: &str| {
+            logger.format(
+                &Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(level)
+                    .key_values(&kvs)
+                    .build(),
+            )
+        };
+
+        assert_eq!(
+            format(
+                Level::Info,
+                &[
+                    ("resource", "file"),
+                    ("name", "/etc/motd"),
+                    ("result", "changed")
+                ],
+                "successfully applied file `/etc/motd`"
+            ),
+            "changed   file `/etc/motd`"
+        );
+        assert_eq!(
+            format(
+                Level::Error,
+                &[
+                    ("resource", "group"),
+                    ("name", "admins"),
+                    ("result", "failed")
+                ],
+                "failed to apply group `admins`"
+            ),
+            "failed    group `admins`\n          failed to apply group `admins`"
+        );
+        assert_eq!(
+            format(Level::Warn, &[("scope", "main")], "using cached catalog"),
+            "warning: using cached catalog"
+        );
+        assert_eq!(
+            format(Level::Info, &[], "applied resource catalog"),
+            "applied resource catalog"
+        );
+    }
+}
@@ -0,0 +1,998 @@
+mod assets;
+mod bootstrap;
+mod breaker;
+mod configuration;
+mod diff;
+mod disable;
+mod endpoints;
+mod facts;
+mod history;
+mod interactive;
+mod local;
+mod lock;
+mod metrics;
+mod overrides;
+mod platform;
+mod priority;
+mod privileges;
+mod resources;
+mod root;
+mod schema;
+mod show;
+mod state;
+mod systemd;
+mod tmp;
+mod update;
+mod util;
+mod watch;
+
+use assets::AssetCache;
+use breaker::{Breaker, Failures};
+use clap::{builder::BoolishValueParser, Args, Parser, Subcommand};
+use common::{error::Terminate, Priority};
+use configuration::{Outcome, Overrides, Scheduling, Selection};
+use rand::Rng;
+use signal_hook::{consts::signal::*, iterator::Signals};
+use std::{
+    collections::HashSet,
+    io::IsTerminal,
+    path::PathBuf,
+    process::ExitCode,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use watch::{RateLimit, Watcher};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const APPLICATION: &str = env!("CARGO_PKG_NAME");
+
+/// The interval between two runs in daemon mode, unless configured otherwise.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The number of consecutive failed runs after which pullconf backs off,
+/// unless configured otherwise.
+const DEFAULT_FAILURE_THRESHOLD: u64 = 5;
+
+/// The delay between runs once pullconf backs off, unless configured
+/// otherwise.
+const DEFAULT_FAILURE_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// The total size of the asset cache in MiB, unless configured otherwise.
+const DEFAULT_ASSET_CACHE_SIZE: u64 = 512;
+
+/// The time after which unused assets are evicted from the cache, unless
+/// configured otherwise.
+const DEFAULT_ASSET_CACHE_AGE: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// Settings that apply to every run, whether pullconf runs once or as a
+/// daemon.
+#[derive(Clone)]
+struct Options {
+    noop: bool,
+    /// Whether a drift report is written after each run, see `--audit`.
+    audit: bool,
+    offline: bool,
+    /// A file to read the resource catalog from instead of pullconfd, see
+    /// `pullconf apply --local`.
+    local: Option<PathBuf>,
+    scheduling: Scheduling,
+    breaker: Breaker,
+    assets: AssetCache,
+    /// Whether the daemon remediates out-of-band changes to files,
+    /// directories and symlinks immediately, see `watch::Watcher`.
+    watch: bool,
+    /// A directory that Prometheus metrics are written to after each run,
+    /// see `metrics::record`.
+    metrics: Option<PathBuf>,
+    overrides: Overrides,
+    selection: Selection,
+}
+
+/// Pull-based Linux configuration management system, client component.
+/// Without a subcommand pullconf fetches the resource catalog of this
+/// system from pullconfd and applies it once, like `pullconf apply`.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    run: RunArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Options of runs, which may be passed before or after the subcommand.
+/// Boolean environment variables accept e.g. `true`, `1`, `false` or `0`,
+/// any other value is refused instead of being ignored.
+#[derive(Args)]
+struct RunArgs {
+    /// Evaluate the current state of every resource without modifying
+    /// anything.
+    #[arg(long, global = true, env = "PULLCONF_NOOP", value_parser = BoolishValueParser::new())]
+    noop: bool,
+    /// Like --noop, but additionally save a drift report.
+    #[arg(long, global = true)]
+    audit: bool,
+    /// Apply the saved resource catalog without querying pullconfd.
+    #[arg(long, global = true, visible_alias = "cached")]
+    offline: bool,
+    /// Read the resource catalog from a TOML or JSON file instead of
+    /// pullconfd.
+    #[arg(long, global = true)]
+    local: Option<PathBuf>,
+    /// Apply resources to another system whose root directory is mounted
+    /// here.
+    #[arg(long, global = true)]
+    root: Option<String>,
+    /// Only apply resources whose privileges have been granted, which
+    /// permits runs without root privileges.
+    #[arg(long, global = true, env = "PULLCONF_UNPRIVILEGED", value_parser = BoolishValueParser::new())]
+    unprivileged: bool,
+    /// Wait for a random delay of up to this many seconds before
+    /// contacting pullconfd.
+    #[arg(long, global = true, env = "PULLCONF_SPLAY")]
+    splay: Option<u64>,
+    /// Replaces $PULLCONF_SERVER.
+    #[arg(long, global = true)]
+    server: Option<String>,
+    /// A file whose content replaces $PULLCONF_API_KEY.
+    #[arg(long, global = true)]
+    api_key_file: Option<PathBuf>,
+    /// Replaces the output of `hostname --fqdn`.
+    #[arg(long, global = true)]
+    hostname: Option<String>,
+    /// Apply a resource catalog that is older than the one fetched before.
+    #[arg(long, global = true)]
+    allow_rollback: bool,
+    /// Only apply these comma-separated resources, e.g.
+    /// `file:/etc/motd`, and what they depend on.
+    #[arg(long, global = true)]
+    only: Option<String>,
+    /// Only apply resources with one of these comma-separated tags and
+    /// what they depend on.
+    #[arg(long, global = true)]
+    tags: Option<String>,
+    /// Skip resources with one of these comma-separated tags along with
+    /// their dependents.
+    #[arg(long, global = true)]
+    skip_tags: Option<String>,
+    /// Skip resources of these comma-separated types along with their
+    /// dependents.
+    #[arg(long, global = true)]
+    skip_type: Option<String>,
+    /// The maximum number of resources that are applied concurrently.
+    #[arg(long, global = true, env = "PULLCONF_PARALLELISM")]
+    parallelism: Option<u64>,
+    /// The CPU and IO priority of resources that do not set their own.
+    #[arg(long, global = true, env = "PULLCONF_PRIORITY")]
+    priority: Option<Priority>,
+    /// Seconds after which no further resources are started.
+    #[arg(long, global = true, env = "PULLCONF_RUN_BUDGET")]
+    run_budget: Option<u64>,
+    /// The number of consecutive failed runs after which pullconf backs
+    /// off.
+    #[arg(long, global = true, env = "PULLCONF_FAILURE_THRESHOLD")]
+    failure_threshold: Option<u64>,
+    /// Seconds between runs once pullconf backs off.
+    #[arg(long, global = true, env = "PULLCONF_FAILURE_BACKOFF")]
+    failure_backoff: Option<u64>,
+    /// The total size of the asset cache in MiB.
+    #[arg(long, global = true, env = "PULLCONF_ASSET_CACHE_SIZE")]
+    asset_cache_size: Option<u64>,
+    /// Seconds after which unused assets are evicted from the cache.
+    #[arg(long, global = true, env = "PULLCONF_ASSET_CACHE_AGE")]
+    asset_cache_age: Option<u64>,
+    /// Remediate out-of-band changes to files, directories and symlinks
+    /// immediately in daemon mode.
+    #[arg(long, global = true, env = "PULLCONF_WATCH", value_parser = BoolishValueParser::new())]
+    watch: bool,
+    /// A directory that Prometheus metrics are written to after each run.
+    #[arg(long, global = true, env = "PULLCONF_METRICS_DIR")]
+    metrics_dir: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch the resource catalog and apply it once.
+    Apply,
+    /// Fetch and apply the resource catalog repeatedly until SIGTERM or
+    /// SIGINT is received.
+    Daemon {
+        /// Seconds between two runs.
+        #[arg(long, env = "PULLCONF_INTERVAL")]
+        interval: Option<u64>,
+    },
+    /// Verify the connection to pullconfd, save the server and the API key,
+    /// install the scheduling of runs and perform the first run.
+    Bootstrap {
+        /// The API key, which then appears in the process list, see
+        /// --api-key-file.
+        #[arg(long)]
+        api_key: Option<String>,
+    },
+    /// Print the facts of this system as JSON.
+    Facts,
+    /// Print the report of the last run.
+    Last {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the history of runs.
+    History {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Skip subsequent runs until pullconf is enabled again.
+    Disable {
+        /// Why pullconf is disabled, which is logged by skipped runs.
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Enable runs again after `pullconf disable`.
+    Enable,
+    /// Print the resource catalog that pullconfd intends for this system.
+    ShowCatalog {
+        /// Only print resources of these comma-separated types.
+        #[arg(long = "type")]
+        types: Option<String>,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print how the resource catalog from pullconfd differs from the
+    /// saved one.
+    Diff {
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Run pullconf with the arguments of this process.
+pub fn main() -> ExitCode {
+    // Arguments are parsed strictly, so that e.g. a misspelled --noop fails
+    // instead of modifying the system. Usage errors exit with 1, as 2 means
+    // that resources were changed, see `Outcome`.
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(error) => {
+            let _ = error.print();
+
+            return if error.use_stderr() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+
+    let args = cli.run;
+
+    // Create a new lifecycle ID that will be attached to every emitted
+    // log output. Since this program is designed to run repeatedly via
+    // some external scheduling mechanism (e.g. systemd timers), this ID
+    // identifies all logs that were emitted during one iteration of the
+    // program.
+    // The ID will also be passed to every module and function that emits
+    // logs. In daemon mode all runs share the same ID.
+    let pid = std::process::id();
+
+    // Initialize logfmt logging.
+    let log_target = std::env::var("PULLCONF_LOG_TARGET")
+        .ok()
+        .unwrap_or("stderr".to_string());
+    // Manual runs on a terminal print concise, human-readable output unless
+    // a log format is set explicitly. Daemons keep logging structured data.
+    let log_format = std::env::var("PULLCONF_LOG_FORMAT").ok().unwrap_or(
+        if std::io::stderr().is_terminal() && !matches!(cli.command, Some(Command::Daemon { .. })) {
+            "pretty".to_string()
+        } else {
+            "logfmt".to_string()
+        },
+    );
+    if log_target == "syslog" {
+        if let Err(error) = common::syslog::Syslog::init(APPLICATION, VERSION) {
+            eprintln!("failed to initialize syslog logging: {}", error);
+            return ExitCode::FAILURE;
+        }
+    } else if log_target != "stderr" {
+        eprintln!("unknown log target {}", log_target);
+        return ExitCode::FAILURE;
+    } else if log_format == "logfmt" {
+        std_logger::Config::logfmt()
+            .with_kvs(&[("application", APPLICATION), ("version", VERSION)])
+            .with_call_location(false)
+            .init()
+    } else if log_format == "json" {
+        std_logger::Config::json()
+            .with_kvs(&[("application", APPLICATION), ("version", VERSION)])
+            .with_call_location(false)
+            .init()
+    } else if log_format == "pretty" {
+        if let Err(error) = interactive::Interactive::init() {
+            eprintln!("failed to initialize logging: {}", error);
+            return ExitCode::FAILURE;
+        }
+    } else {
+        eprintln!("unknown log format {}", log_format);
+        return ExitCode::FAILURE;
+    }
+
+    // Print the facts of this system, which does not require root privileges.
+    if matches!(cli.command, Some(Command::Facts)) {
+        return match ureq::serde_json::to_string_pretty(&facts::collect(pid)) {
+            Ok(facts) => {
+                println!("{}", facts);
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                log::error!(scope = "main", pid; "failed to serialize facts: {}", error);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    // Print what pullconf did recently, which only requires read access to
+    // its state directory.
+    match &cli.command {
+        Some(Command::Last { json }) => {
+            return match history::print_last(pid, *json) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => error.into(),
+            }
+        }
+        Some(Command::History { json }) => {
+            return match history::print_history(pid, *json) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => error.into(),
+            }
+        }
+        _ => (),
+    }
+
+    // Without root privileges pullconf may run in least-privilege mode, in
+    // which it only applies resources whose privileges have been granted,
+    // e.g. via file capabilities and sudo. An alternate root may belong to
+    // the user that pullconf runs as, e.g. an image that is built without
+    // root privileges, in which case resources are applied as that user.
+    if !nix::unistd::getuid().is_root() {
+        if args.unprivileged {
+            if let Err(error) = privileges::init(pid) {
+                return error.into();
+            }
+        } else if args.root.is_none() {
+            log::error!(
+                scope = "main",
+                pid;
+                "pullconf must be executed as root, unless least-privilege mode is enabled with --unprivileged or resources are applied to an alternate root with --root"
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Resources may be applied to another system whose root directory is
+    // mounted here, e.g. `pullconf apply --root /mnt/target` while building
+    // an image, with the same resource catalog as in production.
+    if let Some(directory) = &args.root {
+        if matches!(
+            cli.command,
+            Some(Command::Daemon { .. } | Command::Bootstrap { .. })
+        ) {
+            log::error!(
+                scope = "main",
+                pid;
+                "--root cannot be combined with daemon or bootstrap, which manage this system"
+            );
+            return ExitCode::FAILURE;
+        }
+
+        if let Err(error) = root::init(pid, directory) {
+            return error.into();
+        }
+    }
+
+    // The platform of the system that resources are applied to determines
+    // e.g. the package manager, so it is selected after the alternate root.
+    if let Err(error) = platform::init(pid) {
+        return error.into();
+    }
+
+    // Disabling pullconf, e.g. during an incident, only affects subsequent
+    // runs and does not wait for a run that is in progress.
+    match &cli.command {
+        Some(Command::Disable { reason }) => {
+            return match disable::disable(pid, reason.as_deref()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => error.into(),
+            }
+        }
+        Some(Command::Enable) => {
+            return match disable::enable(pid) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(error) => error.into(),
+            }
+        }
+        _ => (),
+    }
+
+    // In audit mode the resource catalog is evaluated like in noop mode and
+    // the resources that are not in their desired state are saved in a
+    // drift report, e.g. for compliance scans in between enforcing runs.
+    let audit = args.audit;
+
+    // In noop mode the resource catalog is only evaluated against the
+    // current state of the system, without modifying anything.
+    let noop = audit || args.noop;
+
+    if audit {
+        log::info!(scope = "main", pid; "running in audit mode, no resource will be modified");
+    } else if noop {
+        log::info!(scope = "main", pid; "running in noop mode, no resource will be modified");
+    }
+
+    // In offline mode the saved resource catalog is applied without querying
+    // pullconfd, e.g. on hosts that are temporarily cut off from the server.
+    let offline = args.offline;
+
+    // Apply a resource catalog from a local file without pullconfd, e.g.
+    // `pullconf apply --local catalog.toml` while building an image.
+    let local = args.local;
+
+    // Without requests to pullconfd there is no point in spreading them.
+    let splay = args
+        .splay
+        .map(Duration::from_secs)
+        .filter(|splay| !splay.is_zero() && !offline && local.is_none());
+
+    // Command line options that take precedence over the environment, e.g. to
+    // test against a staging server.
+    let overrides = Overrides {
+        server: args.server,
+        api_key_file: args.api_key_file,
+        hostname: args.hostname,
+        allow_rollback: args.allow_rollback,
+    };
+
+    // Converge only the given resources or those with the given tags (and
+    // what they depend on), e.g. `--only file:/etc/ssh/sshd_config` or
+    // `--tags bootstrap,security`, and leave out certain resource types
+    // along with their dependents, e.g. `--skip-type apt::package`.
+    let selection = Selection {
+        only: list(args.only.as_deref()),
+        tags: list(args.tags.as_deref()),
+        skip_tags: list(args.skip_tags.as_deref()),
+        skip_types: list(args.skip_type.as_deref()),
+    };
+
+    // Independent resources may be applied concurrently, which speeds up
+    // large catalogs. By default resources are applied one after another.
+    let parallelism = match args.parallelism {
+        Some(0) => {
+            log::error!(scope = "main", pid; "parallelism must be greater than zero");
+            return ExitCode::FAILURE;
+        }
+        parallelism => parallelism.unwrap_or(1) as usize,
+    };
+
+    // Resources may be applied at a lower CPU and IO priority, so that runs
+    // do not degrade latency-sensitive workloads on the host. Resources may
+    // set their own priority in the configuration.
+    let priority = args.priority.unwrap_or_default();
+
+    // On constrained devices a run may be limited to a budget, after which
+    // the resources that have not been started yet are deferred to the next
+    // run, e.g. `--run-budget 300`.
+    let budget = args
+        .run_budget
+        .map(Duration::from_secs)
+        .filter(|budget| !budget.is_zero());
+
+    // Back off after repeated failed runs instead of hammering a broken
+    // system every few minutes.
+    let breaker = Breaker {
+        threshold: args
+            .failure_threshold
+            .unwrap_or(DEFAULT_FAILURE_THRESHOLD)
+            .try_into()
+            .unwrap_or(u32::MAX),
+        backoff: args
+            .failure_backoff
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_FAILURE_BACKOFF),
+    };
+
+    // Downloaded file contents are cached, so that identical content is not
+    // downloaded again, e.g. after a file has been deleted.
+    let assets = AssetCache {
+        max_size: args
+            .asset_cache_size
+            .unwrap_or(DEFAULT_ASSET_CACHE_SIZE)
+            .saturating_mul(1024 * 1024),
+        max_age: args
+            .asset_cache_age
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_ASSET_CACHE_AGE),
+    };
+
+    // In daemon mode, changes that are made out-of-band to files,
+    // directories and symlinks of the resource catalog may be remediated
+    // as soon as they happen instead of at the next run.
+    let watch = args.watch;
+
+    // The metrics of each run may be written to the directory of the
+    // textfile collector of node_exporter, e.g.
+    // `--metrics-dir /var/lib/prometheus/node-exporter`.
+    let metrics = args.metrics_dir;
+
+    let options = Options {
+        noop,
+        audit,
+        offline,
+        local,
+        scheduling: Scheduling {
+            parallelism,
+            priority,
+            budget,
+        },
+        breaker,
+        assets,
+        watch,
+        metrics,
+        overrides,
+        selection,
+    };
+
+    // Print what pullconfd intends for this system, optionally only certain
+    // resource types or resources, e.g. `pullconf show-catalog --type
+    // apt::package` or `pullconf show-catalog --only file:/etc/motd`.
+    if let Some(Command::ShowCatalog { types, json }) = &cli.command {
+        let configuration = match &options.local {
+            Some(path) => configuration::Configuration::local(pid, path),
+            None => configuration::Configuration::get(pid, &options.overrides, options.offline),
+        };
+
+        return match configuration.and_then(|configuration| {
+            show::print_catalog(
+                pid,
+                configuration.resources(),
+                &list(types.as_deref()),
+                &options.selection.only,
+                *json,
+            )
+        }) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => error.into(),
+        };
+    }
+
+    // Preview what the next run changes compared to the last one, i.e. how
+    // the resource catalog from pullconfd differs from the saved one. The
+    // saved resource catalog is left as it is.
+    if let Some(Command::Diff { json }) = &cli.command {
+        let fresh = match &options.local {
+            Some(path) => configuration::Configuration::local(pid, path),
+            None => configuration::Configuration::fetch(pid, &options.overrides),
+        };
+
+        return match configuration::Configuration::saved(pid)
+            .and_then(|saved| fresh.map(|fresh| (saved, fresh)))
+            .and_then(|(saved, fresh)| {
+                diff::compare(&saved, fresh.resources()).map_err(|error| {
+                    log::error!(scope = "diff", pid; "failed to compare resource catalogs: {}", error);
+                    Terminate
+                })
+            })
+            .and_then(|diff| diff::print(pid, &diff, *json))
+        {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => error.into(),
+        };
+    }
+
+    // Onboard a new system with a single command, e.g. `pullconf bootstrap
+    // --server pullconf.example.com --api-key-file /root/pullconf.key`,
+    // followed by its first run.
+    if let Some(Command::Bootstrap { api_key }) = &cli.command {
+        if let Err(error) = bootstrap::bootstrap(pid, &options.overrides, api_key.clone()) {
+            return error.into();
+        }
+
+        // From now on the server and the API key are read from the
+        // environment, so that the saved resource catalog is updated.
+        let options = Options {
+            overrides: Overrides {
+                hostname: options.overrides.hostname.clone(),
+                allow_rollback: options.overrides.allow_rollback,
+                ..Default::default()
+            },
+            ..options
+        };
+
+        return match run(pid, &options, None, true, None) {
+            Ok((outcome, _)) => outcome.into(),
+            Err(error) => error.into(),
+        };
+    }
+
+    if let Some(Command::Daemon { interval }) = cli.command {
+        let interval = match interval {
+            Some(0) => {
+                log::error!(scope = "main", pid; "interval must be greater than zero");
+                return ExitCode::FAILURE;
+            }
+            interval => interval
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_INTERVAL),
+        };
+
+        daemon(pid, &options, splay, interval)
+    } else {
+        // Manual runs on a terminal are never held back by the circuit
+        // breaker, e.g. to verify a fix right away.
+        match run(pid, &options, splay, std::io::stdin().is_terminal(), None) {
+            Ok((outcome, _)) => outcome.into(),
+            Err(error) => error.into(),
+        }
+    }
+}
+
+/// Fetch the client configuration from pullconfd, or read it from a local
+/// file, and apply it once. The exit code tells whether anything was changed
+/// or failed to apply, see `configuration::Outcome`. Unless forced, the run
+/// is skipped while the circuit breaker holds back runs. If a watcher is
+/// given, it watches the paths of the resource catalog afterwards. The
+/// polling interval that pullconfd asks for, if any, is returned along with
+/// the outcome.
+fn run(
+    pid: u32,
+    options: &Options,
+    splay: Option<Duration>,
+    force: bool,
+    watcher: Option<&Watcher>,
+) -> Result<(Outcome, Option<Duration>), Terminate> {
+    // Sleep for a random delay up to the configured maximum, so that clients
+    // whose timers trigger at the same time do not contact pullconfd at once.
+    // The lock is acquired afterwards, so that a manual invocation is not
+    // blocked by a run that is merely waiting.
+    if let Some(max) = splay {
+        let delay = Duration::from_millis(rand::thread_rng().gen_range(0..=max.as_millis() as u64));
+
+        log::info!(
+            scope = "main",
+            pid;
+            "sleeping for {:.3} seconds before contacting the server",
+            delay.as_secs_f64()
+        );
+
+        systemd::sleep(pid, delay);
+    }
+
+    // Hold an exclusive lock until the end of this run, so that concurrent
+    // runs cannot modify the same resources at the same time.
+    let lock = lock::Lock::acquire(pid)?;
+
+    if let Some(description) = disable::disabled() {
+        log::warn!(
+            scope = "main",
+            pid;
+            "skipping run as pullconf has been disabled {}, run `pullconf enable` to enable it again",
+            description
+        );
+
+        return Ok((Outcome::Disabled, None));
+    }
+
+    // Remove temporary files that a run which crashed left behind.
+    tmp::cleanup(pid);
+
+    // Runs of a local resource catalog or against an alternate root neither
+    // depend on nor affect the circuit breaker.
+    let breaker = !options.noop && options.local.is_none() && root::get().is_none();
+
+    if !force && breaker {
+        let failures = Failures::load();
+
+        if let Some(remaining) = options
+            .breaker
+            .remaining(&failures, OffsetDateTime::now_utc())
+        {
+            log::warn!(
+                scope = "breaker",
+                pid;
+                "skipping run as the last {} runs failed, the next run is attempted in {} seconds, run pullconf manually to retry now",
+                failures.count,
+                remaining.as_secs()
+            );
+
+            return Ok((Outcome::Quarantined, None));
+        }
+    }
+
+    let configuration = match &options.local {
+        Some(path) => configuration::Configuration::local(pid, path),
+        None => configuration::Configuration::get(pid, &options.overrides, options.offline),
+    };
+
+    let outcome = match configuration {
+        Ok(configuration) => {
+            // If pullconfd advertises another version of pullconf, replace this
+            // executable and let the new version perform the run. The new version
+            // keeps the PID and acquires the lock again, so it has to be released
+            // first.
+            if !options.noop && update::enabled() {
+                if let Some(executable) = update::update(pid, &configuration)? {
+                    drop(lock);
+                    return Err(update::restart(pid, &executable));
+                }
+            }
+
+            if let Some(watcher) = watcher {
+                watcher.update(pid, configuration.watched(&options.selection));
+            }
+
+            let interval = configuration.interval();
+
+            configuration
+                .apply(
+                    pid,
+                    options.noop,
+                    options.audit,
+                    &options.selection,
+                    &options.scheduling,
+                    options.metrics.as_deref(),
+                )
+                .map(|outcome| (outcome, interval))
+        }
+        // A run that fails before the resource catalog is applied, e.g.
+        // because pullconfd rejects the API key, has failed as a whole.
+        Err(error) => {
+            if breaker {
+                breaker::record(pid, true);
+            }
+
+            Err(error)
+        }
+    };
+
+    // Keep the asset cache within its limits, which only affects the
+    // downloads of subsequent runs.
+    assets::evict(pid, &options.assets);
+
+    let failures = Failures::load();
+
+    if breaker && options.breaker.is_open(&failures) {
+        log::warn!(
+            scope = "breaker",
+            pid;
+            "the last {} runs failed, backing off for {} seconds between runs until a run succeeds",
+            failures.count,
+            options.breaker.backoff.as_secs()
+        );
+    }
+
+    outcome
+}
+
+/// Events that are forwarded from the signal handling and watching threads
+/// to the daemon.
+enum Event {
+    Run,
+    Shutdown,
+    /// A resource was changed out-of-band at the given time.
+    Changed(String, Instant),
+}
+
+/// Fetch and apply the client configuration repeatedly until SIGTERM or
+/// SIGINT is received. A run is started every time the interval elapses
+/// after the previous run, or immediately on SIGHUP, in which case the
+/// splay is skipped. The interval that pullconfd sends along with the
+/// resource catalog takes precedence over the configured one.
+fn daemon(
+    pid: u32,
+    options: &Options,
+    splay: Option<Duration>,
+    mut interval: Duration,
+) -> ExitCode {
+    let scope = "daemon";
+
+    let mut signals = match Signals::new([SIGTERM, SIGINT, SIGHUP]) {
+        Ok(signals) => signals,
+        Err(error) => {
+            log::error!(scope, pid; "failed to register signal handlers: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (events, receiver) = mpsc::channel();
+
+    let watcher = if options.watch {
+        let events = events.clone();
+
+        match Watcher::start(pid, move |name| {
+            events.send(Event::Changed(name, Instant::now())).is_ok()
+        }) {
+            Ok(watcher) => Some(watcher),
+            Err(_) => return ExitCode::FAILURE,
+        }
+    } else {
+        None
+    };
+
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            log::debug!(scope, pid, signal; "received signal");
+
+            let event = match signal {
+                SIGHUP => Event::Run,
+                _ => Event::Shutdown,
+            };
+
+            if events.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    log::info!(
+        scope,
+        pid;
+        "running in daemon mode with an interval of {} seconds",
+        interval.as_secs()
+    );
+
+    systemd::notify(pid, "READY=1\nSTATUS=starting first run");
+
+    let mut immediate = false;
+    let mut limit = RateLimit::default();
+
+    'outer: loop {
+        systemd::notify(pid, "STATUS=applying resource catalog");
+
+        // Failures are logged per resource, so the outcome of a single
+        // run does not terminate the daemon.
+        let splay = splay.filter(|_| !immediate);
+
+        // The daemon holds back runs by itself, see below, so runs are
+        // forced.
+        let result = match run(pid, options, splay, true, watcher.as_ref()) {
+            Ok((outcome, requested)) => {
+                // Follow the interval that pullconfd asks for, which is
+                // kept until it sends another one.
+                if let Some(requested) = requested.filter(|requested| *requested != interval) {
+                    log::info!(
+                        scope,
+                        pid;
+                        "polling in an interval of {} seconds as requested by the server",
+                        requested.as_secs()
+                    );
+
+                    interval = requested;
+                }
+
+                outcome.to_string()
+            }
+            Err(_) => "fatal error".to_string(),
+        };
+
+        // Changes that are reported during the run were likely made by the
+        // run itself.
+        let mut quiet_until = Instant::now() + watch::QUIET_PERIOD;
+
+        // Resources that were changed out-of-band and are remediated once no
+        // further changes are reported for a moment.
+        let mut changed = HashSet::new();
+        let mut remediate_at: Option<Instant> = None;
+
+        // While the circuit breaker is open, the daemon waits for at least
+        // the backoff between runs.
+        let failures = Failures::load();
+
+        let (state, wait) = if !options.noop && options.breaker.is_open(&failures) {
+            (
+                format!("quarantined after {} failed runs", failures.count),
+                interval.max(options.breaker.backoff),
+            )
+        } else {
+            ("idle".to_string(), interval)
+        };
+
+        let next_run = (OffsetDateTime::now_utc() + wait)
+            .format(&Rfc3339)
+            .unwrap_or_default();
+
+        systemd::notify(
+            pid,
+            &format!(
+                "STATUS={}, last run: {}, next run: {}",
+                state, result, next_run
+            ),
+        );
+
+        // Wait for the interval to elapse or for a signal, while resetting
+        // the watchdog timer in between.
+        let deadline = Instant::now() + wait;
+
+        loop {
+            systemd::keepalive(pid);
+
+            if remediate_at.is_some_and(|at| Instant::now() >= at) {
+                remediate_at = None;
+
+                let now = Instant::now();
+
+                let only = changed
+                    .drain()
+                    .filter(|name: &String| limit.allow(pid, name, now))
+                    .collect::<Vec<String>>();
+
+                if !only.is_empty() {
+                    log::info!(
+                        scope,
+                        pid;
+                        "remediating resources that were changed out-of-band: {}",
+                        only.join(", ")
+                    );
+
+                    let options = Options {
+                        selection: Selection {
+                            only,
+                            ..Default::default()
+                        },
+                        ..options.clone()
+                    };
+
+                    if run(pid, &options, None, true, None).is_err() {
+                        log::warn!(scope, pid; "failed to remediate resources");
+                    }
+
+                    quiet_until = Instant::now() + watch::QUIET_PERIOD;
+                }
+            }
+
+            let timeout = deadline
+                .saturating_duration_since(Instant::now())
+                .min(systemd::keepalive_interval());
+
+            let timeout = match remediate_at {
+                Some(at) => timeout.min(at.saturating_duration_since(Instant::now())),
+                None => timeout,
+            };
+
+            match receiver.recv_timeout(timeout) {
+                Ok(Event::Changed(_, at)) if at < quiet_until => (),
+                Ok(Event::Changed(name, _)) => {
+                    changed.insert(name);
+                    remediate_at = Some(Instant::now() + watch::DEBOUNCE);
+                }
+                Ok(Event::Run) => {
+                    log::info!(scope, pid; "starting an immediate run on SIGHUP");
+                    immediate = true;
+                    continue 'outer;
+                }
+                Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+                    immediate = false;
+                    continue 'outer;
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Ok(Event::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                    log::info!(scope, pid; "shutting down");
+                    systemd::notify(pid, "STOPPING=1");
+                    return ExitCode::SUCCESS;
+                }
+            }
+        }
+    }
+}
+
+/// Split the comma-separated values of a command line option, e.g.
+/// `--tags bootstrap,security`.
+fn list(value: Option<&str>) -> Vec<String> {
+    value
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,191 @@
+use crate::{configuration::name, resources::Resource};
+use common::error::Terminate;
+use log::{error, info};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+};
+use ureq::serde_json::{self, json, Value};
+use uuid::Uuid;
+
+/// Read a resource catalog from a local file instead of pullconfd, e.g. to
+/// build images, to test resource definitions or on air-gapped systems.
+/// The file is either JSON or TOML, depending on its extension, and lists
+/// the resources in the format of the resource catalog, e.g. as it is
+/// printed by `pullconfd render --format json`:
+///
+/// ```toml
+/// [[resources]]
+/// type = "group"
+/// parameters = { ensure = "present", name = "admins", system = false }
+///
+/// [[resources]]
+/// type = "user"
+/// requires = ["group:admins"]
+/// ...
+/// ```
+///
+/// Unlike in the catalog, IDs are optional and dependencies may be given
+/// by name, like with `--only`.
+pub fn load(pid: u32, path: &Path) -> Result<VecDeque<Resource>, Terminate> {
+    let scope = "local";
+
+    let content = fs::read_to_string(path).map_err(|error| {
+        error!(scope, pid; "failed to read {}: {}", path.display(), error);
+        Terminate
+    })?;
+
+    let catalog = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str::<Value>(&content).map_err(|error| error.to_string()),
+        Some("toml") => toml::from_str::<Value>(&content).map_err(|error| error.to_string()),
+        _ => Err("expected a file with the extension .json or .toml".to_string()),
+    }
+    .and_then(compile)
+    .map_err(|error| {
+        error!(scope, pid; "failed to compile resource catalog from {}: {}", path.display(), error);
+        Terminate
+    })?;
+
+    info!(
+        scope,
+        pid;
+        "read {} resources from {}, pullconfd is not contacted",
+        catalog.len(),
+        path.display()
+    );
+
+    Ok(catalog)
+}
+
+/// Turn the resources of a local file into a resource catalog, i.e. assign
+/// IDs to resources that lack one and resolve dependencies given by name.
+fn compile(catalog: Value) -> Result<VecDeque<Resource>, String> {
+    let mut entries = match catalog {
+        Value::Object(mut catalog) => match catalog
+            .remove("resources")
+            .or_else(|| catalog.remove("data"))
+        {
+            Some(Value::Array(entries)) => entries,
+            _ => return Err("expected a list of resources named `resources`".to_string()),
+        },
+        _ => return Err("expected a table or an object".to_string()),
+    };
+
+    let mut requires = Vec::with_capacity(entries.len());
+    let mut ids = HashMap::new();
+
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let Value::Object(entry) = entry else {
+            return Err(format!(
+                "resource #{} is not a table or an object",
+                index + 1
+            ));
+        };
+
+        entry
+            .entry("id")
+            .or_insert_with(|| Value::from(Uuid::new_v4().to_string()));
+        entry
+            .entry("relationships")
+            .or_insert_with(|| json!({ "requires": [] }));
+
+        requires.push(match entry.remove("requires") {
+            Some(names) => serde_json::from_value::<Vec<String>>(names).map_err(|_| {
+                format!(
+                    "requires of resource #{} must be a list of resource names, e.g. \"file:/etc/motd\"",
+                    index + 1
+                )
+            })?,
+            None => vec![],
+        });
+
+        let resource = serde_json::from_value::<Resource>(Value::Object(entry.clone()))
+            .map_err(|error| format!("resource #{} is invalid: {}", index + 1, error))?;
+
+        if ids
+            .insert(
+                name(&resource),
+                (resource.kind().to_string(), resource.id()),
+            )
+            .is_some()
+        {
+            return Err(format!("resource {} is defined twice", name(&resource)));
+        }
+    }
+
+    let mut resources = VecDeque::with_capacity(entries.len());
+
+    for (mut entry, names) in entries.into_iter().zip(requires) {
+        for dependency in names {
+            let (kind, id) = ids
+                .get(&dependency)
+                .ok_or_else(|| format!("required resource {} is not defined", dependency))?;
+
+            if let Some(Value::Array(requires)) = entry.pointer_mut("/relationships/requires") {
+                requires.push(json!({ "type": kind, "id": id }));
+            }
+        }
+
+        let resource =
+            serde_json::from_value::<Resource>(entry).map_err(|error| error.to_string())?;
+
+        // File contents can only be downloaded from pullconfd.
+        if resource.kind() == "file"
+            && resource
+                .parameters()
+                .is_ok_and(|parameters| !parameters["source"].is_null())
+        {
+            return Err(format!(
+                "resource {} has a source, which requires pullconfd, use content instead",
+                name(&resource)
+            ));
+        }
+
+        resources.push_back(resource);
+    }
+
+    Ok(resources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_local_catalog() {
+        let catalog = toml::from_str::<Value>(
+            r#"
+            [[resources]]
+            type = "group"
+            parameters = { ensure = "present", name = "admins", system = false }
+
+            [[resources]]
+            type = "group"
+            requires = ["group:admins"]
+            parameters = { ensure = "present", name = "web", system = false }
+            "#,
+        )
+        .unwrap();
+
+        let resources = compile(catalog.clone()).unwrap();
+
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[1].dependencies().len(), 1);
+        assert_eq!(resources[1].dependencies()[0].id, resources[0].id());
+
+        let mut missing = catalog.clone();
+        missing["resources"][1]["requires"] = json!(["group:missing"]);
+
+        assert!(compile(missing).is_err());
+
+        let mut duplicate = catalog;
+        duplicate["resources"][1]["parameters"]["name"] = json!("admins");
+        duplicate["resources"][1]
+            .as_object_mut()
+            .unwrap()
+            .remove("requires");
+
+        assert!(compile(duplicate).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use crate::state;
+use common::error::Terminate;
+use log::{debug, error};
+use nix::{
+    errno::Errno,
+    fcntl::{Flock, FlockArg},
+};
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    sync::LazyLock,
+};
+
+static LOCK_FILE: LazyLock<String> = LazyLock::new(|| state::path("lock"));
+
+/// An exclusive lock that prevents concurrent runs of pullconf, e.g. when a
+/// manual invocation overlaps with a run triggered by the timer unit.
+/// The lock is an advisory lock on the lock file, which the kernel releases
+/// once the process that holds it exits, even if it crashes, so the lock
+/// file is never removed and a lock is never stale. The lock file contains
+/// the PID of the process that acquired the lock last, for informational
+/// purposes only.
+#[derive(Debug)]
+pub struct Lock {
+    /// Unlocked when dropped.
+    _lock: Flock<fs::File>,
+}
+
+impl Lock {
+    /// Lock the lock file, creating it if it does not exist yet, without
+    /// waiting for another process that holds the lock.
+    pub fn acquire(pid: u32) -> Result<Self, Terminate> {
+        let scope = "lock";
+
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(&*LOCK_FILE)
+            .map_err(|error| {
+                error!(scope, pid; "failed to open lock file {}: {}", *LOCK_FILE, error);
+                Terminate
+            })?;
+
+        let mut lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((mut file, Errno::EWOULDBLOCK)) => {
+                let mut holder = String::new();
+
+                // The holder may not have written its PID yet.
+                let holder = match file.read_to_string(&mut holder) {
+                    Ok(_) if !holder.trim().is_empty() => holder.trim().to_string(),
+                    _ => "unknown".to_string(),
+                };
+
+                error!(
+                    scope,
+                    pid;
+                    "another pullconf process (PID {}) holds the lock file {}",
+                    holder,
+                    *LOCK_FILE
+                );
+
+                return Err(Terminate);
+            }
+            Err((_, error)) => {
+                error!(scope, pid; "failed to lock lock file {}: {}", *LOCK_FILE, error);
+                return Err(Terminate);
+            }
+        };
+
+        if let Err(error) = lock.set_len(0).and_then(|_| write!(lock, "{}", pid)) {
+            error!(scope, pid; "failed to write PID to lock file {}: {}", *LOCK_FILE, error);
+            return Err(Terminate);
+        }
+
+        debug!(scope, pid; "acquired lock file {}", *LOCK_FILE);
+
+        Ok(Self { _lock: lock })
+    }
+}
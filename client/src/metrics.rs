@@ -0,0 +1,153 @@
+use common::report::{Action, Run};
+use log::{debug, warn};
+use std::{fmt::Write as _, fs, path::Path};
+use time::OffsetDateTime;
+
+/// The file in the metrics directory that the metrics are written to. The
+/// textfile collector of node_exporter reads every file with a `.prom`
+/// extension in its directory.
+const FILE_NAME: &str = "pullconf.prom";
+
+/// Write the metrics of a run in the Prometheus text format to the given
+/// directory, e.g. the directory of the textfile collector of node_exporter,
+/// so that the health of pullconf is scraped along with the other metrics
+/// of the system. A failure is logged, but does not affect the outcome of
+/// the run.
+pub fn record(pid: u32, directory: &Path, run: &Run) {
+    let scope = "metrics";
+    let path = directory.join(FILE_NAME);
+    let metrics = render(run, OffsetDateTime::now_utc().unix_timestamp());
+
+    // Write the metrics atomically, so that node_exporter never reads a
+    // partial file. The temporary file is ignored due to its extension.
+    let temporary = path.with_extension("prom.tmp");
+
+    match fs::write(&temporary, metrics).and_then(|_| fs::rename(&temporary, &path)) {
+        Ok(()) => debug!(scope, pid; "wrote metrics to {}", path.display()),
+        Err(error) => {
+            warn!(scope, pid; "failed to write metrics to {}: {}", path.display(), error)
+        }
+    }
+}
+
+/// Render the metrics of a run that finished at the given UNIX timestamp.
+fn render(run: &Run, timestamp: i64) -> String {
+    let mut metrics = String::new();
+
+    let _ = writeln!(
+        metrics,
+        "# HELP pullconf_last_run_timestamp The time the last run finished as UNIX timestamp.\n\
+         # TYPE pullconf_last_run_timestamp gauge\n\
+         pullconf_last_run_timestamp {}",
+        timestamp
+    );
+
+    let _ = writeln!(
+        metrics,
+        "# HELP pullconf_last_run_noop Whether the last run was a noop run.\n\
+         # TYPE pullconf_last_run_noop gauge\n\
+         pullconf_last_run_noop {}",
+        u8::from(run.noop)
+    );
+
+    let _ = writeln!(
+        metrics,
+        "# HELP pullconf_run_duration_seconds The duration of the last run.\n\
+         # TYPE pullconf_run_duration_seconds gauge\n\
+         pullconf_run_duration_seconds {}",
+        run.duration
+    );
+
+    let _ = writeln!(
+        metrics,
+        "# HELP pullconf_resources The number of resources by their result in the last run.\n\
+         # TYPE pullconf_resources gauge"
+    );
+
+    // Every result is reported, so that its time series does not disappear
+    // after a run without such resources. Deferred resources are reported
+    // separately, although they are skipped in the run.
+    for result in Action::ALL {
+        let count = match result {
+            Action::Skipped => run.count(result).saturating_sub(run.deferred),
+            _ => run.count(result),
+        };
+
+        let _ = writeln!(
+            metrics,
+            "pullconf_resources{{result=\"{}\"}} {}",
+            result, count
+        );
+    }
+
+    let _ = writeln!(
+        metrics,
+        "pullconf_resources{{result=\"deferred\"}} {}",
+        run.deferred
+    );
+
+    if let Some(etag) = &run.etag {
+        let _ = writeln!(
+            metrics,
+            "# HELP pullconf_catalog_etag_info The etag of the resource catalog of the last run.\n\
+             # TYPE pullconf_catalog_etag_info gauge\n\
+             pullconf_catalog_etag_info{{etag=\"{}\"}} 1",
+            escape(etag)
+        );
+    }
+
+    metrics
+}
+
+/// Escape a label value, see the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::report::ResourceResult;
+    use uuid::Uuid;
+
+    #[test]
+    fn render_metrics() {
+        let resource = |id: u128, result: Action| ResourceResult {
+            id: Uuid::from_u128(id),
+            resource: format!("file `/etc/{}`", id),
+            result,
+            duration: 0.0,
+            failure: None,
+        };
+
+        let run = Run {
+            timestamp: "2024-09-01T12:00:00Z".to_string(),
+            noop: false,
+            etag: Some("\"abc\"".to_string()),
+            outcome: "changed".to_string(),
+            duration: 1.5,
+            deferred: 1,
+            resources: vec![
+                resource(1, Action::Unchanged),
+                resource(2, Action::Changed),
+                resource(3, Action::Skipped),
+                resource(4, Action::Skipped),
+                resource(5, Action::Failed),
+            ],
+        };
+
+        let metrics = render(&run, 1725192000);
+
+        assert!(metrics.contains("pullconf_last_run_timestamp 1725192000\n"));
+        assert!(metrics.contains("pullconf_last_run_noop 0\n"));
+        assert!(metrics.contains("pullconf_run_duration_seconds 1.5\n"));
+        assert!(metrics.contains("pullconf_resources{result=\"changed\"} 1\n"));
+        assert!(metrics.contains("pullconf_resources{result=\"created\"} 0\n"));
+        assert!(metrics.contains("pullconf_resources{result=\"skipped\"} 1\n"));
+        assert!(metrics.contains("pullconf_resources{result=\"deferred\"} 1\n"));
+        assert!(metrics.contains("pullconf_catalog_etag_info{etag=\"\\\"abc\\\"\"} 1\n"));
+    }
+}
@@ -0,0 +1,155 @@
+use common::error::Terminate;
+use log::{error, warn};
+use serde::Deserialize;
+use std::{fs, io::ErrorKind};
+
+const OVERRIDES_FILE: &str = "/etc/pullconf/overrides.toml";
+
+/// Resources of the catalog that are skipped or only evaluated in noop mode
+/// on this system, e.g. to keep pullconf from reverting a hotfix until the
+/// configuration on the server has caught up:
+///
+/// ```toml
+/// [[skip]]
+/// resource = "file:/etc/nginx/nginx.conf"
+/// reason = "hotfix for INC-1234"
+///
+/// [[noop]]
+/// resource = "apt::package:nginx"
+/// ```
+///
+/// Resources are named by their type and primary parameter, like with
+/// `--only`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocalOverrides {
+    #[serde(default)]
+    skip: Vec<Entry>,
+    #[serde(default)]
+    noop: Vec<Entry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    resource: String,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// How a resource is overridden.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Override<'a> {
+    Skip(Option<&'a str>),
+    Noop(Option<&'a str>),
+}
+
+impl LocalOverrides {
+    /// Read the overrides file. A missing file means that nothing is
+    /// overridden, whereas a file that cannot be parsed is an error, as
+    /// resources that are meant to be left alone would be applied.
+    pub fn load(pid: u32) -> Result<Self, Terminate> {
+        let scope = "overrides";
+
+        let content = match fs::read_to_string(OVERRIDES_FILE) {
+            Ok(content) => content,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => {
+                error!(scope, pid; "failed to read {}: {}", OVERRIDES_FILE, error);
+                return Err(Terminate);
+            }
+        };
+
+        let overrides = Self::parse(&content).map_err(|error| {
+            error!(scope, pid; "failed to parse {}: {}", OVERRIDES_FILE, error);
+            Terminate
+        })?;
+
+        if !overrides.is_empty() {
+            warn!(
+                scope,
+                pid;
+                "{} skips {} and forces noop mode for {} resources, remove it once the configuration on the server has been fixed",
+                OVERRIDES_FILE,
+                overrides.skip.len(),
+                overrides.noop.len()
+            );
+        }
+
+        Ok(overrides)
+    }
+
+    fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.skip.is_empty() && self.noop.is_empty()
+    }
+
+    /// Return how the resource with the given name, e.g.
+    /// `file:/etc/motd`, is overridden. Skipping takes precedence over noop
+    /// mode.
+    pub fn find<'a>(&'a self, name: &str) -> Option<Override<'a>> {
+        let entry = |entries: &'a [Entry]| {
+            entries
+                .iter()
+                .find(|entry| entry.resource == name)
+                .map(|entry| entry.reason.as_deref())
+        };
+
+        entry(&self.skip)
+            .map(Override::Skip)
+            .or_else(|| entry(&self.noop).map(Override::Noop))
+    }
+
+    /// Return the resources that are overridden, but not part of the
+    /// resource catalog, e.g. due to a typo.
+    pub fn unmatched<'a>(&'a self, names: &[String]) -> Vec<&'a str> {
+        self.skip
+            .iter()
+            .chain(self.noop.iter())
+            .map(|entry| entry.resource.as_str())
+            .filter(|resource| !names.iter().any(|name| name == resource))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_overridden_resources() {
+        let overrides = LocalOverrides::parse(
+            r#"
+            [[skip]]
+            resource = "file:/etc/nginx/nginx.conf"
+            reason = "hotfix"
+
+            [[noop]]
+            resource = "apt::package:nginx"
+
+            [[noop]]
+            resource = "file:/etc/nginx/nginx.conf"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            overrides.find("file:/etc/nginx/nginx.conf"),
+            Some(Override::Skip(Some("hotfix")))
+        );
+        assert_eq!(
+            overrides.find("apt::package:nginx"),
+            Some(Override::Noop(None))
+        );
+        assert_eq!(overrides.find("file:/etc/motd"), None);
+        assert_eq!(
+            overrides.unmatched(&["file:/etc/nginx/nginx.conf".to_string()]),
+            vec!["apt::package:nginx"]
+        );
+
+        assert!(LocalOverrides::parse("[[skip]]\nname = \"file:/etc/motd\"\n").is_err());
+    }
+}
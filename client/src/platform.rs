@@ -0,0 +1,155 @@
+use crate::{facts, root};
+use common::error::Terminate;
+use log::{debug, error};
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+/// Directories in which programs of resources are searched for if they are
+/// not found at their path on Debian, in the order of precedence. Programs
+/// from ports, e.g. sudo on FreeBSD, are installed below `/usr/local`.
+const PROGRAM_DIRS: [&str; 6] = [
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+    "/usr/local/sbin",
+    "/usr/local/bin",
+];
+
+/// The platform whose package manager and conventions resources follow,
+/// see `init`.
+static PLATFORM: OnceLock<Platform> = OnceLock::new();
+
+/// The platforms that pullconf manages resources of. They differ in the
+/// package manager that `apt::package` resources use, the tools that manage
+/// users and groups, the service manager that `pullconf bootstrap`
+/// schedules runs with and the location of some programs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Platform {
+    /// Debian and its derivatives, e.g. Ubuntu, with apt and systemd.
+    Debian,
+    /// Alpine Linux with apk and OpenRC.
+    Alpine,
+    /// FreeBSD with pkg, pw(8) and rc.d scripts that are enabled in
+    /// rc.conf(5).
+    FreeBsd,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "debian" => Ok(Self::Debian),
+            "alpine" => Ok(Self::Alpine),
+            "freebsd" => Ok(Self::FreeBsd),
+            _ => Err(format!(
+                "unknown platform `{}`, expected `debian`, `alpine` or `freebsd`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Debian => f.write_str("debian"),
+            Self::Alpine => f.write_str("alpine"),
+            Self::FreeBsd => f.write_str("freebsd"),
+        }
+    }
+}
+
+/// Select the platform from $PULLCONF_PLATFORM or else detect it from the
+/// `ID` fact of the system that resources are applied to, i.e. of the
+/// alternate root if there is one.
+pub fn init(pid: u32) -> Result<(), Terminate> {
+    let scope = "platform";
+
+    let platform = match env::var("PULLCONF_PLATFORM") {
+        Ok(value) => value.parse::<Platform>().map_err(|error| {
+            error!(scope, pid; "PULLCONF_PLATFORM is invalid: {}", error);
+            Terminate
+        })?,
+        Err(_) => detect(),
+    };
+
+    debug!(scope, pid; "applying resources with the conventions of {}", platform);
+
+    let _ = PLATFORM.set(platform);
+
+    Ok(())
+}
+
+/// The platform that resources are applied to. Without `init`, e.g. when
+/// only facts are collected, the platform is detected.
+pub fn get() -> Platform {
+    *PLATFORM.get_or_init(|| {
+        env::var("PULLCONF_PLATFORM")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_else(detect)
+    })
+}
+
+/// Every Linux system that is not Alpine is treated like Debian, which
+/// pullconf has always assumed. FreeBSD is told apart by the target that
+/// pullconf was built for, as its binaries do not run elsewhere anyway.
+fn detect() -> Platform {
+    if cfg!(target_os = "freebsd") {
+        return Platform::FreeBsd;
+    }
+
+    let contents = root::path(Path::new("/etc/os-release"))
+        .and_then(fs::read_to_string)
+        .or_else(|_| root::path(Path::new("/usr/lib/os-release")).and_then(fs::read_to_string))
+        .unwrap_or_default();
+
+    match facts::parse_os_release(&contents)
+        .get("ID")
+        .map(String::as_str)
+    {
+        Some("alpine") => Platform::Alpine,
+        _ => Platform::Debian,
+    }
+}
+
+/// Locate a program of the system that resources are applied to. Programs
+/// are given by their path on Debian, e.g. `/usr/sbin/useradd`, and are
+/// searched for by their name in the usual directories if they do not exist
+/// there, e.g. `/sbin/apk` on Alpine. If a program is found nowhere, its
+/// path on Debian is returned, so that it is reported as missing.
+pub fn program(path: &str) -> PathBuf {
+    let path = Path::new(path);
+
+    let Some(name) = path.file_name() else {
+        return path.to_path_buf();
+    };
+
+    std::iter::once(path.to_path_buf())
+        .chain(
+            PROGRAM_DIRS
+                .iter()
+                .map(|directory| Path::new(directory).join(name)),
+        )
+        .find(|candidate| root::path(candidate).is_ok_and(|path| path.is_file()))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_platform() {
+        assert_eq!("alpine".parse::<Platform>(), Ok(Platform::Alpine));
+        assert_eq!(Platform::Debian.to_string(), "debian");
+        assert_eq!("freebsd".parse::<Platform>(), Ok(Platform::FreeBsd));
+        assert!("openbsd".parse::<Platform>().is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use common::Priority;
+#[cfg(target_os = "linux")]
+use log::warn;
+#[cfg(target_os = "linux")]
+use nix::errno::Errno;
+
+/// See ioprio_set(2), for which libc provides no constants.
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_BE: libc::c_int = 2;
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+
+/// The previous CPU and IO priority of a thread whose priority was lowered,
+/// which is restored when the guard is dropped.
+#[derive(Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct Guard {
+    pid: u32,
+    tid: libc::pid_t,
+    nice: libc::c_int,
+    ioprio: libc::c_int,
+}
+
+/// Lower the CPU and IO priority of the calling thread while a resource is
+/// applied. On Linux both apply to individual threads, so resources that are
+/// applied concurrently by other workers keep their priority, and they are
+/// inherited by the commands that the thread executes, e.g. `apt-get`.
+/// A priority that is already lower, e.g. because pullconf itself runs with
+/// a niceness, is kept. Failures are logged, as they do not affect whether
+/// the resource can be applied.
+#[cfg(target_os = "linux")]
+pub fn lower(pid: u32, priority: Priority) -> Option<Guard> {
+    let (nice, ioprio) = match priority {
+        Priority::Normal => return None,
+        Priority::Low => (10, (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7),
+        Priority::Idle => (19, IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT),
+    };
+
+    let tid = unsafe { libc::gettid() };
+
+    let guard = match (get_nice(tid), get_ioprio(tid)) {
+        (Ok(nice), Ok(ioprio)) => Guard {
+            pid,
+            tid,
+            nice,
+            ioprio,
+        },
+        (Err(error), _) | (_, Err(error)) => {
+            warn!(scope = "priority", pid; "failed to read priority of thread {}: {}", tid, error);
+            return None;
+        }
+    };
+
+    if let Err(error) = set_nice(tid, nice.max(guard.nice)) {
+        warn!(scope = "priority", pid; "failed to lower CPU priority to {}: {}", priority, error);
+    }
+
+    if guard.ioprio >> IOPRIO_CLASS_SHIFT != IOPRIO_CLASS_IDLE {
+        if let Err(error) = set_ioprio(tid, ioprio) {
+            warn!(scope = "priority", pid; "failed to lower IO priority to {}: {}", priority, error);
+        }
+    }
+
+    Some(guard)
+}
+
+/// Other systems, e.g. FreeBSD, only lower the priority of whole processes,
+/// which would affect resources that are applied concurrently, so resources
+/// are applied at the priority of pullconf there.
+#[cfg(not(target_os = "linux"))]
+pub fn lower(_pid: u32, _priority: Priority) -> Option<Guard> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Err(error) = set_nice(self.tid, self.nice).and(set_ioprio(self.tid, self.ioprio)) {
+            warn!(scope = "priority", pid = self.pid; "failed to restore priority of thread {}: {}", self.tid, error);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn get_nice(tid: libc::pid_t) -> Result<libc::c_int, Errno> {
+    // -1 is a valid niceness, so errors are only told apart by errno.
+    Errno::clear();
+
+    match unsafe { libc::getpriority(libc::PRIO_PROCESS, tid as libc::id_t) } {
+        -1 if Errno::last_raw() != 0 => Err(Errno::last()),
+        nice => Ok(nice),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_nice(tid: libc::pid_t, nice: libc::c_int) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice) })
+        .map(drop)
+}
+
+#[cfg(target_os = "linux")]
+fn get_ioprio(tid: libc::pid_t) -> Result<libc::c_int, Errno> {
+    Errno::result(unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, tid) })
+        .map(|ioprio| ioprio as libc::c_int)
+}
+
+#[cfg(target_os = "linux")]
+fn set_ioprio(tid: libc::pid_t, ioprio: libc::c_int) -> Result<(), Errno> {
+    Errno::result(unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) })
+        .map(drop)
+}
@@ -0,0 +1,306 @@
+use crate::root;
+use common::error::Terminate;
+use log::{error, info};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Mutex, OnceLock},
+};
+
+/// Commands that require root privileges are executed with sudo when
+/// pullconf runs as an unprivileged user.
+const SUDO: &str = "/usr/bin/sudo";
+
+/// The privileges that were granted to pullconf in least-privilege mode. As
+/// root pullconf is granted every privilege and this is not set.
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Capabilities that resources need to manage files that do not belong to
+/// the user that pullconf runs as, see capabilities(7).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Capability {
+    Chown,
+    DacOverride,
+    Fowner,
+}
+
+impl Capability {
+    /// The number of the capability in a capability set.
+    fn bit(&self) -> u32 {
+        match self {
+            Self::Chown => 0,
+            Self::DacOverride => 1,
+            Self::Fowner => 3,
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Chown => f.write_str("CAP_CHOWN"),
+            Self::DacOverride => f.write_str("CAP_DAC_OVERRIDE"),
+            Self::Fowner => f.write_str("CAP_FOWNER"),
+        }
+    }
+}
+
+/// The privileges that a resource requires to be applied, i.e. the
+/// capabilities for what pullconf does itself and the commands that it
+/// executes with sudo.
+#[derive(Debug, Default)]
+pub struct Privileges {
+    pub capabilities: Vec<Capability>,
+    pub commands: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct Policy {
+    /// The effective capabilities of the process, e.g. granted by file
+    /// capabilities or `AmbientCapabilities` of the service unit.
+    capabilities: u64,
+    /// Whether sudo permits a command without a password, which is only
+    /// asked once per command.
+    commands: Mutex<HashMap<PathBuf, bool>>,
+}
+
+impl Policy {
+    fn missing(&self, capabilities: &[Capability]) -> Vec<Capability> {
+        capabilities
+            .iter()
+            .filter(|capability| self.capabilities & (1 << capability.bit()) == 0)
+            .copied()
+            .collect()
+    }
+
+    fn permits(&self, program: &Path) -> bool {
+        let mut commands = self
+            .commands
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        *commands.entry(program.to_path_buf()).or_insert_with(|| {
+            sudo(&["-n", "-l"], program)
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success())
+        })
+    }
+}
+
+/// Enter least-privilege mode, in which pullconf runs as an unprivileged
+/// user. Resources are only applied if the privileges that they require
+/// have been granted, see `check`.
+pub fn init(pid: u32) -> Result<(), Terminate> {
+    let scope = "privileges";
+
+    let capabilities = capabilities(pid)?;
+
+    let policy = Policy {
+        capabilities,
+        commands: Mutex::new(HashMap::new()),
+    };
+
+    let all = [
+        Capability::Chown,
+        Capability::DacOverride,
+        Capability::Fowner,
+    ];
+    let missing = policy.missing(&all);
+
+    let granted = all
+        .iter()
+        .filter(|capability| !missing.contains(capability))
+        .map(Capability::to_string)
+        .collect::<Vec<String>>();
+
+    info!(
+        scope,
+        pid;
+        "running in least-privilege mode with {}, commands that require root are executed with `sudo -n`",
+        if granted.is_empty() {
+            "no capabilities".to_string()
+        } else {
+            granted.join(", ")
+        }
+    );
+
+    let _ = POLICY.set(policy);
+
+    Ok(())
+}
+
+/// Whether pullconf runs in least-privilege mode.
+pub fn is_unprivileged() -> bool {
+    POLICY.get().is_some()
+}
+
+/// Create a command that is executed with root privileges. In
+/// least-privilege mode it is executed with `sudo -n`, which fails instead
+/// of prompting for a password. With an alternate root the program of the
+/// target system is executed, see `root::command`.
+pub fn command<S: AsRef<OsStr>>(program: S) -> Command {
+    if is_unprivileged() {
+        sudo(&["-n"], program)
+    } else {
+        root::command(program)
+    }
+}
+
+/// Create a command that executes a program with sudo and the given
+/// options. With an alternate root sudo executes chroot(8), so sudo has to
+/// permit e.g. `/usr/sbin/chroot /mnt/target /usr/bin/apt-get`.
+fn sudo<S: AsRef<OsStr>>(options: &[&str], program: S) -> Command {
+    let mut command = Command::new(SUDO);
+    command.args(options).arg("--");
+
+    if let Some(root) = root::get() {
+        command.arg(root::CHROOT).arg(root);
+    }
+
+    command.arg(program);
+    command
+}
+
+/// Return the program that a command executes, i.e. neither sudo nor
+/// chroot, e.g. for error messages.
+pub fn program(command: &Command) -> &OsStr {
+    let mut program = command.get_program();
+    let mut args = command.get_args();
+
+    if program == SUDO {
+        if let Some(next) = args.by_ref().skip_while(|arg| *arg != "--").nth(1) {
+            program = next;
+        }
+    }
+
+    // The first argument of chroot is the alternate root.
+    if program == root::CHROOT {
+        if let Some(next) = args.nth(1) {
+            program = next;
+        }
+    }
+
+    program
+}
+
+/// Check whether the privileges that a resource requires have been
+/// granted, which is always the case as root. Otherwise the reason why the
+/// resource cannot be applied is returned.
+pub fn check(required: &Privileges) -> Result<(), String> {
+    let Some(policy) = POLICY.get() else {
+        return Ok(());
+    };
+
+    let missing = policy.missing(&required.capabilities);
+
+    if !missing.is_empty() {
+        return Err(format!(
+            "it requires {}, which is not granted to pullconf",
+            missing
+                .iter()
+                .map(Capability::to_string)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+    }
+
+    if let Some(command) = required
+        .commands
+        .iter()
+        .find(|command| !policy.permits(command))
+    {
+        return Err(format!(
+            "it requires executing {} with sudo, which is not permitted without a password",
+            command.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Read the effective capabilities of this process.
+#[cfg(target_os = "linux")]
+fn capabilities(pid: u32) -> Result<u64, Terminate> {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_capabilities(&status))
+        .ok_or_else(|| {
+            error!(scope = "privileges", pid; "failed to read the capabilities of this process from /proc/self/status");
+            Terminate
+        })
+}
+
+/// Capabilities are specific to Linux, and e.g. FreeBSD has no equivalent
+/// that lets an unprivileged user manage files of other users.
+#[cfg(not(target_os = "linux"))]
+fn capabilities(pid: u32) -> Result<u64, Terminate> {
+    error!(scope = "privileges", pid; "least-privilege mode requires capabilities, which are only available on Linux");
+    Err(Terminate)
+}
+
+/// Parse the effective capabilities from the status of a process.
+#[cfg(any(target_os = "linux", test))]
+fn parse_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_capabilities() {
+        let status = "Name:\tpullconf\nCapInh:\t0000000000000000\nCapEff:\t0000000000000009\n";
+
+        let policy = Policy {
+            capabilities: parse_capabilities(status).unwrap(),
+            commands: Mutex::new(HashMap::new()),
+        };
+
+        assert_eq!(
+            policy.missing(&[
+                Capability::Chown,
+                Capability::DacOverride,
+                Capability::Fowner
+            ]),
+            vec![Capability::DacOverride]
+        );
+        assert!(parse_capabilities("Name:\tpullconf\n").is_none());
+    }
+
+    #[test]
+    fn programs_of_commands() {
+        let mut command = Command::new(SUDO);
+        command.args([
+            "-n",
+            "--",
+            root::CHROOT,
+            "/mnt/target",
+            "/usr/bin/apt-get",
+            "install",
+        ]);
+        assert_eq!(program(&command), "/usr/bin/apt-get");
+
+        let mut command = Command::new(SUDO);
+        command.args(["-n", "--", "/usr/bin/apt-get", "install"]);
+        assert_eq!(program(&command), "/usr/bin/apt-get");
+
+        let mut command = Command::new(root::CHROOT);
+        command.args(["/mnt/target", "/usr/bin/apt-get"]);
+        assert_eq!(program(&command), "/usr/bin/apt-get");
+
+        assert_eq!(
+            program(&Command::new("/usr/bin/apt-get")),
+            "/usr/bin/apt-get"
+        );
+    }
+}
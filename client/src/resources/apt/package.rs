@@ -1,15 +1,35 @@
-use crate::resources::{Action, Resource, ResourceTrait};
+use crate::{
+    facts,
+    platform::{self, Platform},
+    privileges,
+    resources::{self, Action, Resource, ResourceTrait},
+    root,
+};
+use anyhow::Context;
 use common::{
     resources::apt::package::{Ensure, Parameters, Relationships, Version},
-    ResourceMetadata,
+    Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use std::{collections::HashMap, fs, process::Command, str::FromStr};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
 use uuid::Uuid;
 
 const DPKG_QUERY: &str = "/usr/bin/dpkg-query";
 const APT_GET: &str = "/usr/bin/apt-get";
+const APK: &str = "/sbin/apk";
+const PKG: &str = "/usr/sbin/pkg";
+
+/// The number of seconds that apt waits for the dpkg lock, e.g. while
+/// unattended-upgrades is running, or apk and pkg for their database lock
+/// before they give up.
+const LOCK_TIMEOUT: u64 = 600;
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Package {
@@ -17,7 +37,17 @@ pub struct Package {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Package {
@@ -95,7 +125,7 @@ impl ResourceTrait for Package {
 
     fn check_prerequisites(&self, pid: u32) -> Option<Action> {
         fn find(package: &Package, pid: u32, program: &str) -> Option<Action> {
-            match fs::metadata(program) {
+            match root::path(&platform::program(program)).and_then(fs::metadata) {
                 Ok(metadata) => {
                     if metadata.is_file() {
                         None
@@ -134,17 +164,24 @@ impl ResourceTrait for Package {
             }
         }
 
-        let dpkg_query = find(self, pid, DPKG_QUERY);
-        let apt_get = find(self, pid, APT_GET);
+        match platform::get() {
+            Platform::Debian => {
+                let dpkg_query = find(self, pid, DPKG_QUERY);
+                let apt_get = find(self, pid, APT_GET);
 
-        dpkg_query.or(apt_get)
+                dpkg_query.or(apt_get)
+            }
+            // Installed packages are read from the database of apk.
+            Platform::Alpine => find(self, pid, APK),
+            Platform::FreeBsd => find(self, pid, PKG),
+        }
     }
 }
 
 impl Package {
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -162,7 +199,18 @@ impl Package {
                self.repr(),
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      name = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr(),
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -186,13 +234,14 @@ impl Package {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         if let Some(current_version) = self.exists(pid)? {
             match self.parameters.ensure {
                 Ensure::Present => {
@@ -200,19 +249,19 @@ impl Package {
                         .parameters
                         .version
                         .as_ref()
-                        .is_some_and(|version| *version != current_version)
+                        .is_some_and(|version| version.as_str() != current_version)
                     {
-                        self.install(pid, Action::Changed)
+                        self.install(pid, noop, Action::Changed)
                     } else {
                         Ok(Action::Unchanged)
                     }
                 }
-                Ensure::Absent => self.remove(pid, false),
-                Ensure::Purged => self.remove(pid, true),
+                Ensure::Absent => self.remove(pid, noop, false),
+                Ensure::Purged => self.remove(pid, noop, true),
             }
         } else {
             match self.parameters.ensure {
-                Ensure::Present => self.install(pid, Action::Created),
+                Ensure::Present => self.install(pid, noop, Action::Created),
                 Ensure::Absent | Ensure::Purged => Ok(Action::Unchanged),
             }
         }
@@ -221,7 +270,7 @@ impl Package {
     /// Install or up-/downgrade the package.
     /// The `action` parameter is used to return the correct action
     /// according to the context this function is executed in.
-    fn install(&self, pid: u32, action: Action) -> Result<Action, anyhow::Error> {
+    fn install(&self, pid: u32, noop: bool, action: Action) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -229,37 +278,41 @@ impl Package {
             "installing package"
         );
 
-        let mut command = Command::new(APT_GET);
-        command.arg("install");
+        if noop {
+            return Ok(action);
+        }
 
+        let mut command = match platform::get() {
+            Platform::Debian => apt_get("install"),
+            Platform::Alpine => apk("add"),
+            Platform::FreeBsd => pkg("install"),
+        };
+
+        // Both apt-get and apk pin versions as `<name>=<version>`, whereas
+        // pkg expects `<name>-<version>`.
         if let Some(version) = &self.parameters.version {
-            command.arg(&format!("{}={}", self.parameters.name.as_str(), version));
+            let separator = match platform::get() {
+                Platform::FreeBsd => '-',
+                Platform::Debian | Platform::Alpine => '=',
+            };
+
+            command.arg(format!(
+                "{}{}{}",
+                self.parameters.name.as_str(),
+                separator,
+                version
+            ));
         } else {
             command.arg(self.parameters.name.as_str());
         }
 
-        let output = command
-            .arg("--quiet")
-            .arg("--quiet")
-            .arg("--yes")
-            .output()?;
-
-        if !output.status.success() {
-            let s = String::from_utf8_lossy(&output.stderr).to_owned();
-
-            anyhow::bail!(
-                "failed to install package, {} exited with status {}: {}",
-                APT_GET,
-                output.status.code().unwrap(),
-                s.trim_end()
-            );
-        }
+        resources::run(&mut command).context("failed to install package")?;
 
         Ok(action)
     }
 
     /// Remove the package from the system.
-    fn remove(&self, pid: u32, purge: bool) -> Result<Action, anyhow::Error> {
+    fn remove(&self, pid: u32, noop: bool, purge: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -267,37 +320,41 @@ impl Package {
             "removing package"
         );
 
-        let mut command = Command::new(APT_GET);
-        command.arg("remove");
+        if noop {
+            return Ok(Action::Deleted);
+        }
+
+        let mut command = match platform::get() {
+            Platform::Debian => apt_get("remove"),
+            Platform::Alpine => apk("del"),
+            Platform::FreeBsd => pkg("delete"),
+        };
 
-        if purge {
+        // apk deletes modified configuration files with `--purge` as well,
+        // pkg always keeps them, so purging is the same as removing there.
+        if purge && platform::get() != Platform::FreeBsd {
             command.arg("--purge");
         }
 
-        let output = command
-            .arg("--quiet")
-            .arg("--quiet")
-            .arg("--yes")
-            .arg(self.parameters.name.as_str())
-            .output()?;
-
-        if !output.status.success() {
-            let s = String::from_utf8_lossy(&output.stderr).to_owned();
-
-            anyhow::bail!(
-                "failed to remove package, {} exited with status {}: {}",
-                APT_GET,
-                output.status.code().unwrap(),
-                s.trim_end()
-            );
-        }
+        command.arg(self.parameters.name.as_str());
+
+        resources::run(&mut command).context("failed to remove package")?;
 
         Ok(Action::Deleted)
     }
 
-    /// Try to find a package by this name within the system.
-    fn exists(&self, pid: u32) -> Result<Option<Version>, anyhow::Error> {
-        let mut command = Command::new(DPKG_QUERY);
+    /// Try to find a package by this name within the system and return its
+    /// version.
+    fn exists(&self, pid: u32) -> Result<Option<String>, anyhow::Error> {
+        match platform::get() {
+            Platform::Debian => self.query_dpkg(pid),
+            Platform::Alpine => self.query_apk(pid),
+            Platform::FreeBsd => self.query_pkg(pid),
+        }
+    }
+
+    fn query_dpkg(&self, pid: u32) -> Result<Option<String>, anyhow::Error> {
+        let mut command = root::command(platform::program(DPKG_QUERY));
         command.args(["-W", "-f", "'${VERSION}'", self.parameters.name.as_str()]);
 
         debug!(
@@ -311,11 +368,11 @@ impl Package {
 
         let output = command.output()?;
 
-        let s = String::from_utf8_lossy(&output.stdout).to_owned();
+        let s = String::from_utf8_lossy(&output.stdout).into_owned();
 
         if output.status.success() {
             match Version::from_str(s.trim_start_matches('\'').trim_end_matches('\'')) {
-                Ok(version) => Ok(Some(version)),
+                Ok(version) => Ok(Some(version.to_string())),
                 Err(error) => anyhow::bail!(
                     "failed to parse output from dpkg-query as package version: {}",
                     error
@@ -325,4 +382,117 @@ impl Package {
             Ok(None)
         }
     }
+
+    /// Read the version of the package from the database of apk, as the
+    /// output of apk itself is meant for humans. Versions of Alpine, e.g.
+    /// `1.2.4_git20230717-r4`, are not necessarily valid Debian versions
+    /// and are thus compared as they are.
+    fn query_apk(&self, pid: u32) -> Result<Option<String>, anyhow::Error> {
+        let database = root::path(Path::new(facts::APK_DATABASE))
+            .with_context(|| format!("failed to resolve {}", facts::APK_DATABASE))?;
+
+        debug!(
+            pid,
+            resource = self.kind(),
+            name = self.display();
+            "reading installed packages from {}",
+            database.display()
+        );
+
+        let contents = fs::read_to_string(&database)
+            .with_context(|| format!("failed to read {}", database.display()))?;
+
+        Ok(facts::parse_apk_database(&contents)
+            .into_iter()
+            .find(|package| package.name == self.parameters.name.as_str())
+            .map(|package| package.version))
+    }
+
+    /// Query the version of the package from pkg, which exits with a
+    /// non-zero code if the package is not installed.
+    fn query_pkg(&self, pid: u32) -> Result<Option<String>, anyhow::Error> {
+        let mut command = root::command(platform::program(PKG));
+        command.args(["query", "%v", self.parameters.name.as_str()]);
+
+        debug!(
+            pid,
+            resource = self.kind(),
+            name = self.display();
+            "executing {:?} with args {:?}",
+            command.get_program(),
+            command.get_args()
+        );
+
+        let output = command.output()?;
+
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        Ok(Some(version).filter(|version| output.status.success() && !version.is_empty()))
+    }
+}
+
+/// The package manager that `apt::package` resources execute on this
+/// platform, e.g. to check whether sudo permits it.
+pub(in crate::resources) fn package_manager() -> PathBuf {
+    match platform::get() {
+        Platform::Debian => platform::program(APT_GET),
+        Platform::Alpine => platform::program(APK),
+        Platform::FreeBsd => platform::program(PKG),
+    }
+}
+
+/// Prepare an apt-get command that never prompts, keeps locally modified
+/// configuration files and waits for the dpkg lock instead of failing
+/// immediately if another process holds it.
+fn apt_get(subcommand: &str) -> Command {
+    let mut command = privileges::command(platform::program(APT_GET));
+
+    command
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .env("APT_LISTCHANGES_FRONTEND", "none")
+        .arg("--option")
+        .arg(format!("DPkg::Lock::Timeout={}", LOCK_TIMEOUT))
+        .arg("--option")
+        .arg("Dpkg::Options::=--force-confdef")
+        .arg("--option")
+        .arg("Dpkg::Options::=--force-confold")
+        .arg("--quiet")
+        .arg("--quiet")
+        .arg("--yes")
+        .arg(subcommand);
+
+    command
+}
+
+/// Prepare an apk command that neither prompts nor prints progress bars and
+/// waits for the database lock like apt-get does.
+fn apk(subcommand: &str) -> Command {
+    let mut command = privileges::command(platform::program(APK));
+
+    command
+        .arg("--no-interactive")
+        .arg("--no-progress")
+        .arg("--quiet")
+        .arg("--wait")
+        .arg(LOCK_TIMEOUT.to_string())
+        .arg(subcommand);
+
+    command
+}
+
+/// Prepare a pkg command that never prompts, not even to bootstrap pkg
+/// itself, and retries to acquire the database lock for as long as apt-get
+/// waits for the dpkg lock.
+fn pkg(subcommand: &str) -> Command {
+    let mut command = privileges::command(platform::program(PKG));
+
+    command
+        .env("ASSUME_ALWAYS_YES", "yes")
+        .env("LOCK_WAIT", "1")
+        .env("LOCK_RETRIES", LOCK_TIMEOUT.to_string())
+        .arg(subcommand)
+        .arg("--quiet")
+        .arg("--yes");
+
+    command
 }
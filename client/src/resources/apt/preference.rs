@@ -1,8 +1,13 @@
-use crate::resources::{Action, Resource, ResourceTrait};
+use crate::{
+    resources::{Action, Resource, ResourceTrait},
+    root,
+    tmp::TempFile,
+    util::log_diff,
+};
 use anyhow::Context;
 use common::{
     resources::apt::preference::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -11,6 +16,7 @@ use std::{
     collections::HashMap,
     fs,
     io::{self, Read},
+    path::PathBuf,
     time::SystemTime,
 };
 use uuid::Uuid;
@@ -21,7 +27,17 @@ pub struct Preference {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Preference {
@@ -99,9 +115,14 @@ impl ResourceTrait for Preference {
 }
 
 impl Preference {
+    /// The preferences file on this system, see `root::path`.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.target)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -114,7 +135,18 @@ impl Preference {
                self.repr(),
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      name = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -138,14 +170,15 @@ impl Preference {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
-        let file = match fs::File::open(&*self.parameters.target) {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
+        let file = match self.local_path().and_then(fs::File::open) {
             Ok(file) => Some(file),
             Err(error) if error.kind() == io::ErrorKind::NotFound => None,
             Err(error) => anyhow::bail!(
@@ -174,12 +207,12 @@ impl Preference {
                         // Compute the checksum from the desired file contents.
                         let checksum = format!("{:x}", Sha256::digest(content.as_bytes()));
 
-                        // Compute the checksum of the current file contents.
-                        let current_checksum = {
-                            let mut data = vec![];
-                            file.read_to_end(&mut data)?;
-                            format!("{:x}", Sha256::digest(data))
-                        };
+                        // Compute the checksum of the current file contents,
+                        // which are kept in order to log the difference to the
+                        // desired contents.
+                        let mut current = vec![];
+                        file.read_to_end(&mut current)?;
+                        let current_checksum = format!("{:x}", Sha256::digest(&current));
 
                         // If the current and desired content checksums do not
                         // match, update the file.
@@ -188,10 +221,10 @@ impl Preference {
                         } else {
                             let mtime = file.metadata()?.modified()?;
 
-                            self.update(pid, content, mtime)
+                            self.update(pid, noop, &current, content, mtime)
                         }
                     }
-                    None => match self.create(pid, content) {
+                    None => match self.create(pid, noop, content) {
                         Ok(action) => Ok(action),
                         Err(error) => {
                             debug!(pid,
@@ -200,23 +233,37 @@ impl Preference {
                                    "deleting file `{}` as at least one condition failed",
                                    self.parameters.target.display()
                             );
-                            fs::remove_file(&*self.parameters.target).ok();
+                            self.local_path().and_then(fs::remove_file).ok();
                             Err(error)
                         }
                     },
                 }
             }
             Ensure::Absent => match file {
-                Some(file) => self.delete(pid, file.metadata()?),
+                Some(file) => self.delete(pid, noop, file.metadata()?),
                 None => Ok(Action::Unchanged),
             },
         }
     }
 
+    fn log_diff(&self, pid: u32, noop: bool, current: &[u8], desired: &[u8]) {
+        log_diff(
+            pid,
+            noop,
+            self.kind(),
+            &self.parameters.target,
+            current,
+            desired,
+            false,
+        );
+    }
+
     /// Update the target file contents.
     fn update(
         &self,
         pid: u32,
+        noop: bool,
+        current: &[u8],
         content: String,
         mtime: SystemTime,
     ) -> Result<Action, anyhow::Error> {
@@ -227,11 +274,17 @@ impl Preference {
                self.parameters.target.display()
         );
 
-        let tmp_path = format!("/tmp/{}.pullconf", self.parameters.name);
+        self.log_diff(pid, noop, current, content.as_bytes());
 
-        fs::write(&tmp_path, content.as_bytes())?;
+        if noop {
+            return Ok(Action::Changed);
+        }
 
-        if fs::metadata(&self.parameters.target)
+        let replacement = TempFile::write(&self.parameters.name.to_string(), content.as_bytes())?;
+
+        if self
+            .local_path()
+            .and_then(fs::metadata)
             .context("failed to query target file metadata")?
             .modified()
             .is_ok_and(|_mtime| _mtime == mtime)
@@ -241,17 +294,18 @@ impl Preference {
                 resource = self.kind(),
                 name = self.display();
                 "renaming replacement file `{}` to original target file {}",
-                tmp_path,
+                replacement.path().display(),
                 self.parameters.target.display()
             );
 
-            fs::rename(tmp_path, &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         } else {
             anyhow::bail!(
                 "target file `{}` changed before replacement file `{}` could be renamed",
                 self.parameters.target.display(),
-                tmp_path
+                replacement.path().display()
             );
         }
 
@@ -259,7 +313,7 @@ impl Preference {
     }
 
     /// Create the target file.
-    fn create(&self, pid: u32, content: String) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool, content: String) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                name = self.display();
@@ -267,14 +321,25 @@ impl Preference {
                self.parameters.target.display()
         );
 
-        fs::write(&self.parameters.target, content)
+        self.log_diff(pid, noop, &[], content.as_bytes());
+
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        fs::write(self.local_path()?, content)
             .context("failed to write contents to target file")?;
 
         Ok(Action::Created)
     }
 
     /// Delete the target file.
-    fn delete(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn delete(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                name = self.display();
@@ -283,7 +348,13 @@ impl Preference {
         );
 
         if metadata.is_file() {
-            fs::remove_file(&*self.parameters.target).context("failed to delete target file")?
+            if noop {
+                return Ok(Action::Deleted);
+            }
+
+            self.local_path()
+                .and_then(fs::remove_file)
+                .context("failed to delete target file")?
         } else {
             anyhow::bail!(
                 "failed to delete target `{}` as it is not a file",
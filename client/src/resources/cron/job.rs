@@ -1,8 +1,14 @@
-use crate::resources::{Action, Resource, ResourceTrait};
+use crate::{
+    resources::{Action, Resource, ResourceTrait},
+    root,
+    tmp::TempFile,
+    util::log_diff,
+};
 use anyhow::Context;
 use common::{
+    report::ResourceResult,
     resources::cron::job::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -10,18 +16,37 @@ use sha2::{Digest, Sha256};
 use std::{
     collections::HashMap,
     fs,
-    io::{self, Read},
-    time::SystemTime,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
 };
 use uuid::Uuid;
 
+/// The directory in which pullconfd places the files of cron jobs.
+const CRON_DIR: &str = "/etc/cron.d";
+
+/// The first line of every file that pullconf writes for a cron job, which
+/// tells them apart from the files of packages or administrators when cron
+/// jobs are purged, see `purge`.
+const MARKER: &str = "# Managed by pullconf, manual changes are overwritten.";
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Job {
     pub id: Uuid,
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Job {
@@ -99,9 +124,14 @@ impl ResourceTrait for Job {
 }
 
 impl Job {
+    /// The crontab file on this system, see `root::path`.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.target)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -114,7 +144,18 @@ impl Job {
                self.repr(),
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      name = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -138,14 +179,15 @@ impl Job {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
-        let file = match fs::File::open(&*self.parameters.target) {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
+        let file = match self.local_path().and_then(fs::File::open) {
             Ok(file) => Some(file),
             Err(error) if error.kind() == io::ErrorKind::NotFound => None,
             Err(error) => anyhow::bail!(
@@ -160,7 +202,9 @@ impl Job {
                 // Build the desired file content from the resource parameters.
                 let mut content = format!(
                     "{} {} {}\n",
-                    self.parameters.schedule, self.parameters.user, self.parameters.command
+                    self.parameters.schedule.cron(),
+                    self.parameters.user,
+                    self.parameters.command
                 );
 
                 for item in &self.parameters.environment {
@@ -178,6 +222,8 @@ impl Job {
                     content.insert_str(0, &line);
                 }
 
+                content.insert_str(0, &format!("{}\n", MARKER));
+
                 // Create or update the file, depending on the current file
                 // state.
                 match file {
@@ -185,12 +231,12 @@ impl Job {
                         // Compute the checksum from the desired file contents.
                         let checksum = format!("{:x}", Sha256::digest(content.as_bytes()));
 
-                        // Compute the checksum of the current file contents.
-                        let current_checksum = {
-                            let mut data = vec![];
-                            file.read_to_end(&mut data)?;
-                            format!("{:x}", Sha256::digest(data))
-                        };
+                        // Compute the checksum of the current file contents,
+                        // which are kept in order to log the difference to the
+                        // desired contents.
+                        let mut current = vec![];
+                        file.read_to_end(&mut current)?;
+                        let current_checksum = format!("{:x}", Sha256::digest(&current));
 
                         // If the current and desired content checksums do not
                         // match, update the file.
@@ -199,10 +245,10 @@ impl Job {
                         } else {
                             let mtime = file.metadata()?.modified()?;
 
-                            self.update(pid, content, mtime)
+                            self.update(pid, noop, &current, content, mtime)
                         }
                     }
-                    None => match self.create(pid, content) {
+                    None => match self.create(pid, noop, content) {
                         Ok(action) => Ok(action),
                         Err(error) => {
                             debug!(pid,
@@ -211,23 +257,37 @@ impl Job {
                                    "deleting file `{}` as at least one condition failed",
                                    self.parameters.target.display()
                             );
-                            fs::remove_file(&*self.parameters.target).ok();
+                            self.local_path().and_then(fs::remove_file).ok();
                             Err(error)
                         }
                     },
                 }
             }
             Ensure::Absent => match file {
-                Some(file) => self.delete(pid, file.metadata()?),
+                Some(file) => self.delete(pid, noop, file.metadata()?),
                 None => Ok(Action::Unchanged),
             },
         }
     }
 
+    fn log_diff(&self, pid: u32, noop: bool, current: &[u8], desired: &[u8]) {
+        log_diff(
+            pid,
+            noop,
+            self.kind(),
+            &self.parameters.target,
+            current,
+            desired,
+            false,
+        );
+    }
+
     /// Update the target file contents.
     fn update(
         &self,
         pid: u32,
+        noop: bool,
+        current: &[u8],
         content: String,
         mtime: SystemTime,
     ) -> Result<Action, anyhow::Error> {
@@ -238,11 +298,17 @@ impl Job {
                self.parameters.target.display()
         );
 
-        let tmp_path = format!("/tmp/{}.pullconf", self.parameters.name);
+        self.log_diff(pid, noop, current, content.as_bytes());
+
+        if noop {
+            return Ok(Action::Changed);
+        }
 
-        fs::write(&tmp_path, content.as_bytes())?;
+        let replacement = TempFile::write(&self.parameters.name.to_string(), content.as_bytes())?;
 
-        if fs::metadata(&self.parameters.target)
+        if self
+            .local_path()
+            .and_then(fs::metadata)
             .context("failed to query target file metadata")?
             .modified()
             .is_ok_and(|_mtime| _mtime == mtime)
@@ -252,17 +318,18 @@ impl Job {
                 resource = self.kind(),
                 name = self.display();
                 "renaming replacement file `{}` to original target file {}",
-                tmp_path,
+                replacement.path().display(),
                 self.parameters.target.display()
             );
 
-            fs::rename(tmp_path, &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         } else {
             anyhow::bail!(
                 "target file `{}` changed before replacement file `{}` could be renamed",
                 self.parameters.target.display(),
-                tmp_path
+                replacement.path().display()
             );
         }
 
@@ -270,7 +337,7 @@ impl Job {
     }
 
     /// Create the target file.
-    fn create(&self, pid: u32, content: String) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool, content: String) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                name = self.display();
@@ -278,14 +345,25 @@ impl Job {
                self.parameters.target.display()
         );
 
-        fs::write(&self.parameters.target, content)
+        self.log_diff(pid, noop, &[], content.as_bytes());
+
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        fs::write(self.local_path()?, content)
             .context("failed to write contents to target file")?;
 
         Ok(Action::Created)
     }
 
     /// Delete the target file.
-    fn delete(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn delete(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                name = self.display();
@@ -294,7 +372,13 @@ impl Job {
         );
 
         if metadata.is_file() {
-            fs::remove_file(&*self.parameters.target).context("failed to delete target file")?
+            if noop {
+                return Ok(Action::Deleted);
+            }
+
+            self.local_path()
+                .and_then(fs::remove_file)
+                .context("failed to delete target file")?
         } else {
             anyhow::bail!(
                 "failed to delete target `{}` as it is not a file",
@@ -305,3 +389,135 @@ impl Job {
         Ok(Action::Deleted)
     }
 }
+
+/// Delete the files of cron jobs that pullconf wrote, but whose resources
+/// are no longer part of the resource catalog, e.g. as they were removed
+/// from the configuration of pullconfd. Files in `/etc/cron.d` without the
+/// marker of pullconf, e.g. those of packages, are left as they are.
+/// `managed` holds the targets of every cron job in the catalog.
+pub fn purge(pid: u32, noop: bool, managed: &[&Path]) -> Vec<ResourceResult> {
+    let kind = "cron::job";
+    let entries = root::path(Path::new(CRON_DIR))
+        .and_then(|directory| Ok((fs::read_dir(&directory)?, directory)));
+
+    let (entries, directory) = match entries {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return vec![],
+        Err(error) => {
+            let action = Action::Failed;
+
+            error!(pid,
+                   resource = kind,
+                   result:% = action;
+                   "failed to purge cron jobs, as {} cannot be read: {}",
+                   CRON_DIR,
+                   error
+            );
+
+            return vec![ResourceResult {
+                id: Uuid::nil(),
+                resource: format!("{} `{}`", kind, CRON_DIR),
+                result: action,
+                duration: 0.0,
+                failure: Some(format!("failed to read {}: {}", CRON_DIR, error)),
+            }];
+        }
+    };
+
+    let mut names = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| !managed.contains(&Path::new(CRON_DIR).join(name).as_path()))
+        .filter(|name| is_marked(&directory.join(name)))
+        .collect::<Vec<String>>();
+
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let start = Instant::now();
+            let repr = format!("{} `{}`", kind, name);
+
+            let (action, failure) = if noop {
+                (Action::Deleted, None)
+            } else {
+                match fs::remove_file(directory.join(&name)) {
+                    Ok(()) => (Action::Deleted, None),
+                    Err(error) => (
+                        Action::Failed,
+                        Some(format!("failed to delete target file: {}", error)),
+                    ),
+                }
+            };
+
+            match &failure {
+                Some(failure) => error!(pid,
+                                        resource = kind,
+                                        name = name,
+                                        result:% = action;
+                                        "failed to purge {}: {}",
+                                        repr,
+                                        failure
+                ),
+                None if noop => info!(pid,
+                                      resource = kind,
+                                      name = name,
+                                      result:% = action;
+                                      "not purging {} in noop mode, it is not part of the resource catalog",
+                                      repr
+                ),
+                None => info!(pid,
+                              resource = kind,
+                              name = name,
+                              result:% = action;
+                              "purged {}, as it is not part of the resource catalog",
+                              repr
+                ),
+            }
+
+            ResourceResult {
+                id: Uuid::nil(),
+                resource: repr,
+                result: action,
+                duration: start.elapsed().as_secs_f64(),
+                failure,
+            }
+        })
+        .collect()
+}
+
+/// Check whether pullconf wrote a file, i.e. whether its first line is the
+/// marker.
+fn is_marked(path: &Path) -> bool {
+    let mut line = String::new();
+
+    fs::File::open(path)
+        .and_then(|file| BufReader::new(file).read_line(&mut line))
+        .is_ok_and(|_| line.trim_end() == MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_marker() {
+        let directory = std::env::temp_dir().join(format!("pullconf-cron-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        fs::write(
+            directory.join("managed"),
+            format!("{}\n* * * * * root true\n", MARKER),
+        )
+        .unwrap();
+        fs::write(directory.join("package"), "* * * * * root true\n").unwrap();
+
+        assert!(is_marked(&directory.join("managed")));
+        assert!(!is_marked(&directory.join("package")));
+        assert!(!is_marked(&directory.join("missing")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
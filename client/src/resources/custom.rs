@@ -0,0 +1,382 @@
+use super::{Action, Resource, ResourceTrait};
+use crate::{privileges, root};
+use anyhow::Context;
+use common::{
+    resources::custom::{Parameters, Relationships},
+    Priority, ResourceMetadata,
+};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use ureq::serde_json;
+use uuid::Uuid;
+
+/// Directory with the executables that apply custom resources, each named
+/// after the provider that it implements. With an alternate root the
+/// providers of the target system are executed.
+const PROVIDERS_DIR: &str = "/usr/lib/pullconf/providers";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Custom {
+    pub id: Uuid,
+    pub parameters: Parameters,
+    pub relationships: Relationships,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
+}
+
+/// The JSON document that a provider receives on stdin.
+#[derive(Serialize)]
+struct Request<'a> {
+    id: Uuid,
+    noop: bool,
+    #[serde(flatten)]
+    parameters: &'a Parameters,
+    dependencies: Vec<Dependency<'a>>,
+}
+
+/// The result of a dependency in this run, so that a provider can act on
+/// what its dependencies did, e.g. only re-index a database if its data
+/// directory was just created.
+#[derive(Serialize)]
+struct Dependency<'a> {
+    id: Uuid,
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: String,
+    action: Action,
+    /// The time it took to apply the dependency in seconds.
+    duration: f64,
+}
+
+impl<'a> From<&'a Resource> for Dependency<'a> {
+    fn from(resource: &'a Resource) -> Self {
+        Self {
+            id: resource.id(),
+            kind: resource.kind(),
+            name: resource.display(),
+            action: *resource.action(),
+            duration: resource.duration(),
+        }
+    }
+}
+
+/// The JSON document that a provider prints to stdout once it is done.
+#[derive(Deserialize)]
+struct Response {
+    action: String,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+impl ResourceTrait for Custom {
+    fn kind(&self) -> &str {
+        self.parameters.provider.as_str()
+    }
+
+    fn display(&self) -> String {
+        self.parameters.name.clone()
+    }
+
+    fn id(&self) -> Uuid {
+        self.id
+    }
+
+    fn dependencies(&self) -> &[ResourceMetadata] {
+        self.relationships.requires.as_slice()
+    }
+
+    fn maybe_return_early(
+        &self,
+        pid: u32,
+        applied_resources: &HashMap<Uuid, Resource>,
+    ) -> Option<Action> {
+        if let Some(dependency) = self.find_failed_dependency(applied_resources) {
+            let action = Action::Skipped;
+
+            warn!(pid,
+                  resource = self.kind(),
+                  name = self.display(),
+                  result:% = action;
+                  "skipping {} as {} has failed to apply",
+                  self.repr(),
+                  dependency.repr()
+            );
+
+            return Some(action);
+        }
+
+        if let Some(dependency) = self.find_skipped_dependency(applied_resources) {
+            let action = Action::Skipped;
+
+            warn!(pid,
+                  resource = self.kind(),
+                  name = self.display(),
+                  result:% = action;
+                  "skipping {} as {} has been skipped",
+                  self.repr(),
+                  dependency.repr()
+            );
+
+            return Some(action);
+        }
+
+        if self.parameters.ensure.is_present() {
+            if let Some(dependency) = self.find_absent_dependency(applied_resources) {
+                let action = Action::Failed;
+
+                error!(
+                    pid,
+                    resource = self.kind(),
+                    name = self.display(),
+                    result:% = action;
+                    "cannot apply {} as {} is set to absent",
+                    self.repr(),
+                    dependency.repr()
+                );
+
+                return Some(action);
+            }
+        }
+
+        None
+    }
+
+    fn check_prerequisites(&self, pid: u32) -> Option<Action> {
+        let program = self.program();
+
+        let error = match root::path(&program).and_then(fs::metadata) {
+            Ok(metadata) if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 => {
+                return None
+            }
+            Ok(_) => "is not an executable file".to_string(),
+            Err(error) => format!("cannot be accessed: {}", error),
+        };
+
+        let action = Action::Failed;
+
+        error!(
+            pid,
+            resource = self.kind(),
+            name = self.display(),
+            result:% = action;
+            "cannot apply {} as provider `{}` {}",
+            self.repr(),
+            program.display(),
+            error
+        );
+
+        Some(action)
+    }
+}
+
+impl Custom {
+    /// A wrapper around the actual apply function. This ensure that some
+    /// meaningful log messages are printed and pre-checks are done.
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
+        if let Some(action) = self.maybe_return_early(pid, applied_resources) {
+            self.action = action;
+            return;
+        }
+
+        if let Some(action) = self.check_prerequisites(pid) {
+            self.action = action;
+            return;
+        }
+
+        debug!(pid,
+               resource = self.kind(),
+               name = self.display();
+               "applying {}",
+               self.repr(),
+        );
+
+        match self._apply(noop, applied_resources) {
+            Ok((action, message)) => {
+                let message = message.map(|m| format!(": {}", m)).unwrap_or_default();
+
+                if noop {
+                    info!(pid,
+                          resource = self.kind(),
+                          name = self.display(),
+                          result:% = action;
+                          "not applying {} in noop mode{}",
+                          self.repr(),
+                          message
+                    );
+                } else {
+                    info!(pid,
+                          resource = self.kind(),
+                          name = self.display(),
+                          result:% = action;
+                          "successfully applied {}{}",
+                          self.repr(),
+                          message
+                    );
+                }
+
+                self.action = action;
+            }
+            Err(error) => {
+                let action = Action::Failed;
+
+                error!(pid,
+                       resource = self.kind(),
+                       name = self.display(),
+                       result:% = action;
+                       "failed to apply {}: {:#}",
+                       self.repr(),
+                       error
+                );
+
+                self.failure = Some(format!("{:#}", error));
+                self.action = action;
+            }
+        }
+    }
+
+    /// Run the provider, pass the resource to it and evaluate the action
+    /// that it reports. In noop mode the provider is expected to report
+    /// what it would do without changing anything.
+    fn _apply(
+        &self,
+        noop: bool,
+        applied_resources: &HashMap<Uuid, Resource>,
+    ) -> Result<(Action, Option<String>), anyhow::Error> {
+        let program = self.program();
+
+        let request = serde_json::to_vec(&Request {
+            id: self.id,
+            noop,
+            parameters: &self.parameters,
+            dependencies: self
+                .applied_dependencies(applied_resources)
+                .map(Dependency::from)
+                .collect(),
+        })?;
+
+        let mut child = privileges::command(&program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run provider `{}`", program.display()))?;
+
+        // The provider may exit without reading its input, so a failure to
+        // write is only reported if the provider does not fail itself.
+        let written = child
+            .stdin
+            .take()
+            .map(|mut stdin| stdin.write_all(&request));
+
+        let output = child.wait_with_output()?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "provider `{}` exited with {}: {}",
+                program.display(),
+                output.status,
+                super::tail(&output).unwrap_or_default()
+            );
+        }
+
+        if let Some(Err(error)) = written {
+            anyhow::bail!("failed to pass resource to provider: {}", error);
+        }
+
+        parse_response(&output.stdout)
+    }
+
+    pub(super) fn program(&self) -> PathBuf {
+        Path::new(PROVIDERS_DIR).join(self.parameters.provider.as_str())
+    }
+}
+
+/// Parse the output of a provider, e.g. `{"action": "changed"}`, into an
+/// action and an optional message that explains it.
+fn parse_response(stdout: &[u8]) -> Result<(Action, Option<String>), anyhow::Error> {
+    let response: Response =
+        serde_json::from_slice(stdout).context("provider returned an invalid response")?;
+
+    let action = match response.action.as_str() {
+        "unchanged" => Action::Unchanged,
+        "created" => Action::Created,
+        "changed" => Action::Changed,
+        "deleted" => Action::Deleted,
+        "failed" => anyhow::bail!(
+            "{}",
+            response
+                .message
+                .as_deref()
+                .unwrap_or("provider reported a failure")
+        ),
+        other => anyhow::bail!("provider returned an unknown action `{}`", other),
+    };
+
+    Ok((action, response.message.filter(|m| !m.is_empty())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_response() {
+        let (action, message) =
+            parse_response(br#"{"action": "changed", "message": "granted privileges"}"#).unwrap();
+
+        assert_eq!(action, Action::Changed);
+        assert_eq!(message.as_deref(), Some("granted privileges"));
+
+        assert!(parse_response(br#"{"action": "unchanged"}"#)
+            .is_ok_and(|(action, message)| action == Action::Unchanged && message.is_none()));
+        assert!(
+            parse_response(br#"{"action": "failed", "message": "no such role"}"#)
+                .is_err_and(|error| error.to_string() == "no such role")
+        );
+        assert!(parse_response(br#"{"action": "restarted"}"#).is_err());
+        assert!(parse_response(b"done").is_err());
+    }
+
+    #[test]
+    fn dependency_result() {
+        let mut resource = serde_json::from_value::<Resource>(serde_json::json!({
+            "type": "group",
+            "id": Uuid::nil(),
+            "parameters": { "ensure": "present", "name": "postgres", "system": true },
+            "relationships": { "requires": [] }
+        }))
+        .unwrap();
+
+        resource.set_action(Action::Created);
+        resource.set_duration(0.25);
+
+        assert_eq!(
+            serde_json::to_value(Dependency::from(&resource)).unwrap(),
+            serde_json::json!({
+                "id": Uuid::nil(),
+                "type": "group",
+                "name": "postgres",
+                "action": "created",
+                "duration": 0.25
+            })
+        );
+    }
+}
@@ -1,9 +1,9 @@
 use super::{Action, Resource, ResourceTrait};
-use crate::util::uid_and_gid;
+use crate::{root, util::uid_and_gid};
 use anyhow::Context;
 use common::{
     resources::directory::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -12,6 +12,7 @@ use std::{
     default::Default,
     fs, io,
     os::unix::fs::{chown, MetadataExt},
+    path::PathBuf,
 };
 use uuid::Uuid;
 
@@ -21,7 +22,17 @@ pub struct Directory {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Directory {
@@ -99,9 +110,14 @@ impl ResourceTrait for Directory {
 }
 
 impl Directory {
+    /// The path of the directory on this system, see `root::path`.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.path)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -114,7 +130,18 @@ impl Directory {
                self.repr()
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      path = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr(),
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -138,6 +165,7 @@ impl Directory {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
@@ -146,8 +174,8 @@ impl Directory {
     /// Apply this resource's configuration. This function can be called repeatedly
     /// and produce the same result if neither the configuration nor the actual
     /// directory in the file system change.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
-        let metadata = match fs::metadata(&*self.parameters.path) {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
+        let metadata = match self.local_path().and_then(fs::metadata) {
             Ok(metadata) => Some(metadata),
             Err(error) if error.kind() == io::ErrorKind::NotFound => None,
             Err(error) => anyhow::bail!("failed to query directory metadata: {:#}", error),
@@ -158,7 +186,7 @@ impl Directory {
                 Ensure::Present => {
                     // When some error occurs during directory creation it can be safely
                     // deleted again (cleaned up) as it did not exist in the first place.
-                    match self.create(pid) {
+                    match self.create(pid, noop) {
                         Ok(action) => Ok(action),
                         Err(error) => {
                             debug!(pid,
@@ -167,7 +195,7 @@ impl Directory {
                                    "deleting {} as at least one condition failed",
                                    self.repr(),
                             );
-                            fs::remove_dir(&*self.parameters.path).ok();
+                            self.local_path().and_then(fs::remove_dir).ok();
                             Err(error)
                         }
                     }
@@ -175,15 +203,20 @@ impl Directory {
                 Ensure::Absent => Ok(Action::Unchanged),
             },
             Some(metadata) => match self.parameters.ensure {
-                Ensure::Present => self.maybe_update(pid, metadata),
-                Ensure::Absent => self.delete(pid, metadata),
+                Ensure::Present => self.maybe_update(pid, noop, metadata),
+                Ensure::Absent => self.delete(pid, noop, metadata),
             },
         }
     }
 
     /// Change the directory's ownership parameters if the actual ownership
     /// configuration in the file system differ from the desired state.
-    fn maybe_update(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn maybe_update(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
@@ -207,26 +240,34 @@ impl Directory {
                    gid
             );
 
-            chown(&*self.parameters.path, Some(uid), Some(gid))
-                .context("failed to set directory owner and group")?;
+            if !noop {
+                chown(self.local_path()?, Some(uid), Some(gid))
+                    .context("failed to set directory owner and group")?;
+            }
 
             action = Action::Changed;
         }
 
         if self.parameters.purge && !self.relationships.children.is_empty() {
-            for entry in fs::read_dir(&*self.parameters.path)? {
+            for entry in self.local_path().and_then(fs::read_dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 let kind = entry.file_type()?;
 
+                // Children are managed by their path in the target system.
+                let child_path = self.parameters.path.join(entry.file_name());
+
                 if kind.is_dir() {
                     if !self
                         .relationships
                         .children
                         .iter()
-                        .any(|child| child.is_dir(&path))
+                        .any(|child| child.is_dir(&child_path))
                     {
-                        fs::remove_dir_all(path)?;
+                        if !noop {
+                            fs::remove_dir_all(path)?;
+                        }
+
                         action = Action::Changed;
                     }
                 } else if kind.is_file() {
@@ -234,21 +275,26 @@ impl Directory {
                         .relationships
                         .children
                         .iter()
-                        .any(|child| child.is_file(&path))
+                        .any(|child| child.is_file(&child_path))
                     {
-                        fs::remove_file(path)?;
+                        if !noop {
+                            fs::remove_file(path)?;
+                        }
+
                         action = Action::Changed;
                     }
-                } else if kind.is_symlink() {
-                    if !self
+                } else if kind.is_symlink()
+                    && !self
                         .relationships
                         .children
                         .iter()
-                        .any(|child| child.is_symlink(&path))
-                    {
+                        .any(|child| child.is_symlink(&child_path))
+                {
+                    if !noop {
                         fs::remove_file(path)?;
-                        action = Action::Changed;
                     }
+
+                    action = Action::Changed;
                 }
             }
         }
@@ -257,16 +303,22 @@ impl Directory {
     }
 
     /// Create the directory and set ownership parameters.
-    fn create(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
                "directory does not exist, creating new empty directory"
         );
 
+        if noop {
+            return Ok(Action::Created);
+        }
+
         let (uid, gid) = uid_and_gid(&self.parameters.owner, &self.parameters.group)?;
 
-        fs::create_dir(&*self.parameters.path).context("failed to create directory")?;
+        self.local_path()
+            .and_then(fs::create_dir)
+            .context("failed to create directory")?;
 
         debug!(pid,
                resource = self.kind(),
@@ -276,14 +328,19 @@ impl Directory {
                gid
         );
 
-        chown(&*self.parameters.path, Some(uid), Some(gid))
+        chown(self.local_path()?, Some(uid), Some(gid))
             .context("failed to set directory owner and group")?;
 
         Ok(Action::Created)
     }
 
     // Recursively (!) delete this directory.
-    fn delete(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn delete(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
@@ -291,7 +348,13 @@ impl Directory {
         );
 
         if metadata.is_dir() {
-            fs::remove_dir_all(&*self.parameters.path).context("failed to delete directory")?
+            if noop {
+                return Ok(Action::Deleted);
+            }
+
+            self.local_path()
+                .and_then(fs::remove_dir_all)
+                .context("failed to delete directory")?
         } else {
             anyhow::bail!("failed to delete resource as it is not a directory")
         }
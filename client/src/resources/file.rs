@@ -1,9 +1,12 @@
 use super::{Action, Resource, ResourceTrait};
-use crate::util::uid_and_gid;
+use crate::{
+    assets, root,
+    util::{log_diff, uid_and_gid},
+};
 use anyhow::Context;
 use common::{
     resources::file::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata, SafePathBuf,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -13,6 +16,7 @@ use std::{
     fs,
     io::{self, Read, Write},
     os::unix::fs::{chown, MetadataExt, PermissionsExt},
+    path::PathBuf,
 };
 use ureq::Agent;
 use url::Url;
@@ -24,7 +28,17 @@ pub struct File {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for File {
@@ -102,11 +116,18 @@ impl ResourceTrait for File {
 }
 
 impl File {
+    /// The path of the file on this system, which differs from its path
+    /// parameter when resources are applied to an alternate root.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.path)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
     pub fn apply(
         &mut self,
         pid: u32,
+        noop: bool,
         agent: &Agent,
         base_url: &Url,
         api_key: &str,
@@ -124,7 +145,18 @@ impl File {
                self.repr(),
         );
 
-        match self._apply(pid, agent, base_url, api_key) {
+        match self._apply(pid, noop, agent, base_url, api_key) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      path = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -148,6 +180,7 @@ impl File {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
@@ -161,11 +194,12 @@ impl File {
     pub fn _apply(
         &self,
         pid: u32,
+        noop: bool,
         agent: &Agent,
         base_url: &Url,
         api_key: &str,
     ) -> Result<Action, anyhow::Error> {
-        let metadata = match fs::metadata(&*self.parameters.path) {
+        let metadata = match self.local_path().and_then(fs::metadata) {
             Ok(metadata) => Some(metadata),
             Err(error) if error.kind() == io::ErrorKind::NotFound => None,
             Err(error) => anyhow::bail!("failed to query file metadata: {:#}", error),
@@ -176,7 +210,7 @@ impl File {
                 Ensure::Present => {
                     // When some error occurs during file creation it can be safely
                     // deleted again (cleaned up) as it did not exist in the first place.
-                    match self.create(pid, agent, base_url, api_key) {
+                    match self.create(pid, noop, agent, base_url, api_key) {
                         Ok(action) => Ok(action),
                         Err(error) => {
                             debug!(pid,
@@ -184,7 +218,7 @@ impl File {
                                    path = self.display();
                                    "deleting file as at least one condition failed"
                             );
-                            fs::remove_file(&*self.parameters.path).ok();
+                            self.local_path().and_then(fs::remove_file).ok();
                             Err(error)
                         }
                     }
@@ -192,8 +226,8 @@ impl File {
                 Ensure::Absent => Ok(Action::Unchanged),
             },
             Some(metadata) => match self.parameters.ensure {
-                Ensure::Present => self.maybe_update(pid, agent, base_url, api_key, metadata),
-                Ensure::Absent => self.delete(pid, metadata),
+                Ensure::Present => self.maybe_update(pid, noop, agent, base_url, api_key, metadata),
+                Ensure::Absent => self.delete(pid, noop, metadata),
             },
         }
     }
@@ -203,6 +237,7 @@ impl File {
     fn maybe_update(
         &self,
         pid: u32,
+        noop: bool,
         agent: &Agent,
         base_url: &Url,
         api_key: &str,
@@ -232,12 +267,16 @@ impl File {
                    permissions.mode()
             );
 
-            let handle = fs::File::open(&*self.parameters.path)
-                .context("failed to open file in read-only mode")?;
+            if !noop {
+                let handle = self
+                    .local_path()
+                    .and_then(fs::File::open)
+                    .context("failed to open file in read-only mode")?;
 
-            handle
-                .set_permissions(permissions)
-                .context("failed to set permissions")?;
+                handle
+                    .set_permissions(permissions)
+                    .context("failed to set permissions")?;
+            }
 
             action = Action::Changed;
         }
@@ -254,13 +293,18 @@ impl File {
                    gid
             );
 
-            chown(&*self.parameters.path, Some(uid), Some(gid))
-                .context("failed to set file owner and group")?;
+            if !noop {
+                chown(self.local_path()?, Some(uid), Some(gid))
+                    .context("failed to set file owner and group")?;
+            }
 
             action = Action::Changed;
         }
 
-        // Compute an etag from the current file contents.
+        // Compute an etag from the current file contents, which are kept
+        // in order to log the difference to the desired content.
+        let mut current = vec![];
+
         let etag = {
             debug!(pid,
                    resource = self.kind(),
@@ -268,60 +312,41 @@ impl File {
                    "computing etag (sha256 digest) from current file content",
             );
 
-            let mut bytes = vec![];
-
-            let mut handle = fs::File::open(&*self.parameters.path)
+            let mut handle = self
+                .local_path()
+                .and_then(fs::File::open)
                 .context("failed to open file in read-only mode")?;
 
-            handle.read_to_end(&mut bytes)?;
+            handle.read_to_end(&mut current)?;
 
-            format!("{:x}", Sha256::digest(bytes))
+            format!("{:x}", Sha256::digest(&current))
         };
 
-        // Either download the file content from the server (the etag ensures that
-        // the server does not re-send the data when the remote file content does
-        // not differ from the current content) or simply write the inline content
-        // from the configuration to the file (if etag and checksum differ).
+        // Either fetch the file content from the server or the asset cache, or
+        // simply write the inline content from the configuration to the file (if
+        // etag and checksum differ).
         if let Some(path) = &self.parameters.source {
-            let url = base_url.join(&format!("/assets{}", path.display()))?;
-
-            debug!(pid,
-                   resource = self.kind(),
-                   path = self.display();
-                   "downloading file from {}",
-                   url
-            );
-
-            let response = agent
-                .get(url.as_str())
-                .set("Accept", "text/plain")
-                .set("X-API-KEY", api_key)
-                .set("If-None-Match", &etag)
-                .call()
-                .context("failed to download file contents")?;
+            let bytes = self.fetch(pid, agent, base_url, api_key, path, Some((&etag, &current)))?;
 
-            if response.status() != 304 {
+            if format!("{:x}", Sha256::digest(&bytes)) != etag {
                 debug!(pid,
                        resource = self.kind(),
                        path = self.display();
                        "remote file content has changed, writing new content to file",
                 );
 
-                let mut bytes = vec![];
+                self.log_diff(pid, noop, &current, &bytes);
 
-                response
-                    .into_reader()
-                    .read_to_end(&mut bytes)
-                    .context("failed to write payload to buffer")?;
+                if !noop {
+                    let mut handle = fs::OpenOptions::new()
+                        .write(true)
+                        .open(self.local_path()?)
+                        .context("failed to open file in write mode")?;
 
-                let mut handle = fs::OpenOptions::new()
-                    .write(true)
-                    .open(&*self.parameters.path)
-                    .context("failed to open file in write mode")?;
-
-                handle
-                    .write_all(&bytes)
-                    .context("failed to write payload to file")?;
+                    handle
+                        .write_all(&bytes)
+                        .context("failed to write payload to file")?;
+                }
 
                 action = Action::Changed;
             } else {
@@ -339,8 +364,12 @@ impl File {
                        "remote file content has changed, writing new content to file",
                 );
 
-                fs::write(&*self.parameters.path, content.as_bytes())
-                    .context("failed to write inline string to file")?;
+                self.log_diff(pid, noop, &current, content.as_bytes());
+
+                if !noop {
+                    fs::write(self.local_path()?, content.as_bytes())
+                        .context("failed to write inline string to file")?;
+                }
 
                 action = Action::Changed;
             } else {
@@ -361,6 +390,7 @@ impl File {
     fn create(
         &self,
         pid: u32,
+        noop: bool,
         agent: &Agent,
         base_url: &Url,
         api_key: &str,
@@ -371,10 +401,31 @@ impl File {
                "file does no exist, creating file",
         );
 
+        // Fetch the content first, so that it can be compared to the
+        // (empty) current content in noop mode as well.
+        let content = if let Some(path) = &self.parameters.source {
+            Some(self.fetch(pid, agent, base_url, api_key, path, None)?)
+        } else {
+            self.parameters
+                .content
+                .as_ref()
+                .map(|content| content.as_bytes().to_vec())
+        };
+
+        if let Some(content) = &content {
+            self.log_diff(pid, noop, &[], content);
+        }
+
+        if noop {
+            return Ok(Action::Created);
+        }
+
         let (uid, gid) = uid_and_gid(&self.parameters.owner, &self.parameters.group)?;
 
-        let mut handle =
-            fs::File::create_new(&*self.parameters.path).context("failed to create file")?;
+        let mut handle = self
+            .local_path()
+            .and_then(fs::File::create_new)
+            .context("failed to create file")?;
 
         let permissions =
             fs::Permissions::from_mode(u32::from_str_radix(&self.parameters.mode, 8)?);
@@ -398,57 +449,182 @@ impl File {
                gid
         );
 
-        chown(&*self.parameters.path, Some(uid), Some(gid))
+        chown(self.local_path()?, Some(uid), Some(gid))
             .context("failed to set file owner and group")?;
 
-        if let Some(path) = &self.parameters.source {
-            let mut bytes = vec![];
-
-            let url = base_url.join(&format!("/assets{}", path.display()))?;
-
+        if let Some(content) = content {
             debug!(pid,
                    resource = self.kind(),
                    path = self.display();
-                   "downloading file from {}",
-                   url
+                   "writing content to file",
             );
 
-            agent
-                .get(url.as_str())
-                .set("Accept", "text/plain")
-                .set("X-API-KEY", api_key)
-                .call()
-                .context("failed to download file contents")?
-                .into_reader()
-                .read_to_end(&mut bytes)
-                .context("failed to write payload to buffer")?;
+            handle
+                .write_all(&content)
+                .context("failed to write content to file")?;
+        }
+
+        Ok(Action::Created)
+    }
+
+    /// Return the content of the file on the server. The digest of the
+    /// content that was downloaded most recently or, if there is none, of
+    /// the current content is sent as etag, so that the server does not
+    /// re-send content that is already on this system.
+    fn fetch(
+        &self,
+        pid: u32,
+        agent: &Agent,
+        base_url: &Url,
+        api_key: &str,
+        source: &SafePathBuf,
+        current: Option<(&str, &[u8])>,
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        // Resources with the same source wait for each other here, so that
+        // only the first one downloads the content.
+        let download = assets::Download::new(source).context("failed to prepare download")?;
 
+        let cached = assets::lookup(source);
+
+        let url = base_url.join(&format!("/assets{}", source.display()))?;
+
+        debug!(pid,
+               resource = self.kind(),
+               path = self.display();
+               "downloading file from {}",
+               url
+        );
+
+        let mut request = agent
+            .get(url.as_str())
+            .set("Accept", "text/plain")
+            .set("X-API-KEY", api_key);
+
+        if let Some(etag) = cached
+            .as_ref()
+            .map(|(digest, _)| digest.as_str())
+            .or(current.map(|(etag, _)| etag))
+        {
+            request = request.set("If-None-Match", etag);
+        }
+
+        // Resume an interrupted download, unless the content has changed in
+        // the meantime.
+        let partial = download.partial();
+
+        if let Some((offset, etag)) = &partial {
+            request = request
+                .set("Range", &format!("bytes={}-", offset))
+                .set("If-Range", etag);
+        }
+
+        let response = request.call().context("failed to download file contents")?;
+
+        if response.status() == 304 {
             debug!(pid,
                    resource = self.kind(),
                    path = self.display();
-                   "writing content to file",
+                   "remote file content has not changed, skipping download",
             );
 
-            handle
-                .write_all(&bytes)
-                .context("failed to write payload to file")?;
-        } else if let Some(content) = &self.parameters.content {
+            return match (cached, current) {
+                (Some((_, bytes)), _) => Ok(bytes),
+                (None, Some((_, bytes))) => {
+                    self.store(pid, source, bytes);
+                    Ok(bytes.to_vec())
+                }
+                (None, None) => anyhow::bail!("server returned 304 without an etag"),
+            };
+        }
+
+        let resumed = response.status() == 206;
+
+        if let (true, Some((offset, _))) = (resumed, &partial) {
+            if !response
+                .header("content-range")
+                .is_some_and(|range| range.starts_with(&format!("bytes {}-", offset)))
+            {
+                download.discard();
+                anyhow::bail!("server returned an unexpected range of the file contents");
+            }
+
             debug!(pid,
                    resource = self.kind(),
                    path = self.display();
-                   "writing content to file",
+                   "resuming interrupted download after {} bytes",
+                   offset
             );
+        }
 
-            handle
-                .write_all(content.as_bytes())
-                .context("failed to write static content to file")?;
+        let etag = response.header("etag").map(|etag| etag.to_string());
+
+        let mut handle = if resumed && partial.is_some() {
+            download.resume()
+        } else {
+            download.restart(etag.as_deref())
         }
+        .context("failed to open partial download")?;
 
-        Ok(Action::Created)
+        if let Err(error) = io::copy(&mut response.into_reader(), &mut handle) {
+            if etag.is_none() {
+                download.discard();
+                return Err(error).context("failed to download file contents");
+            }
+
+            return Err(error).context("download was interrupted, it is resumed by the next run");
+        }
+
+        drop(handle);
+
+        let bytes = download
+            .finish()
+            .context("failed to read downloaded file contents")?;
+
+        // pullconfd computes etags as the SHA-256 digest of the content,
+        // which guards against resuming a download with the wrong content.
+        if let Some(etag) = etag.filter(|etag| etag.len() == 64) {
+            if format!("{:x}", Sha256::digest(&bytes)) != etag.to_ascii_lowercase() {
+                anyhow::bail!("downloaded file contents do not match etag {}", etag);
+            }
+        }
+
+        self.store(pid, source, &bytes);
+
+        Ok(bytes)
+    }
+
+    /// Add downloaded content to the asset cache, which is not required for
+    /// the file to be applied.
+    fn store(&self, pid: u32, source: &SafePathBuf, bytes: &[u8]) {
+        if let Err(error) = assets::store(source, bytes) {
+            warn!(pid,
+                  resource = self.kind(),
+                  path = self.display();
+                  "failed to add file content to asset cache: {}",
+                  error
+            );
+        }
+    }
+
+    fn log_diff(&self, pid: u32, noop: bool, current: &[u8], desired: &[u8]) {
+        log_diff(
+            pid,
+            noop,
+            self.kind(),
+            &self.parameters.path,
+            current,
+            desired,
+            self.parameters.sensitive,
+        );
     }
 
     /// Delete this file.
-    fn delete(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn delete(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
@@ -456,7 +632,13 @@ impl File {
         );
 
         if metadata.is_file() {
-            fs::remove_file(&*self.parameters.path).context("failed to delete file")?
+            if noop {
+                return Ok(Action::Deleted);
+            }
+
+            self.local_path()
+                .and_then(fs::remove_file)
+                .context("failed to delete file")?
         } else {
             anyhow::bail!("failed to delete resource as it is not a file")
         }
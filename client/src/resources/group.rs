@@ -1,27 +1,41 @@
 use super::{Action, Resource, ResourceTrait};
+use crate::{
+    platform::{self, Platform},
+    privileges, root,
+};
+use anyhow::Context;
 use common::{
     resources::group::{Name, Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use std::{
-    collections::HashMap,
-    fs,
-    process::{Command, Stdio},
-};
+use std::{collections::HashMap, fs};
 use uuid::Uuid;
 
 const GROUPADD: &str = "/usr/sbin/groupadd";
 const GROUPDEL: &str = "/usr/sbin/groupdel";
 
+/// FreeBSD manages both users and groups with pw(8).
+pub(super) const PW: &str = "/usr/sbin/pw";
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct Group {
     pub id: Uuid,
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Group {
@@ -99,7 +113,7 @@ impl ResourceTrait for Group {
 
     fn check_prerequisites(&self, pid: u32) -> Option<Action> {
         fn find(group: &Group, pid: u32, program: &str) -> Option<Action> {
-            match fs::metadata(program) {
+            match root::path(&platform::program(program)).and_then(fs::metadata) {
                 Ok(metadata) => {
                     if metadata.is_file() {
                         None
@@ -138,17 +152,16 @@ impl ResourceTrait for Group {
             }
         }
 
-        let groupadd = find(self, pid, GROUPADD);
-        let groupdel = find(self, pid, GROUPDEL);
-
-        groupadd.or(groupdel)
+        programs()
+            .iter()
+            .fold(None, |action, program| action.or(find(self, pid, program)))
     }
 }
 
 impl Group {
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -166,7 +179,18 @@ impl Group {
                self.repr(),
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      name = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr(),
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -190,28 +214,29 @@ impl Group {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         if exists(&self.parameters.name)? {
             match self.parameters.ensure {
                 Ensure::Present => Ok(Action::Unchanged),
-                Ensure::Absent => self.delete(pid),
+                Ensure::Absent => self.delete(pid, noop),
             }
         } else {
             match self.parameters.ensure {
-                Ensure::Present => self.create(pid),
+                Ensure::Present => self.create(pid, noop),
                 Ensure::Absent => Ok(Action::Unchanged),
             }
         }
     }
 
     /// Add the group to the system.
-    fn create(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -219,31 +244,37 @@ impl Group {
             "creating group"
         );
 
-        let mut command = Command::new(GROUPADD);
-
-        if self.parameters.system {
-            command.arg("--system");
+        if noop {
+            return Ok(Action::Created);
         }
 
-        let status = command
-            .arg(self.parameters.name.as_str())
-            .stderr(Stdio::null())
-            .stdout(Stdio::null())
-            .status()?;
-
-        if !status.success() {
-            anyhow::bail!(
-                "failed to create group, {} exited with status {}",
-                GROUPADD,
-                status.code().unwrap()
-            );
-        }
+        let mut command = match platform::get() {
+            // pw(8) has no notion of system groups.
+            Platform::FreeBsd => {
+                let mut command = privileges::command(platform::program(PW));
+                command.args(["groupadd", "-n"]);
+                command
+            }
+            Platform::Debian | Platform::Alpine => {
+                let mut command = privileges::command(platform::program(GROUPADD));
+
+                if self.parameters.system {
+                    command.arg("--system");
+                }
+
+                command
+            }
+        };
+
+        command.arg(self.parameters.name.as_str());
+
+        super::run(&mut command).context("failed to create group")?;
 
         Ok(Action::Created)
     }
 
     /// Delete the group from the system.
-    fn delete(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn delete(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -251,33 +282,43 @@ impl Group {
             "deleting group"
         );
 
-        let status = Command::new(GROUPDEL)
-            .arg(self.parameters.name.as_str())
-            .stderr(Stdio::null())
-            .stdout(Stdio::null())
-            .status()?;
-
-        if !status.success() {
-            anyhow::bail!(
-                "failed to delete group, {} exited with status {}",
-                GROUPDEL,
-                status.code().unwrap()
-            );
+        if noop {
+            return Ok(Action::Deleted);
         }
 
+        let mut command = match platform::get() {
+            Platform::FreeBsd => {
+                let mut command = privileges::command(platform::program(PW));
+                command.args(["groupdel", "-n"]);
+                command
+            }
+            Platform::Debian | Platform::Alpine => privileges::command(platform::program(GROUPDEL)),
+        };
+
+        super::run(command.arg(self.parameters.name.as_str())).context("failed to delete group")?;
+
         Ok(Action::Deleted)
     }
 }
 
-/// Try to find a group by its name within the system.
+/// The programs that manage groups on this platform.
+pub(super) fn programs() -> &'static [&'static str] {
+    match platform::get() {
+        Platform::FreeBsd => &[PW],
+        Platform::Debian | Platform::Alpine => &[GROUPADD, GROUPDEL],
+    }
+}
+
+/// Try to find a group by its name within the system. The lookup goes
+/// through NSS, so groups from other sources than `/etc/group`, e.g. LDAP,
+/// are found as well, except within an alternate root.
 pub(super) fn exists(name: &Name) -> Result<bool, anyhow::Error> {
-    for line in fs::read_to_string("/etc/group")?.lines() {
-        if let Some((first_column, _)) = line.split_once(':') {
-            if first_column == name.as_str() {
-                return Ok(true);
-            }
-        }
+    if root::get().is_some() {
+        return Ok(root::lookup("group", name.as_str(), 4)?.is_some());
     }
 
-    Ok(false)
+    let group = nix::unistd::Group::from_name(name.as_str())
+        .with_context(|| format!("failed to search for group '{}'", name.as_str()))?;
+
+    Ok(group.is_some())
 }
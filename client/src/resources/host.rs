@@ -1,8 +1,9 @@
 use super::{Action, Resource, ResourceTrait};
+use crate::{root, tmp::TempFile, util::log_diff};
 use anyhow::Context;
 use common::{
     resources::host::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -11,6 +12,7 @@ use std::{
     default::Default,
     fs,
     io::{self, Read},
+    path::PathBuf,
     time::SystemTime,
 };
 use uuid::Uuid;
@@ -21,7 +23,17 @@ pub struct Host {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Host {
@@ -101,9 +113,14 @@ impl ResourceTrait for Host {
 }
 
 impl Host {
+    /// The hosts file on this system, see `root::path`.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.target)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -117,7 +134,19 @@ impl Host {
             self.repr()
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(
+                    pid,
+                    resource = self.kind(),
+                    ip_address:% = self.display(),
+                    result:% = action;
+                    "not applying {} in noop mode",
+                    self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(
                     pid,
@@ -143,14 +172,15 @@ impl Host {
                     error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
-        match fs::File::open(&self.parameters.target) {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
+        match self.local_path().and_then(fs::File::open) {
             Ok(mut file) => {
                 // If the file is found read its entire contents to a string.
                 // Anticipate that users may have used non-utf8 characters to
@@ -224,8 +254,10 @@ impl Host {
                 // 2. the current host state (not found/full match/partial match)
                 match self.parameters.ensure {
                     Ensure::Absent => match _match {
-                        Some(Match::Full(index)) => self.delete(pid, index, mtime, content),
-                        Some(Match::Partial(index)) => self.delete(pid, index, mtime, content),
+                        Some(Match::Full(index)) => self.delete(pid, noop, index, mtime, content),
+                        Some(Match::Partial(index)) => {
+                            self.delete(pid, noop, index, mtime, content)
+                        }
                         None => {
                             debug!(
                                 pid,
@@ -249,9 +281,9 @@ impl Host {
                             Ok(Action::default())
                         }
                         Some(Match::Partial(index)) => {
-                            self.update(pid, index, mtime, content, parameters)
+                            self.update(pid, noop, index, mtime, content, parameters)
                         }
-                        None => self.create(pid, mtime, content, parameters),
+                        None => self.create(pid, noop, mtime, content, parameters),
                     },
                 }
             }
@@ -270,11 +302,24 @@ impl Host {
         }
     }
 
+    fn log_diff(&self, pid: u32, noop: bool, current: &str, desired: &str) {
+        log_diff(
+            pid,
+            noop,
+            self.kind(),
+            &self.parameters.target,
+            current.as_bytes(),
+            desired.as_bytes(),
+            false,
+        );
+    }
+
     /// Update the host in the target file.
     /// This effectively replaces the target file with the updated host in it.
     fn update(
         &self,
         pid: u32,
+        noop: bool,
         index: usize,
         mtime: SystemTime,
         content: String,
@@ -308,9 +353,17 @@ impl Host {
             self.parameters.target.display()
         );
 
-        fs::write("/tmp/hosts.pullconf", new_content.as_bytes())?;
+        self.log_diff(pid, noop, &content, &new_content);
 
-        if fs::metadata(&self.parameters.target)
+        if noop {
+            return Ok(Action::Changed);
+        }
+
+        let replacement = TempFile::write("hosts", new_content.as_bytes())?;
+
+        if self
+            .local_path()
+            .and_then(fs::metadata)
             .context("failed to query target file metadata")?
             .modified()
             .is_ok_and(|_mtime| _mtime == mtime)
@@ -323,7 +376,8 @@ impl Host {
                 self.parameters.target.display()
             );
 
-            fs::rename("/tmp/hosts.pullconf", &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         } else {
             anyhow::bail!("target file changed before replacement file could be renamed");
@@ -337,8 +391,9 @@ impl Host {
     fn create(
         &self,
         pid: u32,
+        noop: bool,
         mtime: SystemTime,
-        mut content: String,
+        content: String,
         parameters: Vec<String>,
     ) -> Result<Action, anyhow::Error> {
         debug!(
@@ -349,12 +404,18 @@ impl Host {
             self.parameters.target.display()
         );
 
-        if !content.as_bytes().last().is_some_and(|byte| *byte == 0xA) {
-            content.push('\n');
+        let mut new_content = content.clone();
+
+        if new_content
+            .as_bytes()
+            .last()
+            .is_none_or(|byte| *byte != 0xA)
+        {
+            new_content.push('\n');
         }
 
-        content.push_str(&parameters.as_slice().join("\t"));
-        content.push('\n');
+        new_content.push_str(&parameters.as_slice().join("\t"));
+        new_content.push('\n');
 
         debug!(
             pid,
@@ -364,9 +425,17 @@ impl Host {
             self.parameters.target.display()
         );
 
-        fs::write("/tmp/hosts.pullconf", content.as_bytes())?;
+        self.log_diff(pid, noop, &content, &new_content);
+
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        let replacement = TempFile::write("hosts", new_content.as_bytes())?;
 
-        if fs::metadata(&self.parameters.target)
+        if self
+            .local_path()
+            .and_then(fs::metadata)
             .context("failed to query target file metadata")?
             .modified()
             .is_ok_and(|_mtime| _mtime == mtime)
@@ -379,7 +448,8 @@ impl Host {
                 self.parameters.target.display()
             );
 
-            fs::rename("/tmp/hosts.pullconf", &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         } else {
             anyhow::bail!("target file changed before replacement file could be renamed");
@@ -393,6 +463,7 @@ impl Host {
     fn delete(
         &self,
         pid: u32,
+        noop: bool,
         index: usize,
         mtime: SystemTime,
         content: String,
@@ -422,9 +493,17 @@ impl Host {
             self.parameters.target.display()
         );
 
-        fs::write("/tmp/hosts.pullconf", new_content.as_bytes())?;
+        self.log_diff(pid, noop, &content, &new_content);
+
+        if noop {
+            return Ok(Action::Deleted);
+        }
+
+        let replacement = TempFile::write("hosts", new_content.as_bytes())?;
 
-        if fs::metadata(&self.parameters.target)
+        if self
+            .local_path()
+            .and_then(fs::metadata)
             .context("failed to query target file metadata")?
             .modified()
             .is_ok_and(|_mtime| _mtime == mtime)
@@ -437,7 +516,8 @@ impl Host {
                 self.parameters.target.display()
             );
 
-            fs::rename("/tmp/hosts.pullconf", &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         } else {
             anyhow::bail!("target file changed before replacement file could be renamed");
@@ -1,5 +1,6 @@
 pub mod apt;
 pub mod cron;
+pub mod custom;
 pub mod directory;
 pub mod file;
 pub mod group;
@@ -8,28 +9,41 @@ pub mod resolv_conf;
 pub mod symlink;
 pub mod user;
 
-use common::ResourceMetadata;
+use crate::{
+    platform,
+    privileges::{self, Capability, Privileges},
+};
+use anyhow::Context;
+pub use common::report::Action;
+
+use common::{Priority, Purge, ResourceMetadata};
 use serde::Deserialize;
 use std::{
     collections::{HashMap, VecDeque},
-    fmt,
+    process::{Command, Output, Stdio},
 };
-use ureq::Agent;
+use ureq::{serde_json, Agent};
 use url::Url;
 use uuid::Uuid;
 
-/// A struct containing the deserialized form of a pullconfd API error.
-#[derive(Debug, Deserialize)]
-pub struct Error {
-    pub title: String,
-    pub detail: String,
-}
+/// The maximum number of lines and bytes of a failed command's output that
+/// are included in its error message.
+const OUTPUT_TAIL_LINES: usize = 10;
+const OUTPUT_TAIL_BYTES: usize = 1024;
 
 /// The expected payload of a pullconfd API response when the request
 /// is successful.
 #[derive(Debug, Deserialize)]
 pub struct Resources {
     pub data: VecDeque<Resource>,
+    /// The commit time of the configuration that pullconfd serves, if it
+    /// is managed from git, see `configuration::check_serial`.
+    #[serde(default)]
+    pub serial: Option<u64>,
+    /// Namespaces in which pullconf removes what it created earlier, but
+    /// which is no longer part of the catalog.
+    #[serde(default)]
+    pub purge: Purge,
 }
 
 /// A resource from the API response that provides the client's resource
@@ -46,6 +60,7 @@ pub enum Resource {
     AptPreference(apt::preference::Preference),
     #[serde(rename = "cron::job")]
     CronJob(cron::job::Job),
+    Custom(custom::Custom),
     Directory(directory::Directory),
     File(file::File),
     Group(group::Group),
@@ -66,6 +81,7 @@ impl Resource {
             Self::AptPackage(resource) => resource.id(),
             Self::AptPreference(resource) => resource.id(),
             Self::CronJob(resource) => resource.id(),
+            Self::Custom(resource) => resource.id(),
             Self::Directory(resource) => resource.id(),
             Self::File(resource) => resource.id(),
             Self::Group(resource) => resource.id(),
@@ -76,6 +92,42 @@ impl Resource {
         }
     }
 
+    /// Allow calling the `kind` function from resources implementing the
+    /// `ResourceTrait`.
+    pub fn kind(&self) -> &str {
+        match self {
+            Self::AptPackage(resource) => resource.kind(),
+            Self::AptPreference(resource) => resource.kind(),
+            Self::CronJob(resource) => resource.kind(),
+            Self::Custom(resource) => resource.kind(),
+            Self::Directory(resource) => resource.kind(),
+            Self::File(resource) => resource.kind(),
+            Self::Group(resource) => resource.kind(),
+            Self::Host(resource) => resource.kind(),
+            Self::ResolvConf(resource) => resource.kind(),
+            Self::Symlink(resource) => resource.kind(),
+            Self::User(resource) => resource.kind(),
+        }
+    }
+
+    /// Allow calling the `display` function from resources implementing the
+    /// `ResourceTrait`.
+    pub fn display(&self) -> String {
+        match self {
+            Self::AptPackage(resource) => resource.display(),
+            Self::AptPreference(resource) => resource.display(),
+            Self::CronJob(resource) => resource.display(),
+            Self::Custom(resource) => resource.display(),
+            Self::Directory(resource) => resource.display(),
+            Self::File(resource) => resource.display(),
+            Self::Group(resource) => resource.display(),
+            Self::Host(resource) => resource.display(),
+            Self::ResolvConf(resource) => resource.display(),
+            Self::Symlink(resource) => resource.display(),
+            Self::User(resource) => resource.display(),
+        }
+    }
+
     /// Allow calling the `repr` function from resources implementing the
     /// `ResourceTrait`.
     /// This shortcut allows the calling function to skip the usual pattern
@@ -85,6 +137,7 @@ impl Resource {
             Self::AptPackage(resource) => resource.repr(),
             Self::AptPreference(resource) => resource.repr(),
             Self::CronJob(resource) => resource.repr(),
+            Self::Custom(resource) => resource.repr(),
             Self::Directory(resource) => resource.repr(),
             Self::File(resource) => resource.repr(),
             Self::Group(resource) => resource.repr(),
@@ -104,6 +157,7 @@ impl Resource {
             Self::AptPackage(resource) => resource.is_ready(applied_resources),
             Self::AptPreference(resource) => resource.is_ready(applied_resources),
             Self::CronJob(resource) => resource.is_ready(applied_resources),
+            Self::Custom(resource) => resource.is_ready(applied_resources),
             Self::Directory(resource) => resource.is_ready(applied_resources),
             Self::File(resource) => resource.is_ready(applied_resources),
             Self::Group(resource) => resource.is_ready(applied_resources),
@@ -120,24 +174,61 @@ impl Resource {
     pub fn apply(
         &mut self,
         pid: u32,
+        noop: bool,
         agent: &Agent,
         base_url: &Url,
         api_key: &str,
         applied_resources: &HashMap<Uuid, Resource>,
     ) {
         match self {
-            Self::AptPackage(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::AptPreference(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::CronJob(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::Directory(ref mut resource) => resource.apply(pid, applied_resources),
+            Self::AptPackage(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::AptPreference(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::CronJob(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::Custom(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::Directory(ref mut resource) => resource.apply(pid, noop, applied_resources),
             Self::File(ref mut resource) => {
-                resource.apply(pid, agent, base_url, api_key, applied_resources)
+                resource.apply(pid, noop, agent, base_url, api_key, applied_resources)
             }
-            Self::Group(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::Host(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::ResolvConf(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::Symlink(ref mut resource) => resource.apply(pid, applied_resources),
-            Self::User(ref mut resource) => resource.apply(pid, applied_resources),
+            Self::Group(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::Host(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::ResolvConf(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::Symlink(ref mut resource) => resource.apply(pid, noop, applied_resources),
+            Self::User(ref mut resource) => resource.apply(pid, noop, applied_resources),
+        }
+    }
+
+    /// Return the action that resulted from applying the resource.
+    pub fn action(&self) -> &Action {
+        match self {
+            Self::AptPackage(resource) => &resource.action,
+            Self::AptPreference(resource) => &resource.action,
+            Self::CronJob(resource) => &resource.action,
+            Self::Custom(resource) => &resource.action,
+            Self::Directory(resource) => &resource.action,
+            Self::File(resource) => &resource.action,
+            Self::Group(resource) => &resource.action,
+            Self::Host(resource) => &resource.action,
+            Self::ResolvConf(resource) => &resource.action,
+            Self::Symlink(resource) => &resource.action,
+            Self::User(resource) => &resource.action,
+        }
+    }
+
+    /// Return the error that caused the resource to fail, if it failed
+    /// while being applied.
+    pub fn failure(&self) -> Option<&str> {
+        match self {
+            Self::AptPackage(resource) => resource.failure.as_deref(),
+            Self::AptPreference(resource) => resource.failure.as_deref(),
+            Self::CronJob(resource) => resource.failure.as_deref(),
+            Self::Custom(resource) => resource.failure.as_deref(),
+            Self::Directory(resource) => resource.failure.as_deref(),
+            Self::File(resource) => resource.failure.as_deref(),
+            Self::Group(resource) => resource.failure.as_deref(),
+            Self::Host(resource) => resource.failure.as_deref(),
+            Self::ResolvConf(resource) => resource.failure.as_deref(),
+            Self::Symlink(resource) => resource.failure.as_deref(),
+            Self::User(resource) => resource.failure.as_deref(),
         }
     }
 
@@ -147,6 +238,7 @@ impl Resource {
             Self::AptPackage(resource) => resource.action == Action::Skipped,
             Self::AptPreference(resource) => resource.action == Action::Skipped,
             Self::CronJob(resource) => resource.action == Action::Skipped,
+            Self::Custom(resource) => resource.action == Action::Skipped,
             Self::Directory(resource) => resource.action == Action::Skipped,
             Self::File(resource) => resource.action == Action::Skipped,
             Self::Group(resource) => resource.action == Action::Skipped,
@@ -163,6 +255,7 @@ impl Resource {
             Self::AptPackage(resource) => resource.action == Action::Failed,
             Self::AptPreference(resource) => resource.action == Action::Failed,
             Self::CronJob(resource) => resource.action == Action::Failed,
+            Self::Custom(resource) => resource.action == Action::Failed,
             Self::Directory(resource) => resource.action == Action::Failed,
             Self::File(resource) => resource.action == Action::Failed,
             Self::Group(resource) => resource.action == Action::Failed,
@@ -181,6 +274,7 @@ impl Resource {
             }
             Self::AptPreference(resource) => resource.parameters.ensure.is_absent(),
             Self::CronJob(resource) => resource.parameters.ensure.is_absent(),
+            Self::Custom(resource) => resource.parameters.ensure.is_absent(),
             Self::Directory(resource) => resource.parameters.ensure.is_absent(),
             Self::File(resource) => resource.parameters.ensure.is_absent(),
             Self::Group(resource) => resource.parameters.ensure.is_absent(),
@@ -190,47 +284,277 @@ impl Resource {
             Self::User(resource) => resource.parameters.ensure.is_absent(),
         }
     }
-}
 
-/// This enum describes possible actions that are the result of
-/// applying a resource.
-#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
-pub enum Action {
-    // This variant applies when a resource remains unchanged,
-    // either present or absent.
-    #[default]
-    Unchanged,
-    // This variant applies when a resource needed to be created
-    // because it did not exist before.
-    Created,
-    // This variant applies when a resource exists but needed to
-    // be changed in order to reach the desired state.
-    Changed,
-    // This variant applies when a resource has been deleted.
-    Deleted,
-    // This variant applies whenever any preconditions hinder the
-    // resource from being applied.
-    // This is usually the case when a dependency of this resource
-    // failed to apply or has been skipped itself.
-    Skipped,
-    // This variant applies when a resource could not successfully
-    // be configured according to its desired state.
-    // It also applies when certain preconditions fail, e.g. when
-    // a dependency of this resource is absent.
-    Failed,
-}
+    /// Return the tags that were assigned to the resource in the
+    /// configuration.
+    pub fn tags(&self) -> &[String] {
+        match self {
+            Self::AptPackage(resource) => resource.tags.as_slice(),
+            Self::AptPreference(resource) => resource.tags.as_slice(),
+            Self::CronJob(resource) => resource.tags.as_slice(),
+            Self::Custom(resource) => resource.tags.as_slice(),
+            Self::Directory(resource) => resource.tags.as_slice(),
+            Self::File(resource) => resource.tags.as_slice(),
+            Self::Group(resource) => resource.tags.as_slice(),
+            Self::Host(resource) => resource.tags.as_slice(),
+            Self::ResolvConf(resource) => resource.tags.as_slice(),
+            Self::Symlink(resource) => resource.tags.as_slice(),
+            Self::User(resource) => resource.tags.as_slice(),
+        }
+    }
+
+    /// Return the priority at which the resource is applied, if it was set
+    /// in the configuration.
+    pub fn priority(&self) -> Option<Priority> {
+        match self {
+            Self::AptPackage(resource) => resource.priority,
+            Self::AptPreference(resource) => resource.priority,
+            Self::CronJob(resource) => resource.priority,
+            Self::Custom(resource) => resource.priority,
+            Self::Directory(resource) => resource.priority,
+            Self::File(resource) => resource.priority,
+            Self::Group(resource) => resource.priority,
+            Self::Host(resource) => resource.priority,
+            Self::ResolvConf(resource) => resource.priority,
+            Self::Symlink(resource) => resource.priority,
+            Self::User(resource) => resource.priority,
+        }
+    }
+
+    /// Return the parameters of the resource as they were defined in the
+    /// configuration, e.g. to print them.
+    pub fn parameters(&self) -> Result<serde_json::Value, serde_json::Error> {
+        match self {
+            Self::AptPackage(resource) => serde_json::to_value(&resource.parameters),
+            Self::AptPreference(resource) => serde_json::to_value(&resource.parameters),
+            Self::CronJob(resource) => serde_json::to_value(&resource.parameters),
+            Self::Custom(resource) => serde_json::to_value(&resource.parameters),
+            Self::Directory(resource) => serde_json::to_value(&resource.parameters),
+            Self::File(resource) => serde_json::to_value(&resource.parameters),
+            Self::Group(resource) => serde_json::to_value(&resource.parameters),
+            Self::Host(resource) => serde_json::to_value(&resource.parameters),
+            Self::ResolvConf(resource) => serde_json::to_value(&resource.parameters),
+            Self::Symlink(resource) => serde_json::to_value(&resource.parameters),
+            Self::User(resource) => serde_json::to_value(&resource.parameters),
+        }
+    }
+
+    /// Return the metadata of the resources that this resource depends on.
+    pub fn dependencies(&self) -> &[ResourceMetadata] {
+        match self {
+            Self::AptPackage(resource) => resource.dependencies(),
+            Self::AptPreference(resource) => resource.dependencies(),
+            Self::CronJob(resource) => resource.dependencies(),
+            Self::Custom(resource) => resource.dependencies(),
+            Self::Directory(resource) => resource.dependencies(),
+            Self::File(resource) => resource.dependencies(),
+            Self::Group(resource) => resource.dependencies(),
+            Self::Host(resource) => resource.dependencies(),
+            Self::ResolvConf(resource) => resource.dependencies(),
+            Self::Symlink(resource) => resource.dependencies(),
+            Self::User(resource) => resource.dependencies(),
+        }
+    }
+
+    /// Return the name of a lock that must be held while the resource is
+    /// applied, if resources of its type cannot be applied concurrently,
+    /// e.g. because they are managed by tools that share a lock file or by
+    /// editing the same file.
+    pub fn exclusive(&self) -> Option<&str> {
+        match self {
+            Self::AptPackage(_) => Some("dpkg"),
+            Self::Custom(resource) => Some(resource.parameters.provider.as_str()),
+            Self::Group(_) | Self::User(_) => Some("passwd"),
+            Self::Host(_) => Some("/etc/hosts"),
+            Self::ResolvConf(_) => Some("/etc/resolv.conf"),
+            Self::AptPreference(_)
+            | Self::CronJob(_)
+            | Self::Directory(_)
+            | Self::File(_)
+            | Self::Symlink(_) => None,
+        }
+    }
+
+    /// Return the privileges that are required to apply the resource when
+    /// pullconf does not run as root, see `privileges::check`.
+    pub fn privileges(&self) -> Privileges {
+        match self {
+            Self::AptPackage(_) => Privileges {
+                capabilities: vec![],
+                commands: vec![apt::package::package_manager()],
+            },
+            Self::Custom(resource) => Privileges {
+                capabilities: vec![],
+                commands: vec![resource.program()],
+            },
+            Self::Group(_) => Privileges {
+                capabilities: vec![],
+                commands: group::programs()
+                    .iter()
+                    .map(|program| platform::program(program))
+                    .collect(),
+            },
+            // The shadow entry of a user is read by pullconf itself.
+            Self::User(_) => Privileges {
+                capabilities: vec![Capability::DacOverride],
+                commands: user::programs()
+                    .iter()
+                    .map(|program| platform::program(program))
+                    .collect(),
+            },
+            // Files are written, owned and permitted by pullconf itself.
+            Self::AptPreference(_)
+            | Self::CronJob(_)
+            | Self::Directory(_)
+            | Self::File(_)
+            | Self::Host(_)
+            | Self::ResolvConf(_)
+            | Self::Symlink(_) => Privileges {
+                capabilities: vec![
+                    Capability::Chown,
+                    Capability::DacOverride,
+                    Capability::Fowner,
+                ],
+                commands: vec![],
+            },
+        }
+    }
+
+    /// Set the action without applying the resource, e.g. to skip a
+    /// resource that is not selected.
+    pub fn set_action(&mut self, action: Action) {
+        match self {
+            Self::AptPackage(ref mut resource) => resource.action = action,
+            Self::AptPreference(ref mut resource) => resource.action = action,
+            Self::CronJob(ref mut resource) => resource.action = action,
+            Self::Custom(ref mut resource) => resource.action = action,
+            Self::Directory(ref mut resource) => resource.action = action,
+            Self::File(ref mut resource) => resource.action = action,
+            Self::Group(ref mut resource) => resource.action = action,
+            Self::Host(ref mut resource) => resource.action = action,
+            Self::ResolvConf(ref mut resource) => resource.action = action,
+            Self::Symlink(ref mut resource) => resource.action = action,
+            Self::User(ref mut resource) => resource.action = action,
+        }
+    }
+
+    /// Return the time it took to apply the resource in seconds, which is
+    /// zero until it has been applied.
+    pub fn duration(&self) -> f64 {
+        match self {
+            Self::AptPackage(resource) => resource.duration,
+            Self::AptPreference(resource) => resource.duration,
+            Self::CronJob(resource) => resource.duration,
+            Self::Custom(resource) => resource.duration,
+            Self::Directory(resource) => resource.duration,
+            Self::File(resource) => resource.duration,
+            Self::Group(resource) => resource.duration,
+            Self::Host(resource) => resource.duration,
+            Self::ResolvConf(resource) => resource.duration,
+            Self::Symlink(resource) => resource.duration,
+            Self::User(resource) => resource.duration,
+        }
+    }
 
-impl fmt::Display for Action {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    pub fn set_duration(&mut self, duration: f64) {
         match self {
-            Self::Unchanged => f.write_str("unchanged"),
-            Self::Created => f.write_str("created"),
-            Self::Changed => f.write_str("changed"),
-            Self::Deleted => f.write_str("deleted"),
-            Self::Skipped => f.write_str("skipped"),
-            Self::Failed => f.write_str("failed"),
+            Self::AptPackage(ref mut resource) => resource.duration = duration,
+            Self::AptPreference(ref mut resource) => resource.duration = duration,
+            Self::CronJob(ref mut resource) => resource.duration = duration,
+            Self::Custom(ref mut resource) => resource.duration = duration,
+            Self::Directory(ref mut resource) => resource.duration = duration,
+            Self::File(ref mut resource) => resource.duration = duration,
+            Self::Group(ref mut resource) => resource.duration = duration,
+            Self::Host(ref mut resource) => resource.duration = duration,
+            Self::ResolvConf(ref mut resource) => resource.duration = duration,
+            Self::Symlink(ref mut resource) => resource.duration = duration,
+            Self::User(ref mut resource) => resource.duration = duration,
         }
     }
+
+    /// Fail the resource without applying it, e.g. because it requires
+    /// privileges that have not been granted.
+    pub fn fail(&mut self, reason: String) {
+        match self {
+            Self::AptPackage(ref mut resource) => resource.failure = Some(reason),
+            Self::AptPreference(ref mut resource) => resource.failure = Some(reason),
+            Self::CronJob(ref mut resource) => resource.failure = Some(reason),
+            Self::Custom(ref mut resource) => resource.failure = Some(reason),
+            Self::Directory(ref mut resource) => resource.failure = Some(reason),
+            Self::File(ref mut resource) => resource.failure = Some(reason),
+            Self::Group(ref mut resource) => resource.failure = Some(reason),
+            Self::Host(ref mut resource) => resource.failure = Some(reason),
+            Self::ResolvConf(ref mut resource) => resource.failure = Some(reason),
+            Self::Symlink(ref mut resource) => resource.failure = Some(reason),
+            Self::User(ref mut resource) => resource.failure = Some(reason),
+        }
+
+        self.set_action(Action::Failed);
+    }
+}
+
+/// Run a command and capture its standard output and standard error. If the
+/// command fails, the error contains its exit status and the tail of its
+/// output, as the exit status alone rarely explains what went wrong.
+pub(crate) fn run(command: &mut Command) -> Result<Output, anyhow::Error> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("failed to execute {:?}", privileges::program(command)))?;
+
+    if !output.status.success() {
+        let status = match output.status.code() {
+            Some(code) => format!("status {}", code),
+            None => output.status.to_string(),
+        };
+
+        match tail(&output) {
+            Some(tail) => anyhow::bail!(
+                "{:?} exited with {}: {}",
+                privileges::program(command),
+                status,
+                tail
+            ),
+            None => anyhow::bail!("{:?} exited with {}", privileges::program(command), status),
+        }
+    }
+
+    Ok(output)
+}
+
+/// Return the last lines of a command's output, standard output first and
+/// standard error last, joined into a single line and truncated from the
+/// front.
+pub(crate) fn tail(output: &Output) -> Option<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let lines = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>();
+
+    let tail = lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("; ");
+
+    if tail.is_empty() {
+        return None;
+    }
+
+    if tail.len() <= OUTPUT_TAIL_BYTES {
+        return Some(tail);
+    }
+
+    let mut start = tail.len() - OUTPUT_TAIL_BYTES;
+
+    while !tail.is_char_boundary(start) {
+        start += 1;
+    }
+
+    Some(format!("...{}", &tail[start..]))
 }
 
 pub trait ResourceTrait {
@@ -289,17 +613,26 @@ pub trait ResourceTrait {
                 .all(|dependency| applied_resources.contains_key(&dependency.id))
     }
 
+    /// Return the dependencies that can be found in the collection of
+    /// already applied resources, along with their action and duration,
+    /// e.g. to only do something if a dependency was just created.
+    fn applied_dependencies<'a>(
+        &'a self,
+        applied_resources: &'a HashMap<Uuid, Resource>,
+    ) -> impl Iterator<Item = &'a Resource> {
+        self.dependencies()
+            .iter()
+            .filter_map(|dependency| applied_resources.get(&dependency.id))
+    }
+
     /// Find the first dependency that can be found in the collection of
     /// already applied resources that has failed.
     fn find_failed_dependency<'a>(
         &'a self,
         applied_resources: &'a HashMap<Uuid, Resource>,
-    ) -> Option<&Resource> {
-        self.dependencies().iter().find_map(|dependency| {
-            applied_resources
-                .get(&dependency.id)
-                .filter(|resource| resource.is_failed())
-        })
+    ) -> Option<&'a Resource> {
+        self.applied_dependencies(applied_resources)
+            .find(|resource| resource.is_failed())
     }
 
     /// Find the first dependency that can be found in the collection of
@@ -307,12 +640,9 @@ pub trait ResourceTrait {
     fn find_skipped_dependency<'a>(
         &'a self,
         applied_resources: &'a HashMap<Uuid, Resource>,
-    ) -> Option<&Resource> {
-        self.dependencies().iter().find_map(|dependency| {
-            applied_resources
-                .get(&dependency.id)
-                .filter(|resource| resource.is_skipped())
-        })
+    ) -> Option<&'a Resource> {
+        self.applied_dependencies(applied_resources)
+            .find(|resource| resource.is_skipped())
     }
 
     /// Find the first dependency that can be found in the collection of
@@ -320,11 +650,47 @@ pub trait ResourceTrait {
     fn find_absent_dependency<'a>(
         &'a self,
         applied_resources: &'a HashMap<Uuid, Resource>,
-    ) -> Option<&Resource> {
-        self.dependencies().iter().find_map(|dependency| {
-            applied_resources
-                .get(&dependency.id)
-                .filter(|resource| resource.is_absent())
-        })
+    ) -> Option<&'a Resource> {
+        self.applied_dependencies(applied_resources)
+            .find(|resource| resource.is_absent())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn command_output_tail() {
+        let output = |stdout: &str, stderr: &str| Output {
+            status: std::process::ExitStatus::from_raw(256),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+
+        assert_eq!(tail(&output("", "\n")), None);
+        assert_eq!(
+            tail(&output(
+                "Reading package lists...\n",
+                "E: Unable to locate package nginx-ful\n"
+            ))
+            .as_deref(),
+            Some("Reading package lists...; E: Unable to locate package nginx-ful")
+        );
+
+        let lines = (1..=20)
+            .map(|n| format!("line {}\n", n))
+            .collect::<String>();
+        let tail = tail(&output(&lines, "")).unwrap();
+
+        assert!(tail.starts_with("line 11; "));
+        assert!(tail.ends_with("; line 20"));
+
+        let long = "x".repeat(OUTPUT_TAIL_BYTES * 2);
+        let tail = super::tail(&output("", &long)).unwrap();
+
+        assert_eq!(tail.len(), OUTPUT_TAIL_BYTES + 3);
+        assert!(tail.starts_with("..."));
     }
 }
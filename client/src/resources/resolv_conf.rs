@@ -1,8 +1,9 @@
 use super::{Action, Resource, ResourceTrait};
+use crate::{root, tmp::TempFile, util::log_diff};
 use anyhow::Context;
 use common::{
     resources::resolv_conf::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
@@ -11,6 +12,7 @@ use std::{
     collections::HashMap,
     fs,
     io::{self, Read},
+    path::PathBuf,
 };
 use uuid::Uuid;
 
@@ -20,7 +22,17 @@ pub struct ResolvConf {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for ResolvConf {
@@ -100,9 +112,14 @@ impl ResourceTrait for ResolvConf {
 }
 
 impl ResolvConf {
+    /// The resolv.conf file on this system, see `root::path`.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::path(&self.parameters.target)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -116,7 +133,19 @@ impl ResolvConf {
             self.repr()
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(
+                    pid,
+                    resource = self.kind(),
+                    path = self.display(),
+                    result:% = action;
+                    "not applying {} in noop mode",
+                    self.repr(),
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(
                     pid,
@@ -142,23 +171,24 @@ impl ResolvConf {
                     error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
-        match fs::File::open(&self.parameters.target) {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
+        match self.local_path().and_then(fs::File::open) {
             Ok(mut file) => {
                 // If the file is found compute its current checksum.
                 // This is used to determine if any modifications have been
                 // made that do not match the desired state of the resource.
-                let current_checksum = {
-                    let mut data = vec![];
-                    file.read_to_end(&mut data)?;
-                    format!("{:x}", Sha256::digest(data))
-                };
+                // The current content is kept in order to log the difference
+                // to the desired content.
+                let mut current = vec![];
+                file.read_to_end(&mut current)?;
+                let current_checksum = format!("{:x}", Sha256::digest(&current));
 
                 // Build the expected file content from the resource
                 // parameters. Then also compute its checksum.
@@ -224,12 +254,12 @@ impl ResolvConf {
                 // Apply the resource based on the result of the checksum comparison
                 // and the desired resource state.
                 match self.parameters.ensure {
-                    Ensure::Absent => self.clear(pid),
+                    Ensure::Absent => self.clear(pid, noop),
                     Ensure::Present => {
                         if _match {
                             Ok(Action::Unchanged)
                         } else {
-                            self.populate(pid, content)
+                            self.populate(pid, noop, &current, content)
                         }
                     }
                 }
@@ -250,7 +280,13 @@ impl ResolvConf {
     }
 
     /// Replace the current target file contents with the desired contents.
-    fn populate(&self, pid: u32, content: String) -> Result<Action, anyhow::Error> {
+    fn populate(
+        &self,
+        pid: u32,
+        noop: bool,
+        current: &[u8],
+        content: String,
+    ) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -267,9 +303,23 @@ impl ResolvConf {
             self.parameters.target.display()
         );
 
-        fs::write("/tmp/resolv.conf.pullconf", content.as_bytes())?;
+        log_diff(
+            pid,
+            noop,
+            self.kind(),
+            &self.parameters.target,
+            current,
+            content.as_bytes(),
+            false,
+        );
+
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        let replacement = TempFile::write("resolv.conf", content.as_bytes())?;
 
-        if let Err(error) = fs::metadata(&self.parameters.target) {
+        if let Err(error) = self.local_path().and_then(fs::metadata) {
             anyhow::bail!("target file cannot be accessed: {}", error);
         } else {
             debug!(
@@ -280,7 +330,8 @@ impl ResolvConf {
                 self.parameters.target.display()
             );
 
-            fs::rename("/tmp/resolv.conf.pullconf", &self.parameters.target)
+            replacement
+                .persist(&self.local_path()?)
                 .context("failed to replace target file")?;
         }
 
@@ -288,7 +339,7 @@ impl ResolvConf {
     }
 
     /// Clear the target file.
-    fn clear(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn clear(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -297,10 +348,14 @@ impl ResolvConf {
             self.parameters.target.display()
         );
 
+        if noop {
+            return Ok(Action::Deleted);
+        }
+
         let _ = fs::OpenOptions::new()
             .write(true)
             .truncate(true)
-            .open(&self.parameters.target)
+            .open(self.local_path()?)
             .context("failed to truncate target file")?;
 
         Ok(Action::Deleted)
@@ -1,13 +1,15 @@
 use super::{Action, Resource, ResourceTrait};
+use crate::root;
 use anyhow::Context;
 use common::{
     resources::symlink::{Parameters, Relationships},
-    Ensure, ResourceMetadata,
+    Ensure, Priority, ResourceMetadata,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
 use std::{
     collections::HashMap, default::Default, fs, io, os::unix::fs::symlink as create_symlink,
+    path::PathBuf,
 };
 use uuid::Uuid;
 
@@ -17,7 +19,17 @@ pub struct Symlink {
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for Symlink {
@@ -95,9 +107,15 @@ impl ResourceTrait for Symlink {
 }
 
 impl Symlink {
+    /// The path of the symlink on this system, see `root::link_path`. Its target
+    /// is left as it is, as it is resolved within the target system.
+    fn local_path(&self) -> io::Result<PathBuf> {
+        root::link_path(&self.parameters.path)
+    }
+
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -110,7 +128,18 @@ impl Symlink {
                self.repr(),
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      path = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -134,6 +163,7 @@ impl Symlink {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
@@ -142,10 +172,11 @@ impl Symlink {
     /// Apply this resource's configuration. This function can be called repeatedly
     /// and produce the same result if neither the configuration nor the actual
     /// symlink in the file system change.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         // Check if the intended symlink target exists by searching for it in
         // the filesystem.
-        let target_exists = match fs::symlink_metadata(&*self.parameters.target) {
+        let target_exists = match root::path(&self.parameters.target).and_then(fs::symlink_metadata)
+        {
             Ok(_) => true,
             Err(error) if error.kind() == io::ErrorKind::NotFound => false,
             Err(error) => anyhow::bail!("failed to query symlink metadata: {:#}", error),
@@ -159,7 +190,7 @@ impl Symlink {
             )
         }
 
-        let metadata = match fs::symlink_metadata(&*self.parameters.path) {
+        let metadata = match self.local_path().and_then(fs::symlink_metadata) {
             Ok(metadata) => Some(metadata),
             Err(error) if error.kind() == io::ErrorKind::NotFound => None,
             Err(error) => anyhow::bail!("failed to query symlink metadata: {:#}", error),
@@ -170,7 +201,7 @@ impl Symlink {
                 Ensure::Present => {
                     // When some error occurs during symlink creation it can be safely
                     // deleted again (cleaned up) as it did not exist in the first place.
-                    match self.create(pid) {
+                    match self.create(pid, noop) {
                         Ok(action) => Ok(action),
                         Err(error) => {
                             debug!(pid,
@@ -179,7 +210,7 @@ impl Symlink {
                                    "deleting symlink as at least one condition failed",
                             );
 
-                            fs::remove_file(&*self.parameters.path).ok();
+                            self.local_path().and_then(fs::remove_file).ok();
 
                             Err(error)
                         }
@@ -188,14 +219,19 @@ impl Symlink {
                 Ensure::Absent => Ok(Action::Unchanged),
             },
             Some(metadata) => match self.parameters.ensure {
-                Ensure::Present => self.maybe_update(pid, metadata),
-                Ensure::Absent => self.delete(pid, metadata),
+                Ensure::Present => self.maybe_update(pid, noop, metadata),
+                Ensure::Absent => self.delete(pid, noop, metadata),
             },
         }
     }
 
     /// Re-create this symlink if the current target differs from the one that was configured.
-    fn maybe_update(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn maybe_update(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(
             "{}: symlink exists, checking if current and desired states match",
             self.parameters.path.display()
@@ -207,7 +243,7 @@ impl Symlink {
             anyhow::bail!("failed to update resource as it is not a symlink")
         }
 
-        match fs::read_link(&*self.parameters.path) {
+        match self.local_path().and_then(fs::read_link) {
             Ok(target) => {
                 if target != *self.parameters.target {
                     debug!(pid,
@@ -216,10 +252,14 @@ impl Symlink {
                            "symlink exists, but points to the wrong target, will be deleted and re-created",
                     );
 
-                    fs::remove_file(&*self.parameters.path).context("failed to delete symlink")?;
+                    if !noop {
+                        self.local_path()
+                            .and_then(fs::remove_file)
+                            .context("failed to delete symlink")?;
 
-                    create_symlink(&*self.parameters.target, &*self.parameters.path)
-                        .context("failed to create symlink")?;
+                        create_symlink(&*self.parameters.target, self.local_path()?)
+                            .context("failed to create symlink")?;
+                    }
 
                     action = Action::Changed;
                 }
@@ -231,21 +271,30 @@ impl Symlink {
     }
 
     /// Create this symlink.
-    fn create(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
                "creating symlink as it does no exist",
         );
 
-        create_symlink(&*self.parameters.target, &*self.parameters.path)
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        create_symlink(&*self.parameters.target, self.local_path()?)
             .context("failed to create symlink")?;
 
         Ok(Action::Created)
     }
 
     /// Delete this symlink.
-    fn delete(&self, pid: u32, metadata: fs::Metadata) -> Result<Action, anyhow::Error> {
+    fn delete(
+        &self,
+        pid: u32,
+        noop: bool,
+        metadata: fs::Metadata,
+    ) -> Result<Action, anyhow::Error> {
         debug!(pid,
                resource = self.kind(),
                path = self.display();
@@ -253,7 +302,13 @@ impl Symlink {
         );
 
         if metadata.is_symlink() {
-            fs::remove_file(&*self.parameters.path).context("failed to delete symlink")?
+            if noop {
+                return Ok(Action::Deleted);
+            }
+
+            self.local_path()
+                .and_then(fs::remove_file)
+                .context("failed to delete symlink")?
         } else {
             anyhow::bail!("failed to delete resource as it is not a symlink")
         }
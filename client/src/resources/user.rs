@@ -1,18 +1,17 @@
 use super::{group, Action, Resource, ResourceTrait};
+use crate::{
+    platform::{self, Platform},
+    privileges, root,
+};
+use anyhow::Context;
 use common::{
     resources::user::{Name, Parameters, Password, Relationships, EXPIRY_DATE_FORMAT},
-    Ensure, ResourceMetadata, SafePathBuf,
+    Ensure, Priority, ResourceMetadata, SafePathBuf,
 };
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use std::{
-    collections::HashMap,
-    default::Default,
-    fs,
-    process::{Command, Stdio},
-    str::FromStr,
-};
-use time::Date;
+use std::{collections::HashMap, default::Default, fs, process::Command, str::FromStr};
+use time::{format_description::FormatItem, macros::format_description, Date};
 use uuid::Uuid;
 
 const USERADD: &str = "/usr/sbin/useradd";
@@ -21,13 +20,61 @@ const PASSWD: &str = "/usr/bin/passwd";
 const DELUSER: &str = "/usr/sbin/deluser";
 const ID: &str = "/usr/bin/id";
 
+/// Sets the password hash of a user account on FreeBSD, as pw(8) only
+/// reads it from a file descriptor.
+const CHPASS: &str = "/usr/bin/chpass";
+
+/// The prefix with which pw(8) locks the password of a user account.
+const PW_LOCKED: &str = "*LOCKED*";
+
+/// pw(8) expects expiry dates as e.g. `31-12-2030`.
+const PW_EXPIRY_DATE_FORMAT: &[FormatItem] = format_description!("[day]-[month]-[year]");
+
+/// The options of useradd(8) and usermod(8) and their counterparts of pw(8).
+struct Options {
+    comment: &'static str,
+    shell: &'static str,
+    home: &'static str,
+    expiry_date: &'static str,
+    gid: &'static str,
+    groups: &'static str,
+}
+
+const SHADOW_OPTIONS: Options = Options {
+    comment: "--comment",
+    shell: "--shell",
+    home: "--home",
+    expiry_date: "--expiredate",
+    gid: "--gid",
+    groups: "--groups",
+};
+
+const PW_OPTIONS: Options = Options {
+    comment: "-c",
+    shell: "-s",
+    home: "-d",
+    expiry_date: "-e",
+    gid: "-g",
+    groups: "-G",
+};
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct User {
     pub id: Uuid,
     pub parameters: Parameters,
     pub relationships: Relationships,
     #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
     pub action: Action,
+    /// The error that caused the resource to fail, if any.
+    #[serde(skip)]
+    pub failure: Option<String>,
+    /// The time it took to apply the resource in seconds.
+    #[serde(skip)]
+    pub duration: f64,
 }
 
 impl ResourceTrait for User {
@@ -105,7 +152,7 @@ impl ResourceTrait for User {
 
     fn check_prerequisites(&self, pid: u32) -> Option<Action> {
         fn find(user: &User, pid: u32, program: &str) -> Option<Action> {
-            match fs::metadata(program) {
+            match root::path(&platform::program(program)).and_then(fs::metadata) {
                 Ok(metadata) => {
                     if metadata.is_file() {
                         None
@@ -144,20 +191,17 @@ impl ResourceTrait for User {
             }
         }
 
-        let useradd = find(self, pid, USERADD);
-        let usermod = find(self, pid, USERMOD);
-        let passwd = find(self, pid, PASSWD);
-        let deluser = find(self, pid, DELUSER);
-        let id = find(self, pid, ID);
-
-        useradd.or(usermod).or(passwd).or(deluser).or(id)
+        programs()
+            .iter()
+            .chain([&ID])
+            .fold(None, |action, program| action.or(find(self, pid, program)))
     }
 }
 
 impl User {
     /// A wrapper around the actual apply function. This ensure that some
     /// meaningful log messages are printed and pre-checks are done.
-    pub fn apply(&mut self, pid: u32, applied_resources: &HashMap<Uuid, Resource>) {
+    pub fn apply(&mut self, pid: u32, noop: bool, applied_resources: &HashMap<Uuid, Resource>) {
         if let Some(action) = self.maybe_return_early(pid, applied_resources) {
             self.action = action;
             return;
@@ -175,7 +219,18 @@ impl User {
                self.repr()
         );
 
-        match self._apply(pid) {
+        match self._apply(pid, noop) {
+            Ok(action) if noop => {
+                info!(pid,
+                      resource = self.kind(),
+                      name = self.display(),
+                      result:% = action;
+                      "not applying {} in noop mode",
+                      self.repr()
+                );
+
+                self.action = action;
+            }
             Ok(action) => {
                 info!(pid,
                       resource = self.kind(),
@@ -199,27 +254,28 @@ impl User {
                        error
                 );
 
+                self.failure = Some(format!("{:#}", error));
                 self.action = action;
             }
         }
     }
 
     /// Apply this resource's configuration.
-    pub fn _apply(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    pub fn _apply(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         match find(&self.parameters.name)? {
             None => match self.parameters.ensure {
-                Ensure::Present => self.create(pid),
+                Ensure::Present => self.create(pid, noop),
                 Ensure::Absent => Ok(Action::Unchanged),
             },
             Some(current_user) => match self.parameters.ensure {
-                Ensure::Present => self.maybe_update(pid, current_user),
-                Ensure::Absent => self.delete(pid),
+                Ensure::Present => self.maybe_update(pid, noop, current_user),
+                Ensure::Absent => self.delete(pid, noop),
             },
         }
     }
 
     /// Create the user account.
-    fn create(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn create(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -227,100 +283,178 @@ impl User {
             "creating user account"
         );
 
+        if noop {
+            return Ok(Action::Created);
+        }
+
+        let mut command = match platform::get() {
+            Platform::FreeBsd => self.pw_useradd()?,
+            Platform::Debian | Platform::Alpine => self.useradd()?,
+        };
+
+        debug!(
+            pid,
+            resource = self.kind(),
+            name = self.display();
+            "executing {:?} with args {:?}",
+            command.get_program(),
+            command.get_args()
+        );
+
+        super::run(&mut command).context("failed to create user account")?;
+
+        // pw(8) only reads password hashes from a file descriptor, so the
+        // password is set separately.
+        if let (Platform::FreeBsd, Password::Unlocked(password)) =
+            (platform::get(), &self.parameters.password)
         {
-            let mut command = Command::new(USERADD);
+            let mut chpass = privileges::command(platform::program(CHPASS));
 
-            command.arg("--create-home");
-            command.arg("--home-dir");
-            command.arg(self.parameters.home.as_path().as_os_str());
+            chpass
+                .arg("-p")
+                .arg(password)
+                .arg(self.parameters.name.as_str());
 
-            if self.parameters.system {
-                command.arg("--system");
-            }
+            super::run(&mut chpass).context("failed to set user account password")?;
+        }
 
-            if let Some(comment) = &self.parameters.comment {
-                command.arg("--comment");
-                command.arg(comment);
-            }
+        Ok(Action::Created)
+    }
 
-            if let Some(shell) = &self.parameters.shell {
-                command.arg("--shell");
-                command.arg(shell.as_path().as_os_str());
-            }
+    /// Prepare the useradd(8) command that creates the user account.
+    fn useradd(&self) -> Result<Command, anyhow::Error> {
+        let mut command = privileges::command(platform::program(USERADD));
 
-            if let Password::Unlocked(password) = &self.parameters.password {
-                command.arg("--password");
-                command.arg(password);
-            }
+        command.arg("--create-home");
+        command.arg("--home-dir");
+        command.arg(self.parameters.home.as_path().as_os_str());
 
-            if group::exists(&self.parameters.group)? {
-                command.arg("--no-user-group");
-                command.arg("--gid");
-                command.arg(self.parameters.group.as_str());
-            } else if self.parameters.group == self.parameters.name {
-                command.arg("--user-group");
-            } else {
-                command.arg("--gid");
-                command.arg(self.parameters.group.as_str());
-            }
+        if self.parameters.system {
+            command.arg("--system");
+        }
 
-            if !self.parameters.groups.is_empty() {
-                command.arg("--groups");
-                command.arg(
-                    self.parameters
-                        .groups
-                        .iter()
-                        .map(|name| name.as_str())
-                        .collect::<Vec<&str>>()
-                        .as_slice()
-                        .join(","),
-                );
-            }
+        if let Some(comment) = &self.parameters.comment {
+            command.arg("--comment");
+            command.arg(comment);
+        }
 
-            command
-                .arg(self.parameters.name.as_str())
-                .stderr(Stdio::null())
-                .stdout(Stdio::null());
+        if let Some(shell) = &self.parameters.shell {
+            command.arg("--shell");
+            command.arg(shell.as_path().as_os_str());
+        }
 
-            let status = command.status()?;
+        if let Password::Unlocked(password) = &self.parameters.password {
+            command.arg("--password");
+            command.arg(password);
+        }
 
-            debug!(
-                pid,
-                resource = self.kind(),
-                name = self.display();
-                "executing {:?} with args {:?}",
-                command.get_program(),
-                command.get_args()
-            );
+        if group::exists(&self.parameters.group)? {
+            command.arg("--no-user-group");
+            command.arg("--gid");
+            command.arg(self.parameters.group.as_str());
+        } else if self.parameters.group == self.parameters.name {
+            command.arg("--user-group");
+        } else {
+            command.arg("--gid");
+            command.arg(self.parameters.group.as_str());
+        }
 
-            if !status.success() {
-                anyhow::bail!(
-                    "failed to create user account, {:?} exited with status {}",
-                    command.get_program(),
-                    status.code().unwrap()
-                );
-            }
+        if !self.parameters.groups.is_empty() {
+            command.arg("--groups");
+            command.arg(self.groups());
         }
 
-        Ok(Action::Created)
+        command.arg(self.parameters.name.as_str());
+
+        Ok(command)
+    }
+
+    /// Prepare the pw(8) command that creates the user account on FreeBSD,
+    /// which has no notion of system accounts. Without a primary group pw
+    /// creates a group named after the user.
+    fn pw_useradd(&self) -> Result<Command, anyhow::Error> {
+        let mut command = privileges::command(platform::program(group::PW));
+
+        command.arg("useradd");
+        command.arg("-n");
+        command.arg(self.parameters.name.as_str());
+        command.arg("-m");
+        command.arg("-d");
+        command.arg(self.parameters.home.as_path().as_os_str());
+
+        if let Some(comment) = &self.parameters.comment {
+            command.arg("-c");
+            command.arg(comment);
+        }
+
+        if let Some(shell) = &self.parameters.shell {
+            command.arg("-s");
+            command.arg(shell.as_path().as_os_str());
+        }
+
+        if group::exists(&self.parameters.group)? || self.parameters.group != self.parameters.name {
+            command.arg("-g");
+            command.arg(self.parameters.group.as_str());
+        }
+
+        if !self.parameters.groups.is_empty() {
+            command.arg("-G");
+            command.arg(self.groups());
+        }
+
+        Ok(command)
+    }
+
+    /// The supplementary groups as a comma-separated list.
+    fn groups(&self) -> String {
+        self.parameters
+            .groups
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<&str>>()
+            .join(",")
     }
 
     /// Update the user account if necessary.
-    fn maybe_update(&self, pid: u32, current_user: CurrentUser) -> Result<Action, anyhow::Error> {
+    fn maybe_update(
+        &self,
+        pid: u32,
+        noop: bool,
+        current_user: CurrentUser,
+    ) -> Result<Action, anyhow::Error> {
         let mut action = Action::default();
 
-        let mut usermod = Command::new(USERMOD);
+        let platform = platform::get();
+
+        let (mut usermod, options) = match platform {
+            Platform::FreeBsd => {
+                let mut command = privileges::command(platform::program(group::PW));
+                command.arg("usermod");
+                (command, PW_OPTIONS)
+            }
+            Platform::Debian | Platform::Alpine => (
+                privileges::command(platform::program(USERMOD)),
+                SHADOW_OPTIONS,
+            ),
+        };
+
+        // Options are only passed if something changes.
+        let initial = usermod.get_args().count();
 
         match (current_user.comment, &self.parameters.comment) {
             (Some(current), Some(desired)) if current != *desired => {
-                usermod.arg("--comment");
+                usermod.arg(options.comment);
                 usermod.arg(desired);
             }
             (Some(_), None) => {
-                usermod.arg("--comment");
+                usermod.arg(options.comment);
+
+                if platform == Platform::FreeBsd {
+                    usermod.arg("");
+                }
             }
             (None, Some(desired)) => {
-                usermod.arg("--comment");
+                usermod.arg(options.comment);
                 usermod.arg(desired);
             }
             _ => {}
@@ -328,35 +462,48 @@ impl User {
 
         if let Some(shell) = &self.parameters.shell {
             if *shell != current_user.shell {
-                usermod.arg("--shell");
+                usermod.arg(options.shell);
                 usermod.arg(shell.as_path().as_os_str());
             }
         }
 
+        // pw(8) creates the new home directory, but does not move the
+        // contents of the current one.
         if self.parameters.home != current_user.home {
-            usermod.arg("--move-home");
-            usermod.arg("--home");
+            usermod.arg(match platform {
+                Platform::FreeBsd => "-m",
+                Platform::Debian | Platform::Alpine => "--move-home",
+            });
+            usermod.arg(options.home);
             usermod.arg(self.parameters.home.as_path().as_os_str());
         }
 
+        let expiry_date = |date: &Date| match platform {
+            Platform::FreeBsd => date.format(&PW_EXPIRY_DATE_FORMAT),
+            Platform::Debian | Platform::Alpine => date.format(&EXPIRY_DATE_FORMAT),
+        };
+
         match (current_user.expiry_date, &self.parameters.expiry_date) {
             (Some(current), Some(desired)) if current != *desired => {
-                usermod.arg("--expiredate");
-                usermod.arg(desired.format(&EXPIRY_DATE_FORMAT)?);
+                usermod.arg(options.expiry_date);
+                usermod.arg(expiry_date(desired)?);
             }
             (None, Some(desired)) => {
-                usermod.arg("--expiredate");
-                usermod.arg(desired.format(&EXPIRY_DATE_FORMAT)?);
+                usermod.arg(options.expiry_date);
+                usermod.arg(expiry_date(desired)?);
             }
             (Some(_), None) => {
-                usermod.arg("--expiredate");
-                usermod.arg("");
+                usermod.arg(options.expiry_date);
+                usermod.arg(match platform {
+                    Platform::FreeBsd => "0",
+                    Platform::Debian | Platform::Alpine => "",
+                });
             }
             _ => {}
         }
 
         if current_user.group != self.parameters.group.as_str() {
-            usermod.arg("--gid");
+            usermod.arg(options.gid);
             usermod.arg(self.parameters.group.as_str());
         }
 
@@ -368,34 +515,52 @@ impl User {
             .collect::<Vec<String>>();
 
         if current_user.groups != _groups {
-            usermod.arg("--groups");
+            usermod.arg(options.groups);
             usermod.arg(_groups.as_slice().join(","));
         }
 
-        let mut passwd = Command::new(PASSWD);
+        // On FreeBSD chpass(1) sets the password hash, which also replaces
+        // the prefix of a locked password, and `pw lock` locks it.
+        let (mut passwd, password_option) = match platform {
+            Platform::FreeBsd => (privileges::command(platform::program(CHPASS)), "-p"),
+            Platform::Debian | Platform::Alpine => {
+                (privileges::command(platform::program(PASSWD)), "--password")
+            }
+        };
 
         match (current_user.password, &self.parameters.password) {
-            (Password::Unlocked(current_password), Password::Unlocked(password)) => {
-                if current_password != *password {
-                    passwd.arg("--password");
-                    passwd.arg(password.as_str());
-                }
-            }
-            (Password::Unlocked(_), Password::Locked) => {
-                usermod.arg("--lock");
+            (Password::Unlocked(current_password), Password::Unlocked(password))
+                if current_password != *password =>
+            {
+                passwd.arg(password_option);
+                passwd.arg(password.as_str());
             }
+            (Password::Unlocked(_), Password::Locked) => match platform {
+                Platform::FreeBsd => {
+                    passwd = privileges::command(platform::program(group::PW));
+                    passwd.arg("lock");
+                }
+                Platform::Debian | Platform::Alpine => {
+                    usermod.arg("--lock");
+                }
+            },
             (Password::Locked, Password::Unlocked(password)) => {
-                usermod.arg("--unlock");
-                passwd.arg("--password");
+                if platform != Platform::FreeBsd {
+                    usermod.arg("--unlock");
+                }
+
+                passwd.arg(password_option);
                 passwd.arg(password.as_str());
             }
             _ => {}
         }
 
-        if usermod.get_args().count() != 0 {
+        if usermod.get_args().count() != initial {
+            if platform == Platform::FreeBsd {
+                usermod.arg("-n");
+            }
+
             usermod.arg(self.parameters.name.as_str());
-            usermod.stderr(Stdio::null());
-            usermod.stdout(Stdio::null());
 
             debug!(
                 pid,
@@ -406,14 +571,8 @@ impl User {
                 usermod.get_args()
             );
 
-            let status = usermod.status()?;
-
-            if !status.success() {
-                anyhow::bail!(
-                    "failed to update user account, {:?} exited with status {}",
-                    usermod.get_program(),
-                    status.code().unwrap()
-                );
+            if !noop {
+                super::run(&mut usermod).context("failed to update user account")?;
             }
 
             action = Action::Changed;
@@ -421,8 +580,6 @@ impl User {
 
         if passwd.get_args().count() != 0 {
             passwd.arg(self.parameters.name.as_str());
-            passwd.stderr(Stdio::null());
-            passwd.stdout(Stdio::null());
 
             debug!(
                 pid,
@@ -433,14 +590,8 @@ impl User {
                 passwd.get_args()
             );
 
-            let status = passwd.status()?;
-
-            if !status.success() {
-                anyhow::bail!(
-                    "failed to set user account password, {:?} exited with status {}",
-                    passwd.get_program(),
-                    status.code().unwrap()
-                );
+            if !noop {
+                super::run(&mut passwd).context("failed to set user account password")?;
             }
 
             action = Action::Changed;
@@ -459,7 +610,7 @@ impl User {
     }
 
     /// Delete the user account.
-    fn delete(&self, pid: u32) -> Result<Action, anyhow::Error> {
+    fn delete(&self, pid: u32, noop: bool) -> Result<Action, anyhow::Error> {
         debug!(
             pid,
             resource = self.kind(),
@@ -467,27 +618,35 @@ impl User {
             "deleting user account"
         );
 
-        let mut command = Command::new(DELUSER);
+        if noop {
+            return Ok(Action::Deleted);
+        }
+
+        let mut command = match platform::get() {
+            Platform::FreeBsd => {
+                let mut command = privileges::command(platform::program(group::PW));
+                command.args(["userdel", "-n"]);
+                command
+            }
+            Platform::Debian | Platform::Alpine => privileges::command(platform::program(DELUSER)),
+        };
 
         command.arg(self.parameters.name.as_str());
 
-        let status = command
-            .stderr(Stdio::null())
-            .stdout(Stdio::null())
-            .status()?;
-
-        if !status.success() {
-            anyhow::bail!(
-                "failed to delete user account, {:?} exited with status {}",
-                command.get_program(),
-                status.code().unwrap()
-            );
-        }
+        super::run(&mut command).context("failed to delete user account")?;
 
         Ok(Action::Deleted)
     }
 }
 
+/// The programs that manage user accounts on this platform.
+pub(super) fn programs() -> &'static [&'static str] {
+    match platform::get() {
+        Platform::FreeBsd => &[group::PW, CHPASS],
+        Platform::Debian | Platform::Alpine => &[USERADD, USERMOD, PASSWD, DELUSER],
+    }
+}
+
 #[derive(Clone, Debug)]
 struct CurrentUser {
     comment: Option<String>,
@@ -502,88 +661,68 @@ struct CurrentUser {
 /// Try to find this user account in the system. If it exists, return the
 /// relevant subset of the current user configuration.
 fn find(name: &Name) -> Result<Option<CurrentUser>, anyhow::Error> {
-    let (comment, home, shell) = {
-        match fs::read_to_string("/etc/passwd")?
-            .lines()
-            .find(|line| matches!(line.split_once(':'), Some((field, _)) if field == name.as_str()))
-        {
-            Some(line) => {
-                let parts = line.split(':').collect::<Vec<&str>>();
-
-                let comment = match parts.get(4) {
-                    Some(value) if !value.is_empty() => Some(value.to_string()),
-                    _ => None,
-                };
+    let (comment, home, shell) = if root::get().is_some() {
+        // name:password:uid:gid:gecos:home:shell
+        let Some(entry) = root::lookup("passwd", name.as_str(), 7)? else {
+            return Ok(None);
+        };
 
-                let home = match parts.get(5) {
-                    Some(value) => SafePathBuf::from_str(value)?,
-                    None => return Ok(None),
-                };
+        let comment = Some(entry[4].clone()).filter(|comment| !comment.is_empty());
 
-                let shell = match parts.get(6) {
-                    Some(value) => SafePathBuf::from_str(value)?,
-                    None => return Ok(None),
-                };
+        let home = SafePathBuf::from_str(&entry[5])?;
+        let shell = SafePathBuf::from_str(&entry[6])?;
 
-                (comment, home, shell)
-            }
+        (comment, home, shell)
+    } else {
+        let user = match nix::unistd::User::from_name(name.as_str())
+            .with_context(|| format!("failed to search for user '{}'", name.as_str()))?
+        {
+            Some(user) => user,
             None => return Ok(None),
-        }
-    };
+        };
 
-    let (password, expiry_date) = {
-        match fs::read_to_string("/etc/shadow")?
-            .lines()
-            .find(|line| matches!(line.split_once(':'), Some((field, _)) if field == name.as_str()))
-        {
-            Some(line) => {
-                let parts = line.split(':').collect::<Vec<&str>>();
+        let comment = match user.gecos.to_str()? {
+            "" => None,
+            value => Some(value.to_string()),
+        };
 
-                let password = match parts.get(1) {
-                    Some(value) => Password::from_str(value)?,
-                    None => return Ok(None),
-                };
+        let home = SafePathBuf::from_str(&user.dir.to_string_lossy())?;
+        let shell = SafePathBuf::from_str(&user.shell.to_string_lossy())?;
 
-                let expiry_date = match parts.get(7) {
-                    Some(value) if !value.is_empty() => {
-                        let epoch = time::macros::date!(1970 - 01 - 01);
-                        let date = epoch + time::Duration::days(value.parse::<i64>()?);
-                        Some(date)
-                    }
-                    _ => None,
-                };
+        (comment, home, shell)
+    };
 
-                (password, expiry_date)
-            }
-            None => return Ok(None),
+    // Accounts from other NSS sources than the local files may lack a shadow
+    // entry, in which case they have no password that pullconf could manage.
+    let (password, expiry_date) = match shadow(name)? {
+        Some((password, expiry_date)) => {
+            let password = Password::from_str(&password)?;
+
+            let expiry_date = expiry_date.map(|days| {
+                let epoch = time::macros::date!(1970 - 01 - 01);
+                epoch + time::Duration::days(days)
+            });
+
+            (password, expiry_date)
         }
+        None => (Password::Locked, None),
     };
 
     let (group, groups) = {
-        let mut command = Command::new(ID);
+        let mut command = root::command(platform::program(ID));
 
-        command.arg("--groups");
-        command.arg("--name");
+        // The short options are understood by the id(1) of FreeBSD as well.
+        command.arg("-G");
+        command.arg("-n");
         command.arg(name.as_str());
 
-        let output = command
-            .stderr(Stdio::null())
-            .stdout(Stdio::piped())
-            .output()?;
+        let output = super::run(&mut command).context("failed to query user's groups")?;
 
-        let mut groups = if !output.status.success() {
-            anyhow::bail!(
-                "failed to query user's groups, {:?} exited with status {}",
-                command.get_program(),
-                output.status.code().unwrap()
-            )
-        } else {
-            String::from_utf8(output.stdout)?
-                .split_whitespace()
-                .filter(|s| !s.is_empty())
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>()
-        };
+        let mut groups = String::from_utf8(output.stdout)?
+            .split_whitespace()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
 
         // The first group is the user's primary group.
         let group = groups.remove(0);
@@ -606,3 +745,131 @@ fn find(name: &Name) -> Result<Option<CurrentUser>, anyhow::Error> {
 
     Ok(Some(user))
 }
+
+/// Query the shadow entry of a user account via NSS and return its password
+/// hash and its expiry date in days since the epoch, if any.
+fn shadow(name: &Name) -> Result<Option<(String, Option<i64>)>, anyhow::Error> {
+    if root::get().is_some() {
+        return match platform::get() {
+            // name:password:uid:gid:class:change:expire:gecos:home:shell
+            Platform::FreeBsd => Ok(root::lookup("master.passwd", name.as_str(), 10)?
+                .map(|entry| (pw_password(&entry[1]), pw_expiry_date(&entry[6])))),
+            // name:password:lastchange:min:max:warn:inactive:expire:reserved
+            Platform::Debian | Platform::Alpine => Ok(root::lookup("shadow", name.as_str(), 8)?
+                .map(|entry| {
+                    let expiry_date = entry[7].parse::<i64>().ok().filter(|days| *days >= 0);
+
+                    (entry[1].clone(), expiry_date)
+                })),
+        };
+    }
+
+    query_shadow(name)
+}
+
+/// FreeBSD has no shadow entries, instead getpwnam(3) returns the password
+/// hash and the expiry date from `master.passwd` to root.
+#[cfg(target_os = "freebsd")]
+fn query_shadow(name: &Name) -> Result<Option<(String, Option<i64>)>, anyhow::Error> {
+    let user = nix::unistd::User::from_name(name.as_str())
+        .with_context(|| format!("failed to search for user '{}'", name.as_str()))?;
+
+    Ok(user.map(|user| {
+        (
+            pw_password(&user.passwd.to_string_lossy()),
+            pw_expiry_date(&user.expire.to_string()),
+        )
+    }))
+}
+
+/// Query the shadow entry of a user account via NSS.
+#[cfg(target_os = "linux")]
+fn query_shadow(name: &Name) -> Result<Option<(String, Option<i64>)>, anyhow::Error> {
+    use std::{
+        ffi::{CStr, CString},
+        io,
+        mem::MaybeUninit,
+        ptr,
+    };
+
+    let name = CString::new(name.as_str())?;
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+
+    loop {
+        let mut entry = MaybeUninit::<libc::spwd>::uninit();
+        let mut result: *mut libc::spwd = ptr::null_mut();
+
+        // SAFETY: all pointers are valid for the duration of the call and
+        // the length passed matches the length of the buffer.
+        let errno = unsafe {
+            libc::getspnam_r(
+                name.as_ptr(),
+                entry.as_mut_ptr(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                &mut result,
+            )
+        };
+
+        match errno {
+            0 if result.is_null() => return Ok(None),
+            0 => {
+                // SAFETY: on success the entry has been initialized and its
+                // strings point into the buffer, which is still alive.
+                let entry = unsafe { entry.assume_init() };
+
+                if entry.sp_pwdp.is_null() {
+                    return Ok(None);
+                }
+
+                let password = unsafe { CStr::from_ptr(entry.sp_pwdp) }
+                    .to_str()?
+                    .to_string();
+
+                let expiry_date = match entry.sp_expire {
+                    days if days >= 0 => Some(days),
+                    _ => None,
+                };
+
+                return Ok(Some((password, expiry_date)));
+            }
+            libc::ENOENT => return Ok(None),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            errno => {
+                return Err(io::Error::from_raw_os_error(errno))
+                    .context("failed to query shadow entry of user account")
+            }
+        }
+    }
+}
+
+/// Passwords that pw(8) locked are prefixed instead of `!`.
+fn pw_password(password: &str) -> String {
+    match password.strip_prefix(PW_LOCKED) {
+        Some(_) => "!".to_string(),
+        None => password.to_string(),
+    }
+}
+
+/// FreeBSD stores expiry dates in seconds since the epoch, where `0` means
+/// that the account never expires.
+fn pw_expiry_date(seconds: &str) -> Option<i64> {
+    seconds
+        .parse::<i64>()
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .map(|seconds| seconds / 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_pw_entries() {
+        assert_eq!(pw_password("*LOCKED*$6$salt$hash"), "!");
+        assert_eq!(pw_password("$6$salt$hash"), "$6$salt$hash");
+        assert_eq!(pw_expiry_date("0"), None);
+        assert_eq!(pw_expiry_date("1924905600"), Some(22279));
+    }
+}
@@ -0,0 +1,252 @@
+use anyhow::Context;
+use common::error::Terminate;
+use log::{error, info};
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Component, Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+/// Commands of resources are executed within the alternate root with
+/// chroot(8), so that e.g. apt-get installs packages into the target system.
+pub const CHROOT: &str = "/usr/sbin/chroot";
+
+/// The number of symlinks that are followed while resolving a path within
+/// the alternate root, like the limit of Linux.
+const MAX_SYMLINKS: usize = 40;
+
+/// The alternate root directory that resources are applied to, see `init`.
+/// Without `--root` this is not set and resources are applied to `/`.
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Apply resources relative to an alternate root directory, e.g. the mount
+/// point of a system image, instead of `/`. Paths of the resource catalog
+/// keep referring to the target system, i.e. `/etc/motd` is written to
+/// `<root>/etc/motd` and symlinks point to targets as seen from within it.
+pub fn init(pid: u32, root: &str) -> Result<(), Terminate> {
+    let scope = "root";
+
+    let path = match Path::new(root).canonicalize() {
+        Ok(path) if path.is_dir() => path,
+        Ok(_) => {
+            error!(scope, pid; "alternate root {} is not a directory", root);
+            return Err(Terminate);
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to access alternate root {}: {}", root, error);
+            return Err(Terminate);
+        }
+    };
+
+    // Applying resources relative to `/` is what happens without `--root`.
+    if path == Path::new("/") {
+        return Ok(());
+    }
+
+    info!(scope, pid; "applying resources relative to alternate root {}", path.display());
+
+    let _ = ROOT.set(path);
+
+    Ok(())
+}
+
+/// The alternate root directory, if pullconf applies resources relative to
+/// one.
+pub fn get() -> Option<&'static Path> {
+    ROOT.get().map(PathBuf::as_path)
+}
+
+/// Return the path on this system that an absolute path of the target
+/// system refers to. Symlinks are resolved as if the alternate root was
+/// `/`, like openat2(2) with `RESOLVE_IN_ROOT`, so that e.g. a symlink to
+/// `/etc/alternatives/editor` within a system image does not lead to the
+/// file of this system.
+pub fn path(path: &Path) -> io::Result<PathBuf> {
+    match get() {
+        Some(root) => resolve(root, path, true),
+        None => Ok(path.to_path_buf()),
+    }
+}
+
+/// Like `path`, but the last component is not followed if it is a symlink,
+/// i.e. the path of a symlink itself.
+pub fn link_path(path: &Path) -> io::Result<PathBuf> {
+    match get() {
+        Some(root) => resolve(root, path, false),
+        None => Ok(path.to_path_buf()),
+    }
+}
+
+/// Resolve a path component by component within the root. Absolute
+/// symlink targets start over at the root and `..` never leaves it, so
+/// every component of the result is a directory within the root, except
+/// for components that do not exist yet and, unless followed, the last one.
+fn resolve(root: &Path, path: &Path, follow: bool) -> io::Result<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    let mut pending = components(path);
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        if component == ".." {
+            if resolved != root {
+                resolved.pop();
+            }
+
+            continue;
+        }
+
+        let candidate = resolved.join(&component);
+
+        if !follow && pending.is_empty() {
+            return Ok(candidate);
+        }
+
+        match fs::symlink_metadata(&candidate) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                links += 1;
+
+                if links > MAX_SYMLINKS {
+                    return Err(io::Error::other(format!(
+                        "too many levels of symbolic links in {}",
+                        path.display()
+                    )));
+                }
+
+                let target = fs::read_link(&candidate)?;
+
+                if target.is_absolute() {
+                    resolved = root.to_path_buf();
+                }
+
+                pending.extend(components(&target));
+            }
+            _ => resolved = candidate,
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// The components of a path that are resolved one by one, in reverse order
+/// so that the next one is popped off the end.
+fn components(path: &Path) -> Vec<OsString> {
+    path.components()
+        .rev()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_os_string()),
+            Component::ParentDir => Some(OsString::from("..")),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => None,
+        })
+        .collect()
+}
+
+/// Create a command that executes a program of the target system, i.e.
+/// within the alternate root via chroot(8) if there is one.
+pub fn command<S: AsRef<OsStr>>(program: S) -> Command {
+    match get() {
+        Some(root) => {
+            let mut command = Command::new(CHROOT);
+            command.arg(root).arg(program);
+            command
+        }
+        None => Command::new(program),
+    }
+}
+
+/// Find the entry of a name in a database of the target system that is
+/// stored in a colon-separated file, i.e. `passwd`, `group` or `shadow`,
+/// and return its fields. Within an alternate root NSS cannot be queried,
+/// so only local accounts are found.
+pub fn lookup(
+    database: &str,
+    name: &str,
+    fields: usize,
+) -> Result<Option<Vec<String>>, anyhow::Error> {
+    let file = path(&Path::new("/etc").join(database))
+        .with_context(|| format!("failed to resolve /etc/{}", database))?;
+
+    let contents =
+        fs::read_to_string(&file).with_context(|| format!("failed to read {}", file.display()))?;
+
+    match find(&contents, name) {
+        Some(entry) if entry.len() < fields => anyhow::bail!(
+            "entry of '{}' in {} has {} instead of {} fields",
+            name,
+            file.display(),
+            entry.len(),
+            fields
+        ),
+        entry => Ok(entry),
+    }
+}
+
+fn find(contents: &str, name: &str) -> Option<Vec<String>> {
+    contents
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|entry| entry[0] == name)
+        .map(|entry| entry.into_iter().map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths_in_root() {
+        let directory = std::env::temp_dir().join(format!("pullconf-root-{}", std::process::id()));
+        let symlink = std::os::unix::fs::symlink;
+
+        fs::create_dir_all(directory.join("etc/alternatives")).unwrap();
+
+        let root = directory.canonicalize().unwrap();
+
+        symlink("/etc/alternatives", root.join("alternatives")).unwrap();
+        symlink("/usr/bin/vim", root.join("etc/alternatives/editor")).unwrap();
+        symlink("../../../../../etc", root.join("etc/escape")).unwrap();
+        symlink("loop", root.join("loop")).unwrap();
+
+        let resolve = |path: &str, follow: bool| resolve(&root, Path::new(path), follow).ok();
+
+        let results = [
+            resolve("/etc/motd", true),
+            resolve("/", true),
+            resolve("/../etc", true),
+            // Absolute and relative symlinks do not lead out of the root.
+            resolve("/alternatives/editor", true),
+            resolve("/etc/escape/passwd", true),
+            // The symlink itself unless it is followed.
+            resolve("/alternatives/editor", false),
+            resolve("/loop", true),
+        ];
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            results,
+            [
+                Some(root.join("etc/motd")),
+                Some(root.clone()),
+                Some(root.join("etc")),
+                Some(root.join("usr/bin/vim")),
+                Some(root.join("etc/passwd")),
+                Some(root.join("etc/alternatives/editor")),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn find_entries() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\
+                      www-data:x:33:33:www-data:/var/www:/usr/sbin/nologin\n";
+
+        assert_eq!(
+            find(passwd, "www-data").unwrap()[2..4],
+            ["33".to_string(), "33".to_string()]
+        );
+        assert_eq!(find(passwd, "www"), None);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::resources::{Resource, Resources};
+use common::{Purge, ResourceType, CATALOG_VERSION};
+use std::collections::{HashMap, VecDeque};
+use ureq::serde_json::{self, Value};
+use uuid::Uuid;
+
+/// A migration upgrades a resource catalog by one version, e.g. by renaming
+/// a parameter, so that catalogs that were saved or sent before a change of
+/// the format remain usable.
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// The migrations of resource catalogs in order, where the last one
+/// upgrades a catalog to `CATALOG_VERSION`. Catalogs that are older than
+/// the first migration are refused.
+const MIGRATIONS: [Migration; CATALOG_VERSION as usize - 1] = [];
+
+/// Validate a resource catalog as a whole before any of its resources is
+/// applied, so that a catalog that this version of pullconf does not
+/// understand is refused instead of failing halfway through a run. Every
+/// problem is reported with the position of the resource in the catalog,
+/// e.g. a resource type that was added in a later version, a missing
+/// parameter or an ID that is shared by two resources.
+pub fn validate(payload: &[u8]) -> Result<Resources, Vec<String>> {
+    let mut catalog = serde_json::from_slice::<Value>(payload)
+        .map_err(|error| vec![format!("the resource catalog is not valid JSON: {}", error)])?;
+
+    let version = match catalog.get("version") {
+        // pullconfd omitted the version before it was introduced.
+        None => CATALOG_VERSION,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                vec![format!(
+                    "the version of the resource catalog is invalid: {}",
+                    version
+                )]
+            })?,
+    };
+
+    if version > CATALOG_VERSION {
+        return Err(vec![format!(
+            "the resource catalog has version {}, but this version of pullconf only supports version {}, update pullconf",
+            version, CATALOG_VERSION
+        )]);
+    }
+
+    migrate(&mut catalog, version, &MIGRATIONS)?;
+
+    let purge = match catalog.get("purge") {
+        Some(purge) => serde_json::from_value::<Purge>(purge.clone()).map_err(|error| {
+            vec![format!(
+                "the namespaces to purge of the resource catalog are invalid: {}",
+                error
+            )]
+        })?,
+        None => Purge::default(),
+    };
+
+    let serial = match catalog.get("serial") {
+        Some(serial) => Some(serial.as_u64().ok_or_else(|| {
+            vec![format!(
+                "the serial of the resource catalog is invalid: {}",
+                serial
+            )]
+        })?),
+        None => None,
+    };
+
+    let Some(entries) = catalog.get("data").and_then(Value::as_array) else {
+        return Err(vec![
+            "the resource catalog lacks a list of resources named `data`".to_string(),
+        ]);
+    };
+
+    let mut errors = vec![];
+    let mut resources = VecDeque::with_capacity(entries.len());
+    let mut ids = HashMap::<Uuid, usize>::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let position = index + 1;
+
+        let kind = match entry.get("type") {
+            Some(Value::String(kind)) => kind,
+            Some(_) => {
+                errors.push(format!(
+                    "resource #{} has a type that is not a string",
+                    position
+                ));
+                continue;
+            }
+            None => {
+                errors.push(format!("resource #{} has no type", position));
+                continue;
+            }
+        };
+
+        if kind.parse::<ResourceType>().is_err() {
+            errors.push(format!(
+                "resource #{} has the type {}, which this version of pullconf does not know",
+                position, kind
+            ));
+            continue;
+        }
+
+        let id = match entry.get("id").and_then(Value::as_str).map(Uuid::parse_str) {
+            Some(Ok(id)) => id,
+            Some(Err(error)) => {
+                errors.push(format!(
+                    "resource #{} ({}) has an invalid ID: {}",
+                    position, kind, error
+                ));
+                continue;
+            }
+            None => {
+                errors.push(format!("resource #{} ({}) has no ID", position, kind));
+                continue;
+            }
+        };
+
+        if let Some(first) = ids.insert(id, position) {
+            errors.push(format!(
+                "resources #{} and #{} share the ID {}",
+                first, position, id
+            ));
+        }
+
+        match serde_json::from_value::<Resource>(entry.clone()) {
+            Ok(resource) => resources.push_back(resource),
+            Err(error) => errors.push(format!(
+                "resource #{} ({} {}) is invalid: {}",
+                position, kind, id, error
+            )),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(Resources {
+            data: resources,
+            serial,
+            purge,
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+/// Upgrade a resource catalog of the given version to `CATALOG_VERSION`.
+fn migrate(catalog: &mut Value, version: u32, migrations: &[Migration]) -> Result<(), Vec<String>> {
+    let oldest = CATALOG_VERSION - migrations.len() as u32;
+
+    if version < oldest {
+        return Err(vec![format!(
+            "the resource catalog has version {}, but this version of pullconf only supports versions {} to {}",
+            version, oldest, CATALOG_VERSION
+        )]);
+    }
+
+    for (from, migration) in (oldest..).zip(migrations).skip((version - oldest) as usize) {
+        migration(catalog).map_err(|error| {
+            vec![format!(
+                "failed to migrate the resource catalog from version {} to {}: {}",
+                from,
+                from + 1,
+                error
+            )]
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ureq::serde_json::json;
+
+    #[test]
+    fn validate_catalog() {
+        let group = |id: u128, name: &str| {
+            json!({
+                "type": "group",
+                "id": Uuid::from_u128(id),
+                "parameters": { "ensure": "present", "name": name, "system": false },
+                "relationships": { "requires": [] }
+            })
+        };
+
+        let catalog = |version: u32, data: Vec<Value>| {
+            serde_json::to_vec(
+                &json!({ "links": { "self": "/" }, "version": version, "data": data }),
+            )
+            .unwrap()
+        };
+
+        let resources = validate(&catalog(1, vec![group(1, "admins"), group(2, "web")])).unwrap();
+        assert_eq!(resources.data.len(), 2);
+
+        assert!(validate(&catalog(CATALOG_VERSION + 1, vec![])).is_err());
+
+        let mut unknown = group(3, "ops");
+        unknown["type"] = json!("firewall::rule");
+        let mut incomplete = group(4, "dev");
+        incomplete["parameters"]
+            .as_object_mut()
+            .unwrap()
+            .remove("ensure");
+
+        let errors = validate(&catalog(
+            1,
+            vec![group(1, "admins"), group(1, "web"), unknown, incomplete],
+        ))
+        .unwrap_err();
+
+        assert_eq!(errors.len(), 3);
+        assert!(errors[0].contains("#1 and #2"));
+        assert!(errors[1].contains("firewall::rule"));
+        assert!(errors[2].contains("`ensure`"));
+    }
+
+    #[test]
+    fn migrate_catalog() {
+        // Rename a parameter of groups as an example.
+        let rename: Migration = |catalog| {
+            for entry in catalog["data"]
+                .as_array_mut()
+                .ok_or("`data` is not a list")?
+            {
+                let parameters = entry["parameters"]
+                    .as_object_mut()
+                    .ok_or("`parameters` is not an object")?;
+
+                if let Some(system) = parameters.remove("is_system") {
+                    parameters.insert("system".to_string(), system);
+                }
+            }
+
+            Ok(())
+        };
+
+        let mut catalog = json!({
+            "version": CATALOG_VERSION - 1,
+            "data": [{ "parameters": { "is_system": true } }]
+        });
+
+        migrate(&mut catalog, CATALOG_VERSION - 1, &[rename]).unwrap();
+        assert_eq!(catalog["data"][0]["parameters"], json!({ "system": true }));
+
+        // Catalogs of the current version are not migrated.
+        let mut current = json!({ "data": [{ "parameters": { "is_system": true } }] });
+        migrate(&mut current, CATALOG_VERSION, &[rename]).unwrap();
+        assert_eq!(
+            current["data"][0]["parameters"],
+            json!({ "is_system": true })
+        );
+
+        assert!(migrate(&mut catalog, CATALOG_VERSION - 1, &[]).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::{configuration::name, resources::Resource};
+use common::error::Terminate;
+use log::error;
+use serde::Serialize;
+use std::collections::VecDeque;
+use ureq::serde_json;
+use uuid::Uuid;
+
+/// A resource of the catalog as it is printed with `--json`.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    name: String,
+    id: Uuid,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    requires: Vec<String>,
+    parameters: serde_json::Value,
+}
+
+/// Return the resources of the catalog with one of the given types, e.g.
+/// `apt::package`, or one of the given names, e.g. `file:/etc/motd`. Without
+/// any filter the whole catalog is returned. Unlike `--only` when applying
+/// the catalog, dependencies are not included.
+fn filter<'a>(
+    resources: &'a VecDeque<Resource>,
+    types: &[String],
+    only: &[String],
+) -> Result<Vec<&'a Resource>, String> {
+    let names = resources.iter().map(name).collect::<Vec<String>>();
+
+    if let Some(missing) = only.iter().find(|only| !names.contains(only)) {
+        return Err(format!("resource {} is not part of the catalog", missing));
+    }
+
+    Ok(resources
+        .iter()
+        .zip(names)
+        .filter(|(resource, name)| {
+            (types.is_empty() && only.is_empty())
+                || types.iter().any(|kind| kind == resource.kind())
+                || only.contains(name)
+        })
+        .map(|(resource, _)| resource)
+        .collect())
+}
+
+/// Print the resource catalog, i.e. what pullconfd intends for this system,
+/// without evaluating it against the current state of the system.
+pub fn print_catalog(
+    pid: u32,
+    resources: &VecDeque<Resource>,
+    types: &[String],
+    only: &[String],
+    json: bool,
+) -> Result<(), Terminate> {
+    let scope = "show";
+
+    let selected = filter(resources, types, only).map_err(|error| {
+        error!(scope, pid; "{}", error);
+        Terminate
+    })?;
+
+    let mut entries = Vec::with_capacity(selected.len());
+
+    for resource in &selected {
+        let parameters = resource.parameters().map_err(|error| {
+            error!(scope, pid; "failed to serialize parameters of {}: {}", name(resource), error);
+            Terminate
+        })?;
+
+        // Dependencies are referenced by their ID, which is of little use
+        // to a reader.
+        let requires = resource
+            .dependencies()
+            .iter()
+            .map(|dependency| {
+                resources
+                    .iter()
+                    .find(|resource| resource.id() == dependency.id)
+                    .map(name)
+                    .unwrap_or_else(|| dependency.id.to_string())
+            })
+            .collect();
+
+        entries.push(Entry {
+            kind: resource.kind(),
+            name: name(resource),
+            id: resource.id(),
+            tags: resource.tags().to_vec(),
+            requires,
+            parameters,
+        });
+    }
+
+    if json {
+        return match serde_json::to_string_pretty(&entries) {
+            Ok(json) => {
+                println!("{}", json);
+                Ok(())
+            }
+            Err(error) => {
+                error!(scope, pid; "failed to serialize resource catalog: {}", error);
+                Err(Terminate)
+            }
+        };
+    }
+
+    for (index, entry) in entries.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+
+        println!("{}", entry.name);
+        println!("  id:         {}", entry.id);
+
+        if !entry.tags.is_empty() {
+            println!("  tags:       {}", entry.tags.join(", "));
+        }
+
+        if !entry.requires.is_empty() {
+            println!("  requires:   {}", entry.requires.join(", "));
+        }
+
+        if let serde_json::Value::Object(parameters) = &entry.parameters {
+            println!("  parameters:");
+
+            for (key, value) in parameters {
+                match value {
+                    serde_json::Value::String(value) if !value.contains('\n') => {
+                        println!("    {}: {}", key, value)
+                    }
+                    value => println!("    {}: {}", key, value),
+                }
+            }
+        }
+    }
+
+    if entries.len() < resources.len() {
+        println!();
+        println!("{} of {} resources shown", entries.len(), resources.len());
+    } else if !entries.is_empty() {
+        println!();
+        println!("{} resources", entries.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_resources_by_type_and_name() {
+        let resources: VecDeque<Resource> = serde_json::from_value(serde_json::json!([
+            {
+                "type": "group",
+                "id": "d8ab8e7c-5d34-4a8a-9b0b-0b5c8a7f3f01",
+                "parameters": { "ensure": "present", "name": "admins", "system": false },
+                "relationships": { "requires": [] },
+            },
+            {
+                "type": "group",
+                "id": "d8ab8e7c-5d34-4a8a-9b0b-0b5c8a7f3f02",
+                "parameters": { "ensure": "present", "name": "web", "system": false },
+                "relationships": { "requires": [] },
+            },
+        ]))
+        .unwrap();
+
+        let names = |selected: Vec<&Resource>| selected.into_iter().map(name).collect::<Vec<_>>();
+
+        assert_eq!(
+            filter(&resources, &[], &[]).map(names),
+            Ok(vec!["group:admins".to_string(), "group:web".to_string()])
+        );
+        assert_eq!(
+            filter(&resources, &["user".to_string()], &[]).map(names),
+            Ok(vec![])
+        );
+        assert_eq!(
+            filter(&resources, &[], &["group:web".to_string()]).map(names),
+            Ok(vec!["group:web".to_string()])
+        );
+        assert!(filter(&resources, &[], &["group:missing".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+use std::sync::LazyLock;
+
+/// The directory that holds the state of pullconf, e.g. the saved resource
+/// catalog, the history of runs and the asset cache, unless another
+/// directory is set with $PULLCONF_STATE_DIR, e.g. for tests.
+const DEFAULT_STATE_DIR: &str = "/var/lib/pullconf";
+
+static STATE_DIR: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("PULLCONF_STATE_DIR")
+        .ok()
+        .map(|directory| directory.trim_end_matches('/').to_string())
+        .filter(|directory| directory.starts_with('/'))
+        .unwrap_or(DEFAULT_STATE_DIR.to_string())
+});
+
+pub fn directory() -> &'static str {
+    &STATE_DIR
+}
+
+/// The path of a file or directory in the state directory.
+pub fn path(name: &str) -> String {
+    format!("{}/{}", directory(), name)
+}
@@ -0,0 +1,86 @@
+use log::{debug, warn};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::{
+    env,
+    os::unix::net::{SocketAddr, UnixDatagram},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+/// Send a state change notification such as `READY=1` to the service
+/// manager. This does nothing unless pullconf is started by systemd
+/// with `Type=notify`.
+pub fn notify(pid: u32, state: &str) {
+    let scope = "systemd";
+
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+
+    // Socket paths that start with `@` refer to the abstract namespace,
+    // which only exists on Linux.
+    let address = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => SocketAddr::from_abstract_name(name),
+        _ => SocketAddr::from_pathname(&path),
+    };
+
+    let result = address
+        .and_then(|address| UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address));
+
+    match result {
+        Ok(_) => debug!(scope, pid; "sent notification {:?}", state),
+        Err(error) => warn!(scope, pid; "failed to notify service manager: {}", error),
+    }
+}
+
+/// Reset the watchdog timer of the service manager. This does nothing
+/// unless `WatchdogSec` is set in the service unit.
+pub fn keepalive(pid: u32) {
+    if watchdog().is_some() {
+        notify(pid, "WATCHDOG=1");
+    }
+}
+
+/// Sleep for the given duration while resetting the watchdog timer often
+/// enough to keep the service from being restarted.
+pub fn sleep(pid: u32, duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining.is_zero() {
+            break;
+        }
+
+        thread::sleep(remaining.min(keepalive_interval()));
+        keepalive(pid);
+    }
+}
+
+/// Return the interval in which the watchdog timer needs to be reset, i.e.
+/// half of the configured timeout as recommended by sd_watchdog_enabled(3),
+/// or an arbitrarily long interval if the watchdog is disabled.
+pub fn keepalive_interval() -> Duration {
+    watchdog().map_or(Duration::MAX, |timeout| timeout / 2)
+}
+
+/// Return the watchdog timeout that systemd passes via `WATCHDOG_USEC`
+/// if it is meant for this process.
+fn watchdog() -> Option<Duration> {
+    let meant_for_this_process = match env::var("WATCHDOG_PID") {
+        Ok(pid) => pid.parse::<u32>().is_ok_and(|pid| pid == process::id()),
+        Err(_) => true,
+    };
+
+    if !meant_for_this_process {
+        return None;
+    }
+
+    env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+        .map(Duration::from_micros)
+}
@@ -0,0 +1,173 @@
+use crate::state;
+use log::{debug, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    fs::{self, DirBuilder, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+/// The directory that holds the replacement files of resources until they
+/// are renamed to their targets.
+static TMP_DIR: LazyLock<String> = LazyLock::new(|| state::path("tmp"));
+
+/// Every temporary file is registered in this journal while it exists, so
+/// that files left behind by a run that crashed can be removed by the next
+/// run.
+static JOURNAL_FILE: LazyLock<String> = LazyLock::new(|| state::path("tmp/journal"));
+
+/// Serializes changes to the journal, as resources may be applied
+/// concurrently.
+static JOURNAL: Mutex<()> = Mutex::new(());
+
+/// A temporary file with a unique name that replaces a target file once it
+/// is complete. The file is removed when it is dropped without having been
+/// persisted.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Create a temporary file with the given content. The prefix is part of
+    /// the file name to tell which resource it belongs to, e.g. `hosts`.
+    pub fn write(prefix: &str, content: &[u8]) -> Result<Self, std::io::Error> {
+        let suffix = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect::<String>();
+
+        let name = format!(
+            "{}.{}.{}",
+            prefix.replace(['/', '\0'], "_"),
+            std::process::id(),
+            suffix
+        );
+
+        let file = Self {
+            path: Path::new(&*TMP_DIR).join(name),
+        };
+
+        register(&file.path)?;
+
+        // The file is removed on drop if it cannot be written completely.
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file.path)
+            .and_then(|mut handle| handle.write_all(content))?;
+
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rename the temporary file to its target, which is replaced
+    /// atomically. If the target is on another file system, e.g. `/etc`
+    /// on a separate partition, the content is copied instead.
+    pub fn persist(self, target: &Path) -> Result<(), std::io::Error> {
+        match fs::rename(&self.path, target) {
+            Err(error) if error.raw_os_error() == Some(libc::EXDEV) => {
+                fs::copy(&self.path, target).map(|_| ())
+            }
+            result => result,
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => (),
+            Err(_) => return,
+        }
+
+        let _ = unregister(&self.path);
+    }
+}
+
+/// Add a temporary file to the journal.
+fn register(path: &Path) -> Result<(), std::io::Error> {
+    let _guard = JOURNAL.lock().unwrap_or_else(|error| error.into_inner());
+
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&*JOURNAL_FILE)
+        .and_then(|mut journal| writeln!(journal, "{}", path.display()))
+}
+
+/// Remove a temporary file from the journal.
+fn unregister(path: &Path) -> Result<(), std::io::Error> {
+    let _guard = JOURNAL.lock().unwrap_or_else(|error| error.into_inner());
+
+    let journal = fs::read_to_string(&*JOURNAL_FILE)?;
+
+    let remaining = journal
+        .lines()
+        .filter(|line| Path::new(line) != path)
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+
+    fs::write(&*JOURNAL_FILE, remaining)
+}
+
+/// Create the directory for temporary files and remove every temporary
+/// file that a previous run left behind, e.g. because it crashed. This
+/// must only be called while holding the lock, so that no other run
+/// creates temporary files at the same time. Failures are logged, as they
+/// do not prevent the run from succeeding.
+pub fn cleanup(pid: u32) {
+    let scope = "tmp";
+
+    if let Err(error) = DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&*TMP_DIR)
+    {
+        warn!(scope, pid; "failed to create directory {}: {}", *TMP_DIR, error);
+        return;
+    }
+
+    let _guard = JOURNAL.lock().unwrap_or_else(|error| error.into_inner());
+
+    // Only files within the directory are ever registered.
+    let mut leftovers = fs::read_to_string(&*JOURNAL_FILE)
+        .unwrap_or_default()
+        .lines()
+        .map(PathBuf::from)
+        .filter(|path| path.parent() == Some(Path::new(&*TMP_DIR)))
+        .collect::<Vec<PathBuf>>();
+
+    // Files that were created, but not registered, are removed as well.
+    let unregistered = fs::read_dir(&*TMP_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path != Path::new(&*JOURNAL_FILE) && !leftovers.contains(path))
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+
+    leftovers.extend(unregistered);
+
+    for path in leftovers {
+        match fs::remove_file(&path) {
+            Ok(()) => debug!(scope, pid; "removed leftover temporary file {}", path.display()),
+            Err(error) if error.kind() == ErrorKind::NotFound => (),
+            Err(error) => {
+                warn!(scope, pid; "failed to remove leftover temporary file {}: {}", path.display(), error)
+            }
+        }
+    }
+
+    if let Err(error) = fs::write(&*JOURNAL_FILE, "") {
+        warn!(scope, pid; "failed to reset journal {}: {}", *JOURNAL_FILE, error);
+    }
+}
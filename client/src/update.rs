@@ -0,0 +1,313 @@
+use crate::{configuration::Configuration, privileges, root};
+use anyhow::Context;
+use common::error::Terminate;
+use log::{debug, error, info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    io::Write,
+    os::unix::{fs::OpenOptionsExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use ureq::Response;
+
+/// The version of pullconf that pullconfd wants this client to run, as sent
+/// along with the resource catalog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Release {
+    pub version: String,
+    /// The SHA256 digest of the binary that is served at `/api/agent`.
+    pub sha256: String,
+}
+
+impl Release {
+    /// Read the advertised release from the headers of a catalog response.
+    /// Returns `None` if pullconfd does not advertise a release or the
+    /// headers are malformed.
+    pub fn from_response(response: &Response) -> Option<Self> {
+        let version = response.header("x-pullconf-version")?.trim();
+        let sha256 = response.header("x-pullconf-sha256")?.trim();
+
+        if version.is_empty()
+            || sha256.len() != 64
+            || !sha256.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return None;
+        }
+
+        Some(Self {
+            version: version.to_string(),
+            sha256: sha256.to_ascii_lowercase(),
+        })
+    }
+}
+
+/// The target that this binary was built for, e.g. `x86_64-linux-gnu`,
+/// `aarch64-linux-musl` or `x86_64-freebsd`, so that pullconfd only serves
+/// a binary that this system can execute.
+pub fn target() -> String {
+    let libc = if cfg!(target_env = "musl") {
+        "-musl"
+    } else if cfg!(target_env = "gnu") {
+        "-gnu"
+    } else {
+        ""
+    };
+
+    format!("{}-{}{}", env::consts::ARCH, env::consts::OS, libc)
+}
+
+/// Whether the client updates itself, which can be disabled by setting
+/// `PULLCONF_SELF_UPDATE` to `false`. In least-privilege mode the client
+/// never updates itself, as replacing the executable would drop its file
+/// capabilities. With an alternate root the client does not update
+/// itself either, as the binary is not part of the target system.
+pub fn enabled() -> bool {
+    !privileges::is_unprivileged()
+        && root::get().is_none()
+        && !env::var("PULLCONF_SELF_UPDATE").is_ok_and(|value| value == "false" || value == "0")
+}
+
+/// Replace the executable of this process with the binary that pullconfd
+/// advertises, if it runs a different version. Returns the path of the
+/// executable if it has been replaced, in which case the caller should
+/// restart via `restart`.
+pub fn update(pid: u32, configuration: &Configuration) -> Result<Option<PathBuf>, Terminate> {
+    let scope = "update";
+
+    let Some(release) = configuration.release() else {
+        return Ok(None);
+    };
+
+    let executable = match current_exe() {
+        Ok(executable) => executable,
+        Err(error) => {
+            error!(scope, pid; "failed to determine path of the running executable: {}", error);
+            return Err(Terminate);
+        }
+    };
+
+    // The previous executable is kept until the updated one runs, see
+    // `restart`.
+    if release.version == crate::VERSION {
+        fs::remove_file(backup(&executable)).ok();
+        return Ok(None);
+    }
+
+    // If the executable already matches the advertised digest, the binary
+    // on the server does not report the advertised version. Replacing it
+    // again would restart pullconf on every run.
+    if fs::read(&executable)
+        .is_ok_and(|bytes| format!("{:x}", Sha256::digest(bytes)) == release.sha256)
+    {
+        warn!(
+            scope,
+            pid;
+            "executable {} matches the binary served by pullconfd, but reports version {} instead of {}",
+            executable.display(),
+            crate::VERSION,
+            release.version
+        );
+        return Ok(None);
+    }
+
+    info!(
+        scope,
+        pid;
+        "updating pullconf from version {} to {}",
+        crate::VERSION,
+        release.version
+    );
+
+    let bytes = match configuration.download_agent(pid) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            error!(scope, pid; "failed to download pullconf {}: {:#}", release.version, error);
+            return Err(Terminate);
+        }
+    };
+
+    let digest = format!("{:x}", Sha256::digest(&bytes));
+
+    if digest != release.sha256 {
+        error!(
+            scope,
+            pid;
+            "downloaded binary has digest {}, expected {}, keeping version {}",
+            digest,
+            release.sha256,
+            crate::VERSION
+        );
+        return Err(Terminate);
+    }
+
+    if let Err(error) = replace(&executable, &bytes) {
+        error!(scope, pid; "failed to replace executable {}: {:#}", executable.display(), error);
+        return Err(Terminate);
+    }
+
+    info!(scope, pid; "replaced executable {} with version {}", executable.display(), release.version);
+
+    Ok(Some(executable))
+}
+
+/// Run the replaced executable in place of this process with the same
+/// arguments. Only returns if the executable cannot be run, in which case
+/// the previous executable is restored.
+pub fn restart(pid: u32, executable: &Path) -> Terminate {
+    let scope = "update";
+
+    debug!(scope, pid; "restarting {}", executable.display());
+
+    let error = Command::new(executable).args(env::args_os().skip(1)).exec();
+
+    error!(scope, pid; "failed to restart {}: {}", executable.display(), error);
+
+    match fs::rename(backup(executable), executable) {
+        Ok(()) => {
+            warn!(scope, pid; "restored version {} of {}", crate::VERSION, executable.display())
+        }
+        Err(error) => {
+            error!(scope, pid; "failed to restore previous executable {}: {}", executable.display(), error)
+        }
+    }
+
+    Terminate
+}
+
+/// The path that the previous executable is kept at during an update.
+fn backup(executable: &Path) -> PathBuf {
+    executable
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .join(".pullconf.previous")
+}
+
+/// Return the path of the running executable. Its path is determined
+/// before it is replaced, as the running executable no longer has a path
+/// afterwards, which e.g. FreeBSD does not report at all.
+#[cfg(target_os = "linux")]
+fn current_exe() -> Result<PathBuf, std::io::Error> {
+    let executable = env::current_exe()?;
+
+    // The path of an executable that has been replaced since it was
+    // started, e.g. by a package upgrade, is reported with a " (deleted)"
+    // suffix.
+    match executable
+        .to_str()
+        .and_then(|path| path.strip_suffix(" (deleted)"))
+    {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => Ok(executable),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_exe() -> Result<PathBuf, std::io::Error> {
+    env::current_exe()
+}
+
+/// Write the new binary next to the executable and rename it over the
+/// executable, so that the executable is never incomplete. The new binary
+/// has to run on this system before, e.g. it must not have been built for
+/// another architecture or C library, and the previous executable is kept
+/// as a backup.
+fn replace(executable: &Path, bytes: &[u8]) -> Result<(), anyhow::Error> {
+    let directory = executable.parent().unwrap_or(Path::new("/"));
+    let temporary = directory.join(".pullconf.update");
+
+    let result = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o755)
+        .open(&temporary)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .context("failed to write new binary")
+        .and_then(|_| check(&temporary))
+        .and_then(|_| {
+            let backup = backup(executable);
+
+            fs::remove_file(&backup).ok();
+            fs::hard_link(executable, &backup).context("failed to keep previous executable")?;
+            fs::rename(&temporary, executable).context("failed to rename new binary")
+        });
+
+    if result.is_err() {
+        fs::remove_file(&temporary).ok();
+    }
+
+    result?;
+
+    // Persist the rename itself.
+    Ok(fs::File::open(directory)?.sync_all()?)
+}
+
+/// Execute a new binary with `--version`, which fails if this system
+/// cannot execute it at all, e.g. as it lacks its dynamic loader.
+fn check(binary: &Path) -> Result<(), anyhow::Error> {
+    let output = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("failed to execute new binary, it was likely built for another target")?;
+
+    if !output.status.success() {
+        anyhow::bail!("new binary failed with {}", output.status);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn release_from_headers() {
+        let sha256 = "A3".repeat(32);
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nX-Pullconf-Version: 0.2.0\r\nX-Pullconf-Sha256: {}\r\n\r\n",
+            sha256
+        )
+        .parse::<Response>()
+        .unwrap();
+
+        assert_eq!(
+            Release::from_response(&response),
+            Some(Release {
+                version: "0.2.0".to_string(),
+                sha256: sha256.to_ascii_lowercase(),
+            })
+        );
+
+        let response =
+            "HTTP/1.1 200 OK\r\nX-Pullconf-Version: 0.2.0\r\nX-Pullconf-Sha256: abc\r\n\r\n"
+                .parse::<Response>()
+                .unwrap();
+
+        assert_eq!(Release::from_response(&response), None);
+
+        let response = "HTTP/1.1 304 Not Modified\r\n\r\n"
+            .parse::<Response>()
+            .unwrap();
+
+        assert_eq!(Release::from_response(&response), None);
+    }
+
+    #[test]
+    fn target_of_this_binary() {
+        let target = target();
+
+        #[cfg(all(target_arch = "x86_64", target_os = "linux", target_env = "gnu"))]
+        assert_eq!(target, "x86_64-linux-gnu");
+
+        assert!(target.starts_with(&format!("{}-{}", env::consts::ARCH, env::consts::OS)));
+    }
+}
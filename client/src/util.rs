@@ -1,7 +1,10 @@
+use crate::root;
 use anyhow::Context;
 use common::{resources::group::Name as Groupname, resources::user::Name as Username};
-use log::debug;
+use log::{debug, log, log_enabled, Level};
 use nix::unistd::{Group, User};
+use similar::TextDiff;
+use std::path::Path;
 
 /// Query the system for a user and optionally a group by their respective names
 /// and return their numeric IDs.
@@ -9,12 +12,31 @@ use nix::unistd::{Group, User};
 /// the search.
 /// Fail if either the user or group cannot be found and thus apparently do not
 /// exist in the system.
+/// With an alternate root the IDs are looked up in the target system.
 pub fn uid_and_gid(
     owner: &Username,
     group: &Option<Groupname>,
 ) -> Result<(u32, u32), anyhow::Error> {
     debug!("querying system for user and group data");
 
+    if root::get().is_some() {
+        // name:password:uid:gid:gecos:home:shell
+        let Some(user) = root::lookup("passwd", owner, 7)? else {
+            anyhow::bail!("failed to find user '{}'", owner)
+        };
+
+        let gid = match group {
+            // name:password:gid:members
+            Some(name) => match root::lookup("group", name, 4)? {
+                Some(group) => group[2].parse::<u32>()?,
+                None => anyhow::bail!("failed to find group '{}'", name),
+            },
+            None => user[3].parse::<u32>()?,
+        };
+
+        return Ok((user[2].parse::<u32>()?, gid));
+    }
+
     let user = match User::from_name(owner)
         .with_context(|| format!("failed to search for user '{}'", owner))?
     {
@@ -36,3 +58,73 @@ pub fn uid_and_gid(
 
     Ok((uid, gid))
 }
+
+/// Contents larger than this many bytes are not diffed.
+const MAX_DIFF_SIZE: usize = 64 * 1024;
+
+/// Log a unified diff between the current and the desired content of a file.
+/// In noop mode the diff is logged at info level, otherwise at debug level.
+/// The diff is replaced by a short note if the file is sensitive, if either
+/// content is not valid UTF-8 or if either content exceeds the size limit.
+pub fn log_diff(
+    pid: u32,
+    noop: bool,
+    resource: &str,
+    path: &Path,
+    current: &[u8],
+    desired: &[u8],
+    sensitive: bool,
+) {
+    let level = if noop { Level::Info } else { Level::Debug };
+
+    if !log_enabled!(level) {
+        return;
+    }
+
+    let diff = diff(path, current, desired, sensitive);
+
+    log!(level, pid, resource, path:% = path.display(); "content differs:\n{}", diff);
+}
+
+fn diff(path: &Path, current: &[u8], desired: &[u8], sensitive: bool) -> String {
+    if sensitive {
+        return "(diff redacted)".to_string();
+    }
+
+    if current.len() > MAX_DIFF_SIZE || desired.len() > MAX_DIFF_SIZE {
+        return format!("(diff omitted, content exceeds {} bytes)", MAX_DIFF_SIZE);
+    }
+
+    let (Ok(current), Ok(desired)) = (std::str::from_utf8(current), std::str::from_utf8(desired))
+    else {
+        return "(diff omitted, content is not valid UTF-8)".to_string();
+    };
+
+    let path = path.display().to_string();
+
+    TextDiff::from_lines(current, desired)
+        .unified_diff()
+        .context_radius(3)
+        .header(&path, &path)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unified_diff() {
+        let path = Path::new("/etc/motd");
+
+        assert_eq!(
+            diff(path, b"foo\nbar\n", b"foo\nbaz\n", false),
+            "--- /etc/motd\n+++ /etc/motd\n@@ -1,2 +1,2 @@\n foo\n-bar\n+baz\n"
+        );
+        assert_eq!(diff(path, b"foo\n", b"bar\n", true), "(diff redacted)");
+        assert_eq!(
+            diff(path, &[0xff], b"bar\n", false),
+            "(diff omitted, content is not valid UTF-8)"
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use common::error::Terminate;
+#[cfg(target_os = "linux")]
+use log::debug;
+use log::{error, warn};
+#[cfg(target_os = "linux")]
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+#[cfg(target_os = "linux")]
+use std::{
+    ffi::OsString,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// The number of times a resource is remediated within `WINDOW`, before
+/// pullconf stops fighting whoever keeps changing it and leaves it to the
+/// next scheduled run.
+const MAX_REMEDIATIONS: usize = 3;
+const WINDOW: Duration = Duration::from_secs(600);
+
+/// Changes to the same resource that happen in quick succession, e.g. while
+/// an editor saves a file, are remediated at once after this delay.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Changes that are reported within this period after a run are attributed
+/// to the run itself and ignored.
+pub const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// Watches the paths of file, directory and symlink resources with inotify
+/// in order to remediate changes that are made out-of-band as soon as they
+/// happen. The parent directories are watched instead of the paths
+/// themselves, so that files that are replaced, e.g. by an editor, or
+/// created or deleted are noticed as well.
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    inotify: Arc<Inotify>,
+    /// The watched directories and, per directory, the names of the entries
+    /// that belong to resources, mapped to the names of these resources.
+    watches: Arc<Mutex<HashMap<WatchDescriptor, HashMap<OsString, String>>>>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    /// Start watching in a separate thread, which passes the names of the
+    /// resources that have changed, e.g. `file:/etc/motd`, to the given
+    /// function until it returns `false`.
+    pub fn start<F>(pid: u32, changed: F) -> Result<Self, Terminate>
+    where
+        F: Fn(String) -> bool + Send + 'static,
+    {
+        let scope = "watch";
+
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC).map_err(|error| {
+            error!(scope, pid; "failed to initialize inotify: {}", error);
+            Terminate
+        })?;
+
+        let watcher = Self {
+            inotify: Arc::new(inotify),
+            watches: Arc::new(Mutex::new(HashMap::new())),
+        };
+
+        let inotify = Arc::clone(&watcher.inotify);
+        let watches = Arc::clone(&watcher.watches);
+
+        thread::spawn(move || loop {
+            let events = match inotify.read_events() {
+                Ok(events) => events,
+                Err(error) => {
+                    error!(scope, pid; "failed to read inotify events, no longer watching for changes: {}", error);
+                    return;
+                }
+            };
+
+            let names = {
+                let watches = watches.lock().unwrap_or_else(|error| error.into_inner());
+
+                events
+                    .into_iter()
+                    .filter_map(|event| watches.get(&event.wd)?.get(event.name.as_ref()?).cloned())
+                    .collect::<Vec<String>>()
+            };
+
+            for name in names {
+                debug!(scope, pid, resource = name.as_str(); "resource has changed");
+
+                if !changed(name) {
+                    return;
+                }
+            }
+        });
+
+        Ok(watcher)
+    }
+
+    /// Replace the watched paths, each along with the name of its resource.
+    /// Paths whose parent directory does not exist are not watched.
+    pub fn update(&self, pid: u32, paths: Vec<(PathBuf, String)>) {
+        let scope = "watch";
+
+        let mut watches = self
+            .watches
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        for (descriptor, _) in watches.drain() {
+            let _ = self.inotify.rm_watch(descriptor);
+        }
+
+        let mut directories = HashMap::<PathBuf, HashMap<OsString, String>>::new();
+
+        for (path, name) in paths {
+            if let (Some(parent), Some(entry)) = (path.parent(), path.file_name()) {
+                directories
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .insert(entry.to_os_string(), name);
+            }
+        }
+
+        let mask = AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_CLOSE_WRITE
+            | AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_MOVED_FROM
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_ONLYDIR;
+
+        for (directory, entries) in directories {
+            match self.inotify.add_watch(&directory, mask) {
+                Ok(descriptor) => {
+                    watches.entry(descriptor).or_default().extend(entries);
+                }
+                Err(error) => {
+                    debug!(scope, pid; "not watching directory {}: {}", directory.display(), error)
+                }
+            }
+        }
+
+        debug!(scope, pid; "watching {} directories for changes", watches.len());
+    }
+}
+
+/// inotify is specific to Linux, so elsewhere, e.g. on FreeBSD, changes are
+/// only remediated by the next scheduled run.
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn start<F>(pid: u32, _changed: F) -> Result<Self, Terminate>
+    where
+        F: Fn(String) -> bool + Send + 'static,
+    {
+        error!(scope = "watch", pid; "watching for changes requires inotify, which is only available on Linux");
+        Err(Terminate)
+    }
+
+    pub fn update(&self, _pid: u32, _paths: Vec<(PathBuf, String)>) {}
+}
+
+/// Limits how often the same resource is remediated, so that pullconf does
+/// not end up in a fight-loop with another process or a person that keeps
+/// changing it.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    remediations: HashMap<String, VecDeque<Instant>>,
+}
+
+impl RateLimit {
+    /// Check whether the resource may be remediated now and, if so, count
+    /// the remediation.
+    pub fn allow(&mut self, pid: u32, name: &str, now: Instant) -> bool {
+        let remediations = self.remediations.entry(name.to_string()).or_default();
+
+        while remediations
+            .front()
+            .is_some_and(|remediation| now.duration_since(*remediation) >= WINDOW)
+        {
+            remediations.pop_front();
+        }
+
+        if remediations.len() >= MAX_REMEDIATIONS {
+            if remediations.len() == MAX_REMEDIATIONS {
+                warn!(
+                    scope = "watch",
+                    pid,
+                    resource = name;
+                    "resource keeps being changed out-of-band, leaving it to the next scheduled run"
+                );
+
+                // Warn only once per window.
+                remediations.push_back(now);
+            }
+
+            return false;
+        }
+
+        remediations.push_back(now);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_remediations() {
+        let mut limit = RateLimit::default();
+        let now = Instant::now();
+
+        for _ in 0..MAX_REMEDIATIONS {
+            assert!(limit.allow(0, "file:/etc/motd", now));
+        }
+
+        assert!(!limit.allow(0, "file:/etc/motd", now));
+        assert!(!limit.allow(0, "file:/etc/motd", now + Duration::from_secs(1)));
+        assert!(limit.allow(0, "file:/etc/hosts", now));
+        assert!(limit.allow(0, "file:/etc/motd", now + WINDOW));
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::{fmt, process::ExitCode};
 
 #[derive(Debug)]
@@ -16,3 +17,66 @@ impl From<Terminate> for ExitCode {
         Self::FAILURE
     }
 }
+
+/// The class of an error that pullconfd responds with. It is coarser than
+/// the `code` of a problem and tells clients how to react to an error,
+/// e.g. to retry on internal errors, but not on validation errors.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The credentials of the request are missing or invalid.
+    Authentication,
+    /// The credentials are valid, but do not permit the request.
+    Authorization,
+    /// The request itself is invalid, so it fails again unless it is
+    /// changed.
+    Validation,
+    NotFound,
+    Conflict,
+    /// The server failed to process a valid request, so it may succeed
+    /// when it is retried later.
+    Internal,
+    /// Any class that this version does not know about, e.g. one that was
+    /// introduced by a newer version of pullconfd.
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    /// Derive the class of an error from the HTTP status code of a
+    /// response, e.g. of an older pullconfd or an intermediary.
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 => Self::Authentication,
+            403 => Self::Authorization,
+            404 => Self::NotFound,
+            409 => Self::Conflict,
+            400 | 413 | 422 | 431 => Self::Validation,
+            500..=599 => Self::Internal,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Check whether a request that failed with this error may succeed
+    /// when it is retried unchanged.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Internal)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            Self::Authentication => "authentication",
+            Self::Authorization => "authorization",
+            Self::Validation => "validation",
+            Self::NotFound => "not-found",
+            Self::Conflict => "conflict",
+            Self::Internal => "internal",
+            Self::Unknown => "unknown",
+        };
+
+        f.write_str(code)
+    }
+}
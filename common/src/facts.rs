@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, net::IpAddr};
+
+/// Information about a client system that is gathered by pullconf on every
+/// run and sent to pullconfd along with the catalog request. Every fact is
+/// collected on a best-effort basis, so facts that cannot be determined are
+/// left empty.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Facts {
+    #[serde(default)]
+    pub os: OperatingSystem,
+    #[serde(default)]
+    pub kernel: Kernel,
+    #[serde(default)]
+    pub processors: Processors,
+    #[serde(default)]
+    pub memory: Memory,
+    #[serde(default, rename = "block-devices")]
+    pub block_devices: Vec<BlockDevice>,
+    #[serde(default)]
+    pub interfaces: Vec<Interface>,
+    /// The virtualization technology as reported by `systemd-detect-virt`,
+    /// e.g. `kvm`, `lxc` or `none` on bare metal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub virtualization: Option<String>,
+    /// Site-specific facts from `/etc/pullconf/facts.d`, e.g. the rack or
+    /// the role of a system.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub external: BTreeMap<String, serde_json::Value>,
+    /// The installed packages, which are only collected if enabled with
+    /// `PULLCONF_FACTS_PACKAGES`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<Package>,
+}
+
+/// Fields from `/etc/os-release`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct OperatingSystem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(
+        default,
+        rename = "version-id",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub version_id: Option<String>,
+    #[serde(
+        default,
+        rename = "version-codename",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub version_codename: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Kernel {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Processors {
+    /// The number of logical processors.
+    #[serde(default)]
+    pub count: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Memory {
+    /// Total memory in bytes.
+    #[serde(default)]
+    pub total: u64,
+    /// Total swap space in bytes.
+    #[serde(default)]
+    pub swap: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BlockDevice {
+    pub name: String,
+    /// Size in bytes.
+    pub size: u64,
+    #[serde(default)]
+    pub rotational: bool,
+    #[serde(default)]
+    pub removable: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Interface {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+    #[serde(default)]
+    pub addresses: Vec<IpAddr>,
+}
+
+/// An installed package as reported by the package manager.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+}
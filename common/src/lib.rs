@@ -1,16 +1,27 @@
 pub mod error;
+pub mod facts;
 pub mod name;
 pub mod path;
+pub mod problem;
+pub mod report;
 pub mod resources;
+pub mod schedule;
+pub mod syslog;
 
 pub use name::Hostname;
 pub use path::SafePathBuf;
+pub use schedule::Schedule;
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 use uuid::Uuid;
 
+/// The version of the format of the resource catalog that pullconfd sends.
+/// It is increased whenever a client needs to be updated to understand the
+/// catalog, e.g. when a resource type is added.
+pub const CATALOG_VERSION: u32 = 1;
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum ResourceType {
     #[serde(rename = "apt::package")]
@@ -19,6 +30,8 @@ pub enum ResourceType {
     AptPreference,
     #[serde(rename = "cron::job")]
     CronJob,
+    #[serde(rename = "custom")]
+    Custom,
     #[serde(rename = "directory")]
     Directory,
     #[serde(rename = "file")]
@@ -43,6 +56,7 @@ impl FromStr for ResourceType {
             "apt::package" => Ok(Self::AptPackage),
             "apt::preference" => Ok(Self::AptPreference),
             "cron::job" => Ok(Self::CronJob),
+            "custom" => Ok(Self::Custom),
             "directory" => Ok(Self::Directory),
             "file" => Ok(Self::File),
             "group" => Ok(Self::Group),
@@ -61,6 +75,7 @@ impl fmt::Display for ResourceType {
             Self::AptPackage => f.write_str("apt::package"),
             Self::AptPreference => f.write_str("apt::preference"),
             Self::CronJob => f.write_str("cron::job"),
+            Self::Custom => f.write_str("custom"),
             Self::Directory => f.write_str("directory"),
             Self::File => f.write_str("file"),
             Self::Group => f.write_str("group"),
@@ -84,11 +99,35 @@ pub struct Links {
     pub previous: Option<String>,
 }
 
+/// Namespaces in which a client removes what pullconf created earlier, but
+/// which is no longer part of its resource catalog, e.g. the cron job of a
+/// resource that was deleted from the configuration of pullconfd.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Purge {
+    /// Remove files in `/etc/cron.d` that pullconf wrote for `cron::job`
+    /// resources which are not part of the catalog.
+    #[serde(default, rename = "cron-jobs")]
+    pub cron_jobs: bool,
+}
+
+impl Purge {
+    pub fn is_empty(&self) -> bool {
+        !self.cron_jobs
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, Serialize)]
 pub struct ResourceMetadata {
     #[serde(rename = "type")]
     pub kind: ResourceType,
     pub id: Uuid,
+    /// Arbitrary labels that allow selecting a subset of the catalog.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The CPU and IO priority at which the client applies the resource,
+    /// which overrides the client's own setting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
 }
 
 impl PartialOrd for ResourceMetadata {
@@ -119,6 +158,46 @@ impl ResourceMetadata {
     }
 }
 
+/// The CPU and IO priority at which a resource is applied, so that heavy
+/// operations, e.g. installing packages or purging directories, do not
+/// degrade latency-sensitive workloads on the same host. Commands that are
+/// executed while a resource is applied inherit its priority.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// The priority of the client itself.
+    #[default]
+    Normal,
+    /// A niceness of 10 and the lowest best-effort IO priority.
+    Low,
+    /// A niceness of 19 and the idle IO class, i.e. only when the host is
+    /// otherwise idle.
+    Idle,
+}
+
+impl FromStr for Priority {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Self::Normal),
+            "low" => Ok(Self::Low),
+            "idle" => Ok(Self::Idle),
+            _ => anyhow::bail!("invalid priority: {}, expected normal, low or idle", s),
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => f.write_str("normal"),
+            Self::Low => f.write_str("low"),
+            Self::Idle => f.write_str("idle"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub enum Ensure {
@@ -1,27 +1,59 @@
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use std::ops::Deref;
-use std::path::{Component, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
 
+/// An absolute path that is normalized when it is parsed, i.e. `.` and `..`
+/// components are resolved lexically, and that is valid UTF-8 without NUL
+/// bytes. Paths are interpolated into commands, configuration files and
+/// asset URLs, so a path never refers to anything but what it reads like.
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct SafePathBuf(PathBuf);
 
+impl SafePathBuf {
+    /// Check whether the path is the given root or below it. Roots are
+    /// compared component-wise, so `/etc/nginx` is not within `/etc/ng`.
+    pub fn is_within(&self, root: &Path) -> bool {
+        self.0.starts_with(root)
+    }
+}
+
 impl TryFrom<PathBuf> for SafePathBuf {
     type Error = anyhow::Error;
 
     fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
-        if !path.is_absolute() {
-            anyhow::bail!("path must be absolute, got `{}`", path.display())
-        } else if path
-            .components()
-            .any(|c| c != Component::RootDir && !matches!(c, Component::Normal(_)))
-        {
+        let Some(s) = path.to_str() else {
+            anyhow::bail!("path must be valid UTF-8, got `{}`", path.display())
+        };
+
+        if s.contains('\0') {
             anyhow::bail!(
-                "path components must not contain relative references such as '.' or '..'",
+                "path must not contain NUL bytes, got `{}`",
+                s.escape_default()
             )
-        } else {
-            Ok(Self(path))
+        } else if !path.is_absolute() {
+            anyhow::bail!("path must be absolute, got `{}`", s)
         }
+
+        // Resolve `.` and `..` without accessing the filesystem, as the
+        // path usually refers to a node on another system.
+        let mut normalized = PathBuf::new();
+
+        for component in path.components() {
+            match component {
+                Component::RootDir => normalized.push(component),
+                Component::CurDir => (),
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        anyhow::bail!("path `{}` must not traverse beyond the root directory", s)
+                    }
+                }
+                Component::Normal(name) => normalized.push(name),
+                Component::Prefix(_) => anyhow::bail!("path `{}` has an unsupported prefix", s),
+            }
+        }
+
+        Ok(Self(normalized))
     }
 }
 
@@ -51,3 +83,26 @@ impl Deref for SafePathBuf {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_paths() {
+        let path = |s: &str| SafePathBuf::from_str(s).map(|path| path.display().to_string());
+
+        assert_eq!(path("/etc/./nginx//sites/").unwrap(), "/etc/nginx/sites");
+        assert_eq!(path("/srv/www/../backup").unwrap(), "/srv/backup");
+        assert_eq!(path("/..").ok(), None);
+        assert_eq!(path("/etc/../../passwd").ok(), None);
+        assert_eq!(path("etc/passwd").ok(), None);
+        assert_eq!(path("/etc/pass\0wd").ok(), None);
+
+        let nginx = SafePathBuf::from_str("/etc/nginx/nginx.conf").unwrap();
+
+        assert!(nginx.is_within(Path::new("/etc/nginx")));
+        assert!(nginx.is_within(Path::new("/")));
+        assert!(!nginx.is_within(Path::new("/etc/ng")));
+    }
+}
@@ -0,0 +1,136 @@
+use crate::error::ErrorCode;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The media type of error responses, see RFC 7807.
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// A stable, machine-readable identifier per class of errors that
+/// pullconfd responds with. Consumers should branch on this code
+/// instead of the human-readable title or detail.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemCode {
+    MissingAuthorization,
+    FailedAuthorization,
+    Forbidden,
+    BadRequest,
+    InvalidQuery,
+    InvalidRegistration,
+    TooManyRegistrations,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    HeadersTooLarge,
+    Internal,
+    /// Any code that this version does not know about, e.g. one that
+    /// was introduced by a newer version of pullconfd.
+    #[serde(other)]
+    Unknown,
+}
+
+impl ProblemCode {
+    /// The URI that identifies the problem type, which is sent as the
+    /// `type` member of a problem.
+    pub fn uri(&self) -> String {
+        format!("urn:pullconf:problem:{}", self)
+    }
+
+    /// The class of the problem, which is sent as the `category` member
+    /// of a problem.
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            Self::MissingAuthorization | Self::FailedAuthorization => ErrorCode::Authentication,
+            Self::Forbidden => ErrorCode::Authorization,
+            Self::BadRequest
+            | Self::InvalidQuery
+            | Self::InvalidRegistration
+            | Self::PayloadTooLarge
+            | Self::HeadersTooLarge => ErrorCode::Validation,
+            Self::NotFound => ErrorCode::NotFound,
+            Self::Conflict => ErrorCode::Conflict,
+            // Registrations are accepted again once pending ones have been
+            // processed, so the request is worth retrying.
+            Self::TooManyRegistrations | Self::Internal => ErrorCode::Internal,
+            Self::Unknown => ErrorCode::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for ProblemCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match self {
+            Self::MissingAuthorization => "missing-authorization",
+            Self::FailedAuthorization => "failed-authorization",
+            Self::Forbidden => "forbidden",
+            Self::BadRequest => "bad-request",
+            Self::InvalidQuery => "invalid-query",
+            Self::InvalidRegistration => "invalid-registration",
+            Self::TooManyRegistrations => "too-many-registrations",
+            Self::NotFound => "not-found",
+            Self::Conflict => "conflict",
+            Self::PayloadTooLarge => "payload-too-large",
+            Self::HeadersTooLarge => "headers-too-large",
+            Self::Internal => "internal",
+            Self::Unknown => "unknown",
+        };
+
+        f.write_str(code)
+    }
+}
+
+/// The payload of an error response in the `application/problem+json`
+/// format, extended by the `code` and `category` members.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub code: ProblemCode,
+    /// pullconfd omitted the category before it was introduced.
+    #[serde(default)]
+    pub category: ErrorCode,
+    pub status: u16,
+    pub title: String,
+    pub detail: String,
+}
+
+impl Problem {
+    /// The class of the problem, derived from the status code if the
+    /// response lacks a category that this version knows about.
+    pub fn error_code(&self) -> ErrorCode {
+        match self.category {
+            ErrorCode::Unknown => ErrorCode::from_status(self.status),
+            category => category,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_problems() {
+        let problem = serde_json::from_str::<Problem>(
+            r#"{"type": "urn:pullconf:problem:forbidden", "code": "forbidden", "status": 403, "title": "access forbidden", "detail": ""}"#,
+        )
+        .unwrap();
+
+        assert_eq!(problem.category, ErrorCode::Unknown);
+        assert_eq!(problem.error_code(), ErrorCode::Authorization);
+
+        let problem = serde_json::from_str::<Problem>(
+            r#"{"type": "urn:pullconf:problem:rate-limited", "code": "rate-limited", "category": "internal", "status": 429, "title": "rate limited", "detail": ""}"#,
+        )
+        .unwrap();
+
+        assert_eq!(problem.code, ProblemCode::Unknown);
+        assert_eq!(problem.error_code(), ErrorCode::Internal);
+        assert!(problem.error_code().is_retryable());
+        assert_eq!(
+            ProblemCode::FailedAuthorization.error_code(),
+            ErrorCode::Authentication
+        );
+        assert_eq!(ErrorCode::NotFound.to_string(), "not-found");
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use uuid::Uuid;
+
+/// This enum describes possible actions that are the result of
+/// applying a resource.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// This variant applies when a resource remains unchanged,
+    /// either present or absent.
+    #[default]
+    Unchanged,
+    /// This variant applies when a resource needed to be created
+    /// because it did not exist before.
+    Created,
+    /// This variant applies when a resource exists but needed to
+    /// be changed in order to reach the desired state.
+    Changed,
+    /// This variant applies when a resource has been deleted.
+    Deleted,
+    /// This variant applies whenever any preconditions hinder the
+    /// resource from being applied.
+    /// This is usually the case when a dependency of this resource
+    /// failed to apply or has been skipped itself.
+    Skipped,
+    /// This variant applies when a resource could not successfully
+    /// be configured according to its desired state.
+    /// It also applies when certain preconditions fail, e.g. when
+    /// a dependency of this resource is absent.
+    Failed,
+}
+
+impl Action {
+    /// Every action in the order in which they are usually reported.
+    pub const ALL: [Self; 6] = [
+        Self::Unchanged,
+        Self::Created,
+        Self::Changed,
+        Self::Deleted,
+        Self::Skipped,
+        Self::Failed,
+    ];
+
+    /// Check whether the resource was (or in noop mode would be)
+    /// modified in order to reach its desired state.
+    pub fn is_change(&self) -> bool {
+        matches!(self, Self::Created | Self::Changed | Self::Deleted)
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(Self::Unchanged),
+            "created" => Ok(Self::Created),
+            "changed" => Ok(Self::Changed),
+            "deleted" => Ok(Self::Deleted),
+            "skipped" => Ok(Self::Skipped),
+            "failed" => Ok(Self::Failed),
+            _ => anyhow::bail!("invalid action: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Padding is honored, so that actions line up in tables.
+        match self {
+            Self::Unchanged => f.pad("unchanged"),
+            Self::Created => f.pad("created"),
+            Self::Changed => f.pad("changed"),
+            Self::Deleted => f.pad("deleted"),
+            Self::Skipped => f.pad("skipped"),
+            Self::Failed => f.pad("failed"),
+        }
+    }
+}
+
+/// The report of a single run, including the result of every resource.
+/// pullconf saves it after every run, and it is shared with pullconfd and
+/// external tooling, so that every consumer reads the same schema.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Run {
+    pub timestamp: String,
+    pub noop: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub outcome: String,
+    /// The duration of the run in seconds.
+    pub duration: f64,
+    /// The number of resources that were deferred to the next run, as the
+    /// run budget was exhausted.
+    #[serde(default)]
+    pub deferred: usize,
+    pub resources: Vec<ResourceResult>,
+}
+
+/// The result of a single resource in a run.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ResourceResult {
+    pub id: Uuid,
+    pub resource: String,
+    pub result: Action,
+    /// The time it took to apply the resource in seconds.
+    pub duration: f64,
+    /// The error that caused the resource to fail, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+/// The summary of a run that is kept in the history.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Summary {
+    pub timestamp: String,
+    pub noop: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub outcome: String,
+    pub duration: f64,
+    pub total: usize,
+    pub changed: usize,
+    pub failed: usize,
+    #[serde(default)]
+    pub deferred: usize,
+    /// The resource that took the longest to apply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slowest: Option<Slowest>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Slowest {
+    pub resource: String,
+    /// The time it took to apply the resource in seconds.
+    pub duration: f64,
+}
+
+impl Run {
+    /// Count the resources that resulted in the given action.
+    pub fn count(&self, action: Action) -> usize {
+        self.resources
+            .iter()
+            .filter(|resource| resource.result == action)
+            .count()
+    }
+}
+
+impl From<&Run> for Summary {
+    fn from(run: &Run) -> Self {
+        Self {
+            timestamp: run.timestamp.clone(),
+            noop: run.noop,
+            etag: run.etag.clone(),
+            outcome: run.outcome.clone(),
+            duration: run.duration,
+            total: run.resources.len(),
+            changed: run
+                .resources
+                .iter()
+                .filter(|resource| resource.result.is_change())
+                .count(),
+            failed: run.count(Action::Failed),
+            deferred: run.deferred,
+            slowest: run
+                .resources
+                .iter()
+                .max_by(|a, b| a.duration.total_cmp(&b.duration))
+                .filter(|resource| resource.duration > 0.0)
+                .map(|resource| Slowest {
+                    resource: resource.resource.clone(),
+                    duration: resource.duration,
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_resource_result() {
+        let result = ResourceResult {
+            id: Uuid::nil(),
+            resource: "file `/etc/motd`".to_string(),
+            result: Action::Changed,
+            duration: 0.5,
+            failure: None,
+        };
+
+        let json = serde_json::to_value(&result).unwrap();
+
+        assert_eq!(json["result"], "changed");
+        assert_eq!(
+            serde_json::from_value::<ResourceResult>(json).unwrap(),
+            result
+        );
+        assert_eq!(format!("{:<9}|", Action::Failed), "failed   |");
+        assert_eq!("skipped".parse::<Action>().unwrap(), Action::Skipped);
+    }
+
+    #[test]
+    fn summarize_run() {
+        let resource = |id: u128, result: Action, duration: f64| ResourceResult {
+            id: Uuid::from_u128(id),
+            resource: format!("file `/etc/{}`", id),
+            result,
+            duration,
+            failure: None,
+        };
+
+        let mut run = Run {
+            timestamp: "2024-09-01T12:00:00Z".to_string(),
+            noop: false,
+            etag: None,
+            outcome: "changed".to_string(),
+            duration: 5.0,
+            deferred: 1,
+            resources: vec![
+                resource(1, Action::Unchanged, 0.5),
+                resource(2, Action::Changed, 3.5),
+                resource(3, Action::Skipped, 0.0),
+            ],
+        };
+
+        let summary = Summary::from(&run);
+
+        assert_eq!(summary.deferred, 1);
+        assert_eq!(
+            summary.slowest,
+            Some(Slowest {
+                resource: "file `/etc/2`".to_string(),
+                duration: 3.5,
+            })
+        );
+
+        run.resources.truncate(0);
+
+        assert_eq!(Summary::from(&run).slowest, None);
+    }
+}
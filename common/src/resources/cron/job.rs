@@ -1,5 +1,5 @@
 use super::super::user::Name as Username;
-use crate::{Ensure, ResourceMetadata};
+use crate::{Ensure, ResourceMetadata, Schedule};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use std::{fmt, ops::Deref, path::PathBuf, str::FromStr};
 
@@ -15,7 +15,7 @@ pub struct Parameters {
     pub target: PathBuf,
     pub name: Name,
     pub environment: Vec<Environment>,
-    pub schedule: String,
+    pub schedule: Schedule,
     pub user: Username,
     pub command: String,
 }
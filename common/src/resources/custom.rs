@@ -0,0 +1,85 @@
+use crate::{Ensure, ResourceMetadata};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::{fmt, ops::Deref, str::FromStr};
+
+/// The parameters of a resource whose type is not built into pullconf.
+/// The client hands them to an external provider, i.e. an executable that
+/// is named after `provider`, which applies the resource.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Parameters {
+    pub ensure: Ensure,
+    pub provider: Provider,
+    pub name: String,
+    /// Arbitrary parameters that are passed on to the provider as they are.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub parameters: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Relationships {
+    pub requires: Vec<ResourceMetadata>,
+}
+
+/// The name of a provider, e.g. `postgresql::database`. It is also the
+/// file name of the provider executable, so it must not contain path
+/// separators.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Provider(String);
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            anyhow::bail!("provider cannot be an empty string")
+        }
+
+        if s.chars().count() > 64 {
+            anyhow::bail!("provider cannot exceed 64 characters")
+        }
+
+        if s.chars().next().is_some_and(|c| !c.is_ascii_alphabetic()) {
+            anyhow::bail!("provider must start with an alphabetic character")
+        }
+
+        if let Some(ref c) = s.chars().find(|c| {
+            !(c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.' | ':'))
+        }) {
+            anyhow::bail!("provider contains invalid character `{}`", c)
+        }
+
+        Ok(Self(s.to_owned()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Provider {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = String::deserialize(deserializer)?;
+
+        Provider::from_str(&v).map_err(Error::custom)
+    }
+}
+
+impl Deref for Provider {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
@@ -13,6 +13,10 @@ pub struct Parameters {
     pub group: Option<Groupname>,
     pub content: Option<String>,
     pub source: Option<SafePathBuf>,
+    /// Whether the file contains secrets, in which case differences
+    /// between its current and desired content are not logged.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -1,5 +1,6 @@
 pub mod apt;
 pub mod cron;
+pub mod custom;
 pub mod directory;
 pub mod file;
 pub mod group;
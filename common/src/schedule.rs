@@ -0,0 +1,602 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// The names of weekdays, starting with Sunday like in cron.
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// The names of months, starting with January.
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// A recurring point in time, e.g. when a cron job is executed. A schedule
+/// is parsed from either a cron expression, e.g. `*/15 2 * * mon-fri`, or a
+/// systemd calendar event, e.g. `Mon..Fri *-*-* 02:00/15:00`, and can be
+/// written in either syntax, so that every resource validates schedules
+/// the same way regardless of the backend that executes them.
+///
+/// Only what both syntaxes can express is accepted: calendar events must
+/// not restrict years or seconds, and days of the month and weekdays must
+/// not be restricted at once, as cron executes a job if either matches
+/// while systemd requires both to match. A schedule is serialized as a
+/// cron expression.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Schedule {
+    /// Once after the system has booted, `@reboot` in cron syntax. There is
+    /// no calendar event for it.
+    Reboot,
+    Calendar(Calendar),
+}
+
+/// The minutes, hours, days of the month, months and weekdays at which a
+/// schedule recurs.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Calendar {
+    pub minutes: Field,
+    pub hours: Field,
+    pub days: Field,
+    pub months: Field,
+    pub weekdays: Field,
+}
+
+/// The values of a component of a calendar that match, e.g. the minutes
+/// 0, 15, 30 and 45.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Field {
+    unit: Unit,
+    values: u64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Month,
+    /// Weekdays are numbered from 0 (Sunday) to 6 (Saturday).
+    Weekday,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Syntax {
+    Cron,
+    OnCalendar,
+}
+
+/// A schedule in cron syntax, e.g. `0 2 * * 1-5`.
+pub struct Cron<'a>(&'a Schedule);
+
+/// A schedule in the syntax of systemd calendar events as used by
+/// `OnCalendar`, e.g. `Mon..Fri *-*-* 02:00:00`.
+pub struct OnCalendar<'a>(&'a Calendar);
+
+impl Unit {
+    fn bounds(&self) -> (u8, u8) {
+        match self {
+            Self::Minute => (0, 59),
+            Self::Hour => (0, 23),
+            Self::Day => (1, 31),
+            Self::Month => (1, 12),
+            Self::Weekday => (0, 6),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::Day => "day of the month",
+            Self::Month => "month",
+            Self::Weekday => "weekday",
+        }
+    }
+
+    /// Parse a single value, which may also be the name of a month or a
+    /// weekday, e.g. `jan` or `Monday`. Sunday may also be given as 7.
+    fn value(&self, s: &str) -> Result<u8, String> {
+        let (min, max) = self.bounds();
+
+        let names: &[&str] = match self {
+            Self::Month => &MONTHS,
+            Self::Weekday => &WEEKDAYS,
+            _ => &[],
+        };
+
+        let lowercase = s.to_ascii_lowercase();
+
+        if let Some(index) = names
+            .iter()
+            .position(|name| lowercase.len() >= 3 && name.starts_with(&lowercase))
+        {
+            return Ok(index as u8 + min);
+        }
+
+        match s.parse::<u8>() {
+            Ok(7) if *self == Self::Weekday => Ok(7),
+            Ok(value) if (min..=max).contains(&value) => Ok(value),
+            _ => Err(format!(
+                "`{}` is not a valid {}, expected {} to {}",
+                s,
+                self.name(),
+                min,
+                max
+            )),
+        }
+    }
+}
+
+impl Field {
+    fn all(unit: Unit) -> Self {
+        let (min, max) = unit.bounds();
+
+        Self {
+            unit,
+            values: (min..=max).fold(0u64, |values, value| values | (1u64 << value)),
+        }
+    }
+
+    fn single(unit: Unit, value: u8) -> Self {
+        Self {
+            unit,
+            values: 1u64 << value,
+        }
+    }
+
+    /// Parse a comma-separated list of values, ranges and steps, e.g.
+    /// `1,5-10,*/15` in cron syntax or `1,5..10,00/15` in systemd syntax.
+    fn parse(unit: Unit, s: &str, syntax: Syntax) -> Result<Self, String> {
+        let (min, max) = unit.bounds();
+        let mut values = 0u64;
+
+        for item in s.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u8>() {
+                    Ok(step) if step > 0 => (range, Some(step)),
+                    _ => return Err(format!("`{}` has an invalid step", item)),
+                },
+                None => (item, None),
+            };
+
+            let separator = match syntax {
+                Syntax::Cron => "-",
+                Syntax::OnCalendar => "..",
+            };
+
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((start, end)) = range.split_once(separator) {
+                (unit.value(start)?, unit.value(end)?)
+            } else if range.is_empty() {
+                return Err(format!("`{}` lacks a value", s));
+            } else {
+                let value = unit.value(range)?;
+                // A step without a range repeats until the end, e.g. `5/15`.
+                (value, if step.is_some() { max } else { value })
+            };
+
+            if start > end {
+                return Err(format!("range `{}` ends before it starts", range));
+            }
+
+            for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+                // Sunday is both 0 and 7.
+                let value = if unit == Unit::Weekday {
+                    value % 7
+                } else {
+                    value
+                };
+                values |= 1u64 << value;
+            }
+        }
+
+        Ok(Self { unit, values })
+    }
+
+    /// Return the matching values in ascending order.
+    pub fn values(&self) -> Vec<u8> {
+        (0..64u8).filter(|value| self.contains(*value)).collect()
+    }
+
+    pub fn contains(&self, value: u8) -> bool {
+        value < 64 && self.values & (1u64 << value) != 0
+    }
+
+    /// Whether every value matches, i.e. the field does not restrict the
+    /// schedule.
+    pub fn is_all(&self) -> bool {
+        *self == Self::all(self.unit)
+    }
+
+    /// Return the step if the values repeat from the first possible value
+    /// until the end, e.g. 15 for the minutes 0, 15, 30 and 45.
+    fn step(&self) -> Option<u8> {
+        let (min, max) = self.unit.bounds();
+        let values = self.values();
+
+        // A step is only worth it for at least three values.
+        let step = match values.as_slice() {
+            [first, second, _, ..] if *first == min => second - first,
+            _ => return None,
+        };
+
+        let repeats = values.windows(2).all(|pair| pair[1] - pair[0] == step)
+            && values.last().is_some_and(|last| last + step > max);
+
+        (step > 1 && repeats).then_some(step)
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, syntax: Syntax) -> fmt::Result {
+        if self.is_all() {
+            return f.write_str("*");
+        }
+
+        let weekdays = syntax == Syntax::OnCalendar && self.unit == Unit::Weekday;
+
+        if let Some(step) = self.step().filter(|_| !weekdays) {
+            return match syntax {
+                Syntax::Cron => write!(f, "*/{}", step),
+                Syntax::OnCalendar => write!(f, "{:02}/{}", self.unit.bounds().0, step),
+            };
+        }
+
+        // systemd weeks start on Monday, so Sunday is listed last.
+        let mut values = self.values();
+
+        if weekdays {
+            values.sort_by_key(|value| (value + 6) % 7);
+        }
+
+        let position = |value: u8| if weekdays { (value + 6) % 7 } else { value };
+
+        let value = |value: u8| match (syntax, self.unit) {
+            (Syntax::OnCalendar, Unit::Weekday) => {
+                let name = WEEKDAYS[value as usize];
+                format!("{}{}", name[..1].to_ascii_uppercase(), &name[1..3])
+            }
+            (Syntax::OnCalendar, _) => format!("{:02}", value),
+            (Syntax::Cron, _) => value.to_string(),
+        };
+
+        let separator = match syntax {
+            Syntax::Cron => "-",
+            Syntax::OnCalendar => "..",
+        };
+
+        let mut items = vec![];
+        let mut index = 0;
+
+        while index < values.len() {
+            let start = index;
+
+            while index + 1 < values.len()
+                && position(values[index + 1]) == position(values[index]) + 1
+            {
+                index += 1;
+            }
+
+            if index - start >= 2 {
+                items.push(format!(
+                    "{}{}{}",
+                    value(values[start]),
+                    separator,
+                    value(values[index])
+                ));
+            } else {
+                items.extend(values[start..=index].iter().map(|v| value(*v)));
+            }
+
+            index += 1;
+        }
+
+        f.write_str(&items.join(","))
+    }
+}
+
+impl Calendar {
+    /// Make sure that the calendar means the same in both syntaxes.
+    fn validate(self) -> Result<Self, String> {
+        if !self.days.is_all() && !self.weekdays.is_all() {
+            return Err("a schedule cannot restrict both days of the month and weekdays, as cron and systemd interpret the combination differently".to_string());
+        }
+
+        Ok(self)
+    }
+}
+
+impl Schedule {
+    /// Parse a cron expression with five fields, e.g. `0 2 * * 1-5`, or a
+    /// macro such as `@daily`.
+    pub fn from_cron(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+
+        let expanded = match s {
+            "@reboot" => return Ok(Self::Reboot),
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            s if s.starts_with('@') => return Err(format!("unknown cron macro `{}`", s)),
+            s => s,
+        };
+
+        let fields = expanded.split_whitespace().collect::<Vec<&str>>();
+
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(format!(
+                "`{}` is not a cron expression, expected five fields: minute, hour, day of the month, month and weekday",
+                s
+            ));
+        };
+
+        Calendar {
+            minutes: Field::parse(Unit::Minute, minutes, Syntax::Cron)?,
+            hours: Field::parse(Unit::Hour, hours, Syntax::Cron)?,
+            days: Field::parse(Unit::Day, days, Syntax::Cron)?,
+            months: Field::parse(Unit::Month, months, Syntax::Cron)?,
+            weekdays: Field::parse(Unit::Weekday, weekdays, Syntax::Cron)?,
+        }
+        .validate()
+        .map(Self::Calendar)
+    }
+
+    /// Parse a systemd calendar event, e.g. `Mon..Fri *-*-* 02:00:00` or
+    /// `daily`, see systemd.time(7).
+    pub fn from_on_calendar(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+
+        let expanded = match s.to_ascii_lowercase().as_str() {
+            "minutely" => "*-*-* *:*:00",
+            "hourly" => "*-*-* *:00:00",
+            "daily" => "*-*-* 00:00:00",
+            "weekly" => "Mon *-*-* 00:00:00",
+            "monthly" => "*-*-01 00:00:00",
+            "quarterly" => "*-01,04,07,10-01 00:00:00",
+            "semiannually" => "*-01,07-01 00:00:00",
+            "yearly" | "annually" => "*-01-01 00:00:00",
+            _ => s,
+        };
+
+        let mut tokens = expanded.split_whitespace().peekable();
+
+        let weekdays =
+            match tokens.next_if(|token| token.starts_with(|c: char| c.is_ascii_alphabetic())) {
+                // Ranges of weekdays may also be written with a hyphen.
+                Some(token) => {
+                    Field::parse(Unit::Weekday, &token.replace('-', ".."), Syntax::OnCalendar)?
+                }
+                None => Field::all(Unit::Weekday),
+            };
+
+        let (months, days) = match tokens.next_if(|token| token.contains('-')) {
+            Some(token) => match token.split('-').collect::<Vec<&str>>().as_slice() {
+                ["*", month, day] | [month, day] => (
+                    Field::parse(Unit::Month, month, Syntax::OnCalendar)?,
+                    Field::parse(Unit::Day, day, Syntax::OnCalendar)?,
+                ),
+                [_, _, _] => {
+                    return Err(format!(
+                        "`{}` restricts years, which cron does not support",
+                        s
+                    ))
+                }
+                _ => return Err(format!("`{}` has an invalid date", s)),
+            },
+            None => (Field::all(Unit::Month), Field::all(Unit::Day)),
+        };
+
+        let (hours, minutes) = match tokens.next_if(|token| token.contains(':')) {
+            Some(token) => match token.split(':').collect::<Vec<&str>>().as_slice() {
+                [hour, minute] | [hour, minute, "0" | "00"] => (
+                    Field::parse(Unit::Hour, hour, Syntax::OnCalendar)?,
+                    Field::parse(Unit::Minute, minute, Syntax::OnCalendar)?,
+                ),
+                [_, _, _] => {
+                    return Err(format!(
+                        "`{}` restricts seconds, which cron does not support",
+                        s
+                    ))
+                }
+                _ => return Err(format!("`{}` has an invalid time", s)),
+            },
+            None => (Field::single(Unit::Hour, 0), Field::single(Unit::Minute, 0)),
+        };
+
+        if let Some(token) = tokens.next() {
+            return Err(format!(
+                "`{}` is not supported in calendar event `{}`, e.g. time zones are not",
+                token, s
+            ));
+        }
+
+        Calendar {
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+        }
+        .validate()
+        .map(Self::Calendar)
+    }
+
+    pub fn cron(&self) -> Cron<'_> {
+        Cron(self)
+    }
+
+    /// Return the schedule as a calendar event, unless it is `@reboot`.
+    pub fn on_calendar(&self) -> Option<OnCalendar<'_>> {
+        match self {
+            Self::Reboot => None,
+            Self::Calendar(calendar) => Some(OnCalendar(calendar)),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    /// Parse either syntax: cron expressions have five fields or are macros
+    /// that start with `@`, calendar events have at most three.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let schedule = if s.starts_with('@') || s.split_whitespace().count() == 5 {
+            Self::from_cron(s)
+        } else {
+            Self::from_on_calendar(s)
+        };
+
+        schedule.map_err(|error| anyhow::anyhow!("invalid schedule: {}", error))
+    }
+}
+
+impl fmt::Display for Cron<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Schedule::Reboot => f.write_str("@reboot"),
+            Schedule::Calendar(calendar) => {
+                calendar.minutes.write(f, Syntax::Cron)?;
+                f.write_str(" ")?;
+                calendar.hours.write(f, Syntax::Cron)?;
+                f.write_str(" ")?;
+                calendar.days.write(f, Syntax::Cron)?;
+                f.write_str(" ")?;
+                calendar.months.write(f, Syntax::Cron)?;
+                f.write_str(" ")?;
+                calendar.weekdays.write(f, Syntax::Cron)
+            }
+        }
+    }
+}
+
+impl fmt::Display for OnCalendar<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let calendar = self.0;
+
+        if !calendar.weekdays.is_all() {
+            calendar.weekdays.write(f, Syntax::OnCalendar)?;
+            f.write_str(" ")?;
+        }
+
+        f.write_str("*-")?;
+        calendar.months.write(f, Syntax::OnCalendar)?;
+        f.write_str("-")?;
+        calendar.days.write(f, Syntax::OnCalendar)?;
+        f.write_str(" ")?;
+        calendar.hours.write(f, Syntax::OnCalendar)?;
+        f.write_str(":")?;
+        calendar.minutes.write(f, Syntax::OnCalendar)?;
+        f.write_str(":00")
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = String::deserialize(deserializer)?;
+
+        Schedule::from_str(&v).map_err(Error::custom)
+    }
+}
+
+impl Serialize for Schedule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&self.cron())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_cron() {
+        let cron = |s: &str| Schedule::from_cron(s).map(|schedule| schedule.cron().to_string());
+
+        assert_eq!(cron("*/15 2 * * mon-fri").unwrap(), "*/15 2 * * 1-5");
+        assert_eq!(cron("0 0 1,15 jan-mar *").unwrap(), "0 0 1,15 1-3 *");
+        assert_eq!(cron("@daily").unwrap(), "0 0 * * *");
+        assert_eq!(cron("@reboot").unwrap(), "@reboot");
+        assert_eq!(cron("0 12 * * 5-7").unwrap(), "0 12 * * 0,5,6");
+        assert_eq!(cron("5/20 * * * *").unwrap(), "5,25,45 * * * *");
+        assert!(cron("0 2 * *").is_err());
+        assert!(cron("60 * * * *").is_err());
+        assert!(cron("0 2 1 * mon").is_err());
+        assert!(cron("0 2 10-1 * *").is_err());
+        assert!(cron("@fortnightly").is_err());
+    }
+
+    #[test]
+    fn parse_on_calendar() {
+        let convert = |s: &str| {
+            let schedule = Schedule::from_on_calendar(s).unwrap();
+            (
+                schedule.cron().to_string(),
+                schedule.on_calendar().unwrap().to_string(),
+            )
+        };
+
+        assert_eq!(
+            convert("Mon..Fri *-*-* 02:00/15:00"),
+            (
+                "*/15 2 * * 1-5".to_string(),
+                "Mon..Fri *-*-* 02:00/15:00".to_string()
+            )
+        );
+        assert_eq!(
+            convert("weekly"),
+            ("0 0 * * 1".to_string(), "Mon *-*-* 00:00:00".to_string())
+        );
+        assert_eq!(
+            convert("Sat,Sun 12:30"),
+            (
+                "30 12 * * 0,6".to_string(),
+                "Sat,Sun *-*-* 12:30:00".to_string()
+            )
+        );
+        assert_eq!(
+            convert("*-01,07-01 00:00:00"),
+            ("0 0 1 1,7 *".to_string(), "*-01,07-01 00:00:00".to_string())
+        );
+        assert!(Schedule::from_on_calendar("2025-*-* 00:00:00").is_err());
+        assert!(Schedule::from_on_calendar("*-*-* 00:00:30").is_err());
+        assert!(Schedule::from_on_calendar("daily UTC").is_err());
+        assert!(Schedule::from_on_calendar("Mon *-*-01 00:00").is_err());
+    }
+
+    #[test]
+    fn parse_either_syntax() {
+        let cron = "30 4 * * 0".parse::<Schedule>().unwrap();
+        let on_calendar = "Sun 04:30".parse::<Schedule>().unwrap();
+
+        assert_eq!(cron, on_calendar);
+        assert!("@reboot"
+            .parse::<Schedule>()
+            .is_ok_and(|schedule| schedule.on_calendar().is_none()));
+    }
+}
@@ -0,0 +1,218 @@
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use std::{fmt::Write, io::Write as _, os::unix::net::UnixDatagram, str::FromStr};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// The socket of the local syslog daemon.
+const SOCKET: &str = "/dev/log";
+
+/// The ID of the structured data element that carries the key-value pairs
+/// of a log record. 32473 is the private enterprise number that RFC 5612
+/// reserves for documentation and examples.
+const SD_ID: &str = "pullconf@32473";
+
+/// A logger that sends RFC 5424 messages to the local syslog daemon. The
+/// key-value pairs of a log record are passed on as structured data, so
+/// they survive forwarding to a central collector.
+pub struct Syslog {
+    socket: UnixDatagram,
+    facility: u8,
+    level: LevelFilter,
+    hostname: String,
+    application: &'static str,
+    version: &'static str,
+}
+
+impl Syslog {
+    /// Install the syslog logger as the global logger. The facility is read
+    /// from `PULLCONF_SYSLOG_FACILITY` and the level from `LOG_LEVEL`, just
+    /// like the default logger does.
+    pub fn init(application: &'static str, version: &'static str) -> Result<(), String> {
+        let facility = match std::env::var("PULLCONF_SYSLOG_FACILITY") {
+            Ok(name) => {
+                facility(&name).ok_or_else(|| format!("unknown syslog facility {}", name))?
+            }
+            Err(_) => facility("daemon").unwrap_or_default(),
+        };
+
+        let level = std::env::var("LOG_LEVEL")
+            .ok()
+            .and_then(|level| LevelFilter::from_str(&level).ok())
+            .unwrap_or(LevelFilter::Info);
+
+        let socket = UnixDatagram::unbound()
+            .and_then(|socket| socket.connect(SOCKET).map(|_| socket))
+            .map_err(|error| format!("failed to connect to {}: {}", SOCKET, error))?;
+
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .map(|hostname| hostname.trim().to_string())
+            .ok()
+            .filter(|hostname| !hostname.is_empty())
+            .unwrap_or("-".to_string());
+
+        let logger = Self {
+            socket,
+            facility,
+            level,
+            hostname,
+            application,
+            version,
+        };
+
+        log::set_boxed_logger(Box::new(logger)).map_err(|error| error.to_string())?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+
+    /// Format a log record as RFC 5424 message, e.g.
+    /// `<30>1 2024-09-01T12:00:00Z host pullconf 42 - [pullconf@32473 ...] message`.
+    fn format(&self, record: &Record, timestamp: OffsetDateTime) -> String {
+        let severity = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+
+        let mut data = Data(String::new());
+
+        let _ = data.visit_pair(Key::from_str("application"), Value::from(self.application));
+        let _ = data.visit_pair(Key::from_str("version"), Value::from(self.version));
+        let _ = record.key_values().visit(&mut data);
+
+        format!(
+            "<{}>1 {} {} {} {} - [{}{}] {}",
+            self.facility * 8 + severity,
+            timestamp.format(&Rfc3339).unwrap_or("-".to_string()),
+            self.hostname,
+            self.application,
+            std::process::id(),
+            SD_ID,
+            data.0,
+            record.args()
+        )
+    }
+}
+
+impl Log for Syslog {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let message = self.format(record, OffsetDateTime::now_utc());
+
+        // Logs must not get lost if the syslog daemon is unavailable, so
+        // they are written to stderr instead.
+        if self.socket.send(message.as_bytes()).is_err() {
+            let _ = writeln!(std::io::stderr(), "{}", message);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Collects the key-value pairs of a log record as structured data
+/// parameters.
+struct Data(String);
+
+impl<'kvs> VisitSource<'kvs> for Data {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        // Parameter names must be printable ASCII without `=`, ` `, `]` and
+        // `"` and may be at most 32 characters long.
+        let name = key
+            .as_str()
+            .chars()
+            .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+            .take(32)
+            .collect::<String>();
+
+        if name.is_empty() {
+            return Ok(());
+        }
+
+        let mut escaped = String::new();
+
+        for c in value.to_string().chars() {
+            if matches!(c, '"' | '\\' | ']') {
+                escaped.push('\\');
+            }
+
+            escaped.push(c);
+        }
+
+        let _ = write!(self.0, " {}=\"{}\"", name, escaped);
+
+        Ok(())
+    }
+}
+
+/// Return the numeric code of a syslog facility.
+fn facility(name: &str) -> Option<u8> {
+    let code = match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        "local0" => 16,
+        "local1" => 17,
+        "local2" => 18,
+        "local3" => 19,
+        "local4" => 20,
+        "local5" => 21,
+        "local6" => 22,
+        "local7" => 23,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_rfc5424_message() {
+        let logger = Syslog {
+            socket: UnixDatagram::unbound().unwrap(),
+            facility: facility("local3").unwrap(),
+            level: LevelFilter::Info,
+            hostname: "web01".to_string(),
+            application: "pullconf",
+            version: "0.1.0",
+        };
+
+        let message = logger.format(
+            &Record::builder()
+                .args(format_args!("applied resource catalog"))
+                .level(Level::Warn)
+                .key_values(&[("scope", "main"), ("name", "say \"hi\" [now]")])
+                .build(),
+            OffsetDateTime::UNIX_EPOCH,
+        );
+
+        assert_eq!(
+            message,
+            format!(
+                "<156>1 1970-01-01T00:00:00Z web01 pullconf {} - [pullconf@32473 application=\"pullconf\" version=\"0.1.0\" scope=\"main\" name=\"say \\\"hi\\\" [now\\]\"] applied resource catalog",
+                std::process::id()
+            )
+        );
+    }
+}
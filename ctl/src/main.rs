@@ -0,0 +1,556 @@
+mod transport;
+
+use crate::transport::{Reply, Transport};
+use clap::{Parser, Subcommand, ValueEnum};
+use common::{
+    error::ErrorCode,
+    problem::{self, Problem},
+    Hostname,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{collections::BTreeMap, env, fs, path::PathBuf, process::ExitCode};
+
+/// The error of a request that failed with an empty 404 response.
+const NOT_FOUND: &str = "not found";
+
+/// Pull-based Linux configuration management system, admin command-line
+/// interface. It manages a running pullconfd via its admin API, either via
+/// the local admin socket or via HTTPS with an admin token, which is read
+/// from --token-file or $PULLCONF_ADMIN_TOKEN.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// The admin socket of pullconfd, which takes precedence over --server.
+    #[arg(long, env = "PULLCONF_ADMIN_SOCKET")]
+    socket: Option<PathBuf>,
+    /// The address of pullconfd, e.g. `pullconf.example.com:443`.
+    #[arg(long, env = "PULLCONF_SERVER")]
+    server: Option<String>,
+    /// A file that contains the admin token for requests via --server.
+    #[arg(long)]
+    token_file: Option<PathBuf>,
+    /// The output format, either a table for humans or the JSON payload
+    /// of the admin API for scripts.
+    #[arg(long, value_enum, global = true, default_value = "table")]
+    format: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage the clients of pullconfd.
+    Clients {
+        #[command(subcommand)]
+        command: ClientsCommand,
+    },
+    /// Print the resource catalog that pullconfd currently serves to a
+    /// client.
+    Catalog {
+        /// The hostname of the client.
+        hostname: Hostname,
+    },
+    /// List how the resource catalog of a client changed between the
+    /// versions that pullconfd served to it, the most recent change first.
+    Changes {
+        /// The hostname of the client.
+        hostname: Hostname,
+        /// Only list changes from this point in time on, e.g.
+        /// `2024-05-14T00:00:00Z`.
+        #[arg(long)]
+        since: Option<String>,
+    },
+    /// List the clients that have a package installed, according to the
+    /// facts that they sent most recently.
+    Packages {
+        /// The name of the package, e.g. `openssl`.
+        name: String,
+        /// Only list clients with a lower version, e.g. `3.0.13-1~deb12u1`.
+        #[arg(long)]
+        below: Option<String>,
+    },
+    /// Reload the configuration of pullconfd and print the validation
+    /// errors if it fails.
+    Reload,
+    /// Approve the pending registration of a client, which adds it to the
+    /// configuration and reloads it.
+    Approve {
+        /// The hostname of the client.
+        hostname: Hostname,
+        /// A group that the client is assigned to, may be repeated.
+        #[arg(long = "group")]
+        groups: Vec<Hostname>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ClientsCommand {
+    /// List every client along with its groups and number of resources.
+    List,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Table,
+}
+
+/// The envelope of every successful response of the admin API.
+#[derive(Deserialize)]
+struct Envelope<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct ClientInventory {
+    name: String,
+    groups: Vec<String>,
+    resources: BTreeMap<String, usize>,
+    decommissioned: bool,
+}
+
+#[derive(Deserialize)]
+struct InstalledPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    architecture: Option<String>,
+    timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct Change {
+    timestamp: String,
+    #[serde(default)]
+    serial: Option<u64>,
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<ChangedResource>,
+}
+
+#[derive(Deserialize)]
+struct ChangedResource {
+    resource: String,
+    parameters: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ReloadResult {
+    success: bool,
+    errors: Vec<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    context: BTreeMap<String, String>,
+    message: String,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("pullconfctl: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<ExitCode, String> {
+    let transport = transport(&cli)?;
+
+    match cli.command {
+        Command::Clients {
+            command: ClientsCommand::List,
+        } => {
+            let reply = call(&transport, "GET", "/admin/clients", None)?;
+
+            match cli.format {
+                Format::Json => print_body(&reply),
+                Format::Table => {
+                    let clients = parse::<Vec<ClientInventory>>(&reply)?;
+
+                    let rows = clients
+                        .into_iter()
+                        .map(|client| {
+                            vec![
+                                client.name,
+                                client.groups.join(","),
+                                client.resources.values().sum::<usize>().to_string(),
+                                if client.decommissioned {
+                                    "decommissioned"
+                                } else {
+                                    "active"
+                                }
+                                .to_string(),
+                            ]
+                        })
+                        .collect::<Vec<_>>();
+
+                    print!(
+                        "{}",
+                        table(&["NAME", "GROUPS", "RESOURCES", "STATE"], &rows)
+                    );
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Catalog { hostname } => {
+            let path = format!("/admin/clients/{}/catalog", hostname);
+
+            let reply = match call(&transport, "GET", &path, None) {
+                Err(error) if error == NOT_FOUND => {
+                    return Err(format!("client `{}` is not known", hostname))
+                }
+                result => result?,
+            };
+
+            match cli.format {
+                Format::Json => print_body(&reply),
+                Format::Table => {
+                    let resources = parse::<Vec<serde_json::Value>>(&reply)?;
+
+                    let rows = resources
+                        .iter()
+                        .map(|resource| {
+                            let field = |name: &str| {
+                                resource[name].as_str().unwrap_or_default().to_string()
+                            };
+
+                            // The parameter that identifies a resource among
+                            // those of its type, if there is one.
+                            let parameters = &resource["parameters"];
+
+                            let name = ["name", "path", "ip-address"]
+                                .iter()
+                                .find_map(|name| parameters[name].as_str())
+                                .unwrap_or_default()
+                                .to_string();
+
+                            vec![field("id"), field("type"), name]
+                        })
+                        .collect::<Vec<_>>();
+
+                    print!("{}", table(&["ID", "TYPE", "NAME"], &rows));
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Changes { hostname, since } => {
+            let mut path = format!("/admin/clients/{}/changes", hostname);
+
+            if let Some(since) = since {
+                path.push_str(&format!("?since={}", encode_query_component(&since)));
+            }
+
+            let reply = match call(&transport, "GET", &path, None) {
+                Err(error) if error == NOT_FOUND => {
+                    return Err(format!(
+                        "no catalog of client `{}` has been recorded",
+                        hostname
+                    ))
+                }
+                result => result?,
+            };
+
+            match cli.format {
+                Format::Json => print_body(&reply),
+                Format::Table => {
+                    let changes = parse::<Vec<Change>>(&reply)?;
+
+                    let mut rows = vec![];
+
+                    for change in changes {
+                        for resource in change.added {
+                            rows.push(vec![
+                                change.timestamp.clone(),
+                                change
+                                    .serial
+                                    .map_or("-".to_string(), |serial| serial.to_string()),
+                                "+".to_string(),
+                                resource,
+                                String::new(),
+                            ]);
+                        }
+
+                        for resource in change.removed {
+                            rows.push(vec![
+                                change.timestamp.clone(),
+                                change
+                                    .serial
+                                    .map_or("-".to_string(), |serial| serial.to_string()),
+                                "-".to_string(),
+                                resource,
+                                String::new(),
+                            ]);
+                        }
+
+                        for changed in change.changed {
+                            rows.push(vec![
+                                change.timestamp.clone(),
+                                change
+                                    .serial
+                                    .map_or("-".to_string(), |serial| serial.to_string()),
+                                "~".to_string(),
+                                changed.resource,
+                                changed.parameters.join(","),
+                            ]);
+                        }
+                    }
+
+                    print!(
+                        "{}",
+                        table(
+                            &["SERVED", "SERIAL", "CHANGE", "RESOURCE", "PARAMETERS"],
+                            &rows
+                        )
+                    );
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Packages { name, below } => {
+            let mut path = format!("/admin/packages/{}", encode_query_component(&name));
+
+            if let Some(below) = below {
+                path.push_str(&format!("?below={}", encode_query_component(&below)));
+            }
+
+            let reply = call(&transport, "GET", &path, None)?;
+
+            match cli.format {
+                Format::Json => print_body(&reply),
+                Format::Table => {
+                    let packages = parse::<Vec<InstalledPackage>>(&reply)?;
+
+                    let rows = packages
+                        .into_iter()
+                        .map(|package| {
+                            vec![
+                                package.name,
+                                package.version,
+                                package.architecture.unwrap_or_default(),
+                                package.timestamp,
+                            ]
+                        })
+                        .collect::<Vec<_>>();
+
+                    print!(
+                        "{}",
+                        table(&["CLIENT", "VERSION", "ARCHITECTURE", "REPORTED"], &rows)
+                    );
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+        Command::Reload => {
+            let reply = call(&transport, "POST", "/admin/reload", None)?;
+
+            report_reload(&reply, cli.format)
+        }
+        Command::Approve { hostname, groups } => {
+            let path = format!("/admin/registrations/{}/approve", hostname);
+
+            let body = serde_json::to_vec(&serde_json::json!({ "groups": groups }))
+                .map_err(|error| error.to_string())?;
+
+            let reply = call(&transport, "POST", &path, Some(&body))?;
+
+            if let Format::Table = cli.format {
+                println!("approved registration of `{}`", hostname);
+            }
+
+            report_reload(&reply, cli.format)
+        }
+    }
+}
+
+/// Select the transport from the command-line arguments, where the admin
+/// socket takes precedence.
+fn transport(cli: &Cli) -> Result<Transport, String> {
+    if let Some(socket) = &cli.socket {
+        return Ok(Transport::Socket(socket.clone()));
+    }
+
+    let Some(server) = &cli.server else {
+        return Err(
+            "either --socket or --server must be given, or $PULLCONF_ADMIN_SOCKET or $PULLCONF_SERVER must be set"
+                .to_string(),
+        );
+    };
+
+    // The token is never accepted as an argument, as arguments are visible
+    // to every user of the system.
+    let token = match &cli.token_file {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|error| format!("failed to read {}: {}", path.display(), error))?,
+        None => env::var("PULLCONF_ADMIN_TOKEN").map_err(|_| {
+            "--token-file must be given or $PULLCONF_ADMIN_TOKEN must be set to use --server"
+                .to_string()
+        })?,
+    };
+
+    Ok(Transport::https(server, token.trim().to_string()))
+}
+
+/// Send a request and turn responses that do not carry a JSON payload into
+/// an error. The admin API also answers some failed requests with a JSON
+/// payload, e.g. a failed reload.
+fn call(
+    transport: &Transport,
+    method: &str,
+    path: &str,
+    body: Option<&[u8]>,
+) -> Result<Reply, String> {
+    let reply = transport.request(method, path, body)?;
+
+    if reply.content_type == "application/json" {
+        return Ok(reply);
+    }
+
+    if reply.content_type == problem::CONTENT_TYPE {
+        let problem = serde_json::from_slice::<Problem>(&reply.body)
+            .map_err(|error| format!("failed to parse error response: {}", error))?;
+
+        let hint = match problem.error_code() {
+            ErrorCode::Authentication => ", check the admin token",
+            ErrorCode::Authorization => ", the admin token lacks the required role",
+            _ => "",
+        };
+
+        return Err(format!(
+            "pullconfd failed to process the request: ({}, {}) {}{}",
+            problem.code, problem.title, problem.detail, hint
+        ));
+    }
+
+    match reply.status {
+        404 => Err(NOT_FOUND.to_string()),
+        status => Err(format!(
+            "unexpected response with status {} and content type `{}`",
+            status, reply.content_type
+        )),
+    }
+}
+
+fn parse<T: DeserializeOwned>(reply: &Reply) -> Result<T, String> {
+    serde_json::from_slice::<Envelope<T>>(&reply.body)
+        .map(|envelope| envelope.data)
+        .map_err(|error| format!("failed to parse response: {}", error))
+}
+
+fn print_body(reply: &Reply) {
+    println!("{}", String::from_utf8_lossy(&reply.body));
+}
+
+/// Print the outcome of a reload, which fails unless the configuration
+/// was loaded successfully.
+fn report_reload(reply: &Reply, format: Format) -> Result<ExitCode, String> {
+    let result = parse::<ReloadResult>(reply)?;
+
+    match format {
+        Format::Json => print_body(reply),
+        Format::Table if result.success => println!("reloaded configuration"),
+        Format::Table => {
+            println!("failed to reload configuration, the previous configuration remains active:");
+
+            for error in &result.errors {
+                let context = error
+                    .context
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>();
+
+                if context.is_empty() {
+                    println!("  {}", error.message);
+                } else {
+                    println!("  {} ({})", error.message, context.join(", "));
+                }
+            }
+        }
+    }
+
+    Ok(if result.success {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Percent-encode a path segment or query component, e.g. a version such
+/// as `1:2.0+dfsg-1`, where `+` would otherwise denote a space.
+fn encode_query_component(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Render rows as a table whose columns are aligned, where the last
+/// column is not padded.
+fn table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths = header.iter().map(|title| title.len()).collect::<Vec<_>>();
+
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header = header
+        .iter()
+        .map(|title| title.to_string())
+        .collect::<Vec<_>>();
+
+    let mut output = String::new();
+
+    for row in std::iter::once(&header).chain(rows) {
+        let last = row.len().saturating_sub(1);
+
+        for (index, (cell, width)) in row.iter().zip(&widths).enumerate() {
+            if index == last {
+                output.push_str(cell);
+            } else {
+                output.push_str(&format!("{:<width$}  ", cell, width = width));
+            }
+        }
+
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_table() {
+        let rows = vec![
+            vec![
+                "web.example.com".to_string(),
+                "nginx".to_string(),
+                "12".to_string(),
+            ],
+            vec!["db.example.com".to_string(), String::new(), "3".to_string()],
+        ];
+
+        assert_eq!(
+            table(&["NAME", "GROUPS", "RESOURCES"], &rows),
+            "NAME             GROUPS  RESOURCES\n\
+             web.example.com  nginx   12\n\
+             db.example.com           3\n"
+        );
+    }
+}
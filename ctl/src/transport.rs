@@ -0,0 +1,187 @@
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How requests reach the admin API of pullconfd, either via its local
+/// admin socket, which requires no token, or via HTTPS with an admin token.
+pub enum Transport {
+    Socket(PathBuf),
+    Https {
+        agent: ureq::Agent,
+        server: String,
+        token: String,
+    },
+}
+
+/// The parts of a response that pullconfctl evaluates.
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Transport {
+    pub fn https(server: &str, token: String) -> Self {
+        let server = server.trim_end_matches('/');
+
+        let server = if server.contains("://") {
+            server.to_string()
+        } else {
+            format!("https://{}", server)
+        };
+
+        Self::Https {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(120))
+                .build(),
+            server,
+            token,
+        }
+    }
+
+    /// Send a request to the admin API and return the response, regardless
+    /// of its status code.
+    pub fn request(&self, method: &str, path: &str, body: Option<&[u8]>) -> Result<Reply, String> {
+        match self {
+            Self::Socket(socket) => request_socket(socket, method, path, body.unwrap_or_default())
+                .map_err(|error| {
+                    format!(
+                        "failed to send request to admin socket {}: {}",
+                        socket.display(),
+                        error
+                    )
+                }),
+            Self::Https {
+                agent,
+                server,
+                token,
+            } => {
+                let request = agent
+                    .request(method, &format!("{}{}", server, path))
+                    .set("x-api-key", token);
+
+                let result = match body {
+                    Some(body) => request
+                        .set("content-type", "application/json")
+                        .send_bytes(body),
+                    None => request.call(),
+                };
+
+                let response = match result {
+                    Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+                    Err(ureq::Error::Transport(error)) => {
+                        return Err(format!("failed to send request to {}: {}", server, error))
+                    }
+                };
+
+                let status = response.status();
+                let content_type = response.content_type().to_string();
+                let mut body = vec![];
+
+                response
+                    .into_reader()
+                    .read_to_end(&mut body)
+                    .map_err(|error| {
+                        format!("failed to read response from {}: {}", server, error)
+                    })?;
+
+                Ok(Reply {
+                    status,
+                    content_type,
+                    body,
+                })
+            }
+        }
+    }
+}
+
+/// Send a single HTTP/1.1 request via the admin socket, which answers one
+/// request per connection and closes it afterwards.
+fn request_socket(
+    socket: &Path,
+    method: &str,
+    path: &str,
+    body: &[u8],
+) -> Result<Reply, io::Error> {
+    let mut stream = UnixStream::connect(socket)?;
+
+    // Reloads compile every resource catalog, which may take a while.
+    stream.set_read_timeout(Some(Duration::from_secs(120)))?;
+
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        method,
+        path,
+        body.len()
+    )?;
+
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = vec![];
+
+    stream.read_to_end(&mut response)?;
+
+    parse_response(&response)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))
+}
+
+fn parse_response(response: &[u8]) -> Option<Reply> {
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+
+    let head = std::str::from_utf8(&response[..end]).ok()?;
+    let mut body = response[end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u16>()
+        .ok()?;
+
+    let mut content_type = String::new();
+
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        if name.eq_ignore_ascii_case("content-type") {
+            // Parameters such as the charset are ignored like ureq does.
+            content_type = value.split(';').next()?.trim().to_string();
+        } else if name.eq_ignore_ascii_case("content-length") {
+            body.truncate(value.trim().parse().ok()?);
+        }
+    }
+
+    Some(Reply {
+        status,
+        content_type,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_socket_response() {
+        let response = b"HTTP/1.1 422 \r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"success\":false}";
+
+        assert_eq!(
+            parse_response(response),
+            Some(Reply {
+                status: 422,
+                content_type: "application/json".to_string(),
+                body: b"{\"success\":false}".to_vec(),
+            })
+        );
+        assert_eq!(parse_response(b"HTTP/1.1 204 \r\n"), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! A harness for integration tests that run pullconfd and pullconf against
+//! each other. Every test gets its own sandbox, a temporary directory that
+//! holds the configuration and state of pullconfd, the state of pullconf
+//! and the alternate root below which the resources of the test are
+//! applied with `--root`, so that the resulting filesystem state can be
+//! asserted without touching the rest of the system.
+//!
+//! pullconfd is started in-process, while pullconf is executed as a process
+//! of its own, which is built by this crate. The accounts of the alternate
+//! root map `root` to the user that runs the tests, so they neither require
+//! root privileges nor anything but `cargo test`.
+
+use std::{
+    env, fs,
+    net::TcpListener,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Mutex,
+};
+
+/// The hostname that pullconf identifies itself with.
+pub const HOSTNAME: &str = "client.pullconf.test";
+
+/// The API key of the client, whose SHA256 hash is written to its client
+/// configuration file.
+const API_KEY: &str = "pullconf-integration-test";
+const API_KEY_HASH: &str = "ac42b8315fd72041354712b862164f52b76fee48a9c4f04e8bef426da03f3246";
+
+/// pullconfd reads its configuration from the environment of this process,
+/// which is shared by the tests that run concurrently.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// A temporary directory along with a pullconfd that serves the resources
+/// of a test, which is stopped and removed when the sandbox is dropped.
+/// Set $PULLCONF_KEEP_SANDBOX to keep the directory, e.g. to inspect the
+/// alternate root after a failed test.
+pub struct Sandbox {
+    directory: PathBuf,
+    port: u16,
+    server: Option<pullconfd::Server>,
+}
+
+impl Sandbox {
+    /// Create an empty sandbox, whose name must be unique among the tests
+    /// that run concurrently.
+    pub fn new(name: &str) -> Self {
+        let directory = env::temp_dir().join(format!("pullconf-{}-{}", name, std::process::id()));
+
+        if directory.exists() {
+            fs::remove_dir_all(&directory).unwrap();
+        }
+
+        for path in [
+            "server/resources/clients",
+            "server/resources/groups",
+            "server/assets",
+            "server/state",
+            "client/state",
+            "root/etc",
+        ] {
+            fs::create_dir_all(directory.join(path)).unwrap();
+        }
+
+        fs::write(directory.join("server/admin-tokens.toml"), "").unwrap();
+
+        // Resources are owned by root unless configured otherwise, which is
+        // the user that runs the tests within the alternate root.
+        let metadata = fs::metadata(&directory).unwrap();
+
+        fs::write(
+            directory.join("root/etc/passwd"),
+            format!(
+                "root:x:{}:{}:root:/root:/bin/sh\n",
+                metadata.uid(),
+                metadata.gid()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            directory.join("root/etc/group"),
+            format!("root:x:{}:\n", metadata.gid()),
+        )
+        .unwrap();
+
+        Self {
+            directory,
+            port: 0,
+            server: None,
+        }
+    }
+
+    /// The alternate root below which the resources of a test are applied.
+    pub fn root(&self) -> PathBuf {
+        self.directory.join("root")
+    }
+
+    /// Resolve a path of the alternate root, e.g. `/etc/motd`.
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root().join(path.trim_start_matches('/'))
+    }
+
+    /// Write the client configuration file of pullconf, which is given
+    /// without `api-key`.
+    pub fn client(&self, configuration: &str) {
+        fs::write(
+            self.directory
+                .join(format!("server/resources/clients/{}.toml", HOSTNAME)),
+            format!("api-key = \"{}\"\n{}", API_KEY_HASH, configuration),
+        )
+        .unwrap();
+    }
+
+    /// Write the configuration file of a group.
+    pub fn group(&self, name: &str, configuration: &str) {
+        fs::write(
+            self.directory
+                .join(format!("server/resources/groups/{}.toml", name)),
+            configuration,
+        )
+        .unwrap();
+    }
+
+    /// Copy a fixture from `fixtures/assets` to the asset directory of
+    /// pullconfd, where it is served at `/<name>`.
+    pub fn asset(&self, name: &str) {
+        fs::copy(
+            fixtures().join("assets").join(name),
+            self.directory.join("server/assets").join(name),
+        )
+        .unwrap();
+    }
+
+    /// Start pullconfd on a random port. A running pullconfd is restarted,
+    /// e.g. to load a changed configuration.
+    pub fn start(&mut self) {
+        self.stop();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        self.port = listener.local_addr().unwrap().port();
+
+        let _environment = ENVIRONMENT
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+
+        remove_environment();
+
+        for (variable, value) in [
+            (
+                "PULLCONF_RESOURCE_DIR",
+                self.directory.join("server/resources"),
+            ),
+            ("PULLCONF_ASSET_DIR", self.directory.join("server/assets")),
+            ("PULLCONF_STATE_DIR", self.directory.join("server/state")),
+            (
+                "PULLCONF_ADMIN_TOKEN_FILE",
+                self.directory.join("server/admin-tokens.toml"),
+            ),
+            (
+                "PULLCONF_TLS_CERTIFICATE",
+                fixtures().join("tls/server.crt"),
+            ),
+            (
+                "PULLCONF_TLS_PRIVATE_KEY",
+                fixtures().join("tls/server.key"),
+            ),
+        ] {
+            env::set_var(variable, value);
+        }
+
+        let server = pullconfd::serve(listener);
+
+        remove_environment();
+
+        self.server = Some(server.expect("pullconfd failed to start"));
+    }
+
+    fn stop(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.stop();
+        }
+    }
+
+    /// Run pullconf with the given arguments against pullconfd and return
+    /// its exit code along with its logs.
+    pub fn apply(&self, args: &[&str]) -> Run {
+        let output = Command::new(binary("pullconf-integration-client"))
+            .args(args)
+            .arg("--root")
+            .arg(self.root())
+            .args(["--hostname", HOSTNAME])
+            // The environment of the tests must not leak into pullconf, e.g.
+            // the server of an installed pullconf.
+            .env_clear()
+            .env("PATH", env::var_os("PATH").unwrap_or_default())
+            .env("PULLCONF_SERVER", format!("localhost:{}", self.port))
+            .env("PULLCONF_API_KEY", API_KEY)
+            .env("PULLCONF_CA_DIR", fixtures().join("ca"))
+            .env("PULLCONF_STATE_DIR", self.directory.join("client/state"))
+            .env("PULLCONF_SELF_UPDATE", "false")
+            .env("PULLCONF_RETRIES", "0")
+            .env("PULLCONF_LOG_FORMAT", "logfmt")
+            .env("LOG_LEVEL", "debug")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+
+        Run(output)
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        self.stop();
+
+        if env::var_os("PULLCONF_KEEP_SANDBOX").is_none() {
+            fs::remove_dir_all(&self.directory).ok();
+        }
+    }
+}
+
+/// The outcome of a run of pullconf.
+pub struct Run(Output);
+
+impl Run {
+    /// The exit code, e.g. `2` if at least one resource was changed.
+    pub fn code(&self) -> Option<i32> {
+        self.0.status.code()
+    }
+
+    /// The logs of the run.
+    pub fn logs(&self) -> String {
+        String::from_utf8_lossy(&self.0.stderr).into_owned()
+    }
+
+    /// Assert the exit code and print the logs otherwise.
+    pub fn expect(&self, code: i32) {
+        assert_eq!(
+            self.code(),
+            Some(code),
+            "pullconf exited with an unexpected code, logs:\n{}",
+            self.logs()
+        );
+    }
+}
+
+/// Settings of the environment that the tests run in must not leak into
+/// pullconfd, e.g. the git repository of an installed pullconfd.
+fn remove_environment() {
+    for (variable, _) in env::vars_os() {
+        if variable.to_string_lossy().starts_with("PULLCONF_") {
+            env::remove_var(variable);
+        }
+    }
+}
+
+/// Locate a binary of this crate, which is built next to the `deps`
+/// directory that holds the test executables.
+fn binary(name: &str) -> PathBuf {
+    env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(Path::parent)
+        .unwrap()
+        .join(name)
+}
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures")
+}
@@ -0,0 +1,140 @@
+//! Apply resource catalogs served by pullconfd to the alternate root of a
+//! sandbox and assert the resulting filesystem state.
+
+use pullconf_integration::Sandbox;
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+const FILES: &str = r#"
+[[resources]]
+type = "directory"
+path = "/etc/app"
+
+[[resources]]
+type = "file"
+path = "/etc/app/app.conf"
+mode = "640"
+content = """
+listen = 8080
+"""
+
+[[resources]]
+type = "file"
+path = "/etc/motd"
+source = "/motd"
+"#;
+
+/// Symlinks to the files above, which cannot be evaluated in noop mode, as
+/// their target does not exist until the files are created.
+const SYMLINKS: &str = r#"
+[[resources]]
+type = "symlink"
+path = "/etc/app/current.conf"
+target = "/etc/app/app.conf"
+"#;
+
+#[test]
+fn apply_files_directories_and_symlinks() {
+    let mut sandbox = Sandbox::new("files");
+
+    sandbox.client(&format!("{}{}", FILES, SYMLINKS));
+    sandbox.asset("motd");
+    sandbox.start();
+
+    sandbox.apply(&[]).expect(2);
+
+    assert!(sandbox.path("etc/app").is_dir());
+    assert_eq!(
+        fs::read_to_string(sandbox.path("etc/app/app.conf")).unwrap(),
+        "listen = 8080\n"
+    );
+    assert_eq!(
+        fs::metadata(sandbox.path("etc/app/app.conf"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o7777,
+        0o640
+    );
+    assert_eq!(
+        fs::read_to_string(sandbox.path("etc/motd")).unwrap(),
+        "Managed by pullconf, served from the asset directory of pullconfd.\n"
+    );
+    assert_eq!(
+        fs::read_link(sandbox.path("etc/app/current.conf")).unwrap(),
+        Path::new("/etc/app/app.conf")
+    );
+
+    // A second run finds every resource in its desired state.
+    sandbox.apply(&[]).expect(0);
+}
+
+#[test]
+fn noop_leaves_root_untouched() {
+    let mut sandbox = Sandbox::new("noop");
+
+    sandbox.client(FILES);
+    sandbox.asset("motd");
+    sandbox.start();
+
+    sandbox.apply(&["--noop"]).expect(2);
+
+    assert!(!sandbox.path("etc/app").exists());
+    assert!(!sandbox.path("etc/motd").exists());
+}
+
+#[test]
+fn remove_absent_and_purged_nodes() {
+    let mut sandbox = Sandbox::new("purge");
+
+    fs::create_dir_all(sandbox.path("etc/app/conf.d")).unwrap();
+    fs::write(sandbox.path("etc/app/conf.d/stale.conf"), "").unwrap();
+    fs::write(sandbox.path("etc/app/obsolete.conf"), "").unwrap();
+
+    sandbox.client(
+        r#"
+[[resources]]
+type = "directory"
+path = "/etc/app/conf.d"
+purge = true
+
+[[resources]]
+type = "file"
+path = "/etc/app/conf.d/app.conf"
+content = ""
+
+[[resources]]
+type = "file"
+ensure = "absent"
+path = "/etc/app/obsolete.conf"
+"#,
+    );
+    sandbox.start();
+
+    sandbox.apply(&[]).expect(2);
+
+    assert!(sandbox.path("etc/app/conf.d/app.conf").is_file());
+    assert!(!sandbox.path("etc/app/conf.d/stale.conf").exists());
+    assert!(!sandbox.path("etc/app/obsolete.conf").exists());
+}
+
+#[test]
+fn apply_resources_of_groups() {
+    let mut sandbox = Sandbox::new("groups");
+
+    fs::create_dir(sandbox.path("srv")).unwrap();
+
+    sandbox.client(r#"groups = ["web"]"#);
+    sandbox.group(
+        "web",
+        r#"
+[[resources]]
+type = "directory"
+path = "/srv/www"
+"#,
+    );
+    sandbox.start();
+
+    sandbox.apply(&[]).expect(2);
+
+    assert!(sandbox.path("srv/www").is_dir());
+}
@@ -0,0 +1,501 @@
+use crate::env;
+use anyhow::Context;
+use common::error::Terminate;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus, RetryPolicy,
+};
+use log::{debug, error, info};
+use rouille::{Response, Server};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    os::unix::fs::{symlink, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::Sender,
+    thread::JoinHandle,
+};
+use time::OffsetDateTime;
+
+/// Certificates are renewed once they expire in less than this many days.
+const RENEWAL_THRESHOLD: i64 = 30;
+
+/// The challenge that proves control over the domain to the ACME server.
+enum Challenge {
+    /// Serve the challenge from a temporary plain HTTP server that binds
+    /// to the given socket. The ACME server always connects to port 80,
+    /// so the socket must be reachable on that port.
+    Http(SocketAddr),
+    /// Run a hook script that creates or removes the TXT record of the
+    /// challenge.
+    Dns(PathBuf),
+}
+
+/// A challenge that has been set up and must be cleaned up once the ACME
+/// server validated it.
+enum Pending {
+    Http(JoinHandle<()>, Sender<()>),
+    Dns(PathBuf, String, String),
+}
+
+/// Obtains and renews the server certificate from an ACME certificate
+/// authority such as Let's Encrypt. The account and the certificate are
+/// persisted in the state directory, so that they survive restarts.
+///
+/// The certificate and private key are written to a new directory on
+/// each renewal, which the `live` symlink is then switched to, so that
+/// both files are replaced at once.
+pub struct AcmeClient {
+    domain: String,
+    email: String,
+    directory: String,
+    challenge: Challenge,
+    persist: PathBuf,
+    live: PathBuf,
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+impl AcmeClient {
+    /// Read the ACME settings from the environment. Returns `None` if no
+    /// domain is configured, in which case the certificate is managed by
+    /// other means.
+    pub fn from_env(state_directory: &Path) -> Result<Option<Self>, Terminate> {
+        let scope = "environment";
+
+        // Empty values are rejected by parse_string, so an empty domain
+        // means that the variable is not set.
+        let domain = env::parse_string("PULLCONF_ACME_DOMAIN", "")?;
+
+        if domain.is_empty() {
+            return Ok(None);
+        }
+
+        let email = env::parse_string("PULLCONF_ACME_EMAIL", "")?;
+
+        if email.is_empty() {
+            error!(
+                scope,
+                variable = "PULLCONF_ACME_EMAIL";
+                "variable must be set when PULLCONF_ACME_DOMAIN is set"
+            );
+            return Err(Terminate);
+        }
+
+        let directory = env::parse_string(
+            "PULLCONF_ACME_DIRECTORY",
+            "https://acme-v02.api.letsencrypt.org/directory",
+        )?;
+
+        let challenge = match env::parse_string("PULLCONF_ACME_CHALLENGE", "http-01")?.as_str() {
+            "http-01" => Challenge::Http(env::parse_socket(
+                "PULLCONF_ACME_HTTP_LISTEN_ON",
+                "0.0.0.0:80",
+            )?),
+            "dns-01" => {
+                let hook = env::parse_path(env::FileType::File, "PULLCONF_ACME_DNS_HOOK", "")?;
+
+                if hook.as_os_str().is_empty() {
+                    error!(
+                        scope,
+                        variable = "PULLCONF_ACME_DNS_HOOK";
+                        "variable must be set when the dns-01 challenge is used"
+                    );
+                    return Err(Terminate);
+                }
+
+                Challenge::Dns(hook)
+            }
+            _ => {
+                error!(
+                    scope,
+                    variable = "PULLCONF_ACME_CHALLENGE";
+                    "value must be either http-01 or dns-01"
+                );
+                return Err(Terminate);
+            }
+        };
+
+        let persist = state_directory.join("acme");
+
+        if let Err(error) = fs::create_dir_all(&persist) {
+            error!(
+                scope = "acme",
+                path:% = persist.display();
+                "failed to create directory: {}",
+                error
+            );
+            return Err(Terminate);
+        }
+
+        let live = persist.join("live");
+
+        Ok(Some(Self {
+            domain,
+            email,
+            directory,
+            challenge,
+            certificate: live.join("server.crt"),
+            key: live.join("server.key"),
+            live,
+            persist,
+        }))
+    }
+
+    /// Ensure that a certificate exists that does not expire within the
+    /// renewal threshold and order a new one otherwise.
+    pub fn renew(&self) -> Result<(), Terminate> {
+        let scope = "acme";
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("failed to start runtime")
+            .and_then(|runtime| runtime.block_on(self.order()))
+            .map_err(|error| {
+                error!(
+                    scope,
+                    domain = self.domain.as_str();
+                    "failed to obtain certificate: {:#}",
+                    error
+                );
+                Terminate
+            })
+    }
+
+    async fn order(&self) -> Result<(), anyhow::Error> {
+        let scope = "acme";
+
+        // The key may be missing if the state directory was only
+        // partially restored.
+        if let (Ok(certificate), true) = (fs::read(&self.certificate), self.key.is_file()) {
+            if let Some(days) = days_left(&certificate) {
+                if days > RENEWAL_THRESHOLD {
+                    debug!(scope, domain = self.domain.as_str(); "certificate is valid for {} more days", days);
+                    return Ok(());
+                }
+            }
+        }
+
+        let account = self.account().await?;
+
+        info!(scope, domain = self.domain.as_str(); "ordering certificate");
+
+        let identifiers = [Identifier::Dns(self.domain.clone())];
+
+        let mut order = account
+            .new_order(&NewOrder::new(&identifiers))
+            .await
+            .context("failed to create order")?;
+
+        let mut pending = vec![];
+
+        let result = self.authorize(&mut order, &mut pending).await;
+
+        let result = match result {
+            Ok(()) => order
+                .poll_ready(&RetryPolicy::default())
+                .await
+                .context("failed to validate challenges"),
+            Err(error) => Err(error),
+        };
+
+        for challenge in pending {
+            match challenge {
+                Pending::Http(handle, sender) => {
+                    let _ = sender.send(());
+                    let _ = handle.join();
+                }
+                Pending::Dns(hook, name, value) => {
+                    if let Err(error) = run(&hook, &["unset", &name, &value]) {
+                        error!(scope, domain = self.domain.as_str(); "failed to remove challenge: {:#}", error);
+                    }
+                }
+            }
+        }
+
+        let status = result?;
+
+        if status != OrderStatus::Ready {
+            anyhow::bail!("order is {:?} instead of ready", status);
+        }
+
+        let key = order.finalize().await.context("failed to finalize order")?;
+
+        let certificate = order
+            .poll_certificate(&RetryPolicy::default())
+            .await
+            .context("failed to download certificate")?;
+
+        self.write(&certificate, &key)?;
+
+        info!(
+            scope,
+            domain = self.domain.as_str();
+            "obtained certificate that is valid for {} days",
+            days_left(certificate.as_bytes()).unwrap_or_default()
+        );
+
+        Ok(())
+    }
+
+    /// Load the ACME account from the state directory or register a new
+    /// one if it does not exist yet.
+    async fn account(&self) -> Result<Account, anyhow::Error> {
+        let path = self.persist.join("account.json");
+
+        if path.is_file() {
+            let credentials: AccountCredentials = fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_slice(&content)?))
+                .with_context(|| format!("failed to read {}", path.display()))?;
+
+            return Account::builder()?
+                .from_credentials(credentials)
+                .await
+                .context("failed to load account");
+        }
+
+        let contact = format!("mailto:{}", self.email);
+
+        let (account, credentials) = Account::builder()?
+            .create(
+                &NewAccount {
+                    contact: &[&contact],
+                    terms_of_service_agreed: true,
+                    only_return_existing: false,
+                },
+                self.directory.clone(),
+                None,
+            )
+            .await
+            .context("failed to register account")?;
+
+        replace(&path, &serde_json::to_vec(&credentials)?)?;
+
+        Ok(account)
+    }
+
+    /// Set up the challenges of all authorizations that are not valid yet
+    /// and notify the ACME server that they are ready to be validated.
+    /// Each challenge is added to `pending`, even if the ACME server could
+    /// not be notified, so that it is cleaned up in any case.
+    async fn authorize(
+        &self,
+        order: &mut Order,
+        pending: &mut Vec<Pending>,
+    ) -> Result<(), anyhow::Error> {
+        let mut authorizations = order.authorizations();
+
+        while let Some(authorization) = authorizations.next().await {
+            let mut authorization = authorization.context("failed to fetch authorization")?;
+
+            match authorization.status {
+                AuthorizationStatus::Pending => (),
+                AuthorizationStatus::Valid => continue,
+                status => anyhow::bail!("authorization is {:?} instead of pending", status),
+            }
+
+            let (kind, name) = match &self.challenge {
+                Challenge::Http(_) => (ChallengeType::Http01, "http-01"),
+                Challenge::Dns(_) => (ChallengeType::Dns01, "dns-01"),
+            };
+
+            let Some(mut challenge) = authorization.challenge(kind) else {
+                anyhow::bail!("ACME server does not offer the {} challenge", name);
+            };
+
+            match &self.challenge {
+                Challenge::Http(socket) => {
+                    let path = format!("/.well-known/acme-challenge/{}", challenge.token);
+                    let proof = challenge.key_authorization().as_str().to_string();
+
+                    let server = Server::new(socket, move |request| {
+                        if request.url() == path {
+                            Response::text(proof.clone())
+                        } else {
+                            Response::empty_404()
+                        }
+                    })
+                    .map_err(|error| {
+                        anyhow::anyhow!("failed to start challenge server on {}: {}", socket, error)
+                    })?;
+
+                    let (handle, sender) = server.stoppable();
+
+                    pending.push(Pending::Http(handle, sender));
+                }
+                Challenge::Dns(hook) => {
+                    let name = format!("_acme-challenge.{}", self.domain);
+                    let value = challenge.key_authorization().dns_value();
+
+                    run(hook, &["set", &name, &value])?;
+
+                    pending.push(Pending::Dns(hook.clone(), name, value));
+                }
+            }
+
+            challenge
+                .set_ready()
+                .await
+                .context("failed to notify ACME server")?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the certificate and private key to a new directory and then
+    /// point the `live` symlink to it. The symlink is replaced atomically,
+    /// so that the server never reads a private key that does not match
+    /// the certificate.
+    fn write(&self, certificate: &str, key: &str) -> Result<(), anyhow::Error> {
+        let name = format!(
+            "certificate-{}",
+            OffsetDateTime::now_utc().unix_timestamp_nanos()
+        );
+        let directory = self.persist.join(&name);
+
+        fs::create_dir(&directory)
+            .with_context(|| format!("failed to create {}", directory.display()))?;
+
+        replace(&directory.join("server.key"), key.as_bytes())?;
+        replace(&directory.join("server.crt"), certificate.as_bytes())?;
+
+        let previous = fs::read_link(&self.live).ok();
+        let temporary = self.persist.join("live.tmp");
+
+        let _ = fs::remove_file(&temporary);
+
+        symlink(&name, &temporary)
+            .and_then(|_| fs::rename(&temporary, &self.live))
+            .with_context(|| format!("failed to replace {}", self.live.display()))?;
+
+        if let Some(previous) = previous {
+            if let Err(error) = fs::remove_dir_all(self.persist.join(&previous)) {
+                error!(
+                    scope = "acme",
+                    path:% = previous.display();
+                    "failed to remove previous certificate: {}",
+                    error
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Return the number of days until the first certificate of the given
+/// PEM-encoded chain expires, or `None` if it cannot be parsed.
+fn days_left(chain: &[u8]) -> Option<i64> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(chain).ok()?;
+    let certificate = pem.parse_x509().ok()?;
+
+    Some(
+        certificate
+            .validity()
+            .time_to_expiration()
+            .map_or(0, |duration| duration.whole_days()),
+    )
+}
+
+fn replace(path: &Path, content: &[u8]) -> Result<(), anyhow::Error> {
+    let temporary = path.with_extension("tmp");
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)
+        .and_then(|mut file| file.write_all(content))
+        .and_then(|_| fs::rename(&temporary, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Run the DNS hook script. The script is called with the action (`set`
+/// or `unset`), the name of the TXT record and its value, and must only
+/// exit once the record has been published.
+fn run(hook: &Path, args: &[&str]) -> Result<(), anyhow::Error> {
+    let output = Command::new(hook)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to execute {}", hook.display()))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} {} exited with status {}: {}",
+            hook.display(),
+            args[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::Duration;
+
+    /// Generate a self-signed certificate for localhost that expires in the
+    /// given number of days, along with its private key.
+    fn generate(days: i64) -> (String, String) {
+        let key = rcgen::KeyPair::generate().unwrap();
+
+        let mut params = rcgen::CertificateParams::new(["localhost".to_string()]).unwrap();
+        params.not_before = OffsetDateTime::now_utc() - Duration::days(1);
+        params.not_after = OffsetDateTime::now_utc() + Duration::days(days) + Duration::hours(1);
+
+        (params.self_signed(&key).unwrap().pem(), key.serialize_pem())
+    }
+
+    #[test]
+    fn renew_expiring_certificates() {
+        let directory = std::env::temp_dir().join(format!("pullconfd-acme-{}", std::process::id()));
+
+        fs::create_dir_all(directory.join("live")).unwrap();
+
+        let client = AcmeClient {
+            domain: "localhost".to_string(),
+            email: "admin@example.com".to_string(),
+            // Nothing listens on the discard port, so every order fails.
+            directory: "http://127.0.0.1:9/directory".to_string(),
+            challenge: Challenge::Http("127.0.0.1:0".parse().unwrap()),
+            persist: directory.clone(),
+            live: directory.join("live"),
+            certificate: directory.join("live/server.crt"),
+            key: directory.join("live/server.key"),
+        };
+
+        let (certificate, key) = generate(90);
+
+        replace(&client.certificate, certificate.as_bytes()).unwrap();
+        replace(&client.key, key.as_bytes()).unwrap();
+
+        // The certificate is valid long enough, so nothing is ordered.
+        let valid = client.renew();
+
+        let (certificate, _) = generate(10);
+
+        replace(&client.certificate, certificate.as_bytes()).unwrap();
+
+        let expiring = client.renew();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(valid.is_ok());
+        assert!(expiring.is_err());
+    }
+
+    #[test]
+    fn count_days_left() {
+        assert_eq!(days_left(generate(90).0.as_bytes()), Some(90));
+        assert_eq!(days_left(generate(-10).0.as_bytes()), Some(0));
+        assert_eq!(days_left(b"not a certificate"), None);
+    }
+}
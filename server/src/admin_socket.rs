@@ -0,0 +1,222 @@
+use crate::{env, handlers, http, SharedAppState};
+use common::error::Terminate;
+use log::{debug, error, info};
+use rand::{distributions::Alphanumeric, Rng};
+use rouille::{Request, Response};
+use std::{
+    fs::{self, Permissions},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+/// A Unix domain socket that exposes the `/admin` API to local tooling
+/// without TLS and admin tokens. Only the owner of the socket, i.e. the
+/// user that pullconfd runs as, and root may connect to it.
+pub struct AdminSocket {
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl AdminSocket {
+    /// Bind to the socket that is configured via the environment, if any.
+    pub fn from_env() -> Result<Option<Self>, Terminate> {
+        let scope = "admin-socket";
+
+        // The socket is optional, so there is no default path.
+        let path = env::parse_path(env::FileType::Socket, "PULLCONF_ADMIN_SOCKET", "")?;
+
+        if path.as_os_str().is_empty() {
+            return Ok(None);
+        }
+
+        // Remove the socket of a previous run, which is not removed when
+        // the process is killed.
+        if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            if let Err(error) = fs::remove_file(&path) {
+                error!(scope, path:% = path.display(); "failed to remove stale socket: {}", error);
+                return Err(Terminate);
+            }
+        }
+
+        let listener = UnixListener::bind(&path)
+            .and_then(|listener| {
+                fs::set_permissions(&path, Permissions::from_mode(0o600))?;
+                Ok(listener)
+            })
+            .map_err(|error| {
+                error!(scope, path:% = path.display(); "failed to bind socket: {}", error);
+                Terminate
+            })?;
+
+        Ok(Some(Self { path, listener }))
+    }
+
+    /// Accept connections and answer one request per connection.
+    /// Connections are handled one after another, as the socket is
+    /// meant for occasional use by local tooling.
+    pub fn serve(self, state: SharedAppState) {
+        let scope = "admin-socket";
+
+        info!(scope, path:% = self.path.display(); "admin socket is accepting connections");
+
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    error!(scope; "failed to accept connection: {}", error);
+                    thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
+            };
+
+            if let Err(error) = handle_connection(stream, state.clone()) {
+                debug!(scope; "failed to handle connection: {}", error);
+            }
+        }
+    }
+}
+
+/// Read a single HTTP/1.1 request, pass it to the admin API and write
+/// the response. The connection is closed afterwards.
+fn handle_connection(stream: UnixStream, state: SharedAppState) -> Result<(), io::Error> {
+    let scope = "admin-socket";
+
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let (max_header_size, max_body_size) = {
+        let state = state.read().unwrap();
+
+        (state.max_header_size, state.max_body_size)
+    };
+
+    let mut reader = BufReader::new(stream);
+
+    let incoming = match http::read_request(&mut reader, max_header_size, max_body_size)? {
+        Ok(incoming) => incoming,
+        Err(error) => {
+            return http::write_response(reader.get_mut(), error.into(), false, true).map(|_| ())
+        }
+    };
+
+    let head = incoming.method == "HEAD";
+
+    let request = Request::fake_http(
+        incoming.method,
+        incoming.url,
+        incoming.headers,
+        incoming.body,
+    );
+
+    let request_id = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(6)
+        .map(char::from)
+        .collect::<String>();
+
+    debug!(
+        scope,
+        request_id,
+        url = request.url();
+        "received {:?}",
+        request
+    );
+
+    let response = if request.url().starts_with("/admin/") {
+        match handlers::admin::handle_local_route(&request_id, &request, state) {
+            Ok(r) => r,
+            Err(e) => e.into(),
+        }
+    } else {
+        Response::empty_404()
+    };
+
+    http::write_response(reader.get_mut(), response, head, true).map(|_| ())
+}
+
+/// Trigger a reload of a running server via its admin socket and return
+/// whether the reload succeeded.
+pub fn reload(path: &Path) -> Result<bool, io::Error> {
+    let mut stream = UnixStream::connect(path)?;
+
+    stream.set_read_timeout(Some(Duration::from_secs(60)))?;
+
+    write!(
+        stream,
+        "POST /admin/reload HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n"
+    )?;
+
+    let mut line = String::new();
+
+    BufReader::new(stream).read_line(&mut line)?;
+
+    Ok(line.split_whitespace().nth(1) == Some("200"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use std::{
+        io::Read,
+        sync::{Arc, RwLock},
+    };
+
+    #[test]
+    fn answer_requests_on_the_admin_socket() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-admin-socket-{}", std::process::id()));
+
+        let state = Arc::new(RwLock::new(AppState::fixture(
+            &directory,
+            &[(
+                "clients/client.example.com.toml",
+                &format!("api-key = \"{}\"", "a".repeat(64)),
+            )],
+        )));
+
+        let request = |request: &str| {
+            let (mut client, server) = UnixStream::pair().unwrap();
+
+            client.write_all(request.as_bytes()).unwrap();
+
+            handle_connection(server, state.clone()).unwrap();
+
+            let mut response = String::new();
+
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let inventory = request("GET /admin/clients HTTP/1.1\r\n\r\n");
+        let client_api = request("GET /api/agent HTTP/1.1\r\n\r\n");
+        let malformed = request("GET /admin/clients\r\n\r\n");
+
+        // Trigger a reload like `pullconfd reload` does.
+        let path = directory.join("admin.sock");
+
+        let socket = AdminSocket {
+            path: path.clone(),
+            listener: UnixListener::bind(&path).unwrap(),
+        };
+
+        let _state = state.clone();
+
+        thread::spawn(move || socket.serve(_state));
+
+        let reloaded = reload(&path).unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(inventory.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(inventory.contains("client.example.com"));
+        assert!(client_api.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(malformed.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(reloaded);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::env;
+use common::{error::Terminate, Hostname};
+use log::error;
+use serde::Serialize;
+use std::{fs::OpenOptions, io::Write, path::PathBuf};
+use time::OffsetDateTime;
+
+/// The identity that an authenticated request was made with.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "lowercase", tag = "kind", content = "name")]
+pub enum Principal {
+    Client(Hostname),
+    Admin(String),
+}
+
+/// A single entry of the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub request_id: &'a str,
+    pub principal: &'a Principal,
+    pub method: &'a str,
+    pub route: &'a str,
+    pub status: u16,
+    /// The size of the response body, if known in advance.
+    pub bytes: Option<usize>,
+    pub etag: Option<&'a str>,
+}
+
+/// An append-only log of every authenticated request with one JSON
+/// object per line. The file is opened anew for every record, so that
+/// it can be rotated by moving it away without notifying pullconfd.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Read the path of the audit log from the environment. Returns `None`
+    /// if the audit log is disabled.
+    pub fn from_env() -> Result<Option<Self>, Terminate> {
+        Ok(env::parse_secret("PULLCONF_AUDIT_LOG")?.map(|path| Self {
+            path: PathBuf::from(path),
+        }))
+    }
+
+    pub fn record(&self, record: &AuditRecord) {
+        let scope = "audit";
+
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(error) => {
+                error!(scope, request_id = record.request_id; "failed to serialize audit record: {}", error);
+                return;
+            }
+        };
+
+        line.push(b'\n');
+
+        // The record is written with a single call, so that concurrent
+        // records are not interleaved.
+        if let Err(error) = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(&line))
+        {
+            error!(
+                scope,
+                request_id = record.request_id,
+                path:% = self.path.display();
+                "failed to write audit record: {}",
+                error
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn append_audit_records() {
+        let path = std::env::temp_dir().join(format!("pullconfd-audit-{}.log", std::process::id()));
+
+        let audit_log = AuditLog { path: path.clone() };
+
+        let client = Principal::Client("client.example.com".parse().unwrap());
+        let admin = Principal::Admin("operator".to_string());
+
+        let record = |request_id, principal, status| AuditRecord {
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+            request_id,
+            principal,
+            method: "GET",
+            route: "/api/clients/{hostname}/resources",
+            status,
+            bytes: Some(2),
+            etag: None,
+        };
+
+        audit_log.record(&record("a", &client, 200));
+
+        // A rotated log is created anew by the next record.
+        let rotated = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        audit_log.record(&record("b", &admin, 401));
+        audit_log.record(&record("c", &admin, 200));
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            rotated,
+            "{\"timestamp\":\"1970-01-01T00:00:00Z\",\"request_id\":\"a\",\"principal\":{\"kind\":\"client\",\"name\":\"client.example.com\"},\"method\":\"GET\",\"route\":\"/api/clients/{hostname}/resources\",\"status\":200,\"bytes\":2,\"etag\":null}\n"
+        );
+
+        let records = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["request_id"], "b");
+        assert_eq!(records[0]["principal"]["kind"], "admin");
+        assert_eq!(records[0]["status"], 401);
+        assert_eq!(records[1]["request_id"], "c");
+    }
+}
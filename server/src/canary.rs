@@ -0,0 +1,191 @@
+use crate::{configuration::Configuration, env, types::Client};
+use common::{error::Terminate, Hostname};
+use log::error;
+use serde::Serialize;
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+/// Staged rollout of configuration changes. While a canary is pending,
+/// the most recently loaded configuration is only served to members of
+/// the canary group, whereas every other client keeps receiving the
+/// stable configuration that was active before, until the canary is
+/// either promoted or discarded via the admin API.
+pub struct Canary {
+    pub group: Hostname,
+    pub pending: Option<Pending>,
+}
+
+/// The stable configuration that is kept while a canary is pending.
+pub struct Pending {
+    pub stable: Configuration,
+    /// The commit of the stable configuration if the resource directory
+    /// is managed by a git repository.
+    pub commit: Option<String>,
+    pub timestamp: OffsetDateTime,
+}
+
+/// The state of a staged rollout as returned by `GET /admin/canary`.
+#[derive(Serialize)]
+pub struct CanaryStatus<'a> {
+    pub group: &'a Hostname,
+    pub pending: bool,
+    #[serde(rename = "stable-commit", skip_serializing_if = "Option::is_none")]
+    pub stable_commit: Option<&'a str>,
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub since: Option<OffsetDateTime>,
+    pub members: Vec<&'a Hostname>,
+}
+
+impl Canary {
+    /// Read the canary group from the environment. Returns `None` if no
+    /// group is configured, in which case every configuration takes
+    /// effect for all clients immediately.
+    pub fn from_env() -> Result<Option<Self>, Terminate> {
+        // Empty values are rejected by parse_string, so an empty group
+        // means that the variable is not set.
+        let group = env::parse_string("PULLCONF_CANARY_GROUP", "")?;
+
+        if group.is_empty() {
+            return Ok(None);
+        }
+
+        let group = Hostname::from_str(&group).map_err(|error| {
+            error!(
+                scope = "environment",
+                variable = "PULLCONF_CANARY_GROUP";
+                "value is not a valid group name: {}",
+                error
+            );
+            Terminate
+        })?;
+
+        Ok(Some(Self {
+            group,
+            pending: None,
+        }))
+    }
+
+    pub fn is_member(&self, client: &Client) -> bool {
+        client.assigned_groups.contains(&self.group)
+    }
+
+    /// Return the stable variant of a client from the most recent
+    /// configuration. Members of the canary group, clients that do not
+    /// exist in the stable configuration and all clients while no canary
+    /// is pending receive the most recent configuration as is.
+    pub fn resolve<'a>(&'a self, client: &'a Client) -> &'a Client {
+        match &self.pending {
+            Some(pending) if !self.is_member(client) => {
+                pending.stable.clients.get(client.name()).unwrap_or(client)
+            }
+            _ => client,
+        }
+    }
+
+    pub fn status<'a>(&'a self, configuration: &'a Configuration) -> CanaryStatus<'a> {
+        let mut members = configuration
+            .clients
+            .values()
+            .filter(|client| self.is_member(client))
+            .map(|client| client.name())
+            .collect::<Vec<_>>();
+
+        members.sort();
+
+        CanaryStatus {
+            group: &self.group,
+            pending: self.pending.is_some(),
+            stable_commit: self
+                .pending
+                .as_ref()
+                .and_then(|pending| pending.commit.as_deref()),
+            since: self.pending.as_ref().map(|pending| pending.timestamp),
+            members,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use std::fs;
+
+    fn configuration(name: &str, motd: &str) -> Configuration {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-canary-{}-{}", name, std::process::id()));
+
+        let web = format!(
+            "api-key = \"{}\"\ngroups = [\"canary\"]\n\n[[resources]]\ntype = \"file\"\npath = \"/etc/motd\"\ncontent = \"{}\"\n",
+            "a".repeat(64),
+            motd
+        );
+        let db = format!(
+            "api-key = \"{}\"\n\n[[resources]]\ntype = \"file\"\npath = \"/etc/motd\"\ncontent = \"{}\"\n",
+            "b".repeat(64),
+            motd
+        );
+
+        let state = AppState::fixture(
+            &directory,
+            &[
+                ("groups/canary.toml", ""),
+                ("clients/web.example.com.toml", &web),
+                ("clients/db.example.com.toml", &db),
+            ],
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        state.configuration
+    }
+
+    #[test]
+    fn serve_stable_configuration_to_non_members() {
+        let stable = configuration("stable", "stable");
+        let latest = configuration("latest", "latest");
+
+        let web = "web.example.com".parse::<Hostname>().unwrap();
+        let db = "db.example.com".parse::<Hostname>().unwrap();
+
+        let mut canary = Canary {
+            group: "canary".parse().unwrap(),
+            pending: None,
+        };
+
+        // Without a pending canary every client receives the latest
+        // configuration.
+        assert!(std::ptr::eq(
+            canary.resolve(&latest.clients[&db]),
+            &latest.clients[&db]
+        ));
+        assert!(!canary.status(&latest).pending);
+
+        canary.pending = Some(Pending {
+            stable,
+            commit: Some("abc123".to_string()),
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+        });
+
+        let stable = &canary.pending.as_ref().unwrap().stable;
+
+        assert!(std::ptr::eq(
+            canary.resolve(&latest.clients[&web]),
+            &latest.clients[&web]
+        ));
+        assert!(std::ptr::eq(
+            canary.resolve(&latest.clients[&db]),
+            &stable.clients[&db]
+        ));
+
+        let status = canary.status(&latest);
+
+        assert!(status.pending);
+        assert_eq!(status.stable_commit, Some("abc123"));
+        assert_eq!(status.since, Some(OffsetDateTime::UNIX_EPOCH));
+        assert_eq!(status.members, vec![&web]);
+    }
+}
@@ -0,0 +1,213 @@
+use common::{error::Terminate, Hostname};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+    time::Duration,
+};
+use time::OffsetDateTime;
+
+/// Data that is recorded whenever a client successfully fetches its
+/// resource catalog.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CheckIn {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub version: Option<String>,
+    pub etag: String,
+}
+
+/// The most recent check-in of every client. Check-ins are persisted
+/// to disk so that they survive restarts of the server.
+#[derive(Debug)]
+pub struct CheckIns {
+    path: PathBuf,
+    entries: HashMap<Hostname, CheckIn>,
+    /// Clients that have already been reported as stale. This prevents
+    /// repeated warnings for the same client until it checks in again.
+    stale: HashSet<Hostname>,
+    /// The point in time from which clients without any check-in are
+    /// considered stale once the threshold has passed.
+    since: OffsetDateTime,
+}
+
+impl CheckIns {
+    /// Read previously persisted check-ins from a file. A missing file
+    /// results in an empty collection.
+    pub fn load(path: PathBuf) -> Result<Self, Terminate> {
+        let scope = "check-ins";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize check-ins: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "check-in file does not exist"
+                );
+
+                HashMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read check-ins: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self {
+            path,
+            entries,
+            stale: HashSet::new(),
+            since: OffsetDateTime::now_utc(),
+        })
+    }
+
+    pub fn get(&self, name: &Hostname) -> Option<&CheckIn> {
+        self.entries.get(name)
+    }
+
+    pub fn is_stale(&self, name: &Hostname) -> bool {
+        self.stale.contains(name)
+    }
+
+    /// Record a check-in and persist all check-ins to disk. Failing to
+    /// persist check-ins is logged, but does not affect the client.
+    pub fn record(&mut self, name: &Hostname, check_in: CheckIn) {
+        let scope = "check-ins";
+
+        if self.stale.remove(name) {
+            info!(scope, client:% = name; "stale client checked in again");
+        }
+
+        self.entries.insert(name.clone(), check_in);
+
+        if let Err(error) = self.save() {
+            error!(
+                scope,
+                source:% = self.path.display();
+                "failed to persist check-ins: {}",
+                error
+            );
+        }
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Emit a warning for every client that has not checked in within
+    /// the given threshold. Clients that never checked in are measured
+    /// against the time the check-ins were loaded. Returns the clients
+    /// that became stale since the last call.
+    pub fn detect_stale<'a>(
+        &mut self,
+        clients: impl Iterator<Item = &'a Hostname>,
+        threshold: Duration,
+    ) -> Vec<Hostname> {
+        let scope = "check-ins";
+
+        let now = OffsetDateTime::now_utc();
+
+        let mut stale = vec![];
+
+        for name in clients {
+            let last = self
+                .entries
+                .get(name)
+                .map(|check_in| check_in.timestamp)
+                .unwrap_or(self.since);
+
+            if now - last > threshold && self.stale.insert(name.clone()) {
+                warn!(
+                    scope,
+                    client:% = name;
+                    "client has not checked in for more than {} seconds",
+                    threshold.as_secs()
+                );
+
+                stale.push(name.clone());
+            }
+        }
+
+        stale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_stale_clients() {
+        let path =
+            std::env::temp_dir().join(format!("pullconfd-check-ins-{}.json", std::process::id()));
+
+        let active = "active.example.com".parse::<Hostname>().unwrap();
+        let stale = "stale.example.com".parse::<Hostname>().unwrap();
+        let unknown = "unknown.example.com".parse::<Hostname>().unwrap();
+
+        let mut check_ins = CheckIns::load(path.clone()).unwrap();
+
+        let check_in = |timestamp| CheckIn {
+            timestamp,
+            version: None,
+            etag: "etag".to_string(),
+        };
+
+        let now = OffsetDateTime::now_utc();
+
+        check_ins.record(&active, check_in(now));
+        check_ins.record(&stale, check_in(now - Duration::from_secs(7200)));
+
+        let clients = [active.clone(), stale.clone(), unknown.clone()];
+        let threshold = Duration::from_secs(3600);
+
+        // Clients that never checked in are measured against the time the
+        // check-ins were loaded, and a stale client is only reported once.
+        assert_eq!(
+            check_ins.detect_stale(clients.iter(), threshold),
+            vec![stale.clone()]
+        );
+        assert!(check_ins.detect_stale(clients.iter(), threshold).is_empty());
+        assert!(check_ins.is_stale(&stale));
+
+        check_ins.record(&stale, check_in(now));
+
+        assert!(!check_ins.is_stale(&stale));
+
+        // Check-ins survive a restart.
+        let check_ins = CheckIns::load(path.clone()).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(check_ins.get(&stale).unwrap().timestamp, now);
+        assert!(check_ins.get(&unknown).is_none());
+    }
+}
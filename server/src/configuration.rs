@@ -1,12 +1,91 @@
-use crate::types::{client, ApiKey, Client, Group};
-use common::{error::Terminate, Hostname};
-use log::{debug, error, warn};
-use std::{collections::HashMap, fs, path::PathBuf, str::FromStr, time::Instant};
+use crate::{
+    diagnostics::validation_error,
+    env,
+    types::{client, resources::Resource, ApiKey, Client, Group},
+};
+use common::{error::Terminate, Hostname, SafePathBuf};
+use log::{debug, info, warn};
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
 #[derive(Default)]
 pub struct Configuration {
     pub clients: HashMap<Hostname, Client>,
+    pub groups: HashMap<Hostname, Group>,
     pub api_keys: HashMap<ApiKey, Hostname>,
+    /// Warnings about configuration that is valid, but has no effect,
+    /// e.g. variables that are never referenced.
+    pub lints: Vec<String>,
+    /// The time it took to load the configuration and compile the
+    /// resource catalogs of all clients.
+    pub compile_time: Duration,
+}
+
+/// The directories that the paths of `file`, `directory` and `symlink`
+/// resources are confined to. Resources of a type without roots may
+/// manage any path.
+struct Roots {
+    file: Vec<SafePathBuf>,
+    directory: Vec<SafePathBuf>,
+    symlink: Vec<SafePathBuf>,
+}
+
+impl Roots {
+    fn from_env() -> Result<Self, Terminate> {
+        Ok(Self {
+            file: env::parse_roots("PULLCONF_FILE_ROOTS")?,
+            directory: env::parse_roots("PULLCONF_DIRECTORY_ROOTS")?,
+            symlink: env::parse_roots("PULLCONF_SYMLINK_ROOTS")?,
+        })
+    }
+
+    /// Make sure that every resource of the client manages a path within
+    /// the roots of its type.
+    fn check(&self, client: &Client) -> Result<(), Terminate> {
+        let scope = "validation";
+
+        for resource in &client.resources {
+            let (roots, path, variable) = match resource {
+                Resource::File(file) => (&self.file, &file.parameters.path, "PULLCONF_FILE_ROOTS"),
+                Resource::Directory(directory) => (
+                    &self.directory,
+                    &directory.parameters.path,
+                    "PULLCONF_DIRECTORY_ROOTS",
+                ),
+                Resource::Symlink(symlink) => (
+                    &self.symlink,
+                    &symlink.parameters.path,
+                    "PULLCONF_SYMLINK_ROOTS",
+                ),
+                _ => continue,
+            };
+
+            if !roots.is_empty() && !roots.iter().any(|root| path.is_within(root)) {
+                validation_error!(
+                    scope,
+                    client:% = client.name,
+                    resource:% = resource.kind();
+                    "{} is outside of the directories allowed by {}: {}",
+                    resource.repr(),
+                    variable,
+                    roots
+                        .iter()
+                        .map(|root| root.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                return Err(Terminate);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl TryFrom<&PathBuf> for Configuration {
@@ -19,12 +98,14 @@ impl TryFrom<&PathBuf> for Configuration {
 
         let start = Instant::now();
 
+        let roots = Roots::from_env()?;
+
         let client_directory = {
             let mut path = resources.to_owned();
             path.push("clients");
 
             if !path.is_dir() {
-                error!(
+                validation_error!(
                     scope,
                     source:% = path.display();
                     "directory containing client configuration files does not exist"
@@ -41,7 +122,7 @@ impl TryFrom<&PathBuf> for Configuration {
             path.push("groups");
 
             if !path.is_dir() {
-                error!(
+                validation_error!(
                     scope,
                     source:% = path.display();
                     "directory containing group configuration files does not exist"
@@ -58,7 +139,7 @@ impl TryFrom<&PathBuf> for Configuration {
         let entries = match fs::read_dir(&group_directory) {
             Ok(e) => e,
             Err(error) => {
-                error!(
+                validation_error!(
                     scope,
                     source:% = group_directory.display();
                     "{}",
@@ -73,7 +154,7 @@ impl TryFrom<&PathBuf> for Configuration {
             let path = match entry {
                 Ok(entry) => entry.path(),
                 Err(error) => {
-                    error!(
+                    validation_error!(
                         scope,
                         source:% = group_directory.display();
                         "{}",
@@ -89,10 +170,12 @@ impl TryFrom<&PathBuf> for Configuration {
                     .extension()
                     .is_some_and(|extension| extension == "toml")
                 {
-                    let (name, group) = parse_file::<Group>(&path)?;
+                    let (name, mut group) = parse_file::<Group>(&path)?;
+
+                    group.source = path.clone();
 
                     if groups.insert(name.clone(), (group, 0)).is_some() {
-                        error!(
+                        validation_error!(
                             scope,
                             source:% = path.display();
                             "group {} appears multiple times, but group names must be unique",
@@ -120,7 +203,7 @@ impl TryFrom<&PathBuf> for Configuration {
         let entries = match fs::read_dir(&client_directory) {
             Ok(e) => e,
             Err(error) => {
-                error!(
+                validation_error!(
                     scope,
                     source:% = client_directory.display();
                     "{}",
@@ -138,7 +221,7 @@ impl TryFrom<&PathBuf> for Configuration {
             let path = match entry {
                 Ok(entry) => entry.path(),
                 Err(error) => {
-                    error!(
+                    validation_error!(
                         scope,
                         source:% = client_directory.display();
                         "{}",
@@ -154,10 +237,13 @@ impl TryFrom<&PathBuf> for Configuration {
                     .extension()
                     .is_some_and(|extension| extension == "toml")
                 {
-                    let (name, intermediate) = parse_file::<client::deserialize::Client>(&path)?;
+                    let (name, mut intermediate) =
+                        parse_file::<client::deserialize::Client>(&path)?;
+
+                    intermediate.source = path.clone();
 
                     if clients.contains_key(&name) {
-                        error!(
+                        validation_error!(
                             scope,
                             source:% = path.display();
                             "client `{}` appears multiple times, but client names must be unique",
@@ -169,10 +255,21 @@ impl TryFrom<&PathBuf> for Configuration {
 
                     let client = Client::try_from((name, intermediate, &mut groups))?;
 
+                    roots.check(&client)?;
+
+                    if client.api_key.is_legacy() {
+                        warn!(
+                            scope,
+                            source:% = path.display();
+                            "client `{}` uses a legacy unsalted sha256 API key hash, consider replacing it with an HMAC-SHA256 hash keyed with the server pepper",
+                            client.name()
+                        );
+                    }
+
                     if let Some(other) =
                         api_keys.insert(client.api_key.clone(), client.name.clone())
                     {
-                        error!(
+                        validation_error!(
                             scope,
                             source:% = path.display();
                             "API key hash from client `{}` matches that from client `{}`, but API keys must be unique",
@@ -200,20 +297,46 @@ impl TryFrom<&PathBuf> for Configuration {
             }
         }
 
+        let mut lints = clients
+            .values_mut()
+            .flat_map(|client| client.lints.drain(..))
+            .collect::<Vec<_>>();
+
         for (name, (_, count)) in &groups {
             if *count == 0 {
-                warn!("group `{}` is never referenced by any client", name);
+                warn!(scope; "group `{}` is never referenced by any client", name);
+                lints.push(format!(
+                    "group `{}` is never referenced by any client",
+                    name
+                ));
             }
         }
 
-        debug!(
-            scope;
-            "took {} ms to parse configuration from `{}`",
-            start.elapsed().as_millis(),
+        let compile_time = start.elapsed();
+
+        info!(
+            scope,
+            clients = clients.len(),
+            groups = groups.len(),
+            resources = clients.values().map(|client| client.resources.len()).sum::<usize>(),
+            inherited = clients.values().map(|client| client.inherited).sum::<usize>(),
+            compile_time_ms = compile_time.as_millis();
+            "compiled resource catalogs from `{}`",
             resources.display()
         );
 
-        Ok(Self { clients, api_keys })
+        let groups = groups
+            .into_iter()
+            .map(|(name, (group, _))| (name, group))
+            .collect();
+
+        Ok(Self {
+            clients,
+            groups,
+            api_keys,
+            lints,
+            compile_time,
+        })
     }
 }
 
@@ -224,7 +347,7 @@ fn parse_file<T: serde::de::DeserializeOwned>(path: &PathBuf) -> Result<(Hostnam
         Some(name) => match Hostname::from_str(name) {
             Ok(name) => name,
             Err(error) => {
-                error!(
+                validation_error!(
                     scope,
                     source:% = path.display();
                     "invalid file name: {}",
@@ -235,7 +358,7 @@ fn parse_file<T: serde::de::DeserializeOwned>(path: &PathBuf) -> Result<(Hostnam
             }
         },
         None => {
-            error!(
+            validation_error!(
                 scope,
                 source:% = path.display();
                 "file name must be valid Unicode",
@@ -248,7 +371,7 @@ fn parse_file<T: serde::de::DeserializeOwned>(path: &PathBuf) -> Result<(Hostnam
     let contents = match fs::read_to_string(path) {
         Ok(c) => c,
         Err(error) => {
-            error!(
+            validation_error!(
                 scope,
                 source:% = path.display();
                 "{}",
@@ -262,7 +385,7 @@ fn parse_file<T: serde::de::DeserializeOwned>(path: &PathBuf) -> Result<(Hostnam
     match toml::from_str::<T>(&contents) {
         Ok(value) => Ok((name, value)),
         Err(error) => {
-            error!(
+            validation_error!(
                 scope,
                 source:% = path.display();
                 "{}",
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::{cell::RefCell, collections::BTreeMap};
+
+/// A single error that was encountered while loading the configuration,
+/// e.g. a syntax error in a configuration file or a resource that fails
+/// validation.
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    /// Key-value pairs that were logged alongside the error message, e.g.
+    /// the source file, client or resource type.
+    pub context: BTreeMap<&'static str, String>,
+    pub message: String,
+}
+
+thread_local! {
+    static COLLECTOR: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/// Run a function and collect every validation error that is emitted via
+/// the `validation_error!` macro on the current thread while it runs.
+pub fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let previous = COLLECTOR.with(|collector| collector.borrow_mut().replace(vec![]));
+
+    let value = f();
+
+    let diagnostics = COLLECTOR.with(|collector| {
+        let mut collector = collector.borrow_mut();
+        let diagnostics = collector.take().unwrap_or_default();
+        *collector = previous;
+        diagnostics
+    });
+
+    (value, diagnostics)
+}
+
+/// Store a validation error if errors are currently being collected on
+/// this thread. This is called by the `validation_error!` macro.
+pub fn record(context: BTreeMap<&'static str, String>, message: String) {
+    COLLECTOR.with(|collector| {
+        if let Some(diagnostics) = collector.borrow_mut().as_mut() {
+            diagnostics.push(Diagnostic { context, message });
+        }
+    })
+}
+
+/// Log an error exactly like `log::error!` and additionally record it
+/// as a `Diagnostic`, so that callers such as the reload endpoint can
+/// report errors without scraping logs. Key-value pairs are only recorded
+/// when they are given an explicit value.
+macro_rules! validation_error {
+    ($($key:tt $(:$capture:tt)? $(= $value:expr)?),+; $($arg:tt)+) => {{
+        log::error!($($key $(:$capture)? $(= $value)?),+; $($arg)+);
+
+        #[allow(unused_mut)]
+        let mut context = std::collections::BTreeMap::new();
+        $($(context.insert(stringify!($key), $value.to_string());)?)+
+        $crate::diagnostics::record(context, format!($($arg)+));
+    }};
+}
+
+pub(crate) use validation_error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_validation_errors() {
+        let scope = "validation";
+        let client = "foo.example.com";
+
+        validation_error!(scope; "not collected");
+
+        let ((), diagnostics) = collect(|| {
+            validation_error!(scope, client:% = client; "unknown group `{}`", "bar");
+        });
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "unknown group `bar`");
+        assert_eq!(
+            diagnostics[0].context.get("client").map(String::as_str),
+            Some("foo.example.com")
+        );
+        assert!(!diagnostics[0].context.contains_key("scope"));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{configuration::Configuration, types::resources::Resource};
+use anyhow::Context;
+use common::Hostname;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+/// How the resource catalog of a single client differs between two
+/// configurations. Resources are identified by their representation,
+/// e.g. ``file `/etc/hosts` ``, and listed in sorted order.
+#[derive(Debug, Default, PartialEq)]
+pub struct ClientDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ClientDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Whether a client exists in both configurations or only in one.
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Compare the resource catalogs of every client in two configurations.
+/// Clients whose catalogs are identical are omitted.
+pub fn compare(
+    old: &Configuration,
+    new: &Configuration,
+) -> BTreeMap<Hostname, (Status, ClientDiff)> {
+    let mut diffs = BTreeMap::new();
+
+    for (name, client) in &new.clients {
+        let (status, diff) = match old.clients.get(name) {
+            Some(previous) => (
+                Status::Modified,
+                compare_resources(previous.resources.iter(), client.resources.iter()),
+            ),
+            None => (
+                Status::Added,
+                compare_resources([].iter(), client.resources.iter()),
+            ),
+        };
+
+        if status == Status::Added || !diff.is_empty() {
+            diffs.insert(name.clone(), (status, diff));
+        }
+    }
+
+    for (name, client) in &old.clients {
+        if !new.clients.contains_key(name) {
+            diffs.insert(
+                name.clone(),
+                (
+                    Status::Removed,
+                    compare_resources(client.resources.iter(), [].iter()),
+                ),
+            );
+        }
+    }
+
+    diffs
+}
+
+fn compare_resources<'a>(
+    old: impl Iterator<Item = &'a Resource>,
+    new: impl Iterator<Item = &'a Resource>,
+) -> ClientDiff {
+    let old = old
+        .map(|resource| (resource.repr(), resource))
+        .collect::<HashMap<_, _>>();
+
+    let new = new
+        .map(|resource| (resource.repr(), resource))
+        .collect::<HashMap<_, _>>();
+
+    let mut diff = ClientDiff::default();
+
+    // Resources only compare equal by their identity, so compare all of
+    // their parameters via their serialized form instead.
+    for (repr, resource) in &new {
+        match old.get(repr) {
+            Some(previous)
+                if serde_json::to_value(previous).ok() != serde_json::to_value(resource).ok() =>
+            {
+                diff.changed.push(repr.clone())
+            }
+            Some(_) => (),
+            None => diff.added.push(repr.clone()),
+        }
+    }
+
+    diff.removed = old
+        .keys()
+        .filter(|repr| !new.contains_key(*repr))
+        .cloned()
+        .collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+
+    diff
+}
+
+/// Print the differences in a format that is suitable for review, e.g. in
+/// comments on merge requests.
+pub fn print(diffs: &BTreeMap<Hostname, (Status, ClientDiff)>) {
+    if diffs.is_empty() {
+        println!("no client is affected");
+        return;
+    }
+
+    for (name, (status, diff)) in diffs {
+        match status {
+            Status::Added => println!("{} (added)", name),
+            Status::Removed => println!("{} (removed)", name),
+            Status::Modified => println!("{}", name),
+        }
+
+        for repr in &diff.added {
+            println!("  + {}", repr);
+        }
+
+        for repr in &diff.removed {
+            println!("  - {}", repr);
+        }
+
+        for repr in &diff.changed {
+            println!("  ~ {}", repr);
+        }
+    }
+}
+
+/// A checkout of a git revision in a temporary directory, which is
+/// removed when the checkout is dropped.
+pub struct GitCheckout {
+    repository: PathBuf,
+    worktree: PathBuf,
+    /// The directory within the checkout that corresponds to the resource
+    /// directory the checkout was created from.
+    pub resources: PathBuf,
+}
+
+impl GitCheckout {
+    /// Check out a revision of the git repository that contains the given
+    /// resource directory.
+    pub fn new(resources: &Path, revision: &str) -> Result<Self, anyhow::Error> {
+        let prefix = git(resources, &["rev-parse", "--show-prefix"])?;
+        let repository = PathBuf::from(git(resources, &["rev-parse", "--show-toplevel"])?.trim());
+
+        let worktree = env::temp_dir().join(format!("pullconfd-diff-{}", process::id()));
+
+        let target = worktree.to_string_lossy();
+
+        git(
+            &repository,
+            &[
+                "worktree",
+                "add",
+                "--quiet",
+                "--detach",
+                target.as_ref(),
+                revision,
+            ],
+        )?;
+
+        Ok(Self {
+            resources: worktree.join(prefix.trim()),
+            repository,
+            worktree,
+        })
+    }
+}
+
+impl Drop for GitCheckout {
+    fn drop(&mut self) {
+        let target = self.worktree.to_string_lossy();
+
+        if git(
+            &self.repository,
+            &["worktree", "remove", "--force", target.as_ref()],
+        )
+        .is_err()
+        {
+            let _ = fs::remove_dir_all(&self.worktree);
+        }
+    }
+}
+
+fn git(directory: &Path, args: &[&str]) -> Result<String, anyhow::Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .with_context(|| format!("failed to execute git {}", args[0]))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} exited with status {}: {}",
+            args[0],
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::resources::deserialize::Resource as DeResource;
+    use std::str::FromStr;
+
+    #[test]
+    fn compare_resource_catalogs() -> Result<(), anyhow::Error> {
+        let client = Hostname::from_str("foo.example.com").unwrap();
+        let variables = HashMap::new();
+
+        let resource = |toml: &str| -> Result<Resource, anyhow::Error> {
+            let resource: DeResource = toml::from_str(toml)?;
+            Ok(Resource::try_from((&resource, &client, &variables)).unwrap())
+        };
+
+        let old = [
+            resource("type = \"file\"\npath = \"/etc/foo\"")?,
+            resource("type = \"file\"\npath = \"/etc/bar\"\ncontent = \"bar\"")?,
+            resource("type = \"directory\"\npath = \"/etc/baz\"")?,
+        ];
+
+        let new = [
+            resource("type = \"file\"\npath = \"/etc/foo\"")?,
+            resource("type = \"file\"\npath = \"/etc/bar\"\ncontent = \"qux\"")?,
+            resource("type = \"directory\"\npath = \"/etc/qux\"")?,
+        ];
+
+        let diff = compare_resources(old.iter(), new.iter());
+
+        assert_eq!(
+            diff,
+            ClientDiff {
+                added: vec!["directory `/etc/qux`".to_string()],
+                removed: vec!["directory `/etc/baz`".to_string()],
+                changed: vec!["file `/etc/bar`".to_string()],
+            }
+        );
+
+        Ok(())
+    }
+}
@@ -1,10 +1,19 @@
-use common::error::Terminate;
+use common::{error::Terminate, SafePathBuf};
 use log::{debug, error};
-use std::{env, net::SocketAddr, path::PathBuf, str::FromStr};
+use std::{
+    env,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 pub enum FileType {
     Directory,
     File,
+    /// A Unix domain socket, which does not need to exist yet, as long as
+    /// the directory it is created in does.
+    Socket,
 }
 
 pub fn parse_path(kind: FileType, variable: &str, default: &str) -> Result<PathBuf, Terminate> {
@@ -41,9 +50,33 @@ pub fn parse_path(kind: FileType, variable: &str, default: &str) -> Result<PathB
                         return Err(Terminate);
                     }
                 }
+                FileType::Socket => {
+                    if !path.is_absolute()
+                        || path.file_name().is_none()
+                        || !path.parent().is_some_and(Path::is_dir)
+                    {
+                        error!(
+                            scope,
+                            variable;
+                            "value must be an absolute path within an existing directory"
+                        );
+                        return Err(Terminate);
+                    }
+                }
             }
 
-            let path = match path.canonicalize() {
+            // The socket itself may not exist yet, so only its directory
+            // is resolved.
+            let path = match kind {
+                FileType::Socket => path
+                    .parent()
+                    .unwrap()
+                    .canonicalize()
+                    .map(|directory| directory.join(path.file_name().unwrap())),
+                FileType::Directory | FileType::File => path.canonicalize(),
+            };
+
+            let path = match path {
                 Ok(p) => p,
                 Err(error) => {
                     error!(scope, variable; "{}", error);
@@ -85,3 +118,254 @@ pub fn parse_socket(variable: &str, default: &str) -> Result<SocketAddr, Termina
         }
     }
 }
+
+/// Parse a comma-separated list of socket addresses, e.g.
+/// `0.0.0.0:443,[::]:443`.
+pub fn parse_sockets(variable: &str, default: &str) -> Result<Vec<SocketAddr>, Terminate> {
+    let scope = "environment";
+
+    let value = env::var(variable).ok();
+
+    let sockets = value
+        .as_deref()
+        .unwrap_or(default)
+        .split(',')
+        .map(|socket| SocketAddr::from_str(socket.trim()))
+        .collect::<Result<Vec<_>, _>>();
+
+    match sockets {
+        Ok(sockets) => {
+            if value.is_some() {
+                debug!(scope, variable; "variable evaluates to {:?}", sockets);
+            } else {
+                debug!(scope, variable; "variable not found, using default {}", default);
+            }
+
+            Ok(sockets)
+        }
+        Err(error) => {
+            error!(scope, variable; "{}", error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Parse a comma-separated list of names, e.g. `a,b`. Returns an empty
+/// list if the variable is not set.
+pub fn parse_list(variable: &str) -> Result<Vec<String>, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            let items = v
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .collect::<Vec<_>>();
+
+            if items.iter().any(String::is_empty) {
+                error!(scope, variable; "value must be a comma-separated list without empty items");
+                return Err(Terminate);
+            }
+
+            debug!(scope, variable; "variable evaluates to {:?}", items);
+
+            Ok(items)
+        }
+        None => {
+            debug!(scope, variable; "variable not found");
+            Ok(vec![])
+        }
+    }
+}
+
+/// Parse a comma-separated list of absolute paths, e.g. `/etc,/srv`, that
+/// resources are confined to. An empty list does not confine resources.
+pub fn parse_roots(variable: &str) -> Result<Vec<SafePathBuf>, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            let roots = v
+                .split(',')
+                .map(|root| SafePathBuf::from_str(root.trim()))
+                .collect::<Result<Vec<_>, _>>();
+
+            match roots {
+                Ok(roots) => {
+                    debug!(scope, variable; "variable evaluates to {:?}", roots);
+                    Ok(roots)
+                }
+                Err(error) => {
+                    error!(scope, variable; "{}", error);
+                    Err(Terminate)
+                }
+            }
+        }
+        None => {
+            debug!(scope, variable; "variable not found");
+            Ok(vec![])
+        }
+    }
+}
+
+pub fn parse_secret(variable: &str) -> Result<Option<String>, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            if v.is_empty() {
+                error!(scope, variable; "value must not be an empty string");
+                return Err(Terminate);
+            }
+
+            debug!(scope, variable; "variable is set");
+
+            Ok(Some(v))
+        }
+        None => {
+            debug!(scope, variable; "variable not found");
+            Ok(None)
+        }
+    }
+}
+
+pub fn parse_string(variable: &str, default: &str) -> Result<String, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            if v.is_empty() {
+                error!(scope, variable; "value must not be an empty string");
+                return Err(Terminate);
+            }
+
+            debug!(scope, variable; "variable evaluates to {}", v);
+
+            Ok(v)
+        }
+        None => {
+            debug!(scope, variable; "variable not found, using default {}", default);
+            Ok(default.to_string())
+        }
+    }
+}
+
+pub fn parse_bool(variable: &str, default: bool) -> Result<bool, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            let value = match bool::from_str(&v) {
+                Ok(value) => value,
+                Err(_) => {
+                    error!(scope, variable; "value must be either true or false");
+                    return Err(Terminate);
+                }
+            };
+
+            debug!(scope, variable; "variable evaluates to {}", value);
+
+            Ok(value)
+        }
+        None => {
+            debug!(scope, variable; "variable not found, using default {}", default);
+            Ok(default)
+        }
+    }
+}
+
+pub fn parse_count(variable: &str, default: usize) -> Result<usize, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            let count = match usize::from_str(&v) {
+                Ok(0) => {
+                    error!(scope, variable; "value must be greater than zero");
+                    return Err(Terminate);
+                }
+                Ok(c) => c,
+                Err(error) => {
+                    error!(scope, variable; "value must be a positive number: {}", error);
+                    return Err(Terminate);
+                }
+            };
+
+            debug!(scope, variable; "variable evaluates to {}", count);
+
+            Ok(count)
+        }
+        None => {
+            debug!(scope, variable; "variable not found, using default {}", default);
+            Ok(default)
+        }
+    }
+}
+
+pub fn parse_duration(variable: &str, default: u64) -> Result<Duration, Terminate> {
+    match parse_optional_duration(variable)? {
+        Some(duration) => Ok(duration),
+        None => {
+            debug!(scope = "environment", variable; "using default {} seconds", default);
+            Ok(Duration::from_secs(default))
+        }
+    }
+}
+
+/// Parse a number of seconds like `parse_duration`, but return `None`
+/// if the variable is not set.
+pub fn parse_optional_duration(variable: &str) -> Result<Option<Duration>, Terminate> {
+    let scope = "environment";
+
+    match env::var(variable).ok() {
+        Some(v) => {
+            let seconds = match u64::from_str(&v) {
+                Ok(s) => s,
+                Err(error) => {
+                    error!(scope, variable; "value must be a number of seconds: {}", error);
+                    return Err(Terminate);
+                }
+            };
+
+            debug!(scope, variable; "variable evaluates to {} seconds", seconds);
+
+            Ok(Some(Duration::from_secs(seconds)))
+        }
+        None => {
+            debug!(scope, variable; "variable not found");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_socket_lists() {
+        let variable = "PULLCONF_TEST_LISTEN";
+
+        env::set_var(variable, "0.0.0.0:443, [::]:443");
+        let sockets = parse_sockets(variable, "");
+
+        env::set_var(variable, "0.0.0.0");
+        let invalid = parse_sockets(variable, "");
+
+        env::remove_var(variable);
+        let default = parse_sockets(variable, "127.0.0.1:8443");
+
+        assert_eq!(
+            sockets.unwrap(),
+            vec![
+                SocketAddr::from_str("0.0.0.0:443").unwrap(),
+                SocketAddr::from_str("[::]:443").unwrap(),
+            ]
+        );
+        assert!(invalid.is_err());
+        assert_eq!(
+            default.unwrap(),
+            vec![SocketAddr::from_str("127.0.0.1:8443").unwrap()]
+        );
+    }
+}
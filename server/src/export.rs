@@ -0,0 +1,163 @@
+use crate::{configuration::Configuration, types::Client};
+use common::Hostname;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The value that replaces variables that likely contain secrets.
+const REDACTED: &str = "[redacted]";
+
+/// Variables whose names contain any of these substrings (ignoring
+/// case) are considered to contain secrets and are redacted.
+const SENSITIVE: &[&str] = &["password", "passwd", "secret", "token", "key", "credential"];
+
+/// The inventory of a single client as it is exported for consumption
+/// by external tools, e.g. a CMDB.
+#[derive(Debug, Serialize)]
+pub struct ClientExport<'a> {
+    pub name: &'a Hostname,
+    pub source: &'a Path,
+    pub groups: &'a [Hostname],
+    pub variables: BTreeMap<&'a str, serde_json::Value>,
+    /// Number of resources per resource type.
+    pub resources: BTreeMap<String, usize>,
+    pub decommissioned: bool,
+}
+
+impl<'a> From<&'a Client> for ClientExport<'a> {
+    fn from(client: &'a Client) -> Self {
+        let variables = client
+            .variables
+            .iter()
+            .map(|(name, value)| {
+                let value = if is_sensitive(name) {
+                    serde_json::Value::from(REDACTED)
+                } else {
+                    serde_json::to_value(value).unwrap_or_default()
+                };
+
+                (name.as_str(), value)
+            })
+            .collect();
+
+        Self {
+            name: &client.name,
+            source: &client.source,
+            groups: &client.assigned_groups,
+            variables,
+            resources: client.resource_counts(),
+            decommissioned: client.decommissioned,
+        }
+    }
+}
+
+/// Collect the inventory of every client, sorted by hostname.
+pub fn inventory(configuration: &Configuration) -> Vec<ClientExport<'_>> {
+    let mut clients = configuration
+        .clients
+        .values()
+        .map(ClientExport::from)
+        .collect::<Vec<_>>();
+
+    clients.sort_by_key(|client| client.name);
+
+    clients
+}
+
+pub fn to_json(clients: &[ClientExport]) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(clients)
+}
+
+/// Format the inventory as CSV with one row per client. Fields that
+/// hold several values are joined by semicolons, e.g. `file=2;user=1`.
+pub fn to_csv(clients: &[ClientExport]) -> String {
+    let mut csv = String::from("name,source,groups,variables,resources,decommissioned\n");
+
+    for client in clients {
+        let groups = client
+            .groups
+            .iter()
+            .map(|group| group.to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let variables = client
+            .variables
+            .iter()
+            .map(|(name, value)| match value.as_str() {
+                Some(value) => format!("{}={}", name, value),
+                None => format!("{}={}", name, value),
+            })
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let resources = client
+            .resources
+            .iter()
+            .map(|(kind, count)| format!("{}={}", kind, count))
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let row = [
+            client.name.to_string(),
+            client.source.display().to_string(),
+            groups,
+            variables,
+            resources,
+            client.decommissioned.to_string(),
+        ];
+
+        let row = row.iter().map(|field| escape(field)).collect::<Vec<_>>();
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+/// Write the exported inventory to a file, or to stdout if no file
+/// is given.
+pub fn write(output: Option<&PathBuf>, contents: &str) -> io::Result<()> {
+    match output {
+        Some(path) => fs::write(path, contents),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE.iter().any(|pattern| name.contains(pattern))
+}
+
+/// Quote a CSV field if it contains a separator, a quote or a line break,
+/// see RFC 4180.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_and_escape() {
+        assert!(is_sensitive("db_password"));
+        assert!(is_sensitive("API_KEY"));
+        assert!(!is_sensitive("domain"));
+
+        assert_eq!(escape("foo"), "foo");
+        assert_eq!(escape("foo,bar"), "\"foo,bar\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
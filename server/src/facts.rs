@@ -0,0 +1,234 @@
+use common::{
+    error::Terminate,
+    facts::{Facts, Package},
+    Hostname,
+};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::BTreeMap, fs, io::ErrorKind, path::PathBuf};
+use time::OffsetDateTime;
+
+/// The facts that a client sent most recently.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClientFacts {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub facts: Facts,
+}
+
+/// The most recent facts of every client. Facts are persisted to disk so
+/// that they survive restarts of the server.
+#[derive(Debug)]
+pub struct FactStore {
+    path: PathBuf,
+    entries: BTreeMap<Hostname, ClientFacts>,
+}
+
+impl FactStore {
+    /// Read previously persisted facts from a file. A missing file results
+    /// in an empty collection.
+    pub fn load(path: PathBuf) -> Result<Self, Terminate> {
+        let scope = "facts";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize facts: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "facts file does not exist"
+                );
+
+                BTreeMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read facts: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn get(&self, name: &Hostname) -> Option<&ClientFacts> {
+        self.entries.get(name)
+    }
+
+    /// Find the clients that reported a package as installed, optionally
+    /// only those with a version lower than the given one, e.g. to find
+    /// the systems that run a vulnerable version. Clients only report their
+    /// packages if `PULLCONF_FACTS_PACKAGES` is enabled.
+    pub fn installed<'a>(
+        &'a self,
+        package: &'a str,
+        below: Option<&'a str>,
+    ) -> impl Iterator<Item = (&'a Hostname, &'a ClientFacts, &'a Package)> {
+        self.entries.iter().flat_map(move |(name, entry)| {
+            entry
+                .facts
+                .packages
+                .iter()
+                .filter(move |installed| installed.name == package)
+                .filter(move |installed| {
+                    below.is_none_or(|below| compare_versions(&installed.version, below).is_lt())
+                })
+                .map(move |installed| (name, entry, installed))
+        })
+    }
+
+    /// Record the facts of a client and persist all facts to disk. Disk
+    /// writes are skipped if the facts did not change.
+    pub fn record(&mut self, name: &Hostname, facts: Facts) -> Result<(), anyhow::Error> {
+        let unchanged = self
+            .entries
+            .get(name)
+            .is_some_and(|entry| entry.facts == facts);
+
+        self.entries.insert(
+            name.clone(),
+            ClientFacts {
+                timestamp: OffsetDateTime::now_utc(),
+                facts,
+            },
+        );
+
+        if unchanged {
+            Ok(())
+        } else {
+            self.save()
+        }
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Compare two Debian package versions like `dpkg --compare-versions`, i.e.
+/// `[epoch:]upstream[-revision]`, where `~` sorts before anything, even the
+/// end of a version, so that `1.0~rc1` is lower than `1.0`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a_upstream, a_revision) = split_version(a);
+    let (b_epoch, b_upstream, b_revision) = split_version(b);
+
+    a_epoch
+        .cmp(&b_epoch)
+        .then_with(|| compare_fragment(a_upstream.as_bytes(), b_upstream.as_bytes()))
+        .then_with(|| compare_fragment(a_revision.as_bytes(), b_revision.as_bytes()))
+}
+
+fn split_version(version: &str) -> (u64, &str, &str) {
+    let (epoch, version) = match version.split_once(':') {
+        Some((epoch, version)) => (epoch.parse().unwrap_or_default(), version),
+        None => (0, version),
+    };
+
+    match version.rsplit_once('-') {
+        Some((upstream, revision)) => (epoch, upstream, revision),
+        None => (epoch, version, ""),
+    }
+}
+
+/// Compare the upstream versions or revisions of two versions, which
+/// alternate between non-digits that are compared character by character
+/// and digits that are compared numerically.
+fn compare_fragment(mut a: &[u8], mut b: &[u8]) -> Ordering {
+    while !a.is_empty() || !b.is_empty() {
+        let a_length = a.iter().take_while(|c| !c.is_ascii_digit()).count();
+        let b_length = b.iter().take_while(|c| !c.is_ascii_digit()).count();
+
+        let ordering = compare_non_digits(&a[..a_length], &b[..b_length]);
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+
+        (a, b) = (&a[a_length..], &b[b_length..]);
+
+        let a_length = a.iter().take_while(|c| c.is_ascii_digit()).count();
+        let b_length = b.iter().take_while(|c| c.is_ascii_digit()).count();
+
+        let ordering = compare_digits(&a[..a_length], &b[..b_length]);
+
+        if ordering.is_ne() {
+            return ordering;
+        }
+
+        (a, b) = (&a[a_length..], &b[b_length..]);
+    }
+
+    Ordering::Equal
+}
+
+fn compare_non_digits(a: &[u8], b: &[u8]) -> Ordering {
+    // Letters sort before other characters and `~` before the end.
+    let weight = |c: Option<&u8>| match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_alphabetic() => *c as i32,
+        Some(c) => *c as i32 + 256,
+    };
+
+    (0..a.len().max(b.len()))
+        .map(|index| weight(a.get(index)).cmp(&weight(b.get(index))))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+fn compare_digits(a: &[u8], b: &[u8]) -> Ordering {
+    let trim = |digits: &[u8]| {
+        let zeros = digits.iter().take_while(|c| **c == b'0').count();
+        digits[zeros..].to_vec()
+    };
+
+    let (a, b) = (trim(a), trim(b));
+
+    a.len().cmp(&b.len()).then_with(|| a.cmp(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_debian_versions() {
+        for (a, b) in [
+            ("3.0.11-1~deb12u2", "3.0.13-1~deb12u1"),
+            ("1.0~rc1", "1.0"),
+            ("1.0", "1.0-1"),
+            ("1.9", "1.10"),
+            ("2.36-9", "1:2.0"),
+            ("1.0a", "1.0+"),
+            ("1.0-1", "1.0-1.1"),
+        ] {
+            assert_eq!(compare_versions(a, b), Ordering::Less, "{} < {}", a, b);
+            assert_eq!(compare_versions(b, a), Ordering::Greater, "{} > {}", b, a);
+        }
+
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+    }
+}
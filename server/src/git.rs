@@ -0,0 +1,476 @@
+use crate::{configuration::Configuration, env, SharedAppState};
+use anyhow::Context;
+use common::error::Terminate;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Mutex,
+    },
+    time::Duration,
+};
+use time::OffsetDateTime;
+
+const GIT: &str = "git";
+
+/// A git repository that pullconfd clones and periodically fetches
+/// in order to manage its resource directory. Every fetched commit is
+/// checked out into a separate directory, so that the active resource
+/// directory remains untouched until the new revision has been
+/// validated successfully.
+#[derive(Debug)]
+pub struct GitSource {
+    pub url: String,
+    pub branch: String,
+    pub interval: Duration,
+    /// The bare clone of the repository.
+    repository: PathBuf,
+    /// The directory that contains one checkout per revision.
+    revisions: PathBuf,
+    /// The most recent commit whose configuration failed validation.
+    /// This prevents validating the same commit over and over again.
+    rejected: Mutex<Option<String>>,
+    /// Channel that allows triggering a sync before the interval elapses,
+    /// e.g. from a webhook.
+    trigger: SyncSender<()>,
+    receiver: Mutex<Receiver<()>>,
+}
+
+/// The revision of the git repository that the current configuration
+/// was loaded from.
+#[derive(Clone, Debug, Serialize)]
+pub struct Revision {
+    pub commit: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl GitSource {
+    /// Read the git source from the environment. Returns `None` if no
+    /// repository URL is configured, in which case the resource directory
+    /// is managed by other means.
+    pub fn from_env(state_directory: &Path) -> Result<Option<Self>, Terminate> {
+        let url = match env::parse_secret("PULLCONF_GIT_URL")? {
+            Some(url) => url,
+            None => return Ok(None),
+        };
+
+        let branch = env::parse_string("PULLCONF_GIT_BRANCH", "main")?;
+
+        let interval = env::parse_duration("PULLCONF_GIT_SYNC_INTERVAL", 300)?;
+
+        // A single pending trigger suffices, as every sync fetches the
+        // latest commit anyway.
+        let (trigger, receiver) = mpsc::sync_channel(1);
+
+        let source = Self {
+            url,
+            branch,
+            interval,
+            repository: state_directory.join("repository.git"),
+            revisions: state_directory.join("revisions"),
+            rejected: Mutex::new(None),
+            trigger,
+            receiver: Mutex::new(receiver),
+        };
+
+        Ok(Some(source))
+    }
+
+    /// Request a sync without waiting for the interval to elapse.
+    /// Requests are coalesced if a sync is already pending.
+    pub fn trigger(&self) {
+        let _ = self.trigger.try_send(());
+    }
+
+    /// Block until either the interval elapses or a sync is triggered.
+    pub fn wait(&self) {
+        if let Ok(receiver) = self.receiver.lock() {
+            let _ = receiver.recv_timeout(self.interval);
+        }
+    }
+
+    /// Remove checkouts of previous runs, which are never reused as the
+    /// active revision is not persisted across restarts.
+    pub fn clean(&self) -> Result<(), anyhow::Error> {
+        if self.revisions.is_dir() {
+            fs::remove_dir_all(&self.revisions).with_context(|| {
+                format!("failed to remove directory {}", self.revisions.display())
+            })?;
+        }
+
+        if self.repository.is_dir() {
+            self.git(&["worktree", "prune"])?;
+        }
+
+        Ok(())
+    }
+
+    /// Clone the repository on first use or fetch the configured branch
+    /// otherwise and return the commit hash that the branch points to.
+    pub fn fetch(&self) -> Result<String, anyhow::Error> {
+        let scope = "git";
+
+        if self.repository.is_dir() {
+            debug!(scope, branch = self.branch.as_str(); "fetching repository");
+
+            let refspec = format!("+refs/heads/{0}:refs/heads/{0}", self.branch);
+
+            self.git(&["fetch", "--quiet", self.url.as_str(), refspec.as_str()])?;
+        } else {
+            debug!(scope, branch = self.branch.as_str(); "cloning repository");
+
+            let repository = self.repository.to_string_lossy();
+
+            run(
+                "clone",
+                Command::new(GIT).args([
+                    "clone",
+                    "--quiet",
+                    "--bare",
+                    "--single-branch",
+                    "--branch",
+                    self.branch.as_str(),
+                    self.url.as_str(),
+                    repository.as_ref(),
+                ]),
+            )?;
+        }
+
+        let reference = format!("refs/heads/{}", self.branch);
+
+        let commit = self.git(&["rev-parse", "--verify", reference.as_str()])?;
+
+        Ok(commit.trim().to_string())
+    }
+
+    /// Check out a commit into its own directory and return its path.
+    pub fn checkout(&self, commit: &str) -> Result<PathBuf, anyhow::Error> {
+        let path = self.revisions.join(commit);
+
+        if path.is_dir() {
+            self.remove(&path)?;
+        }
+
+        let target = path.to_string_lossy();
+
+        self.git(&[
+            "worktree",
+            "add",
+            "--quiet",
+            "--detach",
+            target.as_ref(),
+            commit,
+        ])?;
+
+        Ok(path)
+    }
+
+    /// Remove the checkout of a revision.
+    pub fn remove(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let target = path.to_string_lossy();
+
+        self.git(&["worktree", "remove", "--force", target.as_ref()])?;
+
+        Ok(())
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, anyhow::Error> {
+        let mut command = Command::new(GIT);
+
+        command.arg("--git-dir").arg(&self.repository).args(args);
+
+        run(args[0], &mut command)
+    }
+}
+
+/// Run a git command and return its standard output. Only the git
+/// subcommand is included in errors as the arguments may contain the
+/// credentials of the repository URL.
+fn run(subcommand: &str, command: &mut Command) -> Result<String, anyhow::Error> {
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .with_context(|| format!("failed to execute {} {}", GIT, subcommand))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} {} exited with status {}: {}",
+            GIT,
+            subcommand,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Fetch the repository and, if the branch points to a new commit, load
+/// the configuration from a fresh checkout of that commit. The checkout
+/// only replaces the active resource directory if the configuration is
+/// valid, otherwise the current configuration is kept.
+pub fn sync(state: &SharedAppState, source: &GitSource) -> Result<(), Terminate> {
+    let scope = "git";
+
+    let commit = source.fetch().map_err(|error| {
+        error!(scope, branch = source.branch.as_str(); "failed to fetch repository: {:#}", error);
+        Terminate
+    })?;
+
+    let active = match state.read() {
+        Ok(state) => state
+            .revision
+            .as_ref()
+            .map(|revision| revision.commit.clone()),
+        Err(error) => {
+            error!(scope; "failed to acquire read access to shared application state: {}", error);
+            return Err(Terminate);
+        }
+    };
+
+    if active.as_deref() == Some(commit.as_str())
+        || source.rejected.lock().unwrap().as_deref() == Some(commit.as_str())
+    {
+        debug!(scope, commit = commit.as_str(); "branch still points to the same commit");
+        return Ok(());
+    }
+
+    let path = source.checkout(&commit).map_err(|error| {
+        error!(scope, commit = commit.as_str(); "failed to check out commit: {:#}", error);
+        Terminate
+    })?;
+
+    let configuration = match Configuration::try_from(&path) {
+        Ok(configuration) => configuration,
+        Err(_) => {
+            warn!(
+                scope,
+                commit = commit.as_str();
+                "keeping the current revision as the configuration of the new commit is invalid"
+            );
+
+            if let Err(error) = source.remove(&path) {
+                error!(scope, commit = commit.as_str(); "failed to remove checkout: {:#}", error);
+            }
+
+            if let Some(notifier) = state
+                .read()
+                .ok()
+                .as_ref()
+                .and_then(|state| state.notifier.as_ref())
+            {
+                notifier.reload(Err(format!(
+                    "configuration of commit {} is invalid",
+                    commit
+                )));
+            }
+
+            *source.rejected.lock().unwrap() = Some(commit);
+
+            return Err(Terminate);
+        }
+    };
+
+    let previous = match state.write() {
+        Ok(mut state) => {
+            state.activate(configuration);
+            state.resources = path.clone();
+
+            if let Some(notifier) = &state.notifier {
+                notifier.reload(Ok(()));
+            }
+
+            state.revision.replace(Revision {
+                commit: commit.clone(),
+                timestamp: OffsetDateTime::now_utc(),
+                path,
+            })
+        }
+        Err(error) => {
+            error!(scope; "failed to acquire write access to shared application state: {}", error);
+            return Err(Terminate);
+        }
+    };
+
+    info!(scope, commit = commit.as_str(); "switched to new revision");
+
+    if let Some(previous) = previous {
+        if let Err(error) = source.remove(&previous.path) {
+            error!(
+                scope,
+                commit = previous.commit.as_str();
+                "failed to remove checkout of previous revision: {:#}",
+                error
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Clone or fetch the repository on startup and check out the commit
+/// that the branch points to. Unlike `sync` this fails if the
+/// repository cannot be fetched, as there is no configuration to fall
+/// back to.
+pub fn initialize(source: &GitSource) -> Result<Revision, Terminate> {
+    let scope = "git";
+
+    let revision = source
+        .clean()
+        .and_then(|_| source.fetch())
+        .and_then(|commit| {
+            let path = source.checkout(&commit)?;
+
+            Ok(Revision {
+                commit,
+                timestamp: OffsetDateTime::now_utc(),
+                path,
+            })
+        })
+        .map_err(|error| {
+            error!(scope, branch = source.branch.as_str(); "failed to initialize repository: {:#}", error);
+            Terminate
+        })?;
+
+    info!(scope, commit = revision.commit.as_str(); "checked out revision");
+
+    Ok(revision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use std::sync::{Arc, RwLock};
+
+    /// Commit the given files to a repository, whose resource directory
+    /// is its root.
+    fn commit(repository: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            fs::write(repository.join(path), contents).unwrap();
+        }
+
+        run(
+            "add",
+            Command::new(GIT)
+                .arg("-C")
+                .arg(repository)
+                .args(["add", "--all"]),
+        )
+        .unwrap();
+
+        run(
+            "commit",
+            Command::new(GIT).arg("-C").arg(repository).args([
+                "-c",
+                "user.name=pullconf",
+                "-c",
+                "user.email=pullconf@example.com",
+                "commit",
+                "--quiet",
+                "--no-gpg-sign",
+                "--message",
+                "test",
+            ]),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn sync_valid_revisions() {
+        let directory = std::env::temp_dir().join(format!("pullconfd-git-{}", std::process::id()));
+        let upstream = directory.join("upstream");
+
+        for path in ["upstream/clients", "upstream/groups", "state"] {
+            fs::create_dir_all(directory.join(path)).unwrap();
+        }
+
+        run(
+            "init",
+            Command::new(GIT)
+                .args(["init", "--quiet", "--initial-branch", "main"])
+                .arg(&upstream),
+        )
+        .unwrap();
+
+        let client = |name: &str, key: &str| {
+            (
+                format!("clients/{}.example.com.toml", name),
+                format!("api-key = \"{}\"\n", key.repeat(64)),
+            )
+        };
+
+        // Empty directories are not committed, so the first commit has a
+        // group, too.
+        let (path, contents) = client("web", "a");
+        commit(&upstream, &[(&path, &contents), ("groups/web.toml", "")]);
+
+        let (trigger, receiver) = mpsc::sync_channel(1);
+
+        let source = GitSource {
+            url: upstream.to_string_lossy().into_owned(),
+            branch: "main".to_string(),
+            interval: Duration::from_secs(300),
+            repository: directory.join("state/repository.git"),
+            revisions: directory.join("state/revisions"),
+            rejected: Mutex::new(None),
+            trigger,
+            receiver: Mutex::new(receiver),
+        };
+
+        let revision = initialize(&source).unwrap();
+
+        let mut state = AppState::fixture(&directory.join("fixture"), &[]);
+        state.configuration = Configuration::try_from(&revision.path).unwrap();
+        state.resources = revision.path.clone();
+        state.revision = Some(revision.clone());
+
+        let state = Arc::new(RwLock::new(state));
+
+        // A commit whose configuration is invalid is rejected.
+        commit(
+            &upstream,
+            &[("clients/db.example.com.toml", "api-key = 1\n")],
+        );
+
+        let invalid = sync(&state, &source);
+        let rejected = source.rejected.lock().unwrap().clone();
+        let kept = state
+            .read()
+            .unwrap()
+            .revision
+            .as_ref()
+            .unwrap()
+            .commit
+            .clone();
+
+        let (path, contents) = client("db", "b");
+        commit(&upstream, &[(&path, &contents)]);
+
+        let valid = sync(&state, &source);
+
+        let state = state.read().unwrap();
+        let active = state.revision.clone().unwrap();
+        let clients = state.configuration.clients.len();
+        let previous_removed = !revision.path.exists();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(invalid.is_err());
+        assert!(rejected.is_some());
+        assert_eq!(kept, revision.commit);
+
+        assert!(valid.is_ok());
+        assert_ne!(Some(&active.commit), rejected.as_ref());
+        assert_ne!(active.commit, revision.commit);
+        assert_eq!(clients, 2);
+        assert!(previous_removed);
+    }
+}
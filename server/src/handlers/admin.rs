@@ -0,0 +1,1213 @@
+use crate::{
+    check_ins::CheckIn,
+    diagnostics::Diagnostic,
+    git::Revision,
+    handlers::{catalog_response, decode_query_component, error::Error, read_body},
+    maintenance::Freeze,
+    registrations::Registration,
+    types::{admin::Role, AdminToken, ApiKey, ApiResponse},
+    AppState, SharedAppState,
+};
+use common::{Hostname, Links};
+use log::{debug, error, info};
+use rouille::{router, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, str::FromStr, time::Duration};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Inventory data of a single client as returned by `GET /admin/clients`.
+#[derive(Serialize)]
+struct ClientInventory<'a> {
+    name: &'a Hostname,
+    source: &'a PathBuf,
+    groups: &'a [Hostname],
+    sources: Vec<&'a PathBuf>,
+    resources: BTreeMap<String, usize>,
+    decommissioned: bool,
+}
+
+/// Inventory data of a single group as returned by `GET /admin/groups`.
+#[derive(Serialize)]
+struct GroupInventory<'a> {
+    name: &'a Hostname,
+    source: &'a PathBuf,
+    members: Vec<&'a Hostname>,
+    resources: BTreeMap<String, usize>,
+}
+
+/// Compilation statistics of the resource catalog of a single client
+/// as part of `GET /admin/statistics`.
+#[derive(Serialize)]
+struct CatalogStatistics<'a> {
+    name: &'a Hostname,
+    resources: BTreeMap<String, usize>,
+    total: usize,
+    inherited: usize,
+    #[serde(rename = "compile-time-ms")]
+    compile_time_ms: u64,
+}
+
+/// Compilation statistics of the current configuration as returned by
+/// `GET /admin/statistics`. Catalogs are sorted by their number of
+/// resources in descending order.
+#[derive(Serialize)]
+struct Statistics<'a> {
+    clients: usize,
+    groups: usize,
+    resources: BTreeMap<String, usize>,
+    total: usize,
+    inherited: usize,
+    #[serde(rename = "compile-time-ms")]
+    compile_time_ms: u64,
+    catalogs: Vec<CatalogStatistics<'a>>,
+}
+
+/// The most recent check-in of a single client as returned by
+/// `GET /admin/check-ins`.
+#[derive(Serialize)]
+struct ClientCheckIn<'a> {
+    name: &'a Hostname,
+    #[serde(rename = "check-in")]
+    check_in: Option<CheckIn>,
+    stale: bool,
+}
+
+/// The git revision that the configuration was loaded from as returned
+/// by `GET /admin/revision`.
+#[derive(Serialize)]
+struct ActiveRevision<'a> {
+    branch: &'a str,
+    #[serde(flatten)]
+    revision: Option<&'a Revision>,
+}
+
+/// A registration that awaits approval as returned by
+/// `GET /admin/registrations`.
+#[derive(Serialize)]
+struct PendingRegistration<'a> {
+    name: &'a Hostname,
+    #[serde(flatten)]
+    registration: &'a Registration,
+}
+
+/// The payload of `POST /admin/registrations/<hostname>/approve`.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Approval {
+    #[serde(default)]
+    groups: Vec<Hostname>,
+}
+
+/// The client configuration file that is created when a registration
+/// is approved.
+#[derive(Serialize)]
+struct ClientFile<'a> {
+    #[serde(rename = "api-key")]
+    api_key: &'a ApiKey,
+    groups: &'a [Hostname],
+}
+
+/// The interval at which clients should fetch their catalogs as returned
+/// by `GET /admin/poll-interval`. Both are given in seconds.
+#[derive(Serialize)]
+struct PollInterval {
+    default: Option<u64>,
+    #[serde(rename = "override")]
+    overridden: Option<u64>,
+}
+
+/// The payload of `PUT /admin/poll-interval`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PollIntervalOverride {
+    seconds: u64,
+}
+
+/// A client that has a package installed as returned by
+/// `GET /admin/packages/<name>`.
+#[derive(Serialize)]
+struct InstalledPackage<'a> {
+    name: &'a Hostname,
+    version: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    architecture: Option<&'a str>,
+    /// When the client sent its facts, including its packages, most
+    /// recently.
+    #[serde(with = "time::serde::rfc3339")]
+    timestamp: OffsetDateTime,
+}
+
+/// A client in maintenance mode as returned by `GET /admin/maintenance`.
+#[derive(Serialize)]
+struct FrozenClient<'a> {
+    name: &'a Hostname,
+    #[serde(flatten)]
+    freeze: &'a Freeze,
+}
+
+/// The payload of `PUT /admin/clients/<hostname>/maintenance`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FreezeRequest {
+    reason: String,
+}
+
+/// The outcome of a configuration reload as returned by
+/// `POST /admin/reload`.
+#[derive(Serialize)]
+struct ReloadResult {
+    success: bool,
+    errors: Vec<Diagnostic>,
+}
+
+/// Authenticate requests to the `/admin` API. These requests are
+/// authenticated with admin tokens instead of client API keys.
+pub fn authenticate(
+    request_id: &str,
+    request: &Request,
+    state: &SharedAppState,
+    token: &str,
+) -> Result<AdminToken, Error> {
+    let scope = "admin";
+
+    let state = state.read().unwrap();
+
+    let admin = match ApiKey::candidates(token, state.pepper.as_deref())
+        .iter()
+        .find_map(|encrypted_token| state.admin_tokens.get(encrypted_token))
+    {
+        Some(admin) => admin.clone(),
+        None => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url();
+                "admin failed to authenticate"
+            );
+            return Err(Error::failed_admin_authorization());
+        }
+    };
+
+    debug!(
+        scope,
+        request_id,
+        url = request.url(),
+        admin = admin.name.as_str();
+        "admin authenticated successfully"
+    );
+
+    Ok(admin)
+}
+
+/// Handle requests to the `/admin` API that were received via the local
+/// admin socket. Access to the socket is restricted by its file
+/// permissions, so these requests are not authenticated and may access
+/// every route.
+pub fn handle_local_route(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+) -> Result<Response, Error> {
+    handle_route(request_id, request, state, AdminToken::local())
+}
+
+/// Handle requests to the `/admin` API of an authenticated admin.
+pub fn handle_route(
+    request_id: &str,
+    request: &Request,
+    shared: SharedAppState,
+    admin: AdminToken,
+) -> Result<Response, Error> {
+    let scope = "admin";
+
+    let state = shared.read().unwrap();
+
+    // Reloading requires write access to the shared application state,
+    // so the read lock must be released before the reload is triggered.
+    if request.method() == "POST" && request.url() == "/admin/reload" {
+        authorize(request_id, request, &admin, Role::Reload)?;
+
+        drop(state);
+
+        return Ok(reload(request_id, request, shared, &admin));
+    }
+
+    // Promoting or discarding a canary replaces the configuration, too.
+    if request.method() == "POST"
+        && (request.url() == "/admin/canary/promote" || request.url() == "/admin/canary/discard")
+    {
+        authorize(request_id, request, &admin, Role::Reload)?;
+
+        drop(state);
+
+        return canary(request_id, request, shared, &admin);
+    }
+
+    // Approving a registration adds a client to the configuration and
+    // therefore triggers a reload, too.
+    if let Some(hostname) = request
+        .url()
+        .strip_prefix("/admin/registrations/")
+        .and_then(|path| path.strip_suffix("/approve"))
+        .filter(|_| request.method() == "POST")
+    {
+        authorize(request_id, request, &admin, Role::Full)?;
+
+        let hostname = match Hostname::from_str(hostname) {
+            Ok(hostname) => hostname,
+            Err(_) => return Ok(Response::empty_404()),
+        };
+
+        drop(state);
+
+        approve(request_id, request, shared.clone(), &admin, &hostname)?;
+
+        return Ok(reload(request_id, request, shared, &admin));
+    }
+
+    let configuration = &state.configuration;
+
+    router!(request,
+            (GET) (/admin/clients) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let mut clients = configuration.clients.values().collect::<Vec<_>>();
+
+                clients.sort();
+
+                let data = clients
+                    .into_iter()
+                    .map(|client| ClientInventory {
+                        name: client.name(),
+                        source: &client.source,
+                        groups: &client.assigned_groups,
+                        sources: std::iter::once(&client.source)
+                            .chain(
+                                client
+                                    .assigned_groups
+                                    .iter()
+                                    .filter_map(|name| configuration.groups.get(name))
+                                    .map(|group| &group.source),
+                            )
+                            .collect(),
+                        resources: client.resource_counts(),
+                        decommissioned: client.decommissioned,
+                    })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/clients".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/groups) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let mut groups = configuration.groups.iter().collect::<Vec<_>>();
+
+                groups.sort_by_key(|(name, _)| *name);
+
+                let data = groups
+                    .into_iter()
+                    .map(|(name, group)| {
+                        let mut members = configuration
+                            .clients
+                            .values()
+                            .filter(|client| client.assigned_groups.contains(name))
+                            .map(|client| client.name())
+                            .collect::<Vec<_>>();
+
+                        members.sort();
+
+                        GroupInventory {
+                            name,
+                            source: &group.source,
+                            members,
+                            resources: group.resource_counts(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/groups".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/statistics) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let mut catalogs = configuration
+                    .clients
+                    .values()
+                    .map(|client| CatalogStatistics {
+                        name: client.name(),
+                        resources: client.resource_counts(),
+                        total: client.resources.len(),
+                        inherited: client.inherited,
+                        compile_time_ms: client.compile_time.as_millis() as u64,
+                    })
+                    .collect::<Vec<_>>();
+
+                catalogs.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(b.name)));
+
+                let mut resources = BTreeMap::new();
+
+                for catalog in &catalogs {
+                    for (kind, count) in &catalog.resources {
+                        *resources.entry(kind.clone()).or_default() += count;
+                    }
+                }
+
+                let data = Statistics {
+                    clients: catalogs.len(),
+                    groups: configuration.groups.len(),
+                    resources,
+                    total: catalogs.iter().map(|catalog| catalog.total).sum(),
+                    inherited: catalogs.iter().map(|catalog| catalog.inherited).sum(),
+                    compile_time_ms: configuration.compile_time.as_millis() as u64,
+                    catalogs,
+                };
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/statistics".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/registrations) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let registrations = state.registrations.lock().unwrap();
+
+                let data = registrations
+                    .entries()
+                    .iter()
+                    .map(|(name, registration)| PendingRegistration { name, registration })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/registrations".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (DELETE) (/admin/registrations/{hostname: Hostname}) => {
+                authorize(request_id, request, &admin, Role::Full)?;
+
+                match state.registrations.lock().unwrap().remove(&hostname) {
+                    Ok(Some(_)) => {
+                        info!(
+                            scope,
+                            request_id,
+                            client:% = hostname,
+                            admin = admin.name.as_str();
+                            "registration was rejected"
+                        );
+
+                        Ok(Response::empty_204())
+                    }
+                    Ok(None) => Ok(Response::empty_404()),
+                    Err(error) => {
+                        error!(scope, request_id, client:% = hostname; "failed to persist registrations: {}", error);
+
+                        Ok(Response::empty_204())
+                    }
+                }
+            },
+            (GET) (/admin/poll-interval) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                Ok(poll_interval_response(&state))
+            },
+            (PUT) (/admin/poll-interval) => {
+                authorize(request_id, request, &admin, Role::Reload)?;
+
+                let payload = read_body(request, state.max_body_size)
+                    .and_then(|body| serde_json::from_slice::<PollIntervalOverride>(&body).ok())
+                    .ok_or_else(Error::bad_request)?;
+
+                info!(
+                    scope,
+                    request_id,
+                    admin = admin.name.as_str();
+                    "poll interval was overridden with {} seconds",
+                    payload.seconds
+                );
+
+                *state.poll_interval_override.lock().unwrap() =
+                    Some(Duration::from_secs(payload.seconds));
+
+                Ok(poll_interval_response(&state))
+            },
+            (DELETE) (/admin/poll-interval) => {
+                authorize(request_id, request, &admin, Role::Reload)?;
+
+                info!(scope, request_id, admin = admin.name.as_str(); "poll interval override was removed");
+
+                *state.poll_interval_override.lock().unwrap() = None;
+
+                Ok(poll_interval_response(&state))
+            },
+            (GET) (/admin/canary) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let canary = match &state.canary {
+                    Some(canary) => canary,
+                    None => return Ok(Response::empty_404()),
+                };
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/canary".to_string(),
+                        ..Default::default()
+                    },
+                    data: canary.status(configuration),
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/check-ins) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let check_ins = state.check_ins.lock().unwrap();
+
+                let mut clients = configuration.clients.keys().collect::<Vec<_>>();
+
+                clients.sort();
+
+                let data = clients
+                    .into_iter()
+                    .map(|name| ClientCheckIn {
+                        name,
+                        check_in: check_ins.get(name).cloned(),
+                        stale: check_ins.is_stale(name),
+                    })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/check-ins".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/revision) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let source = match &state.git {
+                    Some(source) => source,
+                    None => {
+                        debug!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            admin = admin.name.as_str();
+                            "resource directory is not managed by a git repository"
+                        );
+
+                        return Ok(Response::empty_404());
+                    }
+                };
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/revision".to_string(),
+                        ..Default::default()
+                    },
+                    data: ActiveRevision {
+                        branch: &source.branch,
+                        revision: state.revision.as_ref(),
+                    },
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/clients/{hostname: Hostname}/catalog) => {
+                authorize(request_id, request, &admin, Role::Full)?;
+
+                match configuration.clients.get(&hostname) {
+                    Some(client) => Ok(catalog_response(request, client, &state.serials)),
+                    None => {
+                        debug!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            admin = admin.name.as_str();
+                            "client `{}` is not known",
+                            hostname
+                        );
+
+                        Ok(Response::empty_404())
+                    }
+                }
+            },
+            (GET) (/admin/clients/{hostname: Hostname}/facts) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let facts = state.facts.lock().unwrap();
+
+                match facts.get(&hostname) {
+                    Some(facts) => {
+                        let response = ApiResponse {
+                            links: Links {
+                                this: format!("/admin/clients/{}/facts", hostname),
+                                ..Default::default()
+                            },
+                            data: facts,
+                        };
+
+                        Ok(Response::json(&response))
+                    }
+                    None => Ok(Response::empty_404()),
+                }
+            },
+            (GET) (/admin/clients/{hostname: Hostname}/changes) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                // Only changes from this point in time on are listed, e.g.
+                // `?since=2024-05-14T00:00:00Z`.
+                let mut since = None;
+
+                for pair in request.raw_query_string().split('&').filter(|pair| !pair.is_empty()) {
+                    if let Some(("since", value)) = pair.split_once('=') {
+                        let value = decode_query_component(value).ok_or_else(|| {
+                            Error::invalid_query("query contains invalid percent-encoding")
+                        })?;
+
+                        since = Some(OffsetDateTime::parse(&value, &Rfc3339).map_err(|_| {
+                            Error::invalid_query("`since` must be an RFC 3339 timestamp")
+                        })?);
+                    }
+                }
+
+                let history = state.history.lock().unwrap();
+
+                match history.changes(&hostname) {
+                    Some(changes) => {
+                        let response = ApiResponse {
+                            links: Links {
+                                this: format!("/admin/clients/{}/changes", hostname),
+                                ..Default::default()
+                            },
+                            data: changes
+                                .into_iter()
+                                .filter(|change| since.is_none_or(|since| change.timestamp >= since))
+                                .collect::<Vec<_>>(),
+                        };
+
+                        Ok(Response::json(&response))
+                    }
+                    None => Ok(Response::empty_404()),
+                }
+            },
+            (GET) (/admin/packages/{package: String}) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                // Only clients with a version lower than `below` are
+                // listed, e.g. `?below=3.0.13-1~deb12u1`.
+                let mut below = None;
+
+                for pair in request.raw_query_string().split('&').filter(|pair| !pair.is_empty()) {
+                    if let Some(("below", value)) = pair.split_once('=') {
+                        below = Some(decode_query_component(value).ok_or_else(|| {
+                            Error::invalid_query("query contains invalid percent-encoding")
+                        })?);
+                    }
+                }
+
+                let facts = state.facts.lock().unwrap();
+
+                let data = facts
+                    .installed(&package, below.as_deref())
+                    .map(|(name, entry, installed)| InstalledPackage {
+                        name,
+                        version: &installed.version,
+                        architecture: installed.architecture.as_deref(),
+                        timestamp: entry.timestamp,
+                    })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: format!("/admin/packages/{}", package),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (GET) (/admin/maintenance) => {
+                authorize(request_id, request, &admin, Role::ReadOnly)?;
+
+                let maintenance = state.maintenance.lock().unwrap();
+
+                let data = maintenance
+                    .entries()
+                    .iter()
+                    .map(|(name, freeze)| FrozenClient { name, freeze })
+                    .collect::<Vec<_>>();
+
+                let response = ApiResponse {
+                    links: Links {
+                        this: "/admin/maintenance".to_string(),
+                        ..Default::default()
+                    },
+                    data,
+                };
+
+                Ok(Response::json(&response))
+            },
+            (PUT) (/admin/clients/{hostname: Hostname}/maintenance) => {
+                authorize(request_id, request, &admin, Role::Reload)?;
+
+                let client = match configuration.clients.get(&hostname) {
+                    Some(client) => match &state.canary {
+                        Some(canary) => canary.resolve(client),
+                        None => client,
+                    },
+                    None => return Ok(Response::empty_404()),
+                };
+
+                let payload = read_body(request, state.max_body_size)
+                    .and_then(|body| serde_json::from_slice::<FreezeRequest>(&body).ok())
+                    .ok_or_else(Error::bad_request)?;
+
+                let freeze = Freeze {
+                    timestamp: OffsetDateTime::now_utc(),
+                    reason: payload.reason,
+                    admin: admin.name.clone(),
+                };
+
+                if let Err(error) = state.maintenance.lock().unwrap().freeze(client, freeze) {
+                    error!(scope, request_id, client:% = hostname; "failed to persist frozen clients: {}", error);
+                }
+
+                info!(
+                    scope,
+                    request_id,
+                    client:% = hostname,
+                    admin = admin.name.as_str();
+                    "client was put into maintenance mode"
+                );
+
+                Ok(Response::empty_204())
+            },
+            (DELETE) (/admin/clients/{hostname: Hostname}/maintenance) => {
+                authorize(request_id, request, &admin, Role::Reload)?;
+
+                match state.maintenance.lock().unwrap().thaw(&hostname) {
+                    Ok(Some(_)) => {
+                        info!(
+                            scope,
+                            request_id,
+                            client:% = hostname,
+                            admin = admin.name.as_str();
+                            "client was released from maintenance mode"
+                        );
+
+                        Ok(Response::empty_204())
+                    }
+                    Ok(None) => Ok(Response::empty_404()),
+                    Err(error) => {
+                        error!(scope, request_id, client:% = hostname; "failed to persist frozen clients: {}", error);
+
+                        Ok(Response::empty_204())
+                    }
+                }
+            },
+            _ => {
+                debug!(
+                    scope,
+                    request_id,
+                    url = request.url(),
+                    admin = admin.name.as_str();
+                    "failed to find route matching this request"
+                );
+
+                Ok(Response::empty_404())
+            }
+    )
+}
+
+/// Trigger the same reload as SIGHUP and respond with its outcome,
+/// including every error that prevented the reload.
+fn reload(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+    admin: &AdminToken,
+) -> Response {
+    let scope = "admin";
+
+    let mut state = state.write().unwrap();
+
+    debug!(
+        scope,
+        request_id,
+        url = request.url(),
+        admin = admin.name.as_str();
+        "reload was triggered via the admin API"
+    );
+
+    let (status, data) = match state.reload(scope) {
+        Ok(()) => (
+            200,
+            ReloadResult {
+                success: true,
+                errors: vec![],
+            },
+        ),
+        Err(errors) => (
+            422,
+            ReloadResult {
+                success: false,
+                errors,
+            },
+        ),
+    };
+
+    let response = ApiResponse {
+        links: Links {
+            this: request.url(),
+            ..Default::default()
+        },
+        data,
+    };
+
+    Response::json(&response).with_status_code(status)
+}
+
+/// Promote a pending canary, so that every client receives the most
+/// recent configuration, or discard it and restore the stable
+/// configuration for every client.
+fn canary(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+    admin: &AdminToken,
+) -> Result<Response, Error> {
+    let scope = "canary";
+
+    let mut state = state.write().unwrap();
+
+    let state = &mut *state;
+
+    let canary = match state.canary.as_mut() {
+        Some(canary) => canary,
+        None => return Ok(Response::empty_404()),
+    };
+
+    let pending = match canary.pending.take() {
+        Some(pending) => pending,
+        None => return Err(Error::conflict("no canary is pending")),
+    };
+
+    if request.url() == "/admin/canary/promote" {
+        info!(scope, request_id, admin = admin.name.as_str(); "canary was promoted");
+    } else {
+        // The active revision still refers to the discarded commit, so
+        // that it is not synced again until a new commit is pushed.
+        state.configuration = pending.stable;
+
+        info!(scope, request_id, admin = admin.name.as_str(); "canary was discarded");
+    }
+
+    let response = ApiResponse {
+        links: Links {
+            this: "/admin/canary".to_string(),
+            ..Default::default()
+        },
+        data: canary.status(&state.configuration),
+    };
+
+    Ok(Response::json(&response))
+}
+
+fn poll_interval_response(state: &AppState) -> Response {
+    let response = ApiResponse {
+        links: Links {
+            this: "/admin/poll-interval".to_string(),
+            ..Default::default()
+        },
+        data: PollInterval {
+            default: state.poll_interval.map(|interval| interval.as_secs()),
+            overridden: state
+                .poll_interval_override
+                .lock()
+                .unwrap()
+                .map(|interval| interval.as_secs()),
+        },
+    };
+
+    Response::json(&response)
+}
+
+/// Create the configuration file of a client from its pending
+/// registration and remove the registration from the queue.
+fn approve(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+    admin: &AdminToken,
+    hostname: &Hostname,
+) -> Result<(), Error> {
+    let scope = "admin";
+
+    let state = state.read().unwrap();
+
+    // Files in a checkout of the git repository would be lost on the next
+    // sync, so clients must be added to the repository instead.
+    if state.git.is_some() {
+        return Err(Error::conflict(
+            "resource directory is managed by a git repository, add the client to the repository instead",
+        ));
+    }
+
+    let approval = match read_body(request, state.max_body_size) {
+        Some(body) if body.is_empty() => Approval::default(),
+        Some(body) => serde_json::from_slice(&body).map_err(|_| Error::bad_request())?,
+        None => return Err(Error::bad_request()),
+    };
+
+    if let Some(group) = approval
+        .groups
+        .iter()
+        .find(|group| !state.configuration.groups.contains_key(*group))
+    {
+        return Err(Error::invalid_registration(&format!(
+            "group `{}` is not known",
+            group
+        )));
+    }
+
+    let mut registrations = state.registrations.lock().unwrap();
+
+    let registration = match registrations.get(hostname) {
+        Some(registration) => registration.clone(),
+        None => return Err(Error::conflict("no registration of this client is pending")),
+    };
+
+    let path = state
+        .resources
+        .join("clients")
+        .join(format!("{}.toml", hostname));
+
+    if path.exists() {
+        return Err(Error::conflict(
+            "a configuration file for this client already exists",
+        ));
+    }
+
+    let file = ClientFile {
+        api_key: &registration.api_key,
+        groups: &approval.groups,
+    };
+
+    let contents = format!(
+        "# Created by pullconfd on approval of the registration from {}.\n{}",
+        registration.address,
+        toml::to_string(&file).expect("client file can be serialized")
+    );
+
+    if let Err(error) = fs::write(&path, contents) {
+        error!(
+            scope,
+            request_id,
+            client:% = hostname,
+            path:% = path.display();
+            "failed to write client configuration file: {}",
+            error
+        );
+
+        return Err(Error::internal("failed to write client configuration file"));
+    }
+
+    if let Err(error) = registrations.remove(hostname) {
+        error!(scope, request_id, client:% = hostname; "failed to persist registrations: {}", error);
+    }
+
+    info!(
+        scope,
+        request_id,
+        client:% = hostname,
+        admin = admin.name.as_str();
+        "registration was approved"
+    );
+
+    Ok(())
+}
+
+/// Ensure that the role of an admin token permits access to a route.
+fn authorize(
+    request_id: &str,
+    request: &Request,
+    admin: &AdminToken,
+    role: Role,
+) -> Result<(), Error> {
+    if admin.permits(role) {
+        Ok(())
+    } else {
+        debug!(
+            scope = "admin",
+            request_id,
+            url = request.url(),
+            admin = admin.name.as_str();
+            "admin token has role `{}`, but this route requires role `{}`",
+            admin.role,
+            role
+        );
+
+        Err(Error::forbidden())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use serde_json::{json, Value};
+    use std::{
+        io::Read,
+        sync::{Arc, RwLock},
+    };
+
+    const FILES: &[(&str, &str)] = &[
+        (
+            "groups/web.toml",
+            "[[resources]]\ntype = \"directory\"\npath = \"/srv/www\"\n",
+        ),
+        (
+            "clients/web.example.com.toml",
+            "api-key = \"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\"\ngroups = [\"web\"]\n\n[[resources]]\ntype = \"file\"\npath = \"/etc/motd\"\ncontent = \"web\"\n",
+        ),
+        (
+            "clients/db.example.com.toml",
+            "api-key = \"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\"\n",
+        ),
+    ];
+
+    /// Create the state of pullconfd in a temporary directory, which the
+    /// test removes once it is done.
+    fn state(name: &str) -> (PathBuf, SharedAppState) {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-admin-{}-{}", name, std::process::id()));
+
+        let state = AppState::fixture(&directory, FILES);
+
+        (directory, Arc::new(RwLock::new(state)))
+    }
+
+    /// Send a request with an admin token of the given role and return the
+    /// status and body of the response.
+    fn request(
+        state: &SharedAppState,
+        role: Role,
+        method: &str,
+        url: &str,
+        body: &str,
+    ) -> (u16, Value) {
+        let request = Request::fake_http(method, url, vec![], body.as_bytes().to_vec());
+
+        let mut admin = AdminToken::local();
+        admin.role = role;
+
+        let response = match handle_route("test", &request, state.clone(), admin) {
+            Ok(response) => response,
+            Err(error) => error.into(),
+        };
+
+        let mut body = String::new();
+
+        response
+            .data
+            .into_reader_and_size()
+            .0
+            .read_to_string(&mut body)
+            .unwrap();
+
+        (
+            response.status_code,
+            serde_json::from_str(&body).unwrap_or(Value::Null),
+        )
+    }
+
+    #[test]
+    fn inventory_of_clients_and_groups() {
+        let (directory, state) = state("inventory");
+
+        let (status, clients) = request(&state, Role::ReadOnly, "GET", "/admin/clients", "");
+        let (_, groups) = request(&state, Role::ReadOnly, "GET", "/admin/groups", "");
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(status, 200);
+        assert_eq!(clients["links"]["self"], "/admin/clients");
+
+        let clients = clients["data"].as_array().unwrap();
+
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0]["name"], "db.example.com");
+        assert_eq!(clients[0]["resources"], json!({}));
+        assert_eq!(clients[1]["name"], "web.example.com");
+        assert_eq!(clients[1]["groups"], json!(["web"]));
+        assert_eq!(clients[1]["sources"].as_array().unwrap().len(), 2);
+        assert_eq!(clients[1]["resources"], json!({"directory": 1, "file": 1}));
+        assert_eq!(clients[1]["decommissioned"], false);
+
+        assert_eq!(groups["data"][0]["name"], "web");
+        assert_eq!(groups["data"][0]["members"], json!(["web.example.com"]));
+        assert_eq!(groups["data"][0]["resources"], json!({"directory": 1}));
+    }
+
+    #[test]
+    fn preview_catalogs() {
+        let (directory, state) = state("preview");
+
+        let url = "/admin/clients/web.example.com/catalog";
+
+        let (status, catalog) = request(&state, Role::Full, "GET", url, "");
+        let (forbidden, _) = request(&state, Role::Reload, "GET", url, "");
+        let (unknown, _) = request(
+            &state,
+            Role::Full,
+            "GET",
+            "/admin/clients/unknown.example.com/catalog",
+            "",
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(status, 200);
+
+        let catalog = catalog.to_string();
+
+        assert!(catalog.contains("/etc/motd"));
+        assert!(catalog.contains("/srv/www"));
+
+        // Catalogs may contain secrets, so they require the full role.
+        assert_eq!(forbidden, 403);
+        assert_eq!(unknown, 404);
+    }
+
+    #[test]
+    fn override_poll_interval() {
+        let (directory, state) = state("poll-interval");
+
+        let url = "/admin/poll-interval";
+
+        let (_, initial) = request(&state, Role::ReadOnly, "GET", url, "");
+        let (forbidden, _) = request(&state, Role::ReadOnly, "PUT", url, r#"{"seconds": 60}"#);
+        let (invalid, _) = request(&state, Role::Reload, "PUT", url, r#"{"minutes": 1}"#);
+        let (status, overridden) = request(&state, Role::Reload, "PUT", url, r#"{"seconds": 60}"#);
+
+        let interval = {
+            let state = state.read().unwrap();
+
+            state.poll_interval(&state.configuration.clients[&"db.example.com".parse().unwrap()])
+        };
+
+        let (_, removed) = request(&state, Role::Reload, "DELETE", url, "");
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(initial["data"], json!({"default": null, "override": null}));
+        assert_eq!(forbidden, 403);
+        assert_eq!(invalid, 400);
+        assert_eq!(status, 200);
+        assert_eq!(overridden["data"], json!({"default": null, "override": 60}));
+        assert_eq!(interval, Some(Duration::from_secs(60)));
+        assert_eq!(removed["data"], json!({"default": null, "override": null}));
+    }
+
+    #[test]
+    fn freeze_and_thaw_clients() {
+        let (directory, state) = state("maintenance");
+
+        let url = "/admin/clients/web.example.com/maintenance";
+        let name = "web.example.com".parse::<Hostname>().unwrap();
+
+        let (frozen, _) = request(
+            &state,
+            Role::Reload,
+            "PUT",
+            url,
+            r#"{"reason": "migration"}"#,
+        );
+        let (invalid, _) = request(&state, Role::Reload, "PUT", url, "{}");
+        let (unknown, _) = request(
+            &state,
+            Role::Reload,
+            "PUT",
+            "/admin/clients/unknown.example.com/maintenance",
+            r#"{"reason": "migration"}"#,
+        );
+        let (_, entries) = request(&state, Role::ReadOnly, "GET", "/admin/maintenance", "");
+
+        // A frozen client keeps its catalog when the configuration changes.
+        fs::write(
+            directory.join("resources/clients/web.example.com.toml"),
+            format!(
+                "{}\n[[resources]]\ntype = \"file\"\npath = \"/etc/issue\"\n",
+                FILES[1].1
+            ),
+        )
+        .unwrap();
+
+        state.write().unwrap().reload("test").unwrap();
+
+        let resources = |state: &SharedAppState| {
+            let state = state.read().unwrap();
+
+            let resources = state
+                .maintenance
+                .lock()
+                .unwrap()
+                .resolve(&state.configuration.clients[&name])
+                .resources
+                .len();
+            resources
+        };
+
+        let while_frozen = resources(&state);
+
+        let (thawed, _) = request(&state, Role::Reload, "DELETE", url, "");
+        let (not_frozen, _) = request(&state, Role::Reload, "DELETE", url, "");
+
+        let after_thaw = resources(&state);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(frozen, 204);
+        assert_eq!(invalid, 400);
+        assert_eq!(unknown, 404);
+        assert_eq!(entries["data"][0]["name"], "web.example.com");
+        assert_eq!(entries["data"][0]["reason"], "migration");
+        assert_eq!(entries["data"][0]["admin"], "local");
+        assert_eq!(while_frozen, 2);
+        assert_eq!(thawed, 204);
+        assert_eq!(not_frozen, 404);
+        assert_eq!(after_thaw, 3);
+    }
+}
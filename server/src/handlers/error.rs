@@ -1,25 +1,19 @@
+use common::problem::{Problem, ProblemCode, CONTENT_TYPE};
 use rouille::Response;
-use serde::{Serialize, Serializer};
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
 pub struct Error {
-    #[serde(serialize_with = "serialize_status")]
     status: u16,
+    code: ProblemCode,
     title: &'static str,
     detail: String,
 }
 
-fn serialize_status<S>(status: &u16, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    serializer.serialize_str(&status.to_string())
-}
-
 impl Error {
     pub fn missing_authorization() -> Self {
         Self {
             status: 401,
+            code: ProblemCode::MissingAuthorization,
             title: "missing authorization",
             detail: "requests must contain an <X-API-KEY> header with an API key".to_string(),
         }
@@ -28,14 +22,124 @@ impl Error {
     pub fn failed_authorization() -> Self {
         Self {
             status: 401,
+            code: ProblemCode::FailedAuthorization,
             title: "failed authorization",
             detail: "provided API key is not associated with any client".to_string(),
         }
     }
 
+    pub fn failed_admin_authorization() -> Self {
+        Self {
+            status: 401,
+            code: ProblemCode::FailedAuthorization,
+            title: "failed authorization",
+            detail: "provided token is not associated with any admin token".to_string(),
+        }
+    }
+
+    pub fn failed_webhook_authorization() -> Self {
+        Self {
+            status: 401,
+            code: ProblemCode::FailedAuthorization,
+            title: "failed authorization",
+            detail: "webhook signature or token does not match the webhook secret".to_string(),
+        }
+    }
+
+    pub fn failed_registration_authorization() -> Self {
+        Self {
+            status: 401,
+            code: ProblemCode::FailedAuthorization,
+            title: "failed authorization",
+            detail: "registration token is missing or does not match the registration token of the server".to_string(),
+        }
+    }
+
+    pub fn invalid_registration(detail: &str) -> Self {
+        Self {
+            status: 422,
+            code: ProblemCode::InvalidRegistration,
+            title: "invalid registration",
+            detail: detail.to_string(),
+        }
+    }
+
+    pub fn too_many_registrations() -> Self {
+        Self {
+            status: 503,
+            code: ProblemCode::TooManyRegistrations,
+            title: "too many registrations",
+            detail: "too many registrations are awaiting approval, try again later".to_string(),
+        }
+    }
+
+    pub fn bad_request() -> Self {
+        Self {
+            status: 400,
+            code: ProblemCode::BadRequest,
+            title: "bad request",
+            detail: "request body is missing, cannot be read or is too large".to_string(),
+        }
+    }
+
+    pub fn invalid_query(detail: &str) -> Self {
+        Self {
+            status: 400,
+            code: ProblemCode::InvalidQuery,
+            title: "invalid query",
+            detail: detail.to_string(),
+        }
+    }
+
+    pub fn payload_too_large() -> Self {
+        Self {
+            status: 413,
+            code: ProblemCode::PayloadTooLarge,
+            title: "payload too large",
+            detail: "request body exceeds the maximum size accepted by the server".to_string(),
+        }
+    }
+
+    pub fn headers_too_large() -> Self {
+        Self {
+            status: 431,
+            code: ProblemCode::HeadersTooLarge,
+            title: "request header fields too large",
+            detail: "request headers exceed the maximum size accepted by the server".to_string(),
+        }
+    }
+
+    pub fn not_found(detail: &str) -> Self {
+        Self {
+            status: 404,
+            code: ProblemCode::NotFound,
+            title: "not found",
+            detail: detail.to_string(),
+        }
+    }
+
+    pub fn conflict(detail: &str) -> Self {
+        Self {
+            status: 409,
+            code: ProblemCode::Conflict,
+            title: "conflict",
+            detail: detail.to_string(),
+        }
+    }
+
+    pub fn internal(detail: &str) -> Self {
+        Self {
+            status: 500,
+            code: ProblemCode::Internal,
+            title: "internal server error",
+            detail: detail.to_string(),
+        }
+    }
+
     pub fn forbidden() -> Self {
         Self {
             status: 403,
+            code: ProblemCode::Forbidden,
             title: "access forbidden",
             detail: "insufficient permissions to access the requested resource".to_string(),
         }
@@ -44,6 +148,16 @@ impl Error {
 
 impl From<Error> for Response {
     fn from(error: Error) -> Self {
-        Response::json(&error).with_status_code(error.status)
+        let problem = Problem {
+            kind: error.code.uri(),
+            code: error.code,
+            category: error.code.error_code(),
+            status: error.status,
+            title: error.title.to_string(),
+            detail: error.detail,
+        };
+
+        Response::from_data(CONTENT_TYPE, serde_json::to_vec(&problem).unwrap())
+            .with_status_code(error.status)
     }
 }
@@ -1,13 +1,34 @@
+pub mod admin;
 pub mod error;
-
-use crate::{handlers::error::Error, types::ApiKey, SharedAppState};
-use common::{Hostname, Links};
-use log::debug;
+pub mod registration;
+pub mod webhook;
+
+use crate::{
+    audit::{AuditRecord, Principal},
+    check_ins::CheckIn,
+    handlers::error::Error,
+    serials::CatalogSerials,
+    types::{resources::Resource, ApiKey, ApiResponse, Client},
+    SharedAppState,
+};
+use common::{facts::Facts, Hostname, Links, ResourceType};
+use log::{debug, error};
 use rand::{distributions::Alphanumeric, Rng};
-use rouille::{content_encoding, router, Request, Response};
-use serde::Serialize;
+use rouille::{content_encoding, router, Request, Response, ResponseBody};
 use sha2::{Digest, Sha256};
-use std::{fs, io::Read, path::PathBuf, time::Instant};
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+    time::Instant,
+};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// The OpenAPI document describing this API.
+const OPENAPI_DOCUMENT: &[u8] = include_bytes!("../../api/openapi.json");
 
 pub fn handle_request(request: &Request, state: SharedAppState) -> Response {
     let start = Instant::now();
@@ -30,31 +51,68 @@ pub fn handle_request(request: &Request, state: SharedAppState) -> Response {
 
     let header = "x-api-key";
 
-    let response = match request.header(header) {
-        Some(key) => {
-            debug!(
-                scope,
-                request_id,
-                url = request.url();
-                "found {} header",
-                header
-            );
-
-            match handle_route(&request_id, request, state, key) {
-                Ok(r) => r,
-                Err(e) => e.into(),
-            }
+    // The identity of the request once it has been authenticated, which
+    // determines whether the request is recorded in the audit log.
+    let mut principal = None;
+
+    let response = if request.method() == "GET" && request.url() == "/openapi.json" {
+        debug!(
+            scope,
+            request_id,
+            url = request.url();
+            "returning OpenAPI document, which does not require authentication"
+        );
+
+        Response::from_data("application/json", OPENAPI_DOCUMENT)
+    } else if request.method() == "POST" && request.url() == "/api/registrations" {
+        match registration::handle_route(&request_id, request, state.clone()) {
+            Ok(r) => r,
+            Err(e) => e.into(),
         }
-        None => {
-            debug!(
-                scope,
-                request_id,
-                url = request.url();
-                "client failed to provide authentication credentials via the {} header",
-                header,
-            );
-
-            Error::missing_authorization().into()
+    } else if request.method() == "POST" && request.url() == "/admin/webhook" {
+        match webhook::handle_route(&request_id, request, state.clone()) {
+            Ok(r) => r,
+            Err(e) => e.into(),
+        }
+    } else {
+        match request.header(header) {
+            Some(key) => {
+                debug!(
+                    scope,
+                    request_id,
+                    url = request.url();
+                    "found {} header",
+                    header
+                );
+
+                let result = if request.url().starts_with("/admin/") {
+                    admin::authenticate(&request_id, request, &state, key).and_then(|admin| {
+                        principal = Some(Principal::Admin(admin.name.clone()));
+                        admin::handle_route(&request_id, request, state.clone(), admin)
+                    })
+                } else {
+                    authenticate(&request_id, request, &state, key).and_then(|client| {
+                        principal = Some(Principal::Client(client.name().clone()));
+                        handle_route(&request_id, request, state.clone(), client)
+                    })
+                };
+
+                match result {
+                    Ok(r) => r,
+                    Err(e) => e.into(),
+                }
+            }
+            None => {
+                debug!(
+                    scope,
+                    request_id,
+                    url = request.url();
+                    "client failed to provide authentication credentials via the {} header",
+                    header,
+                );
+
+                Error::missing_authorization().into()
+            }
         }
     };
 
@@ -65,7 +123,11 @@ pub fn handle_request(request: &Request, state: SharedAppState) -> Response {
         "applying optional encoding based on the accept-encoding header",
     );
 
-    let response = content_encoding::apply(request, response);
+    let mut response = content_encoding::apply(request, response);
+
+    if let Some(principal) = principal {
+        audit(&request_id, request, &state, &principal, &mut response);
+    }
 
     debug!(
         scope,
@@ -86,25 +148,79 @@ pub fn handle_request(request: &Request, state: SharedAppState) -> Response {
     response
 }
 
-fn handle_route(
+/// Record an authenticated request in the audit log, if enabled.
+fn audit(
     request_id: &str,
     request: &Request,
-    state: SharedAppState,
+    state: &SharedAppState,
+    principal: &Principal,
+    response: &mut Response,
+) {
+    let state = state.read().unwrap();
+
+    let audit_log = match &state.audit_log {
+        Some(audit_log) => audit_log,
+        None => return,
+    };
+
+    // The size of the body is only exposed by consuming it, so the body
+    // is rebuilt from its reader afterwards.
+    let (reader, bytes) =
+        std::mem::replace(&mut response.data, ResponseBody::empty()).into_reader_and_size();
+
+    response.data = match bytes {
+        Some(size) => ResponseBody::from_reader_and_size(reader, size),
+        None => ResponseBody::from_reader(reader),
+    };
+
+    let etag = response
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+        .map(|(_, value)| value.as_ref());
+
+    audit_log.record(&AuditRecord {
+        timestamp: OffsetDateTime::now_utc(),
+        request_id,
+        principal,
+        method: request.method(),
+        route: &request.url(),
+        status: response.status_code,
+        bytes,
+        etag,
+    });
+}
+
+/// Authenticate a client by its API key.
+fn authenticate(
+    request_id: &str,
+    request: &Request,
+    state: &SharedAppState,
     api_key: &str,
-) -> Result<Response, Error> {
+) -> Result<Client, Error> {
     let scope = "api";
 
     let state = state.read().unwrap();
 
-    let encrypted_key = ApiKey::encrypt(api_key);
-
-    let client = match state
-        .configuration
-        .api_keys
-        .get(&encrypted_key)
+    // Clients are looked up by the HMAC-SHA256 hash of their API key if
+    // a server pepper is configured, and by the legacy SHA-256 hash
+    // otherwise or if no client matches the former.
+    let client = match ApiKey::candidates(api_key, state.pepper.as_deref())
+        .iter()
+        .find_map(|encrypted_key| state.configuration.api_keys.get(encrypted_key))
         .and_then(|name| state.configuration.clients.get(name))
     {
-        Some(client) => client.clone(),
+        // Clients outside the canary group keep receiving their stable
+        // catalog while a canary is pending, and frozen clients keep
+        // receiving the catalog they were frozen with.
+        Some(client) => {
+            let client = match &state.canary {
+                Some(canary) => canary.resolve(client),
+                None => client,
+            };
+
+            state.maintenance.lock().unwrap().resolve(client)
+        }
         None => {
             debug!(
                 scope,
@@ -124,7 +240,22 @@ fn handle_route(
         "client authenticated successfully"
     );
 
+    Ok(client)
+}
+
+fn handle_route(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+    client: Client,
+) -> Result<Response, Error> {
+    let scope = "api";
+
+    let state = state.read().unwrap();
+
     if let Some(request) = request.remove_prefix("/assets") {
+        let path = request.url();
+
         if !client
             .resources
             .iter()
@@ -136,14 +267,15 @@ fn handle_route(
                         .and_then(|path| path.to_str())
                 })
             })
-            .any(|path| path == request.url())
+            .any(|source| source == path)
+            && !client.assets.iter().any(|pattern| pattern.matches(&path))
         {
             debug!(
                 scope,
                 request_id,
                 url = request.url(),
                 client:% = client.name();
-                "client is not permitted to download file as none of its associated file resources specify this download path",
+                "client is not permitted to download file as neither its associated file resources nor its asset patterns match this download path",
             );
 
             return Err(Error::forbidden());
@@ -159,30 +291,217 @@ fn handle_route(
                     // validating the configuration, and then serve the
                     // serialized catalog from memory, instead of serializing
                     // the catalog on every request.
-                    #[derive(Serialize)]
-                    struct ApiResponse<T> {
-                        pub links: Links,
-                        pub data: T,
+                    if client.name() != &hostname {
+                        debug!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            client:% = client.name();
+                            "client is not permitted to download this resource catalog",
+                        );
+
+                        return Ok(Error::forbidden().into());
+                    }
+
+                    let filter = CatalogFilter::from_query(request.raw_query_string())?;
+
+                    // A filtered catalog is only a subset of the catalog
+                    // that a client applies, so it does not count as a
+                    // check-in.
+                    if !filter.is_empty() {
+                        let resources = client
+                            .resources
+                            .iter()
+                            .filter(|resource| filter.matches(resource))
+                            .collect::<Vec<_>>();
+
+                        let (bytes, etag) = client.serialize_resources(&resources);
+
+                        return Ok(Response::from_data("application/json", bytes).with_etag(request, etag));
                     }
 
+                    // The catalog that a client receives is its desired
+                    // state, which may differ from the loaded configuration
+                    // during canary rollouts or in maintenance mode, so the
+                    // serial is assigned to the catalog as it is served.
+                    let (_, content) = client.serialize_catalog(None);
+
+                    let serial = match state.serials.lock().unwrap().assign(client.name(), &content) {
+                        Ok(serial) => serial,
+                        Err(error) => {
+                            error!(
+                                scope,
+                                request_id,
+                                url = request.url(),
+                                client:% = client.name();
+                                "failed to persist catalog serial: {}",
+                                error
+                            );
+
+                            return Err(Error::internal("failed to assign catalog serial"));
+                        }
+                    };
+
+                    let (bytes, etag) = client.serialize_catalog(Some(serial));
+
+                    // Record this check-in, including the version of the
+                    // client if it identifies itself via the user agent.
+                    let check_in = CheckIn {
+                        timestamp: OffsetDateTime::now_utc(),
+                        version: request
+                            .header("user-agent")
+                            .and_then(|agent| agent.strip_prefix("pullconf/"))
+                            .map(|version| version.to_string()),
+                        etag: etag.clone(),
+                    };
+
+                    match state.check_ins.lock() {
+                        Ok(mut check_ins) => check_ins.record(client.name(), check_in),
+                        Err(error) => error!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            client:% = client.name();
+                            "failed to record check-in: {}",
+                            error
+                        ),
+                    }
+
+                    // Versions are recorded as they are served, too.
+                    if let Err(error) = state.history.lock().unwrap().record(&client, &etag, Some(serial)) {
+                        error!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            client:% = client.name();
+                            "failed to persist catalog history: {}",
+                            error
+                        );
+                    }
+
+                    let response = Response::from_data("application/json", bytes).with_etag(request, etag);
+
+                    // Tell the client when to fetch its catalog again. This
+                    // is sent with 304 responses, too, so that a change of
+                    // the interval takes effect without a change of the
+                    // catalog.
+                    let response = match state.poll_interval(&client) {
+                        Some(interval) => response
+                            .with_unique_header(
+                                "Cache-Control",
+                                format!("private, max-age={}", interval.as_secs()),
+                            )
+                            .with_unique_header(
+                                "X-Pullconf-Interval",
+                                interval.as_secs().to_string(),
+                            ),
+                        None => response,
+                    };
+
+                    // Advertise the version that clients should run, so
+                    // that they can update themselves via `/api/agent`, if
+                    // there is a binary for their target.
+                    let target = request.header("X-Pullconf-Target");
+
+                    Ok(match state.release.as_ref().and_then(|release| Some((release, release.binary(target)?))) {
+                        Some((release, binary)) => response
+                            .with_unique_header("X-Pullconf-Version", release.version.clone())
+                            .with_unique_header("X-Pullconf-Sha256", binary.sha256.clone()),
+                        None => response,
+                    })
+                },
+                (GET) (/api/agent) => {
+                    let target = request.header("X-Pullconf-Target");
+
+                    let Some((release, binary)) = state
+                        .release
+                        .as_ref()
+                        .and_then(|release| Some((release, release.binary(target)?)))
+                    else {
+                        return Ok(Response::empty_404());
+                    };
+
+                    match fs::File::open(&binary.path) {
+                        Ok(file) => Ok(Response::from_file("application/octet-stream", file)
+                            .with_unique_header("X-Pullconf-Version", release.version.clone())
+                            .with_unique_header("X-Pullconf-Sha256", binary.sha256.clone())
+                            .with_unique_header("X-Pullconf-Target", target.unwrap_or_default().to_string())),
+                        Err(error) => {
+                            error!(
+                                scope,
+                                request_id,
+                                url = request.url(),
+                                client:% = client.name();
+                                "failed to open client binary {}: {}",
+                                binary.path.display(),
+                                error
+                            );
+
+                            Err(Error::internal("failed to read client binary"))
+                        }
+                    }
+                },
+                (PUT) (/api/clients/{hostname: Hostname}/facts) => {
                     if client.name() != &hostname {
                         debug!(
                             scope,
                             request_id,
                             url = request.url(),
                             client:% = client.name();
-                            "client is not permitted to download this resource catalog",
+                            "client is not permitted to send facts of another client",
+                        );
+
+                        return Ok(Error::forbidden().into());
+                    }
+
+                    let facts = read_body(request, state.max_body_size)
+                        .and_then(|body| serde_json::from_slice::<Facts>(&body).ok())
+                        .ok_or_else(Error::bad_request)?;
+
+                    if let Err(error) = state.facts.lock().unwrap().record(client.name(), facts) {
+                        error!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            client:% = client.name();
+                            "failed to persist facts: {}",
+                            error
+                        );
+                    }
+
+                    Ok(Response::empty_204())
+                },
+                (GET) (/api/clients/{hostname: Hostname}/resources/{id: Uuid}) => {
+                    if client.name() != &hostname {
+                        debug!(
+                            scope,
+                            request_id,
+                            url = request.url(),
+                            client:% = client.name();
+                            "client is not permitted to download this resource",
                         );
 
                         return Ok(Error::forbidden().into());
                     }
 
+                    let resource = match client.resources.iter().find(|resource| resource.id() == id) {
+                        Some(resource) => resource,
+                        None => {
+                            return Ok(Error::not_found(&format!(
+                                "resource {} is not part of the resource catalog",
+                                id
+                            ))
+                            .into())
+                        }
+                    };
+
                     let response = ApiResponse {
                         links: Links {
-                            this: format!("/api/clients/{}", client.name()),
+                            this: format!("/api/clients/{}/resources/{}", client.name(), id),
+                            client: Some(format!("/api/clients/{}", client.name())),
                             ..Default::default()
                         },
-                        data: &client.resources,
+                        data: resource,
                     };
 
                     let bytes = serde_json::to_vec(&response).unwrap();
@@ -206,6 +525,108 @@ fn handle_route(
     }
 }
 
+/// Filters that select a subset of a resource catalog, parsed from the
+/// repeatable query parameters `type` and `tag`. A resource matches if it
+/// is of any of the given types and carries any of the given tags.
+#[derive(Debug, Default, PartialEq)]
+struct CatalogFilter {
+    types: Vec<ResourceType>,
+    tags: Vec<String>,
+}
+
+impl CatalogFilter {
+    fn from_query(query: &str) -> Result<Self, Error> {
+        let mut filter = Self::default();
+
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            let value = decode_query_component(value)
+                .ok_or_else(|| Error::invalid_query("query contains invalid percent-encoding"))?;
+
+            match key {
+                "type" => {
+                    let kind = ResourceType::from_str(&value).map_err(|_| {
+                        Error::invalid_query(&format!("unknown resource type `{}`", value))
+                    })?;
+
+                    filter.types.push(kind);
+                }
+                "tag" => filter.tags.push(value),
+                _ => continue,
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.tags.is_empty()
+    }
+
+    fn matches(&self, resource: &Resource) -> bool {
+        (self.types.is_empty() || self.types.contains(&resource.kind()))
+            && (self.tags.is_empty()
+                || resource
+                    .metadata()
+                    .tags
+                    .iter()
+                    .any(|tag| self.tags.contains(tag)))
+    }
+}
+
+/// Decode a percent-encoded query component, where `+` denotes a space.
+fn decode_query_component(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'%' => {
+                let high = (iter.next()? as char).to_digit(16)?;
+                let low = (iter.next()? as char).to_digit(16)?;
+                bytes.push((high * 16 + low) as u8);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Read the request body, which may be empty. Returns `None` if the body
+/// cannot be read or exceeds the given size.
+pub fn read_body(request: &Request, max_body_size: u64) -> Option<Vec<u8>> {
+    let mut body = vec![];
+
+    if let Some(data) = request.data() {
+        if data.take(max_body_size + 1).read_to_end(&mut body).is_err()
+            || body.len() as u64 > max_body_size
+        {
+            return None;
+        }
+    }
+
+    Some(body)
+}
+
+/// Respond with the resource catalog of a client exactly as the client
+/// would receive it, including the serial it would be served with.
+pub fn catalog_response(
+    request: &Request,
+    client: &Client,
+    serials: &Mutex<CatalogSerials>,
+) -> Response {
+    let (_, content) = client.serialize_catalog(None);
+
+    let serial = serials.lock().unwrap().peek(client.name(), &content);
+
+    let (bytes, etag) = client.serialize_catalog(Some(serial));
+
+    Response::from_data("application/json", bytes).with_etag(request, etag)
+}
+
 fn match_assets(request: &Request, asset_path: PathBuf) -> Response {
     let mut path = asset_path.clone();
 
@@ -231,13 +652,268 @@ fn match_assets(request: &Request, asset_path: PathBuf) -> Response {
         Err(_) => return Response::empty_404(),
     };
 
-    let mut bytes = vec![];
+    // The etag is computed while reading the file in chunks, and the file
+    // is then streamed to the client, so that large assets are never held
+    // in memory as a whole.
+    let mut hasher = Sha256::new();
 
-    if file.read_to_end(&mut bytes).is_err() {
+    if io::copy(&mut file, &mut hasher).is_err() || file.seek(SeekFrom::Start(0)).is_err() {
         return Response::empty_404();
     }
 
-    let etag = format!("{:x}", Sha256::digest(&bytes));
+    let etag = format!("{:x}", hasher.finalize());
+
+    let size = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Response::empty_404(),
+    };
 
-    Response::from_data("application/octet-stream", bytes).with_etag(request, etag)
+    // Interrupted downloads are resumed from where they stopped, unless the
+    // asset has changed in the meantime, in which case the whole asset is
+    // sent instead.
+    let offset = request
+        .header("If-Range")
+        .filter(|value| *value == etag)
+        .and_then(|_| request.header("Range"))
+        .and_then(parse_range)
+        .filter(|offset| *offset > 0 && *offset < size);
+
+    let response = match offset {
+        Some(offset) if file.seek(SeekFrom::Start(offset)).is_ok() => Response {
+            status_code: 206,
+            headers: vec![
+                ("Content-Type".into(), "application/octet-stream".into()),
+                (
+                    "Content-Range".into(),
+                    format!("bytes {}-{}/{}", offset, size - 1, size).into(),
+                ),
+            ],
+            data: ResponseBody::from_reader_and_size(file, (size - offset) as usize),
+            upgrade: None,
+        },
+        _ => Response::from_file("application/octet-stream", file),
+    };
+
+    response
+        .with_additional_header("Accept-Ranges", "bytes")
+        .with_etag(request, etag)
+}
+
+/// Parse the offset of a `Range` header that requests everything from that
+/// offset on, e.g. `bytes=1024-`, which is how clients resume downloads.
+/// Other ranges are not supported.
+fn parse_range(header: &str) -> Option<u64> {
+    header
+        .trim()
+        .strip_prefix("bytes=")?
+        .strip_suffix('-')?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AppState;
+    use serde_json::Value;
+    use std::sync::{Arc, RwLock};
+
+    /// Send a request with the given API key and return the status, the
+    /// content type and the body of the response.
+    fn request(state: &SharedAppState, key: Option<&str>, url: &str) -> (u16, String, Value) {
+        let headers = key
+            .map(|key| vec![("X-API-KEY".to_string(), key.to_string())])
+            .unwrap_or_default();
+
+        let response = handle_request(
+            &Request::fake_http("GET", url, headers, vec![]),
+            state.clone(),
+        );
+
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.to_string())
+            .unwrap_or_default();
+
+        let mut body = String::new();
+
+        response
+            .data
+            .into_reader_and_size()
+            .0
+            .read_to_string(&mut body)
+            .unwrap();
+
+        (
+            response.status_code,
+            content_type,
+            serde_json::from_str(&body).unwrap_or(Value::Null),
+        )
+    }
+
+    fn state(name: &str) -> (PathBuf, SharedAppState) {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-api-{}-{}", name, std::process::id()));
+
+        let client = format!(
+            "api-key = \"{:x}\"\n\n[[resources]]\ntype = \"file\"\npath = \"/etc/motd\"\ncontent = \"web\"\n",
+            Sha256::digest("web-key")
+        );
+
+        let state = AppState::fixture(
+            &directory,
+            &[
+                ("clients/web.example.com.toml", &client),
+                (
+                    "clients/db.example.com.toml",
+                    &format!("api-key = \"{:x}\"\n", Sha256::digest("db-key")),
+                ),
+            ],
+        );
+
+        (directory, Arc::new(RwLock::new(state)))
+    }
+
+    #[test]
+    fn parse_catalog_filter() {
+        let filter =
+            CatalogFilter::from_query("type=file&type=apt%3A%3Apackage&tag=bootstrap&x=1").unwrap();
+
+        assert_eq!(
+            filter,
+            CatalogFilter {
+                types: vec![ResourceType::File, ResourceType::AptPackage],
+                tags: vec!["bootstrap".to_string()],
+            }
+        );
+
+        assert!(CatalogFilter::from_query("").unwrap().is_empty());
+        assert!(CatalogFilter::from_query("type=unknown").is_err());
+        assert!(CatalogFilter::from_query("tag=%zz").is_err());
+    }
+
+    #[test]
+    fn parse_range_header() {
+        assert_eq!(parse_range("bytes=1024-"), Some(1024));
+        assert_eq!(parse_range("bytes=0-"), Some(0));
+        assert_eq!(parse_range("bytes=0-1023"), None);
+        assert_eq!(parse_range("bytes=-500"), None);
+        assert_eq!(parse_range("items=10-"), None);
+    }
+
+    #[test]
+    fn serve_single_resources() {
+        let (directory, state) = state("resources");
+
+        let id = state.read().unwrap().configuration.clients
+            [&"web.example.com".parse::<Hostname>().unwrap()]
+            .resources[0]
+            .id();
+
+        let url = format!("/api/clients/web.example.com/resources/{}", id);
+
+        let (found, _, resource) = request(&state, Some("web-key"), &url);
+        let (forbidden, _, _) = request(&state, Some("db-key"), &url);
+        let (missing, _, _) = request(
+            &state,
+            Some("web-key"),
+            &format!("/api/clients/web.example.com/resources/{}", Uuid::nil()),
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(found, 200);
+        assert_eq!(resource["links"]["self"], url);
+        assert_eq!(resource["links"]["client"], "/api/clients/web.example.com");
+        assert_eq!(resource["data"]["parameters"]["path"], "/etc/motd");
+        assert_eq!(forbidden, 403);
+        assert_eq!(missing, 404);
+    }
+
+    #[test]
+    fn respond_with_problem_details() {
+        let (directory, state) = state("problems");
+
+        let url = "/api/clients/web.example.com/resources";
+
+        let missing = request(&state, None, url);
+        let failed = request(&state, Some("unknown-key"), url);
+        let invalid = request(&state, Some("web-key"), &format!("{}?type=unknown", url));
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        for (status, content_type, problem, code) in [
+            (401, missing.1, missing.2, "missing-authorization"),
+            (401, failed.1, failed.2, "failed-authorization"),
+            (400, invalid.1, invalid.2, "invalid-query"),
+        ] {
+            assert_eq!(content_type, common::problem::CONTENT_TYPE);
+            assert_eq!(problem["status"], status);
+            assert_eq!(problem["code"], code);
+            assert_eq!(problem["type"], format!("urn:pullconf:problem:{}", code));
+        }
+
+        assert_eq!(missing.0, 401);
+        assert_eq!(failed.0, 401);
+        assert_eq!(invalid.0, 400);
+    }
+
+    #[test]
+    fn serve_openapi_document() {
+        let (directory, state) = state("openapi");
+
+        let (status, _, document) = request(&state, None, "/openapi.json");
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(status, 200);
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+
+        // Every reference points to a component of the document.
+        fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+            match value {
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        match value {
+                            Value::String(reference) if key == "$ref" => found.push(reference),
+                            _ => references(value, found),
+                        }
+                    }
+                }
+                Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+                _ => (),
+            }
+        }
+
+        let mut found = vec![];
+        references(&document, &mut found);
+
+        assert!(!found.is_empty());
+
+        for reference in found {
+            let pointer = reference.strip_prefix('#').unwrap();
+
+            assert!(
+                document.pointer(pointer).is_some(),
+                "unresolved reference {}",
+                reference
+            );
+        }
+
+        for path in [
+            "/api/clients/{hostname}/resources",
+            "/api/clients/{hostname}/resources/{id}",
+            "/api/agent",
+            "/admin/clients/{hostname}/maintenance",
+            "/admin/poll-interval",
+        ] {
+            assert!(
+                document["paths"].get(path).is_some(),
+                "missing path {}",
+                path
+            );
+        }
+    }
 }
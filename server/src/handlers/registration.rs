@@ -0,0 +1,267 @@
+use crate::{
+    handlers::{error::Error, read_body},
+    registrations::Registration,
+    types::{ApiKey, ApiResponse},
+    SharedAppState,
+};
+use common::{Hostname, Links};
+use log::{debug, error, info};
+use rouille::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+/// The payload of a registration request. The client either sends the hash
+/// of its API key or the API key itself, which the server then hashes with
+/// the pepper, as clients do not know it.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegistrationRequest {
+    hostname: Hostname,
+    #[serde(rename = "api-key")]
+    api_key: Option<ApiKey>,
+    key: Option<String>,
+    token: Option<String>,
+}
+
+impl RegistrationRequest {
+    /// The hash of the API key that the registration is queued with. Legacy
+    /// SHA-256 hashes remain valid with a pepper, whereas HMAC-SHA256 hashes
+    /// never match without one.
+    fn hash(&self, pepper: Option<&str>) -> Result<ApiKey, &'static str> {
+        match (&self.api_key, &self.key, pepper) {
+            (Some(_), Some(_), _) => Err("registration must contain either api-key or key"),
+            (None, None, _) => Err("registration must contain api-key or key"),
+            (Some(api_key), None, None) if !api_key.is_legacy() => {
+                Err("server requires plain SHA256 API key hashes, as no pepper is configured")
+            }
+            (Some(api_key), None, _) => Ok(api_key.clone()),
+            (None, Some(key), _) if key.is_empty() => Err("API key must not be empty"),
+            (None, Some(key), Some(pepper)) => Ok(ApiKey::hmac(key, pepper)),
+            (None, Some(key), None) => Ok(ApiKey::encrypt(key)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RegistrationResult<'a> {
+    hostname: &'a Hostname,
+    status: &'static str,
+}
+
+/// Handle `POST /api/registrations`. Unknown clients use this endpoint to
+/// request to be added to the configuration with the hash of the API key
+/// they intend to use. Registrations are queued until an operator approves
+/// them via the admin API, so the request is not authenticated with an
+/// API key. If a registration token is configured, requests must present
+/// it, too.
+pub fn handle_route(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+) -> Result<Response, Error> {
+    let scope = "registration";
+
+    let state = state.read().unwrap();
+
+    if !state.registration_enabled {
+        debug!(
+            scope,
+            request_id,
+            url = request.url();
+            "registration of clients is disabled"
+        );
+
+        return Ok(Response::empty_404());
+    }
+
+    let registration = match read_body(request, state.max_body_size)
+        .and_then(|body| serde_json::from_slice::<RegistrationRequest>(&body).ok())
+    {
+        Some(registration) => registration,
+        None => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url();
+                "failed to read or deserialize registration request"
+            );
+
+            return Err(Error::bad_request());
+        }
+    };
+
+    if let Some(expected) = &state.registration_token {
+        // Compare digests instead of the tokens themselves in order to
+        // not leak the length of the common prefix through timing.
+        if !registration.token.as_ref().is_some_and(|token| {
+            Sha256::digest(token.as_bytes()) == Sha256::digest(expected.as_bytes())
+        }) {
+            debug!(
+                scope,
+                request_id,
+                url = request.url(),
+                client:% = registration.hostname;
+                "registration token is missing or invalid"
+            );
+
+            return Err(Error::failed_registration_authorization());
+        }
+    }
+
+    let api_key = match registration.hash(state.pepper.as_deref()) {
+        Ok(api_key) => api_key,
+        Err(detail) => return Err(Error::invalid_registration(detail)),
+    };
+
+    let hostname = registration.hostname;
+
+    if state.configuration.clients.contains_key(&hostname) {
+        debug!(
+            scope,
+            request_id,
+            url = request.url(),
+            client:% = hostname;
+            "client is already part of the configuration"
+        );
+
+        return Err(Error::conflict(
+            "client is already part of the configuration",
+        ));
+    }
+
+    let mut registrations = state.registrations.lock().unwrap();
+
+    // Repeated registrations with the same key are accepted, so that
+    // clients can safely retry, but a pending registration cannot be
+    // replaced with a different key.
+    match registrations.get(&hostname) {
+        Some(pending) if pending.api_key == api_key => (),
+        Some(_) => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url(),
+                client:% = hostname;
+                "a registration with a different API key is already pending"
+            );
+
+            return Err(Error::conflict(
+                "a registration with a different API key is already pending",
+            ));
+        }
+        None if registrations.is_full() => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url(),
+                client:% = hostname;
+                "registration queue is full"
+            );
+
+            return Err(Error::too_many_registrations());
+        }
+        None => {
+            let pending = Registration {
+                timestamp: OffsetDateTime::now_utc(),
+                api_key,
+                address: request.remote_addr().to_string(),
+            };
+
+            if let Err(error) = registrations.insert(hostname.clone(), pending) {
+                error!(
+                    scope,
+                    request_id,
+                    client:% = hostname;
+                    "failed to persist registrations: {}",
+                    error
+                );
+            }
+
+            info!(
+                scope,
+                request_id,
+                client:% = hostname;
+                "client registered and awaits approval"
+            );
+        }
+    }
+
+    let response = ApiResponse {
+        links: Links {
+            this: "/api/registrations".to_string(),
+            ..Default::default()
+        },
+        data: RegistrationResult {
+            hostname: &hostname,
+            status: "pending",
+        },
+    };
+
+    Ok(Response::json(&response).with_status_code(202))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const LEGACY: &str = "f2ca1bb6c7e907d06dafe4687e579fce76b37e4e93b7605022da52e6ccc26fd2";
+
+    fn request(payload: &str) -> RegistrationRequest {
+        serde_json::from_str(payload).unwrap()
+    }
+
+    #[test]
+    fn hash_api_keys_of_registrations() {
+        let legacy = request(&format!(
+            r#"{{"hostname": "my.example.com", "api-key": "{}"}}"#,
+            LEGACY
+        ));
+
+        // Legacy hashes remain valid with and without a pepper.
+        assert_eq!(
+            legacy.hash(None).unwrap(),
+            ApiKey::from_str(LEGACY).unwrap()
+        );
+        assert_eq!(
+            legacy.hash(Some("pepper")).unwrap(),
+            ApiKey::from_str(LEGACY).unwrap()
+        );
+
+        let hmac = ApiKey::hmac("secret", "pepper");
+        let peppered = request(&format!(
+            r#"{{"hostname": "my.example.com", "api-key": "{}"}}"#,
+            &*hmac
+        ));
+
+        assert_eq!(peppered.hash(Some("pepper")).unwrap(), hmac);
+        assert!(peppered.hash(None).is_err());
+
+        // The API key itself is hashed like the server hashes it on every
+        // request.
+        let key = request(r#"{"hostname": "my.example.com", "key": "secret", "token": "t"}"#);
+
+        assert_eq!(key.hash(Some("pepper")).unwrap(), hmac);
+        assert_eq!(key.hash(None).unwrap(), ApiKey::encrypt("secret"));
+        assert!(ApiKey::candidates("secret", Some("pepper"))
+            .contains(&key.hash(Some("pepper")).unwrap()));
+
+        let both = request(&format!(
+            r#"{{"hostname": "my.example.com", "api-key": "{}", "key": "secret"}}"#,
+            LEGACY
+        ));
+
+        assert!(both.hash(None).is_err());
+        assert!(request(r#"{"hostname": "my.example.com"}"#)
+            .hash(None)
+            .is_err());
+        assert!(request(r#"{"hostname": "my.example.com", "key": ""}"#)
+            .hash(None)
+            .is_err());
+        assert!(serde_json::from_str::<RegistrationRequest>(
+            r#"{"hostname": "my.example.com", "key": "secret", "groups": []}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    handlers::{error::Error, read_body},
+    types::ApiResponse,
+    SharedAppState,
+};
+use common::Links;
+use hmac::{Hmac, Mac};
+use log::{debug, info};
+use rouille::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The fields of GitHub and GitLab push events that are relevant to
+/// decide whether a push concerns the configured branch.
+#[derive(Deserialize)]
+struct PushEvent {
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+}
+
+#[derive(Serialize)]
+struct WebhookResult {
+    triggered: bool,
+}
+
+/// Handle `POST /admin/webhook`. Unlike the rest of the admin API this
+/// endpoint is not authenticated with an admin token, since the sender
+/// cannot be configured to send one. Instead requests must either carry
+/// a GitHub `X-Hub-Signature-256` header with the HMAC-SHA256 of the
+/// payload or a GitLab `X-Gitlab-Token` header, both derived from the
+/// webhook secret.
+pub fn handle_route(
+    request_id: &str,
+    request: &Request,
+    state: SharedAppState,
+) -> Result<Response, Error> {
+    let scope = "webhook";
+
+    let (secret, git, max_body_size) = {
+        let state = state.read().unwrap();
+
+        (
+            state.webhook_secret.clone(),
+            state.git.clone(),
+            state.max_body_size,
+        )
+    };
+
+    let secret = match secret {
+        Some(secret) => secret,
+        None => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url();
+                "webhook is disabled as no webhook secret is configured"
+            );
+
+            return Ok(Response::empty_404());
+        }
+    };
+
+    let payload = match read_body(request, max_body_size) {
+        Some(payload) => payload,
+        None => {
+            debug!(
+                scope,
+                request_id,
+                url = request.url();
+                "failed to read webhook payload or payload exceeds {} bytes",
+                max_body_size
+            );
+
+            return Err(Error::bad_request());
+        }
+    };
+
+    if !verify(request, &payload, &secret) {
+        debug!(
+            scope,
+            request_id,
+            url = request.url();
+            "webhook signature or token is missing or invalid"
+        );
+
+        return Err(Error::failed_webhook_authorization());
+    }
+
+    // Payloads that are not push events (e.g. the ping event that GitHub
+    // sends when a webhook is created) or lack a ref trigger a sync, too.
+    let reference = serde_json::from_slice::<PushEvent>(&payload)
+        .ok()
+        .and_then(|event| event.reference);
+
+    let triggered = match git {
+        Some(source) => {
+            let branch = format!("refs/heads/{}", source.branch);
+
+            if reference.is_some_and(|reference| reference != branch) {
+                debug!(
+                    scope,
+                    request_id,
+                    url = request.url();
+                    "ignoring push to a branch other than `{}`",
+                    source.branch
+                );
+
+                false
+            } else {
+                info!(scope, request_id; "sync of git repository was triggered by webhook");
+
+                source.trigger();
+
+                true
+            }
+        }
+        None => {
+            info!(scope, request_id; "reload was triggered by webhook");
+
+            let _ = state.write().unwrap().reload(scope);
+
+            true
+        }
+    };
+
+    let response = ApiResponse {
+        links: Links {
+            this: "/admin/webhook".to_string(),
+            ..Default::default()
+        },
+        data: WebhookResult { triggered },
+    };
+
+    Ok(Response::json(&response).with_status_code(202))
+}
+
+/// Verify either the GitHub signature or the GitLab token of a request.
+fn verify(request: &Request, payload: &[u8], secret: &str) -> bool {
+    if let Some(signature) = request.header("x-hub-signature-256") {
+        let signature = match signature.strip_prefix("sha256=").and_then(decode_hex) {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+
+        mac.update(payload);
+
+        mac.verify_slice(&signature).is_ok()
+    } else if let Some(token) = request.header("x-gitlab-token") {
+        // Compare digests instead of the tokens themselves in order to
+        // not leak the length of the common prefix through timing.
+        Sha256::digest(token.as_bytes()) == Sha256::digest(secret.as_bytes())
+    } else {
+        false
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            s.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_hex_signature() {
+        assert_eq!(decode_hex("00ff1a"), Some(vec![0x00, 0xff, 0x1a]));
+        assert_eq!(decode_hex("0ff"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::types::{client::Client, resources::Resource};
+use common::{error::Terminate, Hostname};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+};
+use time::OffsetDateTime;
+
+/// A version of the resource catalog of a client, i.e. a catalog that was
+/// served to the client for the first time. Parameters are only kept as
+/// digests, so that the history neither grows with the size of file
+/// contents nor reveals secrets such as password hashes.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CatalogVersion {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub etag: String,
+    /// The serial that the catalog was served with, see `CatalogSerials`.
+    /// Versions that were recorded before catalogs had a serial lack it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<u64>,
+    /// The digest of every parameter, relationship and setting such as
+    /// `tags` per resource, keyed by the representation of the resource,
+    /// e.g. ``file `/etc/motd` ``.
+    pub resources: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl CatalogVersion {
+    fn new(client: &Client, etag: &str, serial: Option<u64>) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc(),
+            etag: etag.to_string(),
+            serial,
+            resources: client
+                .resources
+                .iter()
+                .map(|resource| (resource.repr(), digests(resource)))
+                .collect(),
+        }
+    }
+}
+
+/// How the resource catalog of a client differs from the version before.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Change {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    #[serde(rename = "previous-timestamp", with = "time::serde::rfc3339")]
+    pub previous_timestamp: OffsetDateTime,
+    pub etag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<u64>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ChangedResource>,
+}
+
+/// A resource that exists in both versions, along with the names of its
+/// parameters that differ.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChangedResource {
+    pub resource: String,
+    pub parameters: Vec<String>,
+}
+
+/// The most recent versions of the resource catalog of every client.
+/// Versions are persisted to disk so that they survive restarts of the
+/// server.
+#[derive(Debug)]
+pub struct CatalogHistory {
+    path: PathBuf,
+    /// The number of versions that are kept per client.
+    limit: usize,
+    entries: HashMap<Hostname, VecDeque<CatalogVersion>>,
+}
+
+impl CatalogHistory {
+    /// Read previously persisted versions from a file. A missing file
+    /// results in an empty history.
+    pub fn load(path: PathBuf, limit: usize) -> Result<Self, Terminate> {
+        let scope = "history";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize catalog history: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "catalog history file does not exist"
+                );
+
+                HashMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read catalog history: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self {
+            path,
+            limit,
+            entries,
+        })
+    }
+
+    /// Record the catalog that is served to a client if it differs from
+    /// the most recent version, dropping the oldest versions beyond the
+    /// limit, and persist the history to disk.
+    pub fn record(
+        &mut self,
+        client: &Client,
+        etag: &str,
+        serial: Option<u64>,
+    ) -> Result<(), anyhow::Error> {
+        let versions = self.entries.entry(client.name().clone()).or_default();
+
+        if versions.back().is_some_and(|latest| latest.etag == etag) {
+            return Ok(());
+        }
+
+        let version = CatalogVersion::new(client, etag, serial);
+
+        match versions.back_mut() {
+            // A change of settings such as `purge` changes the serial,
+            // and thus the etag, of catalogs whose resources remain the
+            // same, which is not a change of its own.
+            Some(latest) if latest.resources == version.resources => {
+                latest.etag = version.etag;
+                latest.serial = version.serial;
+            }
+            _ => {
+                versions.push_back(version);
+
+                while versions.len() > self.limit {
+                    versions.pop_front();
+                }
+            }
+        }
+
+        self.save()
+    }
+
+    /// The changes between consecutive versions of the catalog of a
+    /// client, the most recent change first.
+    pub fn changes(&self, name: &Hostname) -> Option<Vec<Change>> {
+        let versions = self.entries.get(name)?;
+
+        Some(
+            versions
+                .iter()
+                .zip(versions.iter().skip(1))
+                .rev()
+                .map(|(previous, current)| compare(previous, current))
+                .collect(),
+        )
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
+
+fn compare(previous: &CatalogVersion, current: &CatalogVersion) -> Change {
+    let mut change = Change {
+        timestamp: current.timestamp,
+        previous_timestamp: previous.timestamp,
+        etag: current.etag.clone(),
+        serial: current.serial,
+        added: vec![],
+        removed: vec![],
+        changed: vec![],
+    };
+
+    for (repr, digests) in &current.resources {
+        match previous.resources.get(repr) {
+            Some(previous) => {
+                let parameters = digests
+                    .keys()
+                    .chain(previous.keys())
+                    .filter(|name| digests.get(*name) != previous.get(*name))
+                    .cloned()
+                    .collect::<BTreeSet<String>>();
+
+                if !parameters.is_empty() {
+                    change.changed.push(ChangedResource {
+                        resource: repr.clone(),
+                        parameters: parameters.into_iter().collect(),
+                    });
+                }
+            }
+            None => change.added.push(repr.clone()),
+        }
+    }
+
+    change.removed = previous
+        .resources
+        .keys()
+        .filter(|repr| !current.resources.contains_key(*repr))
+        .cloned()
+        .collect();
+
+    change
+}
+
+/// Compute the digest of every parameter and relationship of a resource as
+/// well as of settings such as `tags`. Its type and ID are part of its
+/// representation already.
+fn digests(resource: &Resource) -> BTreeMap<String, String> {
+    let mut digests = BTreeMap::new();
+
+    let Ok(Value::Object(fields)) = serde_json::to_value(resource) else {
+        return digests;
+    };
+
+    for (name, value) in fields {
+        match value {
+            _ if name == "type" || name == "id" => (),
+            Value::Object(nested) if name == "parameters" || name == "relationships" => {
+                for (name, value) in nested {
+                    digests.insert(name, digest(&value));
+                }
+            }
+            value => {
+                digests.insert(name, digest(&value));
+            }
+        }
+    }
+
+    digests
+}
+
+fn digest(value: &Value) -> String {
+    format!("{:x}", Sha256::digest(value.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_catalog_versions() {
+        let version = |resources: &[(&str, &[(&str, &str)])]| CatalogVersion {
+            timestamp: OffsetDateTime::UNIX_EPOCH,
+            etag: String::new(),
+            serial: None,
+            resources: resources
+                .iter()
+                .map(|(repr, parameters)| {
+                    (
+                        repr.to_string(),
+                        parameters
+                            .iter()
+                            .map(|(name, digest)| (name.to_string(), digest.to_string()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        };
+
+        let previous = version(&[
+            ("file `/etc/motd`", &[("content", "a"), ("mode", "b")]),
+            ("directory `/etc/foo`", &[]),
+        ]);
+
+        let current = version(&[
+            (
+                "file `/etc/motd`",
+                &[("content", "c"), ("mode", "b"), ("owner", "d")],
+            ),
+            ("directory `/etc/bar`", &[]),
+        ]);
+
+        let change = compare(&previous, &current);
+
+        assert_eq!(change.added, ["directory `/etc/bar`"]);
+        assert_eq!(change.removed, ["directory `/etc/foo`"]);
+        assert_eq!(
+            change.changed,
+            [ChangedResource {
+                resource: "file `/etc/motd`".to_string(),
+                parameters: vec!["content".to_string(), "owner".to_string()],
+            }]
+        );
+    }
+}
@@ -0,0 +1,792 @@
+use crate::{env, handlers, handlers::error::Error, SharedAppState};
+use common::error::Terminate;
+use log::{debug, error, info, warn};
+use rouille::{Request, Response};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How long a connection may be idle between two requests before it is
+/// closed, unless the read timeout is shorter.
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+
+/// Limits that apply to every connection, so that slow or numerous clients
+/// cannot occupy the worker threads indefinitely.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// How long a client may take to send a request, i.e. its headers and
+    /// body, before the connection is closed. This applies to the request
+    /// as a whole, so that it cannot be stretched by sending it slowly.
+    pub read_timeout: Duration,
+    /// How long writing a response may stall before the connection is
+    /// closed.
+    pub write_timeout: Duration,
+    /// The maximum number of connections that are open at the same time
+    /// across all sockets, including connections that wait for a worker.
+    pub max_connections: usize,
+}
+
+impl Limits {
+    pub fn from_env() -> Result<Self, Terminate> {
+        let timeout = |variable: &str| {
+            let timeout = env::parse_duration(variable, 30)?;
+
+            if timeout.is_zero() {
+                error!(scope = "environment", variable; "value must be greater than zero");
+                return Err(Terminate);
+            }
+
+            Ok(timeout)
+        };
+
+        Ok(Self {
+            read_timeout: timeout("PULLCONF_READ_TIMEOUT")?,
+            write_timeout: timeout("PULLCONF_WRITE_TIMEOUT")?,
+            max_connections: env::parse_count("PULLCONF_MAX_CONNECTIONS", 1024)?,
+        })
+    }
+}
+
+/// The HTTPS server, which accepts connections on every socket and passes
+/// the requests to the handlers. TLS is terminated by the server itself, so
+/// that the certificate can be replaced while the sockets remain bound, see
+/// `tls::Certificate`.
+pub struct Server {
+    listeners: Vec<Listener>,
+    stopping: Arc<AtomicBool>,
+}
+
+/// Counts a connection as open until it is dropped.
+struct Open(Arc<AtomicUsize>);
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A socket with the thread that accepts connections and the worker
+/// threads that serve them.
+struct Listener {
+    socket: SocketAddr,
+    acceptor: JoinHandle<()>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Bind to every socket, so that either all sockets are bound or none.
+    pub fn bind(sockets: &[SocketAddr]) -> Result<Vec<TcpListener>, Terminate> {
+        let scope = "http";
+
+        sockets
+            .iter()
+            .map(|socket| {
+                TcpListener::bind(socket).map_err(|error| {
+                    error!(scope, socket:%; "failed to bind socket: {}", error);
+                    Terminate
+                })
+            })
+            .collect()
+    }
+
+    /// Accept connections on every listener and serve them with a fixed
+    /// number of worker threads per listener. Connections beyond the limit
+    /// are closed right away.
+    pub fn start(
+        listeners: Vec<TcpListener>,
+        state: &SharedAppState,
+        config: Arc<ServerConfig>,
+        workers: usize,
+        limits: Limits,
+    ) -> Result<Self, Terminate> {
+        let scope = "http";
+
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let open = Arc::new(AtomicUsize::new(0));
+
+        let mut server = Self {
+            listeners: vec![],
+            stopping: stopping.clone(),
+        };
+
+        for listener in listeners {
+            let socket = listener.local_addr().map_err(|error| {
+                error!(scope; "failed to determine address of socket: {}", error);
+                Terminate
+            })?;
+
+            let (sender, receiver) = mpsc::channel::<(TcpStream, SocketAddr, Open)>();
+            let receiver = Arc::new(Mutex::new(receiver));
+
+            let threads = (0..workers)
+                .map(|_| {
+                    let receiver = receiver.clone();
+                    let state = state.clone();
+                    let config = config.clone();
+                    let stopping = stopping.clone();
+
+                    thread::spawn(move || loop {
+                        // The lock is released before the connection is
+                        // served.
+                        let next = receiver.lock().unwrap().recv();
+
+                        let Ok((stream, remote, _open)) = next else {
+                            break;
+                        };
+
+                        // A panic only closes this connection, so that the
+                        // worker keeps serving the connections that follow.
+                        match panic::catch_unwind(AssertUnwindSafe(|| {
+                            serve(stream, remote, &config, &state, &stopping, limits)
+                        })) {
+                            Ok(Ok(())) => (),
+                            Ok(Err(error)) => {
+                                debug!(scope, remote:%; "connection failed: {}", error)
+                            }
+                            Err(_) => {
+                                error!(scope, remote:%; "worker panicked while serving connection")
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let _stopping = stopping.clone();
+            let _open = open.clone();
+
+            let acceptor = thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if _stopping.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(error) => {
+                            error!(scope, socket:%; "failed to accept connection: {}", error);
+                            thread::sleep(Duration::from_millis(100));
+                            continue;
+                        }
+                    };
+
+                    if _open.fetch_add(1, Ordering::Relaxed) >= limits.max_connections {
+                        _open.fetch_sub(1, Ordering::Relaxed);
+                        warn!(scope, socket:%; "closing connection as {} connections are open", limits.max_connections);
+                        continue;
+                    }
+
+                    let open = Open(_open.clone());
+
+                    match stream.peer_addr() {
+                        Ok(remote) => {
+                            let _ = sender.send((stream, remote, open));
+                        }
+                        Err(error) => {
+                            debug!(scope, socket:%; "failed to determine address of peer: {}", error);
+                        }
+                    }
+                }
+            });
+
+            info!(scope, socket:%, workers; "server is accepting connections");
+
+            server.listeners.push(Listener {
+                socket,
+                acceptor,
+                workers: threads,
+            });
+        }
+
+        Ok(server)
+    }
+
+    /// Stop accepting connections, finish the requests that are in
+    /// progress and release the sockets.
+    pub fn stop(self) {
+        let scope = "http";
+
+        self.stopping.store(true, Ordering::Relaxed);
+
+        for listener in self.listeners {
+            // Wake up the thread that waits for the next connection.
+            let _ = TcpStream::connect_timeout(&listener.socket, Duration::from_secs(1));
+
+            if listener.acceptor.join().is_err() {
+                error!(scope, socket:% = listener.socket; "failed to join acceptor thread");
+            }
+
+            for worker in listener.workers {
+                if worker.join().is_err() {
+                    error!(scope, socket:% = listener.socket; "failed to join worker thread");
+                }
+            }
+        }
+    }
+}
+
+/// A socket whose reads fail once a deadline has passed, however much data
+/// arrived before.
+struct Socket {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+
+        if remaining.is_zero() {
+            return Err(ErrorKind::TimedOut.into());
+        }
+
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Serve the requests of a single connection until the client closes it,
+/// it is idle for longer than `KEEP_ALIVE` or the server is stopped.
+fn serve(
+    stream: TcpStream,
+    remote: SocketAddr,
+    config: &Arc<ServerConfig>,
+    state: &SharedAppState,
+    stopping: &AtomicBool,
+    limits: Limits,
+) -> Result<(), io::Error> {
+    stream.set_write_timeout(Some(limits.write_timeout))?;
+
+    let connection = ServerConnection::new(config.clone()).map_err(io::Error::other)?;
+
+    let socket = Socket {
+        stream,
+        deadline: Instant::now(),
+    };
+
+    let mut reader = BufReader::new(StreamOwned::new(connection, socket));
+
+    let (max_header_size, max_body_size) = {
+        let state = state.read().unwrap();
+
+        (state.max_header_size, state.max_body_size)
+    };
+
+    loop {
+        // Wait for the next request, which includes the TLS handshake on a
+        // new connection.
+        reader.get_mut().sock.deadline = Instant::now() + KEEP_ALIVE.min(limits.read_timeout);
+
+        match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(_) => (),
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                break
+            }
+            Err(error) => return Err(error),
+        }
+
+        reader.get_mut().sock.deadline = Instant::now() + limits.read_timeout;
+
+        let incoming = match read_request(&mut reader, max_header_size, max_body_size)? {
+            Ok(incoming) => incoming,
+            Err(error) => {
+                write_response(reader.get_mut(), error.into(), false, true)?;
+                break;
+            }
+        };
+
+        let mut close = incoming.close || stopping.load(Ordering::Relaxed);
+        let head = incoming.method == "HEAD";
+
+        let request = Request::fake_https_from(
+            remote,
+            incoming.method,
+            incoming.url,
+            incoming.headers,
+            incoming.body,
+        );
+
+        // Answer a request whose handler panicked, e.g. on a poisoned lock,
+        // instead of leaving the client without a response.
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| {
+            handlers::handle_request(&request, state.clone())
+        })) {
+            Ok(response) => response,
+            Err(_) => {
+                error!(
+                    scope = "http",
+                    remote:%;
+                    "handler panicked while serving {} {}",
+                    request.method(),
+                    request.raw_url()
+                );
+
+                close = true;
+
+                Error::internal("failed to handle request").into()
+            }
+        };
+
+        if !write_response(reader.get_mut(), response, head, close)? {
+            break;
+        }
+    }
+
+    let stream = reader.get_mut();
+
+    stream.conn.send_close_notify();
+    stream.flush()
+}
+
+/// A request as it was read from a connection.
+#[derive(Debug)]
+pub struct Incoming {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Whether the client asked to close the connection after the response.
+    pub close: bool,
+}
+
+/// Read a single HTTP/1.1 request. Requests whose request line and headers
+/// exceed `max_header_size` or whose body exceeds `max_body_size` are
+/// rejected without reading them any further, as is a malformed request.
+pub fn read_request<S: Read + Write>(
+    reader: &mut BufReader<S>,
+    max_header_size: usize,
+    max_body_size: u64,
+) -> Result<Result<Incoming, Error>, io::Error> {
+    let mut remaining = max_header_size as u64;
+
+    let mut read_line = |reader: &mut BufReader<S>| -> Result<Option<String>, io::Error> {
+        let mut line = String::new();
+
+        reader.by_ref().take(remaining).read_line(&mut line)?;
+
+        // The limit is reached before the end of the line.
+        if !line.ends_with('\n') {
+            return Ok(None);
+        }
+
+        remaining -= line.len() as u64;
+
+        Ok(Some(line.trim_end().to_string()))
+    };
+
+    let Some(line) = read_line(reader)? else {
+        return Ok(Err(Error::headers_too_large()));
+    };
+
+    let (method, url, close) = match line.split(' ').collect::<Vec<_>>().as_slice() {
+        [method, url, "HTTP/1.1"] => (method.to_string(), url.to_string(), false),
+        [method, url, "HTTP/1.0"] => (method.to_string(), url.to_string(), true),
+        _ => return Ok(Err(Error::bad_request())),
+    };
+
+    let mut headers = vec![];
+
+    loop {
+        let Some(line) = read_line(reader)? else {
+            return Ok(Err(Error::headers_too_large()));
+        };
+
+        if line.is_empty() {
+            break;
+        }
+
+        match line.split_once(':') {
+            Some((name, value)) if !name.is_empty() && name.trim() == name => {
+                headers.push((name.to_string(), value.trim().to_string()))
+            }
+            _ => return Ok(Err(Error::bad_request())),
+        }
+    }
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+
+    let close = match header("connection") {
+        Some(value) if value.eq_ignore_ascii_case("close") => true,
+        Some(value) if value.eq_ignore_ascii_case("keep-alive") => false,
+        _ => close,
+    };
+
+    let chunked =
+        header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked"));
+
+    let length = match header("content-length").map(str::parse::<u64>) {
+        Some(Ok(length)) if !chunked => length,
+        Some(_) => return Ok(Err(Error::bad_request())),
+        None => 0,
+    };
+
+    if length > max_body_size {
+        return Ok(Err(Error::payload_too_large()));
+    }
+
+    if header("expect").is_some_and(|value| value.eq_ignore_ascii_case("100-continue")) {
+        let stream = reader.get_mut();
+
+        stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        stream.flush()?;
+    }
+
+    let mut body = vec![];
+
+    if chunked {
+        loop {
+            let mut line = String::new();
+
+            reader.by_ref().take(64).read_line(&mut line)?;
+
+            let size = match line
+                .trim_end()
+                .split(';')
+                .next()
+                .map(|size| u64::from_str_radix(size.trim(), 16))
+            {
+                Some(Ok(size)) => size,
+                _ => return Ok(Err(Error::bad_request())),
+            };
+
+            // The size is sent by the client, so adding it may overflow.
+            if (body.len() as u64)
+                .checked_add(size)
+                .is_none_or(|length| length > max_body_size)
+            {
+                return Ok(Err(Error::payload_too_large()));
+            }
+
+            if size == 0 {
+                // Skip trailer fields, which are limited like headers.
+                loop {
+                    match read_line(reader)? {
+                        Some(line) if line.is_empty() => break,
+                        Some(_) => (),
+                        None => return Ok(Err(Error::headers_too_large())),
+                    }
+                }
+
+                break;
+            }
+
+            reader.by_ref().take(size).read_to_end(&mut body)?;
+
+            let mut crlf = [0; 2];
+
+            reader.read_exact(&mut crlf)?;
+
+            if &crlf != b"\r\n" {
+                return Ok(Err(Error::bad_request()));
+            }
+        }
+    } else {
+        reader.by_ref().take(length).read_to_end(&mut body)?;
+
+        if (body.len() as u64) < length {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    Ok(Ok(Incoming {
+        method,
+        url,
+        headers,
+        body,
+        close,
+    }))
+}
+
+/// Write a response and return whether the connection can be kept open,
+/// which requires that the length of the body is known. The body of a
+/// response to a HEAD request is omitted.
+pub fn write_response<W: Write>(
+    stream: &mut W,
+    response: Response,
+    head: bool,
+    close: bool,
+) -> Result<bool, io::Error> {
+    let (mut body, length) = response.data.into_reader_and_size();
+
+    let close = close || length.is_none();
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n",
+        response.status_code,
+        reason(response.status_code)
+    )?;
+
+    for (name, value) in &response.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+
+    if let Some(length) = length {
+        write!(stream, "Content-Length: {}\r\n", length)?;
+    }
+
+    if close {
+        write!(stream, "Connection: close\r\n")?;
+    }
+
+    write!(stream, "\r\n")?;
+
+    if !head {
+        io::copy(&mut body, stream)?;
+    }
+
+    stream.flush()?;
+
+    Ok(!close)
+}
+
+/// The reason phrase of the status codes that the handlers respond with.
+/// It is optional, so other status codes are sent without one.
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        416 => "Range Not Satisfiable",
+        422 => "Unprocessable Content",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tls::{self, Certificate, TlsFiles},
+        AppState,
+    };
+    use rustls::{crypto::ring, pki_types::CertificateDer, ClientConfig, ClientConnection};
+    use std::{fs, io::Cursor, sync::RwLock};
+
+    fn read(request: &str) -> Result<Incoming, Error> {
+        read_request(
+            &mut BufReader::new(Cursor::new(request.as_bytes().to_vec())),
+            64,
+            8,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn read_requests() {
+        let incoming = read("POST /api/facts HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody").unwrap();
+
+        assert_eq!(incoming.method, "POST");
+        assert_eq!(incoming.url, "/api/facts");
+        assert_eq!(
+            incoming.headers,
+            [("Content-Length".to_string(), "4".to_string())]
+        );
+        assert_eq!(incoming.body, b"body");
+        assert!(!incoming.close);
+
+        let incoming = read("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nbo\r\n2;x\r\ndy\r\n0\r\n\r\n").unwrap();
+
+        assert_eq!(incoming.body, b"body");
+
+        assert!(read("GET / HTTP/1.0\r\n\r\n").unwrap().close);
+        assert!(
+            read("GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .unwrap()
+                .close
+        );
+    }
+
+    #[test]
+    fn reject_requests() {
+        let status = |request: &str| Response::from(read(request).unwrap_err()).status_code;
+
+        assert_eq!(status("GET /\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost\r\n\r\n"), 400);
+        assert_eq!(status("GET / HTTP/1.1\r\nContent-Length: x\r\n\r\n"), 400);
+        assert_eq!(
+            status(&format!(
+                "GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+                "x".repeat(64)
+            )),
+            431
+        );
+        assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n"), 413);
+        assert_eq!(
+            status(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n9\r\nxxxxxxxxx\r\n0\r\n\r\n"
+            ),
+            413
+        );
+        assert_eq!(
+            status(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\nffffffffffffffff\r\n"
+            ),
+            413
+        );
+    }
+
+    /// Send a request over a TLS connection and return the status of the
+    /// response, after reading its body.
+    fn send<S: Read + Write>(reader: &mut BufReader<S>, request: &str) -> u16 {
+        reader.get_mut().write_all(request.as_bytes()).unwrap();
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let status = line.split(' ').nth(1).unwrap().parse().unwrap();
+
+        let mut length = 0;
+
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+
+            match line.trim_end().split_once(": ") {
+                Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                    length = value.parse().unwrap()
+                }
+                Some(_) => (),
+                None => break,
+            }
+        }
+
+        io::copy(&mut reader.by_ref().take(length), &mut io::sink()).unwrap();
+
+        status
+    }
+
+    #[test]
+    fn answer_requests_whose_handler_panicked() {
+        let directory = std::env::temp_dir().join(format!("pullconfd-http-{}", std::process::id()));
+
+        let state = Arc::new(RwLock::new(AppState::fixture(&directory, &[])));
+
+        let key = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+
+        fs::write(directory.join("server.crt"), key.cert.pem()).unwrap();
+        fs::write(
+            directory.join("server.key"),
+            key.signing_key.serialize_pem(),
+        )
+        .unwrap();
+
+        let certificate = TlsFiles::new(directory.join("server.crt"), directory.join("server.key"))
+            .read()
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = listener.local_addr().unwrap();
+
+        // A single worker has to survive the panic to serve the second
+        // connection.
+        let server = Server::start(
+            vec![listener],
+            &state,
+            tls::server_config(Certificate::new(certificate)).unwrap(),
+            1,
+            Limits {
+                read_timeout: Duration::from_secs(5),
+                write_timeout: Duration::from_secs(5),
+                max_connections: 4,
+            },
+        )
+        .unwrap();
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from(key.cert.der().to_vec()))
+            .unwrap();
+
+        let config = Arc::new(
+            ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        );
+
+        let connect = || {
+            let connection =
+                ClientConnection::new(config.clone(), "localhost".try_into().unwrap()).unwrap();
+
+            BufReader::new(StreamOwned::new(
+                connection,
+                TcpStream::connect(socket).unwrap(),
+            ))
+        };
+
+        let resources =
+            "GET /api/clients/web.example.com/resources HTTP/1.1\r\nX-API-KEY: key\r\n\r\n";
+        let openapi = "GET /openapi.json HTTP/1.1\r\n\r\n";
+
+        let mut connection = connect();
+
+        let before = send(&mut connection, resources);
+
+        // Poison the lock of the state, so that handlers panic once they
+        // try to acquire it.
+        let _ = thread::spawn({
+            let state = state.clone();
+            move || {
+                let _state = state.write().unwrap();
+                panic!("poisoning the state");
+            }
+        })
+        .join();
+
+        let panicked = send(&mut connection, resources);
+
+        state.clear_poison();
+
+        let after = send(&mut connect(), openapi);
+
+        server.stop();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(before, 401);
+        assert_eq!(panicked, 500);
+        assert_eq!(after, 200);
+    }
+}
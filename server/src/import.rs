@@ -0,0 +1,600 @@
+use anyhow::{anyhow, Context};
+use common::Hostname;
+use log::warn;
+use serde::Serialize;
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The implicit groups of every Ansible inventory. Neither is converted
+/// to a pullconf group, but variables of `all` apply to every client.
+const ALL: &str = "all";
+const UNGROUPED: &str = "ungrouped";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Host {
+    pub vars: BTreeMap<String, Value>,
+    /// The groups that the host is listed in directly.
+    pub groups: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Group {
+    pub vars: BTreeMap<String, Value>,
+    /// The groups that list this group as one of their children.
+    pub parents: BTreeSet<String>,
+}
+
+/// Hosts and groups as they are defined in an Ansible inventory,
+/// including variables from `host_vars` and `group_vars`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Inventory {
+    pub hosts: BTreeMap<String, Host>,
+    pub groups: BTreeMap<String, Group>,
+}
+
+/// The contents of a client or group configuration file that is
+/// generated from the inventory.
+#[derive(Debug, Default, Serialize)]
+pub struct Skeleton {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, toml::Value>,
+}
+
+impl Inventory {
+    /// Read an inventory file in either INI or YAML format, which is
+    /// determined by its extension, as well as the `host_vars` and
+    /// `group_vars` directories next to it.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = fs::read_to_string(path).context("failed to read inventory")?;
+
+        let mut inventory = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yml" | "yaml") => Self::parse_yaml(&contents)?,
+            _ => Self::parse_ini(&contents)?,
+        };
+
+        let directory = path.parent().unwrap_or(Path::new("."));
+
+        for (name, host) in inventory.hosts.iter_mut() {
+            host.vars
+                .extend(read_vars(&directory.join("host_vars"), name)?);
+        }
+
+        let mut names = inventory.groups.keys().cloned().collect::<BTreeSet<_>>();
+        names.insert(ALL.to_string());
+
+        for name in names {
+            let vars = read_vars(&directory.join("group_vars"), &name)?;
+
+            if !vars.is_empty() {
+                inventory.groups.entry(name).or_default().vars.extend(vars);
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    pub fn parse_ini(contents: &str) -> Result<Self, anyhow::Error> {
+        enum Section {
+            Hosts,
+            Vars,
+            Children,
+        }
+
+        let mut inventory = Self::default();
+        let mut group = UNGROUPED.to_string();
+        let mut section = Section::Hosts;
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(header) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                (group, section) = match header.split_once(':') {
+                    Some((name, "vars")) => (name.to_string(), Section::Vars),
+                    Some((name, "children")) => (name.to_string(), Section::Children),
+                    Some(_) => return Err(anyhow!("invalid section header on line {}", index + 1)),
+                    None => (header.to_string(), Section::Hosts),
+                };
+
+                inventory.groups.entry(group.clone()).or_default();
+
+                continue;
+            }
+
+            match section {
+                Section::Hosts => {
+                    let mut words = split_words(line).into_iter();
+                    let name = words.next().unwrap_or_default();
+
+                    let host = inventory.hosts.entry(name).or_default();
+                    host.groups.insert(group.clone());
+
+                    for word in words {
+                        let (key, value) = word
+                            .split_once('=')
+                            .ok_or_else(|| anyhow!("expected `key=value` on line {}", index + 1))?;
+
+                        host.vars.insert(key.to_string(), parse_ini_value(value));
+                    }
+                }
+                Section::Vars => {
+                    let (key, value) = line
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("expected `key=value` on line {}", index + 1))?;
+
+                    inventory
+                        .groups
+                        .entry(group.clone())
+                        .or_default()
+                        .vars
+                        .insert(key.trim().to_string(), parse_ini_value(value.trim()));
+                }
+                Section::Children => {
+                    inventory
+                        .groups
+                        .entry(line.to_string())
+                        .or_default()
+                        .parents
+                        .insert(group.clone());
+                }
+            }
+        }
+
+        Ok(inventory)
+    }
+
+    pub fn parse_yaml(contents: &str) -> Result<Self, anyhow::Error> {
+        let root: Mapping = serde_yaml::from_str(contents).context("failed to parse inventory")?;
+
+        let mut inventory = Self::default();
+
+        for (name, node) in &root {
+            inventory.walk_yaml(name, node, None)?;
+        }
+
+        Ok(inventory)
+    }
+
+    fn walk_yaml(
+        &mut self,
+        name: &Value,
+        node: &Value,
+        parent: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("group names must be strings"))?
+            .to_string();
+
+        let group = self.groups.entry(name.clone()).or_default();
+
+        if let Some(parent) = parent {
+            group.parents.insert(parent.to_string());
+        }
+
+        group.vars.extend(mapping_to_vars(node.get("vars"))?);
+
+        if let Some(hosts) = node.get("hosts").and_then(Value::as_mapping) {
+            for (host, vars) in hosts {
+                let host = host
+                    .as_str()
+                    .ok_or_else(|| anyhow!("host names must be strings"))?;
+
+                let entry = self.hosts.entry(host.to_string()).or_default();
+                entry.groups.insert(name.clone());
+                entry.vars.extend(mapping_to_vars(Some(vars))?);
+            }
+        }
+
+        if let Some(children) = node.get("children").and_then(Value::as_mapping) {
+            for (child, node) in children {
+                self.walk_yaml(child, node, Some(&name))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every group that a host is a member of, either directly or
+    /// through the children of a group, ordered by the precedence of
+    /// their variables, i.e. parents before children and groups on the
+    /// same level by name, like Ansible does.
+    fn memberships<'a>(&'a self, host: &'a Host) -> Vec<&'a str> {
+        let mut groups = BTreeSet::new();
+        let mut queue = host.groups.iter().map(String::as_str).collect::<Vec<_>>();
+
+        while let Some(name) = queue.pop() {
+            if groups.insert(name) {
+                if let Some(group) = self.groups.get(name) {
+                    queue.extend(group.parents.iter().map(String::as_str));
+                }
+            }
+        }
+
+        let mut depths = HashMap::new();
+
+        let mut groups = groups
+            .into_iter()
+            .map(|name| (self.depth(name, &mut depths, 0), name))
+            .collect::<Vec<_>>();
+
+        groups.sort();
+
+        groups.into_iter().map(|(_, name)| name).collect()
+    }
+
+    fn depth<'a>(
+        &'a self,
+        name: &'a str,
+        depths: &mut HashMap<&'a str, usize>,
+        guard: usize,
+    ) -> usize {
+        if let Some(depth) = depths.get(name) {
+            return *depth;
+        }
+
+        // Loops between groups are invalid in Ansible, but should not
+        // lead to infinite recursion here.
+        if guard > self.groups.len() {
+            return 0;
+        }
+
+        let depth = self
+            .groups
+            .get(name)
+            .and_then(|group| {
+                group
+                    .parents
+                    .iter()
+                    .map(|parent| self.depth(parent, depths, guard + 1) + 1)
+                    .max()
+            })
+            .unwrap_or(0);
+
+        depths.insert(name, depth);
+
+        depth
+    }
+
+    /// Convert the inventory into configuration files per client and
+    /// group. Hosts and groups whose names are not valid in pullconf
+    /// are skipped, as are variables that cannot be represented in TOML
+    /// and the connection variables that are specific to Ansible.
+    pub fn convert(&self) -> (BTreeMap<Hostname, Skeleton>, BTreeSet<Hostname>) {
+        let scope = "import";
+
+        let mut groups = BTreeSet::new();
+
+        for name in self.groups.keys() {
+            if name == ALL || name == UNGROUPED {
+                continue;
+            }
+
+            match group_name(name) {
+                Some(group) => {
+                    groups.insert(group);
+                }
+                None => {
+                    warn!(scope, group = name.as_str(); "skipping group as its name is not a valid group name")
+                }
+            }
+        }
+
+        let mut clients = BTreeMap::new();
+
+        for (name, host) in &self.hosts {
+            let hostname = match Hostname::from_str(name) {
+                Ok(hostname) => hostname,
+                Err(_) => {
+                    warn!(scope, host = name.as_str(); "skipping host as its name is not a valid hostname");
+                    continue;
+                }
+            };
+
+            let memberships = self.memberships(host);
+
+            let mut vars = BTreeMap::new();
+
+            for group in [ALL].into_iter().chain(memberships.iter().copied()) {
+                if let Some(group) = self.groups.get(group) {
+                    vars.extend(group.vars.iter());
+                }
+            }
+
+            vars.extend(host.vars.iter());
+
+            let mut skeleton = Skeleton {
+                groups: memberships
+                    .iter()
+                    .filter(|name| **name != ALL && **name != UNGROUPED)
+                    .filter_map(|name| group_name(name))
+                    .map(|group| group.to_string())
+                    .collect(),
+                ..Default::default()
+            };
+
+            for (key, value) in vars {
+                if key.starts_with("ansible_") || key == "hostname" {
+                    continue;
+                }
+
+                match toml::Value::try_from(value) {
+                    Ok(value) => {
+                        skeleton.variables.insert(key.clone(), value);
+                    }
+                    Err(error) => {
+                        warn!(
+                            scope,
+                            client:% = hostname,
+                            variable = key.as_str();
+                            "skipping variable as it cannot be converted to TOML: {}",
+                            error
+                        );
+                    }
+                }
+            }
+
+            clients.insert(hostname, skeleton);
+        }
+
+        (clients, groups)
+    }
+}
+
+/// Write the configuration files of clients and groups to the resource
+/// directory. Existing files are never overwritten. Returns the paths
+/// of the files that were written.
+pub fn write(
+    resources: &Path,
+    inventory: &Path,
+    clients: &BTreeMap<Hostname, Skeleton>,
+    groups: &BTreeSet<Hostname>,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+
+    for (name, skeleton) in clients {
+        let contents = format!(
+            "# Imported from the Ansible inventory {}.\n# Run `pullconfd keygen {}` to add an API key.\n\n{}",
+            inventory.display(),
+            name,
+            toml::to_string(skeleton)?
+        );
+
+        files.push((
+            resources.join("clients").join(format!("{}.toml", name)),
+            contents,
+        ));
+    }
+
+    for name in groups {
+        let contents = format!(
+            "# Imported from the Ansible inventory {}.\n",
+            inventory.display()
+        );
+
+        files.push((
+            resources.join("groups").join(format!("{}.toml", name)),
+            contents,
+        ));
+    }
+
+    let mut written = vec![];
+
+    for (path, contents) in files {
+        if path.exists() {
+            warn!(scope = "import", source:% = path.display(); "skipping file as it already exists");
+            continue;
+        }
+
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Convert the name of an Ansible group to a valid group name, since
+/// Ansible allows underscores and uppercase letters.
+fn group_name(name: &str) -> Option<Hostname> {
+    Hostname::from_str(&name.to_lowercase().replace('_', "-")).ok()
+}
+
+/// Read the variables of a host or group from a file named like the
+/// host or group, with or without a `.yml` or `.yaml` extension, or
+/// from every YAML file in a directory of that name.
+fn read_vars(directory: &Path, name: &str) -> Result<BTreeMap<String, Value>, anyhow::Error> {
+    let mut files = vec![];
+
+    for candidate in [
+        name.to_string(),
+        format!("{}.yml", name),
+        format!("{}.yaml", name),
+    ] {
+        let path = directory.join(candidate);
+
+        if path.is_file() {
+            files.push(path);
+        } else if path.is_dir() {
+            let mut entries = fs::read_dir(&path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            entries.sort();
+
+            files.extend(entries.into_iter().filter(|path| {
+                path.is_file()
+                    && matches!(
+                        path.extension().and_then(|extension| extension.to_str()),
+                        Some("yml" | "yaml")
+                    )
+            }));
+        }
+    }
+
+    let mut vars = BTreeMap::new();
+
+    for path in files {
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let value: Value = serde_yaml::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        vars.extend(mapping_to_vars(Some(&value))?);
+    }
+
+    Ok(vars)
+}
+
+fn mapping_to_vars(value: Option<&Value>) -> Result<BTreeMap<String, Value>, anyhow::Error> {
+    let mut vars = BTreeMap::new();
+
+    if let Some(mapping) = value.and_then(Value::as_mapping) {
+        for (key, value) in mapping {
+            let key = key
+                .as_str()
+                .ok_or_else(|| anyhow!("variable names must be strings"))?;
+
+            vars.insert(key.to_string(), value.clone());
+        }
+    }
+
+    Ok(vars)
+}
+
+/// Values in INI inventories are interpreted like YAML scalars, unless
+/// they are quoted.
+fn parse_ini_value(value: &str) -> Value {
+    for quote in ['"', '\''] {
+        if let Some(value) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return Value::String(value.to_string());
+        }
+    }
+
+    serde_yaml::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+/// Split a line at whitespace that is not enclosed in quotes. Quotes
+/// are kept, so that values can be interpreted afterwards.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                word.push(c);
+            }
+            (c, Some(q)) if c == q => {
+                quote = None;
+                word.push(c);
+            }
+            (c, None) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            (c, _) => word.push(c),
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_ini_inventory() -> Result<(), anyhow::Error> {
+        let inventory = Inventory::parse_ini(
+            r#"
+[web_servers]
+www.example.com http_port=8080 motd="hello world" ansible_user=admin
+
+[web_servers:vars]
+http_port=80
+domain=example.com
+
+[production:children]
+web_servers
+
+[production:vars]
+domain=example.org
+environment=production
+"#,
+        )?;
+
+        let (clients, groups) = inventory.convert();
+
+        assert_eq!(
+            groups
+                .iter()
+                .map(|group| group.to_string())
+                .collect::<Vec<_>>(),
+            vec!["production", "web-servers"]
+        );
+
+        let client = &clients[&Hostname::from_str("www.example.com").unwrap()];
+
+        assert_eq!(client.groups, vec!["production", "web-servers"]);
+        assert_eq!(
+            toml::to_string(&client.variables)?,
+            "domain = \"example.com\"\nenvironment = \"production\"\nhttp_port = 8080\nmotd = \"hello world\"\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_yaml_inventory() -> Result<(), anyhow::Error> {
+        let inventory = Inventory::parse_yaml(
+            r#"
+all:
+  vars:
+    ntp: ntp.example.com
+  children:
+    db:
+      hosts:
+        db1.example.com:
+          port: 5432
+"#,
+        )?;
+
+        let (clients, _) = inventory.convert();
+
+        let client = &clients[&Hostname::from_str("db1.example.com").unwrap()];
+
+        assert_eq!(client.groups, vec!["db"]);
+        assert_eq!(
+            toml::to_string(&client.variables)?,
+            "ntp = \"ntp.example.com\"\nport = 5432\n"
+        );
+
+        Ok(())
+    }
+}
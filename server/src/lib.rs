@@ -0,0 +1,1303 @@
+mod acme;
+mod admin_socket;
+mod audit;
+mod canary;
+mod check_ins;
+mod configuration;
+mod diagnostics;
+mod diff;
+mod env;
+mod export;
+mod facts;
+mod git;
+mod handlers;
+mod history;
+mod http;
+mod import;
+mod lint;
+mod maintenance;
+mod notify;
+mod registrations;
+mod release;
+mod serials;
+mod systemd;
+mod tls;
+mod types;
+
+use crate::{
+    acme::AcmeClient,
+    admin_socket::AdminSocket,
+    audit::AuditLog,
+    canary::{Canary, Pending},
+    check_ins::CheckIns,
+    configuration::Configuration,
+    diagnostics::Diagnostic,
+    diff::GitCheckout,
+    facts::FactStore,
+    git::{GitSource, Revision},
+    history::CatalogHistory,
+    maintenance::Maintenance,
+    notify::{Notification, Notifier},
+    registrations::Registrations,
+    release::Release,
+    serials::CatalogSerials,
+    tls::{Certificate, TlsFiles},
+    types::{AdminToken, ApiKey, Client},
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use common::{error::Terminate, Hostname};
+use log::{debug, error, info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use signal_hook::{consts::signal::*, iterator::Signals};
+use std::{
+    collections::HashMap,
+    fs, io, mem,
+    net::TcpListener,
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, RwLock,
+    },
+    thread,
+    time::Duration,
+};
+use time::OffsetDateTime;
+use toml_edit::DocumentMut;
+
+pub use crate::http::Server;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const APPLICATION: &str = env!("CARGO_PKG_NAME");
+
+/// Pull-based Linux configuration management system, server component.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Load and validate the configuration from $PULLCONF_RESOURCE_DIR
+    /// like on startup or reload, then exit without binding a socket.
+    #[arg(long)]
+    check: bool,
+    /// Together with --check, treat warnings about configuration that has
+    /// no effect (e.g. unreferenced variables or assets) as errors.
+    #[arg(long, requires = "check")]
+    strict: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile the resource catalog of a client and print it to stdout.
+    Render {
+        /// The hostname of the client.
+        hostname: Hostname,
+        /// The output format, either the exact JSON payload that the client
+        /// receives or a summarized table.
+        #[arg(long, value_enum, default_value = "json")]
+        format: Format,
+        /// Directory containing the resource configuration, defaults to
+        /// $PULLCONF_RESOURCE_DIR.
+        #[arg(long)]
+        resource_dir: Option<PathBuf>,
+    },
+    /// Compile two configurations and print which resources are added,
+    /// removed or changed per client.
+    Diff {
+        /// The resource directories of the previous and the new
+        /// configuration. With --against-git only the new resource
+        /// directory may be given, which defaults to $PULLCONF_RESOURCE_DIR.
+        #[arg(num_args = 0..=2)]
+        directories: Vec<PathBuf>,
+        /// Compare against a revision of the git repository that contains
+        /// the new resource directory, e.g. `main` or `HEAD~1`.
+        #[arg(long)]
+        against_git: Option<String>,
+    },
+    /// Generate a random API key for a client, write its hash to the
+    /// client's configuration file and print the key to stdout.
+    Keygen {
+        /// The hostname of the client.
+        hostname: Hostname,
+        /// Directory containing the resource configuration, defaults to
+        /// $PULLCONF_RESOURCE_DIR.
+        #[arg(long)]
+        resource_dir: Option<PathBuf>,
+    },
+    /// Export the inventory of every client, i.e. its groups, variables
+    /// and the number of resources per type. Values of variables whose
+    /// names suggest that they contain secrets are redacted.
+    Export {
+        /// The output format.
+        #[arg(long, value_enum, default_value = "json")]
+        format: ExportFormat,
+        /// Write the inventory to this file instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Directory containing the resource configuration, defaults to
+        /// $PULLCONF_RESOURCE_DIR.
+        #[arg(long)]
+        resource_dir: Option<PathBuf>,
+    },
+    /// Generate client and group configuration files from the inventory
+    /// of another configuration management system.
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+    /// Print a JSON Schema describing the format of configuration files.
+    Schema {
+        /// The kind of configuration file that the schema describes.
+        #[arg(value_enum)]
+        kind: SchemaKind,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Table,
+}
+
+#[derive(Subcommand)]
+enum ImportSource {
+    /// Convert an Ansible inventory in INI or YAML format, including
+    /// variables from the `host_vars` and `group_vars` directories next
+    /// to it. Existing configuration files are not overwritten.
+    Ansible {
+        /// The inventory file.
+        inventory: PathBuf,
+        /// Directory containing the resource configuration, defaults to
+        /// $PULLCONF_RESOURCE_DIR.
+        #[arg(long)]
+        resource_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaKind {
+    Client,
+    Group,
+}
+
+/// Events that are sent to the main thread, which owns the server.
+enum Event {
+    /// Gracefully stop the server and exit.
+    Shutdown,
+    /// Replace the TLS certificate and private key if they changed.
+    ReloadTls,
+}
+
+// Type alias for the state data structure that is shared among threads.
+type SharedAppState = Arc<RwLock<AppState>>;
+
+// Data structure containing shared data that is relevant to all handlers and
+// tasks.
+pub struct AppState {
+    configuration: Configuration,
+    resources: PathBuf,
+    assets: PathBuf,
+    pepper: Option<String>,
+    admin_tokens: HashMap<ApiKey, AdminToken>,
+    admin_token_file: PathBuf,
+    check_ins: Mutex<CheckIns>,
+    facts: Mutex<FactStore>,
+    /// The most recent versions of the resource catalog of every client.
+    history: Mutex<CatalogHistory>,
+    /// The serial of the resource catalog of every client.
+    serials: Mutex<CatalogSerials>,
+    stale_threshold: Duration,
+    git: Option<Arc<GitSource>>,
+    revision: Option<Revision>,
+    canary: Option<Canary>,
+    /// The version and binary of pullconf that clients update themselves
+    /// to, if any.
+    release: Option<Release>,
+    webhook_secret: Option<String>,
+    audit_log: Option<AuditLog>,
+    notifier: Option<Notifier>,
+    /// Whether unknown clients may request to be added to the
+    /// configuration, and the token they must present if any.
+    registration_enabled: bool,
+    registration_token: Option<String>,
+    registrations: Mutex<Registrations>,
+    maintenance: Mutex<Maintenance>,
+    /// The interval at which clients should fetch their catalogs, which
+    /// is sent along with every catalog. The override is set via the
+    /// admin API and takes precedence over every other interval.
+    poll_interval: Option<Duration>,
+    poll_interval_override: Mutex<Option<Duration>>,
+    max_header_size: usize,
+    max_body_size: u64,
+}
+
+impl AppState {
+    pub fn initialize() -> Result<Self, Terminate> {
+        let assets = env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_ASSET_DIR",
+            "/etc/pullconfd/assets",
+        )?;
+
+        let pepper = env::parse_secret("PULLCONF_API_KEY_PEPPER")?;
+
+        let webhook_secret = env::parse_secret("PULLCONF_WEBHOOK_SECRET")?;
+
+        let audit_log = AuditLog::from_env()?;
+
+        let notifier = Notifier::from_env()?;
+
+        let max_header_size = env::parse_count("PULLCONF_MAX_HEADER_SIZE", 8192)?;
+
+        let max_body_size = env::parse_count("PULLCONF_MAX_BODY_SIZE", 1024 * 1024)? as u64;
+
+        let admin_token_file = env::parse_path(
+            env::FileType::File,
+            "PULLCONF_ADMIN_TOKEN_FILE",
+            "/etc/pullconfd/admin-tokens.toml",
+        )?;
+
+        let admin_tokens = AdminToken::load(&admin_token_file)?;
+
+        let state_directory = env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_STATE_DIR",
+            "/var/lib/pullconfd",
+        )?;
+
+        let check_ins = CheckIns::load(state_directory.join("check-ins.json"))?;
+
+        let facts = FactStore::load(state_directory.join("facts.json"))?;
+
+        let history = CatalogHistory::load(
+            state_directory.join("catalog-history.json"),
+            env::parse_count("PULLCONF_CATALOG_HISTORY", 20)?,
+        )?;
+
+        let serials = CatalogSerials::load(state_directory.join("catalog-serials.json"))?;
+
+        let registration_enabled = env::parse_bool("PULLCONF_ENABLE_REGISTRATION", false)?;
+
+        let registration_token = env::parse_secret("PULLCONF_REGISTRATION_TOKEN")?;
+
+        let registrations = Registrations::load(state_directory.join("registrations.json"))?;
+
+        let maintenance = Maintenance::load(state_directory.join("maintenance.json"))?;
+
+        // The resource directory is either managed by pullconfd itself as
+        // a checkout of a git repository or by other means.
+        let git = GitSource::from_env(&state_directory)?.map(Arc::new);
+
+        let (resources, revision) = match &git {
+            Some(source) => {
+                let revision = git::initialize(source)?;
+                (revision.path.clone(), Some(revision))
+            }
+            None => {
+                let resources = env::parse_path(
+                    env::FileType::Directory,
+                    "PULLCONF_RESOURCE_DIR",
+                    "/etc/pullconfd/resources",
+                )?;
+                (resources, None)
+            }
+        };
+
+        let stale_threshold = env::parse_duration("PULLCONF_STALE_CLIENT_THRESHOLD", 3600)?;
+
+        let canary = Canary::from_env()?;
+
+        let release = Release::from_env()?;
+
+        let poll_interval = env::parse_optional_duration("PULLCONF_POLL_INTERVAL")?;
+
+        let mut configuration = Configuration::try_from(&resources)?;
+
+        lint::unreferenced_assets(&mut configuration, &assets);
+
+        if let Some(canary) = &canary {
+            if !configuration.groups.contains_key(&canary.group) {
+                warn!(
+                    scope = "main",
+                    group:% = canary.group;
+                    "canary group does not exist, so every client keeps the stable configuration until changes are promoted"
+                );
+            }
+        }
+
+        if pepper.is_none() {
+            for client in configuration
+                .clients
+                .values()
+                .filter(|client| !client.api_key.is_legacy())
+            {
+                warn!(
+                    scope = "main",
+                    client:% = client.name();
+                    "client uses an HMAC-SHA256 API key hash, but PULLCONF_API_KEY_PEPPER is not set, so the client will fail to authenticate"
+                );
+            }
+        }
+
+        let state = AppState {
+            configuration,
+            resources,
+            assets,
+            pepper,
+            admin_tokens,
+            admin_token_file,
+            check_ins: Mutex::new(check_ins),
+            facts: Mutex::new(facts),
+            history: Mutex::new(history),
+            serials: Mutex::new(serials),
+            stale_threshold,
+            git,
+            revision,
+            canary,
+            release,
+            webhook_secret,
+            audit_log,
+            notifier,
+            registration_enabled,
+            registration_token,
+            registrations: Mutex::new(registrations),
+            maintenance: Mutex::new(maintenance),
+            poll_interval,
+            poll_interval_override: Mutex::new(None),
+            max_header_size,
+            max_body_size,
+        };
+
+        Ok(state)
+    }
+
+    /// Replace the configuration with one that was loaded successfully.
+    /// If canary rollouts are enabled and no canary is pending yet, the
+    /// current configuration is kept as the stable configuration for
+    /// every client outside the canary group until the new one is
+    /// promoted. A pending canary is simply replaced.
+    pub fn activate(&mut self, configuration: Configuration) {
+        let previous = mem::replace(&mut self.configuration, configuration);
+
+        if let Some(canary) = self
+            .canary
+            .as_mut()
+            .filter(|canary| canary.pending.is_none())
+        {
+            info!(scope = "canary", group:% = canary.group; "configuration is only served to the canary group until it is promoted");
+
+            canary.pending = Some(Pending {
+                stable: previous,
+                commit: self
+                    .revision
+                    .as_ref()
+                    .map(|revision| revision.commit.clone()),
+                timestamp: OffsetDateTime::now_utc(),
+            });
+        }
+    }
+
+    /// The interval at which a client should fetch its catalog, if any.
+    pub fn poll_interval(&self, client: &Client) -> Option<Duration> {
+        self.poll_interval_override
+            .lock()
+            .unwrap()
+            .or(client.poll_interval)
+            .or(self.poll_interval)
+    }
+
+    /// Re-read the configuration and admin tokens from disk. Either is
+    /// only replaced if it was loaded successfully, otherwise the errors
+    /// that were encountered are returned and the current data is kept.
+    pub fn reload(&mut self, scope: &str) -> Result<(), Vec<Diagnostic>> {
+        let mut failed = false;
+
+        let (configuration, mut errors) =
+            diagnostics::collect(|| Configuration::try_from(&self.resources));
+
+        match configuration {
+            Ok(configuration) => {
+                info!(scope; "successfully reloaded configuration");
+                self.activate(configuration);
+            }
+            Err(_) => {
+                warn!(scope; "keeping the current configuration as reload failed");
+                failed = true;
+            }
+        }
+
+        let (admin_tokens, admin_token_errors) =
+            diagnostics::collect(|| AdminToken::load(&self.admin_token_file));
+
+        match admin_tokens {
+            Ok(admin_tokens) => {
+                info!(scope; "successfully reloaded admin tokens");
+                self.admin_tokens = admin_tokens;
+            }
+            Err(_) => {
+                warn!(scope; "keeping the current admin tokens as reload failed");
+                failed = true;
+            }
+        }
+
+        errors.extend(admin_token_errors);
+
+        if let Some(notifier) = &self.notifier {
+            notifier.reload(if failed {
+                Err(format!(
+                    "reload failed with {} errors: {}",
+                    errors.len(),
+                    errors
+                        .iter()
+                        .map(|error| error.message.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ))
+            } else {
+                Ok(())
+            });
+        }
+
+        if failed {
+            Err(errors)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Load the configuration from resource files below a directory, e.g.
+    /// `clients/foo.example.com.toml`, and keep the state in the same
+    /// directory. Every optional feature is disabled.
+    pub fn fixture(directory: &std::path::Path, files: &[(&str, &str)]) -> Self {
+        for path in ["resources/clients", "resources/groups", "assets", "state"] {
+            fs::create_dir_all(directory.join(path)).unwrap();
+        }
+
+        for (path, contents) in files {
+            fs::write(directory.join("resources").join(path), contents).unwrap();
+        }
+
+        let resources = directory.join("resources");
+        let state = directory.join("state");
+
+        AppState {
+            configuration: Configuration::try_from(&resources).unwrap(),
+            resources,
+            assets: directory.join("assets"),
+            pepper: None,
+            admin_tokens: HashMap::new(),
+            admin_token_file: directory.join("admin-tokens.toml"),
+            check_ins: Mutex::new(CheckIns::load(state.join("check-ins.json")).unwrap()),
+            facts: Mutex::new(FactStore::load(state.join("facts.json")).unwrap()),
+            history: Mutex::new(
+                CatalogHistory::load(state.join("catalog-history.json"), 20).unwrap(),
+            ),
+            serials: Mutex::new(CatalogSerials::load(state.join("catalog-serials.json")).unwrap()),
+            stale_threshold: Duration::from_secs(3600),
+            git: None,
+            revision: None,
+            canary: None,
+            release: None,
+            webhook_secret: None,
+            audit_log: None,
+            notifier: None,
+            registration_enabled: false,
+            registration_token: None,
+            registrations: Mutex::new(
+                Registrations::load(state.join("registrations.json")).unwrap(),
+            ),
+            maintenance: Mutex::new(Maintenance::load(state.join("maintenance.json")).unwrap()),
+            poll_interval: None,
+            poll_interval_override: Mutex::new(None),
+            max_header_size: 8192,
+            max_body_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Validate the configuration that the server would load on startup
+/// or reload and return a non-zero exit code if it is invalid or, in
+/// strict mode, if it contains configuration that has no effect.
+fn check(strict: bool) -> Result<(), Terminate> {
+    let scope = "check";
+
+    let resources = env::parse_path(
+        env::FileType::Directory,
+        "PULLCONF_RESOURCE_DIR",
+        "/etc/pullconfd/resources",
+    )?;
+
+    let admin_token_file = env::parse_path(
+        env::FileType::File,
+        "PULLCONF_ADMIN_TOKEN_FILE",
+        "/etc/pullconfd/admin-tokens.toml",
+    )?;
+
+    let assets = env::parse_path(
+        env::FileType::Directory,
+        "PULLCONF_ASSET_DIR",
+        "/etc/pullconfd/assets",
+    )?;
+
+    let admin_tokens = AdminToken::load(&admin_token_file)?;
+
+    let mut configuration = Configuration::try_from(&resources)?;
+
+    lint::unreferenced_assets(&mut configuration, &assets);
+
+    if strict && !configuration.lints.is_empty() {
+        error!(
+            scope,
+            warnings = configuration.lints.len();
+            "configuration is valid, but contains warnings and --strict is set"
+        );
+
+        return Err(Terminate);
+    }
+
+    info!(
+        scope,
+        clients = configuration.clients.len(),
+        groups = configuration.groups.len(),
+        admin_tokens = admin_tokens.len();
+        "configuration is valid"
+    );
+
+    Ok(())
+}
+
+/// Compile the resource catalog of a single client from the given or
+/// default resource directory and print it to stdout.
+fn render(
+    hostname: &Hostname,
+    format: Format,
+    resource_dir: Option<PathBuf>,
+) -> Result<(), Terminate> {
+    let scope = "render";
+
+    let resources = match resource_dir {
+        Some(path) => path,
+        None => env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_RESOURCE_DIR",
+            "/etc/pullconfd/resources",
+        )?,
+    };
+
+    let configuration = Configuration::try_from(&resources)?;
+
+    let client = configuration.clients.get(hostname).ok_or_else(|| {
+        error!(scope, client:% = hostname; "client is not known");
+        Terminate
+    })?;
+
+    match format {
+        Format::Json => {
+            let (bytes, _) = client.serialize_catalog(None);
+
+            println!("{}", String::from_utf8_lossy(&bytes));
+        }
+        Format::Table => {
+            let mut resources = client.resources.iter().collect::<Vec<_>>();
+
+            resources.sort_by_key(|resource| resource.repr());
+
+            println!("ID                                    RESOURCE");
+
+            for resource in resources {
+                println!("{}  {}", resource.id(), resource.repr());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compile the configurations of two resource directories, or of a
+/// resource directory and a revision of its git repository, and print
+/// the differences of their resource catalogs.
+fn diff(directories: Vec<PathBuf>, against_git: Option<String>) -> Result<(), Terminate> {
+    let scope = "diff";
+
+    let (old, new, _checkout) = match (directories.as_slice(), against_git) {
+        ([old, new], None) => (old.clone(), new.clone(), None),
+        ([], Some(revision)) | ([_], Some(revision)) => {
+            let new = match directories.first() {
+                Some(path) => path.clone(),
+                None => env::parse_path(
+                    env::FileType::Directory,
+                    "PULLCONF_RESOURCE_DIR",
+                    "/etc/pullconfd/resources",
+                )?,
+            };
+
+            let checkout = GitCheckout::new(&new, &revision).map_err(|error| {
+                error!(scope, revision = revision.as_str(); "failed to check out revision: {:#}", error);
+                Terminate
+            })?;
+
+            (checkout.resources.clone(), new, Some(checkout))
+        }
+        _ => {
+            error!(scope; "either two resource directories or --against-git and at most one resource directory must be given");
+            return Err(Terminate);
+        }
+    };
+
+    let old = Configuration::try_from(&old)?;
+    let new = Configuration::try_from(&new)?;
+
+    diff::print(&diff::compare(&old, &new));
+
+    Ok(())
+}
+
+/// Compile the configuration and write the inventory of every client
+/// to stdout or a file.
+fn export(
+    format: ExportFormat,
+    output: Option<PathBuf>,
+    resource_dir: Option<PathBuf>,
+) -> Result<(), Terminate> {
+    let scope = "export";
+
+    let resources = match resource_dir {
+        Some(path) => path,
+        None => env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_RESOURCE_DIR",
+            "/etc/pullconfd/resources",
+        )?,
+    };
+
+    let configuration = Configuration::try_from(&resources)?;
+    let clients = export::inventory(&configuration);
+
+    let contents = match format {
+        ExportFormat::Json => {
+            export::to_json(&clients)
+                .map(|json| json + "\n")
+                .map_err(|error| {
+                    error!(scope; "failed to serialize inventory: {}", error);
+                    Terminate
+                })?
+        }
+        ExportFormat::Csv => export::to_csv(&clients),
+    };
+
+    export::write(output.as_ref(), &contents).map_err(|error| {
+        error!(scope; "failed to write inventory: {}", error);
+        Terminate
+    })
+}
+
+/// Generate configuration files from the inventory of another
+/// configuration management system.
+fn import(source: ImportSource) -> Result<(), Terminate> {
+    let scope = "import";
+
+    let ImportSource::Ansible {
+        inventory,
+        resource_dir,
+    } = source;
+
+    let resources = match resource_dir {
+        Some(path) => path,
+        None => env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_RESOURCE_DIR",
+            "/etc/pullconfd/resources",
+        )?,
+    };
+
+    let (clients, groups) = import::Inventory::load(&inventory)
+        .map_err(|error| {
+            error!(scope, source:% = inventory.display(); "failed to load Ansible inventory: {:#}", error);
+            Terminate
+        })?
+        .convert();
+
+    let written = import::write(&resources, &inventory, &clients, &groups).map_err(|error| {
+        error!(scope; "failed to write configuration files: {:#}", error);
+        Terminate
+    })?;
+
+    for path in &written {
+        println!("{}", path.display());
+    }
+
+    info!(
+        scope;
+        "wrote {} configuration files for {} clients and {} groups, run `pullconfd keygen` for every client to add an API key",
+        written.len(),
+        clients.len(),
+        groups.len()
+    );
+
+    Ok(())
+}
+
+/// Generate a random API key for a client and write its hash to the
+/// client's configuration file, which is created if it does not exist.
+/// The key itself is only printed to stdout and not stored anywhere. If
+/// the admin socket is configured, the running server is reloaded.
+fn keygen(hostname: &Hostname, resource_dir: Option<PathBuf>) -> Result<(), Terminate> {
+    let scope = "keygen";
+
+    let resources = match resource_dir {
+        Some(path) => path,
+        None => env::parse_path(
+            env::FileType::Directory,
+            "PULLCONF_RESOURCE_DIR",
+            "/etc/pullconfd/resources",
+        )?,
+    };
+
+    let pepper = env::parse_secret("PULLCONF_API_KEY_PEPPER")?;
+
+    let key = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect::<String>();
+
+    let hash = match &pepper {
+        Some(pepper) => ApiKey::hmac(&key, pepper),
+        None => ApiKey::encrypt(&key),
+    };
+
+    let path = resources.join("clients").join(format!("{}.toml", hostname));
+
+    // The file is edited instead of rewritten, so that comments and
+    // formatting of an existing configuration are preserved.
+    let mut document = match fs::read_to_string(&path) {
+        Ok(contents) => contents.parse::<DocumentMut>().map_err(|error| {
+            error!(scope, source:% = path.display(); "failed to parse client configuration file: {}", error);
+            Terminate
+        })?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => DocumentMut::new(),
+        Err(error) => {
+            error!(scope, source:% = path.display(); "failed to read client configuration file: {}", error);
+            return Err(Terminate);
+        }
+    };
+
+    document["api-key"] = toml_edit::value(&*hash);
+
+    if let Err(error) = fs::write(&path, document.to_string()) {
+        error!(scope, source:% = path.display(); "failed to write client configuration file: {}", error);
+        return Err(Terminate);
+    }
+
+    info!(
+        scope,
+        client:% = hostname,
+        source:% = path.display();
+        "wrote hash of new API key to client configuration file"
+    );
+
+    let socket = env::parse_path(env::FileType::Socket, "PULLCONF_ADMIN_SOCKET", "")?;
+
+    if socket.as_os_str().is_empty() {
+        warn!(scope; "reload the server to apply the new API key");
+    } else {
+        match admin_socket::reload(&socket) {
+            Ok(true) => info!(scope; "successfully reloaded server"),
+            Ok(false) => warn!(scope; "server kept the current configuration as reload failed"),
+            Err(error) => warn!(scope; "failed to reload server via admin socket: {}", error),
+        }
+    }
+
+    println!("{}", key);
+
+    Ok(())
+}
+
+/// Print the JSON Schema of either client or group configuration files
+/// to stdout. Both schemas include the parameters of every resource type.
+fn schema(kind: SchemaKind) -> Result<(), Terminate> {
+    let scope = "schema";
+
+    let schema = match kind {
+        SchemaKind::Client => schemars::schema_for!(types::client::deserialize::Client),
+        SchemaKind::Group => schemars::schema_for!(types::Group),
+    };
+
+    match serde_json::to_string_pretty(&schema) {
+        Ok(json) => {
+            println!("{}", json);
+            Ok(())
+        }
+        Err(error) => {
+            error!(scope; "failed to serialize schema: {}", error);
+            Err(Terminate)
+        }
+    }
+}
+
+/// Serve requests on a listener with the configuration, state and TLS
+/// certificate from the environment like `pullconfd` does, but without
+/// handling signals or running any of its periodic tasks. This runs
+/// pullconfd in-process, e.g. in the integration tests, until the returned
+/// server is stopped.
+pub fn serve(listener: TcpListener) -> Result<Server, Terminate> {
+    let state = Arc::new(RwLock::new(AppState::initialize()?));
+
+    let certificate = Certificate::new(TlsFiles::from_env()?.read()?);
+
+    let config = tls::server_config(certificate)?;
+
+    Server::start(vec![listener], &state, config, 4, http::Limits::from_env()?)
+}
+
+/// Run pullconfd with the arguments of this process.
+pub fn main() -> ExitCode {
+    let scope = "main";
+
+    let cli = Cli::parse();
+
+    // Initialize structured logging.
+    let log_target = std::env::var("PULLCONF_LOG_TARGET")
+        .ok()
+        .unwrap_or("stderr".to_string());
+    let log_format = std::env::var("PULLCONF_LOG_FORMAT")
+        .ok()
+        .unwrap_or("logfmt".to_string());
+    if log_target == "syslog" {
+        if let Err(error) = common::syslog::Syslog::init(APPLICATION, VERSION) {
+            eprintln!("failed to initialize syslog logging: {}", error);
+            return ExitCode::FAILURE;
+        }
+    } else if log_target != "stderr" {
+        eprintln!("unknown log target {}", log_target);
+        return ExitCode::FAILURE;
+    } else if log_format == "logfmt" {
+        std_logger::Config::logfmt()
+            .with_kvs(&[("application", APPLICATION), ("version", VERSION)])
+            .with_call_location(false)
+            .init()
+    } else if log_format == "json" {
+        std_logger::Config::json()
+            .with_kvs(&[("application", APPLICATION), ("version", VERSION)])
+            .with_call_location(false)
+            .init()
+    } else {
+        eprintln!("unknown log format {}", log_format);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(command) = cli.command {
+        let result = match command {
+            Command::Render {
+                hostname,
+                format,
+                resource_dir,
+            } => render(&hostname, format, resource_dir),
+            Command::Diff {
+                directories,
+                against_git,
+            } => diff(directories, against_git),
+            Command::Keygen {
+                hostname,
+                resource_dir,
+            } => keygen(&hostname, resource_dir),
+            Command::Export {
+                format,
+                output,
+                resource_dir,
+            } => export(format, output, resource_dir),
+            Command::Import { source } => import(source),
+            Command::Schema { kind } => schema(kind),
+        };
+
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => error.into(),
+        };
+    }
+
+    if cli.check {
+        return match check(cli.strict) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => error.into(),
+        };
+    }
+
+    // Initialize the shared data structure.
+    let state = {
+        match AppState::initialize() {
+            Ok(state) => Arc::new(RwLock::new(state)),
+            Err(error) => return error.into(),
+        }
+    };
+
+    let sockets = match env::parse_sockets("PULLCONF_LISTEN_ON", "127.0.0.1:443") {
+        Ok(sockets) => sockets,
+        Err(error) => return error.into(),
+    };
+
+    // The certificate is either obtained from an ACME certificate
+    // authority by pullconfd itself or managed by other means.
+    let acme = match env::parse_path(
+        env::FileType::Directory,
+        "PULLCONF_STATE_DIR",
+        "/var/lib/pullconfd",
+    )
+    .and_then(|state_directory| AcmeClient::from_env(&state_directory))
+    {
+        Ok(acme) => acme,
+        Err(error) => return error.into(),
+    };
+
+    let tls = match &acme {
+        Some(acme) => acme
+            .renew()
+            .map(|_| TlsFiles::new(acme.certificate.clone(), acme.key.clone())),
+        None => TlsFiles::from_env(),
+    };
+
+    let mut tls = match tls {
+        Ok(tls) => tls,
+        Err(error) => return error.into(),
+    };
+
+    let certificate = match tls.read() {
+        Ok(key) => Certificate::new(key),
+        Err(error) => return error.into(),
+    };
+
+    let config = match tls::server_config(certificate.clone()) {
+        Ok(config) => config,
+        Err(error) => return error.into(),
+    };
+
+    // Requests are processed by a fixed number of worker threads instead
+    // of a new thread per request, which bounds the resources that are
+    // consumed when many clients connect at the same time.
+    let workers = match env::parse_count("PULLCONF_WORKER_THREADS", 64) {
+        Ok(workers) => workers,
+        Err(error) => return error.into(),
+    };
+
+    let limits = match http::Limits::from_env() {
+        Ok(limits) => limits,
+        Err(error) => return error.into(),
+    };
+
+    // Listen for incoming connections on the sockets that are passed by a
+    // systemd socket unit, which keeps accepting connections while
+    // pullconfd restarts, or bind to the configured sockets otherwise.
+    let listeners = match systemd::listeners() {
+        Ok(Some(listeners)) => {
+            info!(scope, sockets = listeners.len(); "using sockets passed by systemd, ignoring PULLCONF_LISTEN_ON");
+            Ok(listeners)
+        }
+        Ok(None) => Server::bind(&sockets),
+        Err(error) => Err(error),
+    };
+
+    let server = match listeners
+        .and_then(|listeners| Server::start(listeners, &state, config, workers, limits))
+    {
+        Ok(server) => server,
+        Err(error) => return error.into(),
+    };
+
+    // Expose the admin API to local tooling via a Unix domain socket.
+    match AdminSocket::from_env() {
+        Ok(Some(socket)) => {
+            let _state = state.clone();
+
+            thread::spawn(move || socket.serve(_state));
+        }
+        Ok(None) => (),
+        Err(error) => return error.into(),
+    }
+
+    // The configuration has been validated and all sockets are bound, so
+    // the service manager can consider the service started.
+    systemd::notify("READY=1\nSTATUS=accepting connections");
+
+    let (events, receiver) = mpsc::channel();
+
+    // Create another thread which listens for incoming termination signals
+    // and sends a message to the main thread to initiate graceful shutdown.
+    // Also listen for SIGHUP which prompts a configuration reload and a
+    // reload of the TLS certificate.
+    let _state = state.clone();
+
+    thread::spawn(move || {
+        let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP]).unwrap();
+
+        let scope = "signals";
+
+        'outer: loop {
+            if let Some(signal) = signals.pending().next() {
+                debug!(scope, signal; "received signal");
+
+                match signal {
+                    SIGTERM | SIGINT => {
+                        if let Err(error) = events.send(Event::Shutdown) {
+                            error!(scope, signal; "failed to forward shutdown signal for graceful shutdown: {}", error);
+                        }
+
+                        break 'outer;
+                    }
+                    SIGHUP => {
+                        let mut state = match _state.write() {
+                            Ok(s) => s,
+                            Err(error) => {
+                                error!(scope, signal; "failed to acquire write access to reload shared application state: {}", error);
+                                continue;
+                            }
+                        };
+
+                        systemd::notify("RELOADING=1\nSTATUS=reloading configuration");
+
+                        match state.reload(scope) {
+                            Ok(()) => systemd::notify("READY=1\nSTATUS=accepting connections"),
+                            Err(_) => systemd::notify(
+                                "READY=1\nSTATUS=accepting connections, keeping the previous configuration as reload failed",
+                            ),
+                        }
+
+                        if let Err(error) = events.send(Event::ReloadTls) {
+                            error!(scope, signal; "failed to forward reload of TLS certificate: {}", error);
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+
+    // Create another thread which periodically checks if any client has not
+    // fetched its resource catalog for longer than the configured threshold.
+    let _state = state.clone();
+
+    thread::spawn(move || {
+        let scope = "check-ins";
+
+        loop {
+            thread::sleep(Duration::from_secs(60));
+
+            let state = match _state.read() {
+                Ok(s) => s,
+                Err(error) => {
+                    error!(scope; "failed to acquire read access to shared application state: {}", error);
+                    continue;
+                }
+            };
+
+            match state.check_ins.lock() {
+                Ok(mut check_ins) => {
+                    let stale = check_ins
+                        .detect_stale(state.configuration.clients.keys(), state.stale_threshold);
+
+                    if let Some(notifier) = &state.notifier {
+                        for client in stale {
+                            notifier.send(Notification::new(
+                                notify::Event::ClientStale,
+                                format!(
+                                    "client has not checked in for more than {} seconds",
+                                    state.stale_threshold.as_secs()
+                                ),
+                                Some(client),
+                            ));
+                        }
+                    }
+                }
+                Err(error) => {
+                    error!(scope; "failed to acquire access to client check-ins: {}", error);
+                }
+            };
+        }
+    });
+
+    // Create another thread which periodically, or when triggered by a
+    // webhook, fetches the git repository that manages the resource
+    // directory, if any, and switches to new revisions once they have
+    // been validated.
+    let _state = state.clone();
+
+    if let Some(source) = state.read().ok().and_then(|state| state.git.clone()) {
+        thread::spawn(move || loop {
+            source.wait();
+
+            let _ = git::sync(&_state, &source);
+        });
+    }
+
+    // Create another thread which periodically renews the certificate if
+    // it is obtained via ACME. The renewed certificate is then picked up
+    // like any other change of the certificate file.
+    if let Some(acme) = acme {
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(12 * 60 * 60));
+
+            let _ = acme.renew();
+        });
+    }
+
+    // Wait for events and periodically check if the TLS certificate or
+    // private key changed. New connections are then served with the new
+    // certificate, while the sockets and open connections are kept.
+    loop {
+        let event = match receiver.recv_timeout(Duration::from_secs(30)) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) if tls.changed() => Event::ReloadTls,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => Event::Shutdown,
+        };
+
+        match event {
+            Event::Shutdown => {
+                systemd::notify("STOPPING=1");
+                server.stop();
+                break;
+            }
+            Event::ReloadTls => match tls.read() {
+                Ok(key) => {
+                    certificate.replace(key);
+                    info!(scope; "successfully reloaded TLS certificate");
+                }
+                Err(_) => warn!(scope; "keeping the current TLS certificate as reload failed"),
+            },
+        }
+    }
+
+    info!(scope; "shutdown");
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_api_key_hashes() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-keygen-{}", std::process::id()));
+
+        fs::create_dir_all(directory.join("clients")).unwrap();
+
+        let existing = directory.join("clients/web.example.com.toml");
+
+        fs::write(
+            &existing,
+            "# web server\napi-key = \"old\"\ngroups = [\"web\"]\n",
+        )
+        .unwrap();
+
+        let web = keygen(&"web.example.com".parse().unwrap(), Some(directory.clone()));
+        let db = keygen(&"db.example.com".parse().unwrap(), Some(directory.clone()));
+
+        let existing = fs::read_to_string(&existing).unwrap();
+        let created = fs::read_to_string(directory.join("clients/db.example.com.toml")).unwrap();
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        let hash = |contents: &str| {
+            contents.parse::<DocumentMut>().unwrap()["api-key"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(web.is_ok());
+        assert!(db.is_ok());
+
+        // Comments and other settings of an existing file are kept.
+        assert!(existing.starts_with("# web server\n"));
+        assert!(existing.contains("groups = [\"web\"]"));
+
+        for hash in [hash(&existing), hash(&created)] {
+            assert_eq!(hash.len(), 64);
+            assert!(hash.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+
+        assert_ne!(hash(&existing), hash(&created));
+    }
+
+    #[test]
+    fn check_configurations() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-check-{}", std::process::id()));
+
+        AppState::fixture(&directory, &[]);
+
+        fs::write(directory.join("admin-tokens.toml"), "").unwrap();
+
+        for (variable, path) in [
+            ("PULLCONF_RESOURCE_DIR", "resources"),
+            ("PULLCONF_ADMIN_TOKEN_FILE", "admin-tokens.toml"),
+            ("PULLCONF_ASSET_DIR", "assets"),
+        ] {
+            std::env::set_var(variable, directory.join(path));
+        }
+
+        let valid = check(true);
+
+        // An asset that no client references is a warning.
+        fs::write(directory.join("assets/unused"), "").unwrap();
+
+        let warned = check(false);
+        let strict = check(true);
+
+        for variable in [
+            "PULLCONF_RESOURCE_DIR",
+            "PULLCONF_ADMIN_TOKEN_FILE",
+            "PULLCONF_ASSET_DIR",
+        ] {
+            std::env::remove_var(variable);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(valid.is_ok());
+        assert!(warned.is_ok());
+        assert!(strict.is_err());
+    }
+
+    #[test]
+    fn render_catalogs() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-render-{}", std::process::id()));
+
+        AppState::fixture(
+            &directory,
+            &[(
+                "clients/web.example.com.toml",
+                &format!(
+                    "api-key = \"{}\"\n\n[[resources]]\ntype = \"file\"\npath = \"/etc/motd\"\n",
+                    "a".repeat(64)
+                ),
+            )],
+        );
+
+        let resources = Some(directory.join("resources"));
+        let web = "web.example.com".parse::<Hostname>().unwrap();
+
+        let json = render(&web, Format::Json, resources.clone());
+        let table = render(&web, Format::Table, resources.clone());
+        let unknown = render(&"db.example.com".parse().unwrap(), Format::Json, resources);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(json.is_ok());
+        assert!(table.is_ok());
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::configuration::Configuration;
+use log::warn;
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    static REFERENCED: RefCell<Option<HashSet<String>>> = const { RefCell::new(None) };
+}
+
+/// Run a function and collect the name of every variable that is
+/// resolved on the current thread while it runs.
+pub fn track_variables<T>(f: impl FnOnce() -> T) -> (T, HashSet<String>) {
+    let previous = REFERENCED.with(|referenced| referenced.borrow_mut().replace(HashSet::new()));
+
+    let value = f();
+
+    let referenced = REFERENCED.with(|referenced| {
+        let mut referenced = referenced.borrow_mut();
+        let variables = referenced.take().unwrap_or_default();
+        *referenced = previous;
+        variables
+    });
+
+    (value, referenced)
+}
+
+/// Record that a variable has been resolved, if variables are currently
+/// being tracked on this thread.
+pub fn reference_variable(name: &str) {
+    REFERENCED.with(|referenced| {
+        if let Some(referenced) = referenced.borrow_mut().as_mut() {
+            referenced.insert(name.to_string());
+        }
+    })
+}
+
+/// Warn about every file in the asset directory that is neither the
+/// source of a `file` resource nor matched by the asset patterns of any
+/// client, and record each warning as a lint of the configuration.
+pub fn unreferenced_assets(configuration: &mut Configuration, assets: &Path) {
+    let scope = "validation";
+
+    let mut files = vec![];
+
+    collect_files(assets, &mut files);
+
+    files.sort();
+
+    for file in files {
+        let path = match file.strip_prefix(assets) {
+            Ok(path) => format!("/{}", path.display()),
+            Err(_) => continue,
+        };
+
+        let referenced = configuration.clients.values().any(|client| {
+            client
+                .resources
+                .iter()
+                .filter_map(|resource| resource.as_file())
+                .filter_map(|file| file.parameters.source.as_ref())
+                .any(|source| source.to_str() == Some(path.as_str()))
+                || client.assets.iter().any(|pattern| pattern.matches(&path))
+        });
+
+        if !referenced {
+            warn!(
+                scope,
+                source:% = file.display();
+                "asset is not referenced by any client"
+            );
+
+            configuration
+                .lints
+                .push(format!("asset `{}` is not referenced by any client", path));
+        }
+    }
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_referenced_variables() {
+        reference_variable("ignored");
+
+        let ((), referenced) = track_variables(|| {
+            reference_variable("foo");
+            reference_variable("foo");
+        });
+
+        assert_eq!(referenced, HashSet::from(["foo".to_string()]));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::types::Client;
+use common::{error::Terminate, Hostname};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::ErrorKind,
+    path::PathBuf,
+};
+use time::OffsetDateTime;
+
+/// The reason why a client was put into maintenance mode.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Freeze {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub reason: String,
+    /// The name of the admin token that froze the client.
+    pub admin: String,
+}
+
+/// Clients in maintenance mode keep receiving the catalog that was
+/// active when they were frozen, regardless of configuration changes.
+/// Only the list of frozen clients is persisted to disk, so after a
+/// restart frozen clients receive the catalog that was loaded on
+/// startup.
+#[derive(Debug)]
+pub struct Maintenance {
+    path: PathBuf,
+    entries: BTreeMap<Hostname, Freeze>,
+    catalogs: HashMap<Hostname, Client>,
+}
+
+impl Maintenance {
+    /// Read previously persisted frozen clients from a file. A missing
+    /// file means that no client is frozen.
+    pub fn load(path: PathBuf) -> Result<Self, Terminate> {
+        let scope = "maintenance";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize frozen clients: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "maintenance file does not exist"
+                );
+
+                BTreeMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read frozen clients: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self {
+            path,
+            entries,
+            catalogs: HashMap::new(),
+        })
+    }
+
+    pub fn entries(&self) -> &BTreeMap<Hostname, Freeze> {
+        &self.entries
+    }
+
+    /// Freeze a client with its current catalog and persist the list of
+    /// frozen clients to disk. Freezing a client that is already frozen
+    /// only updates the reason.
+    pub fn freeze(&mut self, client: &Client, freeze: Freeze) -> Result<(), anyhow::Error> {
+        self.catalogs
+            .entry(client.name().clone())
+            .or_insert_with(|| client.clone());
+
+        self.entries.insert(client.name().clone(), freeze);
+        self.save()
+    }
+
+    /// Unfreeze a client and persist the list of frozen clients to disk.
+    pub fn thaw(&mut self, name: &Hostname) -> Result<Option<Freeze>, anyhow::Error> {
+        self.catalogs.remove(name);
+
+        let freeze = self.entries.remove(name);
+
+        if freeze.is_some() {
+            self.save()?;
+        }
+
+        Ok(freeze)
+    }
+
+    /// Return the frozen variant of a client, or the client itself if
+    /// it is not frozen. Clients that were frozen before a restart are
+    /// frozen with the catalog they receive first.
+    pub fn resolve(&mut self, client: &Client) -> Client {
+        if self.entries.contains_key(client.name()) {
+            self.catalogs
+                .entry(client.name().clone())
+                .or_insert_with(|| client.clone())
+                .clone()
+        } else {
+            client.clone()
+        }
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use crate::env;
+use common::{error::Terminate, Hostname};
+use log::{debug, error};
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Events that operators are notified about.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// Loading the configuration failed on reload or when syncing the
+    /// git repository, so the current configuration is kept.
+    ReloadFailed,
+    /// The configuration was loaded successfully after a failed reload.
+    ReloadRecovered,
+    /// A client has not checked in within the stale client threshold.
+    ClientStale,
+}
+
+impl Event {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReloadFailed => "reload-failed",
+            Self::ReloadRecovered => "reload-recovered",
+            Self::ClientStale => "client-stale",
+        }
+    }
+}
+
+/// The payload that is sent unless a template is configured.
+#[derive(Debug, Serialize)]
+pub struct Notification {
+    pub event: Event,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<Hostname>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+}
+
+impl Notification {
+    pub fn new(event: Event, message: String, client: Option<Hostname>) -> Self {
+        Self {
+            event,
+            message,
+            client,
+            timestamp: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Render the payload from a template by substituting the
+    /// placeholders `${event}`, `${message}`, `${client}` and
+    /// `${timestamp}`. Values are escaped as the contents of a JSON
+    /// string, so placeholders must be placed inside quotes.
+    fn render(&self, template: &str) -> String {
+        let escape = |value: &str| {
+            let quoted = serde_json::Value::from(value).to_string();
+            quoted[1..quoted.len() - 1].to_string()
+        };
+
+        let client = self
+            .client
+            .as_ref()
+            .map(|client| client.to_string())
+            .unwrap_or_default();
+
+        let timestamp = self.timestamp.format(&Rfc3339).unwrap_or_default();
+
+        template
+            .replace("${event}", self.event.as_str())
+            .replace("${message}", &escape(&self.message))
+            .replace("${client}", &escape(&client))
+            .replace("${timestamp}", &timestamp)
+    }
+}
+
+/// Sends notifications to webhooks and, via the local sendmail
+/// binary, to email recipients. Notifications are delivered on a
+/// separate thread, so that slow receivers do not block the server.
+#[derive(Debug)]
+pub struct Notifier {
+    webhooks: Vec<String>,
+    recipients: Vec<String>,
+    template: Option<String>,
+    /// Whether the most recent attempt to load the configuration failed.
+    failing: AtomicBool,
+}
+
+impl Notifier {
+    /// Read the notification targets from the environment. Returns `None`
+    /// if neither webhooks nor email recipients are configured.
+    pub fn from_env() -> Result<Option<Self>, Terminate> {
+        let split = |value: String| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        let webhooks = env::parse_secret("PULLCONF_NOTIFY_WEBHOOKS")?
+            .map(split)
+            .unwrap_or_default();
+
+        let recipients = env::parse_secret("PULLCONF_NOTIFY_EMAIL")?
+            .map(split)
+            .unwrap_or_default();
+
+        if webhooks.is_empty() && recipients.is_empty() {
+            return Ok(None);
+        }
+
+        let template = match env::parse_secret("PULLCONF_NOTIFY_TEMPLATE")? {
+            Some(path) => Some(fs::read_to_string(&path).map_err(|error| {
+                error!(
+                    scope = "environment",
+                    variable = "PULLCONF_NOTIFY_TEMPLATE";
+                    "failed to read notification template {}: {}",
+                    path,
+                    error
+                );
+                Terminate
+            })?),
+            None => None,
+        };
+
+        Ok(Some(Self {
+            webhooks,
+            recipients,
+            template,
+            failing: AtomicBool::new(false),
+        }))
+    }
+
+    /// Notify about the outcome of loading the configuration. Every
+    /// failure is reported, whereas success is only reported if the
+    /// previous attempt failed.
+    pub fn reload(&self, result: Result<(), String>) {
+        match result {
+            Ok(()) => {
+                if self.failing.swap(false, Ordering::SeqCst) {
+                    self.send(Notification::new(
+                        Event::ReloadRecovered,
+                        "configuration was loaded successfully after a failed reload".to_string(),
+                        None,
+                    ));
+                }
+            }
+            Err(message) => {
+                self.failing.store(true, Ordering::SeqCst);
+                self.send(Notification::new(Event::ReloadFailed, message, None));
+            }
+        }
+    }
+
+    pub fn send(&self, notification: Notification) {
+        let scope = "notify";
+
+        let payload = match &self.template {
+            Some(template) => notification.render(template),
+            None => match serde_json::to_string(&notification) {
+                Ok(payload) => payload,
+                Err(error) => {
+                    error!(scope; "failed to serialize notification: {}", error);
+                    return;
+                }
+            },
+        };
+
+        let event = notification.event.as_str();
+        let webhooks = self.webhooks.clone();
+        let recipients = self.recipients.clone();
+
+        debug!(scope, event; "sending notification");
+
+        thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(10))
+                .build();
+
+            for url in &webhooks {
+                if let Err(error) = agent
+                    .post(url)
+                    .set("content-type", "application/json")
+                    .send_string(&payload)
+                {
+                    // Only the kind of error is logged, since the URL
+                    // may contain a token.
+                    error!(scope, event; "failed to send notification to webhook: {}", error.kind());
+                }
+            }
+
+            if !recipients.is_empty() {
+                if let Err(error) = sendmail(&recipients, event, &payload) {
+                    error!(scope, event; "failed to send notification via sendmail: {}", error);
+                }
+            }
+        });
+    }
+}
+
+/// Pass an email with the payload as its body to the local MTA.
+fn sendmail(recipients: &[String], event: &str, payload: &str) -> std::io::Result<()> {
+    let mut child = Command::new("/usr/sbin/sendmail")
+        .arg("-t")
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        write!(
+            stdin,
+            "To: {}\r\nSubject: pullconfd: {}\r\nContent-Type: application/json\r\n\r\n{}\r\n",
+            recipients.join(", "),
+            event,
+            payload
+        )?;
+    }
+
+    let status = child.wait()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "sendmail exited with {}",
+            status
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn render_template() {
+        let notification = Notification::new(
+            Event::ClientStale,
+            "client has not checked in for \"3600\" seconds".to_string(),
+            Some(Hostname::from_str("foo.example.com").unwrap()),
+        );
+
+        assert_eq!(
+            notification.render(r#"{"text": "${event} ${client}: ${message}"}"#),
+            r#"{"text": "client-stale foo.example.com: client has not checked in for \"3600\" seconds"}"#
+        );
+    }
+}
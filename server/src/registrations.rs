@@ -0,0 +1,120 @@
+use crate::types::ApiKey;
+use common::{error::Terminate, Hostname};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::PathBuf};
+use time::OffsetDateTime;
+
+/// The maximum number of pending registrations. Further registrations
+/// are rejected until pending ones are approved or rejected, so that
+/// unauthenticated requests cannot grow the queue without bounds.
+const MAX_PENDING: usize = 256;
+
+/// A request of an unknown client to be added to the configuration.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Registration {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    /// The hash of the API key that the client intends to use.
+    #[serde(rename = "api-key")]
+    pub api_key: ApiKey,
+    /// The address that the registration was received from.
+    pub address: String,
+}
+
+/// Registrations that await approval by an operator. Pending
+/// registrations are persisted to disk so that they survive restarts of
+/// the server.
+#[derive(Debug)]
+pub struct Registrations {
+    path: PathBuf,
+    entries: BTreeMap<Hostname, Registration>,
+}
+
+impl Registrations {
+    /// Read previously persisted registrations from a file. A missing
+    /// file results in an empty queue.
+    pub fn load(path: PathBuf) -> Result<Self, Terminate> {
+        let scope = "registrations";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize registrations: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "registration file does not exist"
+                );
+
+                BTreeMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read registrations: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn entries(&self) -> &BTreeMap<Hostname, Registration> {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &Hostname) -> Option<&Registration> {
+        self.entries.get(name)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= MAX_PENDING
+    }
+
+    /// Add a pending registration and persist the queue to disk.
+    pub fn insert(
+        &mut self,
+        name: Hostname,
+        registration: Registration,
+    ) -> Result<(), anyhow::Error> {
+        self.entries.insert(name, registration);
+        self.save()
+    }
+
+    /// Remove a pending registration and persist the queue to disk.
+    pub fn remove(&mut self, name: &Hostname) -> Result<Option<Registration>, anyhow::Error> {
+        let registration = self.entries.remove(name);
+
+        if registration.is_some() {
+            self.save()?;
+        }
+
+        Ok(registration)
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
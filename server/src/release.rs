@@ -0,0 +1,211 @@
+use crate::env;
+use common::error::Terminate;
+use log::{error, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The version of pullconf that clients should run, along with the binaries
+/// that they download to update themselves.
+#[derive(Debug)]
+pub struct Release {
+    pub version: String,
+    /// The binaries of the release by the target that they were built for,
+    /// e.g. `x86_64-linux-gnu` or `aarch64-linux-musl`, as reported by
+    /// clients in the `X-Pullconf-Target` header.
+    binaries: BTreeMap<String, Binary>,
+}
+
+/// A binary of pullconf for a single target.
+#[derive(Debug, PartialEq)]
+pub struct Binary {
+    pub path: PathBuf,
+    /// The SHA-256 digest of the binary, which clients verify before they
+    /// replace themselves.
+    pub sha256: String,
+}
+
+impl Release {
+    /// Read the desired client version and the directory of the matching
+    /// binaries from the environment. Returns `None` if no version is
+    /// configured, in which case clients do not update themselves.
+    pub fn from_env() -> Result<Option<Self>, Terminate> {
+        let scope = "environment";
+
+        // An empty version is only returned if the variable is not set,
+        // because parse_string rejects empty values.
+        let version = env::parse_string("PULLCONF_CLIENT_VERSION", "")?;
+
+        if version.is_empty() {
+            return Ok(None);
+        }
+
+        if !version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+        {
+            error!(
+                scope,
+                variable = "PULLCONF_CLIENT_VERSION";
+                "value is not a valid version: {}",
+                version
+            );
+            return Err(Terminate);
+        }
+
+        let directory = env::parse_path(env::FileType::Directory, "PULLCONF_CLIENT_BINARIES", "")?;
+
+        if directory.as_os_str().is_empty() {
+            error!(
+                scope,
+                variable = "PULLCONF_CLIENT_BINARIES";
+                "variable must be set if PULLCONF_CLIENT_VERSION is set"
+            );
+            return Err(Terminate);
+        }
+
+        let binaries = binaries(&directory)?;
+
+        if binaries.is_empty() {
+            error!(
+                scope,
+                variable = "PULLCONF_CLIENT_BINARIES";
+                "directory {} does not contain a binary for any target",
+                directory.display()
+            );
+            return Err(Terminate);
+        }
+
+        Ok(Some(Self { version, binaries }))
+    }
+
+    /// The binary for the target of a client, if any. Clients that do not
+    /// report their target, or report one without a binary, are not asked
+    /// to update themselves, as they might not be able to execute it.
+    pub fn binary(&self, target: Option<&str>) -> Option<&Binary> {
+        self.binaries.get(target?)
+    }
+}
+
+/// Whether a name is a valid target, e.g. `x86_64-linux-gnu` or
+/// `x86_64-freebsd`, i.e. the architecture, the operating system and
+/// optionally the C library.
+fn is_target(name: &str) -> bool {
+    let parts = name.split('-').collect::<Vec<&str>>();
+
+    (2..=3).contains(&parts.len())
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+}
+
+/// Read the binaries in a directory, each of which is named after the
+/// target it was built for, and compute their digests. Other entries,
+/// e.g. a README, are skipped.
+fn binaries(directory: &Path) -> Result<BTreeMap<String, Binary>, Terminate> {
+    let scope = "environment";
+
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            error!(
+                scope,
+                variable = "PULLCONF_CLIENT_BINARIES";
+                "failed to read directory {}: {}",
+                directory.display(),
+                error
+            );
+            return Err(Terminate);
+        }
+    };
+
+    let mut binaries = BTreeMap::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        let Some(target) = entry
+            .file_name()
+            .to_str()
+            .filter(|name| is_target(name))
+            .map(str::to_string)
+        else {
+            warn!(
+                scope,
+                variable = "PULLCONF_CLIENT_BINARIES";
+                "skipping {}, as it is not named after a target such as x86_64-linux-gnu",
+                path.display()
+            );
+            continue;
+        };
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let sha256 = match fs::read(&path) {
+            Ok(bytes) => format!("{:x}", Sha256::digest(&bytes)),
+            Err(error) => {
+                error!(
+                    scope,
+                    variable = "PULLCONF_CLIENT_BINARIES";
+                    "failed to read client binary {}: {}",
+                    path.display(),
+                    error
+                );
+                return Err(Terminate);
+            }
+        };
+
+        binaries.insert(target, Binary { path, sha256 });
+    }
+
+    Ok(binaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_binaries_by_target() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-release-{}", std::process::id()));
+
+        fs::create_dir_all(directory.join("aarch64-linux-musl")).unwrap();
+        fs::write(directory.join("x86_64-linux-gnu"), "linux").unwrap();
+        fs::write(directory.join("x86_64-freebsd"), "freebsd").unwrap();
+        fs::write(directory.join("README"), "ignored").unwrap();
+
+        let binaries = binaries(&directory);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        let release = Release {
+            version: "0.2.0".to_string(),
+            binaries: binaries.unwrap(),
+        };
+
+        assert_eq!(release.binaries.len(), 2);
+        assert_eq!(
+            release.binary(Some("x86_64-linux-gnu")),
+            Some(&Binary {
+                path: directory.join("x86_64-linux-gnu"),
+                sha256: format!("{:x}", Sha256::digest("linux")),
+            })
+        );
+        assert!(release.binary(Some("aarch64-linux-musl")).is_none());
+        assert!(release.binary(None).is_none());
+
+        assert!(is_target("x86_64-freebsd"));
+        assert!(!is_target("x86_64"));
+        assert!(!is_target("../x86_64-linux-gnu"));
+        assert!(!is_target("x86_64-linux-gnu-extra"));
+    }
+}
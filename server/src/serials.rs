@@ -0,0 +1,157 @@
+use common::{error::Terminate, Hostname};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::ErrorKind, path::PathBuf};
+
+/// The serial of the resource catalog of a client along with the catalog
+/// that it was assigned to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+struct Serial {
+    serial: u64,
+    /// The etag of the catalog without its serial, i.e. the SHA-256 digest
+    /// of its content.
+    content: String,
+}
+
+/// The serials of the resource catalogs of every client. The serial of a
+/// client increases by one whenever its catalog changes, be it due to a
+/// new revision of the configuration, a canary rollout or maintenance mode,
+/// so that a client can refuse an older catalog. Unlike the catalog history
+/// serials are never dropped, and they are persisted to disk before a
+/// catalog is served, so that they survive restarts of the server.
+#[derive(Debug)]
+pub struct CatalogSerials {
+    path: PathBuf,
+    entries: HashMap<Hostname, Serial>,
+}
+
+impl CatalogSerials {
+    /// Read previously persisted serials from a file. A missing file
+    /// results in an empty collection.
+    pub fn load(path: PathBuf) -> Result<Self, Terminate> {
+        let scope = "serials";
+
+        let entries = match fs::read_to_string(&path) {
+            Ok(contents) => match serde_json::from_str(&contents) {
+                Ok(entries) => entries,
+                Err(error) => {
+                    error!(
+                        scope,
+                        source:% = path.display();
+                        "failed to deserialize catalog serials: {}",
+                        error
+                    );
+
+                    return Err(Terminate);
+                }
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                debug!(
+                    scope,
+                    source:% = path.display();
+                    "catalog serial file does not exist"
+                );
+
+                HashMap::new()
+            }
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = path.display();
+                    "failed to read catalog serials: {}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    /// The serial that a catalog with the given content would be served
+    /// with, without assigning it, e.g. to show the catalog to an operator.
+    pub fn peek(&self, name: &Hostname, content: &str) -> u64 {
+        match self.entries.get(name) {
+            Some(latest) if latest.content == content => latest.serial,
+            Some(latest) => latest.serial + 1,
+            None => 1,
+        }
+    }
+
+    /// Assign the serial of a catalog with the given content to a client
+    /// and persist it. The serial is only served if it was persisted, as a
+    /// restart would otherwise hand out the same serial for another catalog.
+    pub fn assign(&mut self, name: &Hostname, content: &str) -> Result<u64, anyhow::Error> {
+        let serial = self.peek(name, content);
+
+        if self
+            .entries
+            .get(name)
+            .is_some_and(|latest| latest.serial == serial)
+        {
+            return Ok(serial);
+        }
+
+        let previous = self.entries.insert(
+            name.clone(),
+            Serial {
+                serial,
+                content: content.to_string(),
+            },
+        );
+
+        if let Err(error) = self.save() {
+            match previous {
+                Some(previous) => self.entries.insert(name.clone(), previous),
+                None => self.entries.remove(name),
+            };
+
+            return Err(error);
+        }
+
+        Ok(serial)
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let mut temporary = self.path.clone();
+        temporary.set_extension("tmp");
+
+        fs::write(&temporary, serde_json::to_vec(&self.entries)?)?;
+        fs::rename(&temporary, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serials_increase_with_changes() {
+        let name = "client.example.com".parse::<Hostname>().unwrap();
+        let path =
+            std::env::temp_dir().join(format!("pullconfd-serials-{}.json", std::process::id()));
+
+        let mut serials = CatalogSerials::load(path.clone()).unwrap();
+
+        assert_eq!(serials.peek(&name, "a"), 1);
+        assert_eq!(serials.assign(&name, "a").unwrap(), 1);
+        assert_eq!(serials.assign(&name, "a").unwrap(), 1);
+        assert_eq!(serials.peek(&name, "b"), 2);
+        assert_eq!(serials.assign(&name, "b").unwrap(), 2);
+
+        // Returning to a previous catalog, e.g. after a canary rollout was
+        // aborted, is a change of its own.
+        assert_eq!(serials.assign(&name, "a").unwrap(), 3);
+
+        // Serials survive a restart.
+        let mut serials = CatalogSerials::load(path.clone()).unwrap();
+
+        assert_eq!(serials.peek(&name, "a"), 3);
+        assert_eq!(serials.assign(&name, "c").unwrap(), 4);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+use common::error::Terminate;
+use log::{debug, error, warn};
+use std::{
+    env,
+    net::TcpListener,
+    os::{
+        fd::{FromRawFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    process,
+};
+
+/// The first file descriptor that systemd passes, see sd_listen_fds(3).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Send a state change notification such as `READY=1` to the service
+/// manager. This does nothing unless pullconfd is started by systemd
+/// with `Type=notify`.
+pub fn notify(state: &str) {
+    let scope = "systemd";
+
+    let path = match env::var("NOTIFY_SOCKET") {
+        Ok(path) => path,
+        Err(_) => return,
+    };
+
+    // Socket paths that start with `@` refer to the abstract namespace.
+    let address = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name),
+        None => SocketAddr::from_pathname(&path),
+    };
+
+    let result = address
+        .and_then(|address| UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address));
+
+    match result {
+        Ok(_) => debug!(scope; "sent notification {:?}", state),
+        Err(error) => warn!(scope; "failed to notify service manager: {}", error),
+    }
+}
+
+/// Take over the listening sockets that systemd passes to pullconfd when
+/// it is started by a socket unit, see sd_listen_fds(3). Returns `None` if
+/// no sockets were passed to this process.
+pub fn listeners() -> Result<Option<Vec<TcpListener>>, Terminate> {
+    let scope = "systemd";
+
+    let activated = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == process::id());
+
+    let count = match env::var("LISTEN_FDS").ok().filter(|_| activated) {
+        Some(count) => match count.parse::<RawFd>() {
+            Ok(count) => count,
+            Err(error) => {
+                error!(scope, variable = "LISTEN_FDS"; "invalid number of sockets: {}", error);
+                return Err(Terminate);
+            }
+        },
+        None => return Ok(None),
+    };
+
+    // The variables must not be passed on to child processes such as git.
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    if count == 0 {
+        return Ok(None);
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // The descriptors belong to this process as LISTEN_PID matches.
+            // They are inherited without close-on-exec, so each is replaced
+            // by a duplicate that is closed on exec.
+            let inherited = unsafe { TcpListener::from_raw_fd(fd) };
+
+            inherited
+                .local_addr()
+                .and_then(|socket| {
+                    let listener = inherited.try_clone()?;
+
+                    debug!(scope, socket:%; "taking over socket passed by systemd");
+
+                    Ok(listener)
+                })
+                .map_err(|error| {
+                    error!(scope, fd; "socket passed by systemd is not a TCP socket: {}", error);
+                    Terminate
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn notify_service_manager() {
+        let path = env::temp_dir().join(format!("pullconfd-notify-{}.sock", process::id()));
+
+        let socket = UnixDatagram::bind(&path).unwrap();
+
+        env::set_var("NOTIFY_SOCKET", &path);
+        notify("READY=1");
+        env::remove_var("NOTIFY_SOCKET");
+
+        let mut buffer = [0; 64];
+        let length = socket.recv(&mut buffer);
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&buffer[..length.unwrap()], b"READY=1");
+    }
+
+    #[test]
+    fn ignore_sockets_of_other_processes() {
+        env::set_var("LISTEN_PID", (process::id() + 1).to_string());
+        env::set_var("LISTEN_FDS", "1");
+
+        assert!(listeners().unwrap().is_none());
+
+        env::set_var("LISTEN_PID", process::id().to_string());
+        env::set_var("LISTEN_FDS", "0");
+
+        assert!(listeners().unwrap().is_none());
+        assert!(env::var_os("LISTEN_PID").is_none());
+        assert!(env::var_os("LISTEN_FDS").is_none());
+    }
+}
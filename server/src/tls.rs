@@ -0,0 +1,379 @@
+use crate::env;
+use common::error::Terminate;
+use log::{debug, error};
+use rustls::{
+    crypto::{ring, CryptoProvider},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    InconsistentKeys, ServerConfig, SupportedProtocolVersion,
+};
+use std::{
+    fmt, fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+/// The certificate and private key files that the server uses to enable
+/// encryption. Both files are watched for changes, so that a renewed
+/// certificate can be loaded without restarting the server.
+pub struct TlsFiles {
+    certificate: PathBuf,
+    key: PathBuf,
+    /// The modification times of the certificate and the private key
+    /// when they were last read.
+    modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+impl TlsFiles {
+    pub fn from_env() -> Result<Self, Terminate> {
+        let certificate = env::parse_path(
+            env::FileType::File,
+            "PULLCONF_TLS_CERTIFICATE",
+            "/etc/pullconfd/tls/server.crt",
+        )?;
+
+        let key = env::parse_path(
+            env::FileType::File,
+            "PULLCONF_TLS_PRIVATE_KEY",
+            "/etc/pullconfd/tls/server.key",
+        )?;
+
+        Ok(Self::new(certificate, key))
+    }
+
+    pub fn new(certificate: PathBuf, key: PathBuf) -> Self {
+        Self {
+            certificate,
+            key,
+            modified: (None, None),
+        }
+    }
+
+    /// Read the certificate chain and private key and ensure that both
+    /// contain PEM-encoded data that the server can use.
+    pub fn read(&mut self) -> Result<Arc<CertifiedKey>, Terminate> {
+        let scope = "tls";
+
+        let modified = self.modification_times();
+
+        let certificate = fs::read(&self.certificate).map_err(|error| {
+            error!(
+                scope,
+                source:% = self.certificate.display();
+                "failed to read TLS certificate file: {}",
+                error
+            );
+            Terminate
+        })?;
+
+        let key = fs::read(&self.key).map_err(|error| {
+            error!(
+                scope,
+                source:% = self.key.display();
+                "failed to read TLS private key file: {}",
+                error
+            );
+            Terminate
+        })?;
+
+        let chain = rustls_pemfile::certs(&mut certificate.as_slice())
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+
+        if chain.is_empty() {
+            error!(
+                scope,
+                source:% = self.certificate.display();
+                "TLS certificate file does not contain a PEM-encoded certificate"
+            );
+
+            return Err(Terminate);
+        }
+
+        let Ok(Some(key)) = rustls_pemfile::private_key(&mut key.as_slice()) else {
+            error!(
+                scope,
+                source:% = self.key.display();
+                "TLS private key file does not contain a PEM-encoded private key"
+            );
+
+            return Err(Terminate);
+        };
+
+        let key = ring::default_provider()
+            .key_provider
+            .load_private_key(key)
+            .map_err(|error| {
+                error!(
+                    scope,
+                    source:% = self.key.display();
+                    "failed to load TLS private key: {}",
+                    error
+                );
+                Terminate
+            })?;
+
+        let key = CertifiedKey::new(chain, key);
+
+        // Either file may be read while the other is being replaced, in
+        // which case the files are read again once both changed. Keys whose
+        // public key cannot be determined are accepted as they are.
+        match key.keys_match() {
+            Ok(()) | Err(rustls::Error::InconsistentKeys(InconsistentKeys::Unknown)) => (),
+            Err(error) => {
+                error!(
+                    scope,
+                    source:% = self.key.display();
+                    "TLS private key does not match certificate {}: {}",
+                    self.certificate.display(),
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        }
+
+        self.modified = modified;
+
+        Ok(Arc::new(key))
+    }
+
+    /// Check if either file has been modified since it was last read.
+    pub fn changed(&self) -> bool {
+        let changed = self.modification_times() != self.modified;
+
+        if changed {
+            debug!(scope = "tls"; "TLS certificate or private key file changed");
+        }
+
+        changed
+    }
+
+    fn modification_times(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: &PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+
+        (modified(&self.certificate), modified(&self.key))
+    }
+}
+
+/// The certificate that is presented to clients. It is shared by all
+/// connections and replaced in place, so that a renewed certificate is
+/// served to new connections without binding the sockets again.
+pub struct Certificate(RwLock<Arc<CertifiedKey>>);
+
+impl Certificate {
+    pub fn new(key: Arc<CertifiedKey>) -> Arc<Self> {
+        Arc::new(Self(RwLock::new(key)))
+    }
+
+    pub fn replace(&self, key: Arc<CertifiedKey>) {
+        *self.0.write().unwrap_or_else(|error| error.into_inner()) = key;
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Certificate")
+    }
+}
+
+impl ResolvesServerCert for Certificate {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.0
+                .read()
+                .unwrap_or_else(|error| error.into_inner())
+                .clone(),
+        )
+    }
+}
+
+/// Create the configuration of the TLS server, which restricts protocol
+/// versions and cipher suites as configured via the environment.
+pub fn server_config(certificate: Arc<Certificate>) -> Result<Arc<ServerConfig>, Terminate> {
+    let scope = "tls";
+
+    let versions: &[&SupportedProtocolVersion] =
+        match env::parse_string("PULLCONF_TLS_MIN_VERSION", "1.2")?.as_str() {
+            "1.2" => &[&rustls::version::TLS13, &rustls::version::TLS12],
+            "1.3" => &[&rustls::version::TLS13],
+            version => {
+                error!(
+                    scope,
+                    variable = "PULLCONF_TLS_MIN_VERSION";
+                    "unsupported TLS version {}, expected either 1.2 or 1.3",
+                    version
+                );
+                return Err(Terminate);
+            }
+        };
+
+    let mut provider = ring::default_provider();
+
+    let names = env::parse_list("PULLCONF_TLS_CIPHER_SUITES")?;
+
+    if !names.is_empty() {
+        provider.cipher_suites = cipher_suites(&provider, &names).map_err(|name| {
+            error!(
+                scope,
+                variable = "PULLCONF_TLS_CIPHER_SUITES";
+                "unsupported cipher suite {}, expected one of {}",
+                name,
+                provider
+                    .cipher_suites
+                    .iter()
+                    .filter_map(|suite| suite.suite().as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            Terminate
+        })?;
+    }
+
+    let config = ServerConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(versions)
+        .map_err(|error| {
+            error!(scope; "invalid TLS configuration: {}", error);
+            Terminate
+        })?
+        .with_no_client_auth()
+        .with_cert_resolver(certificate);
+
+    Ok(Arc::new(config))
+}
+
+/// Select the cipher suites of the provider by their IANA names, e.g.
+/// `TLS13_AES_256_GCM_SHA384`, in the order of the provider. Returns the
+/// first unknown name otherwise.
+fn cipher_suites(
+    provider: &CryptoProvider,
+    names: &[String],
+) -> Result<Vec<rustls::SupportedCipherSuite>, String> {
+    if let Some(name) = names.iter().find(|name| {
+        !provider
+            .cipher_suites
+            .iter()
+            .any(|suite| suite.suite().as_str() == Some(name.as_str()))
+    }) {
+        return Err(name.clone());
+    }
+
+    Ok(provider
+        .cipher_suites
+        .iter()
+        .filter(|suite| {
+            suite
+                .suite()
+                .as_str()
+                .is_some_and(|name| names.iter().any(|n| n == name))
+        })
+        .copied()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{path::Path, process, time::Duration};
+
+    /// Write a file and move its modification time forward, so that the
+    /// change is detected regardless of the resolution of timestamps.
+    fn write(path: &Path, content: &str, modified: u64) {
+        fs::write(path, content).unwrap();
+
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| {
+                file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(modified))
+            })
+            .unwrap();
+    }
+
+    fn generate() -> (String, String) {
+        let key = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+
+        (key.cert.pem(), key.signing_key.serialize_pem())
+    }
+
+    #[test]
+    fn read_certificate_and_key() {
+        let directory = std::env::temp_dir().join(format!("pullconfd-tls-{}", process::id()));
+
+        fs::create_dir_all(&directory).unwrap();
+
+        let certificate = directory.join("server.crt");
+        let key = directory.join("server.key");
+
+        let (first_certificate, first_key) = generate();
+        let (second_certificate, second_key) = generate();
+
+        write(&certificate, &first_certificate, 1);
+        write(&key, &first_key, 1);
+
+        let mut files = TlsFiles::new(certificate.clone(), key.clone());
+
+        assert!(files.read().is_ok());
+        assert!(!files.changed());
+
+        // The certificate is replaced before the private key, so that the
+        // new certificate does not match the old key yet.
+        write(&certificate, &second_certificate, 2);
+
+        assert!(files.changed());
+        assert!(files.read().is_err());
+        assert!(files.changed());
+
+        write(&key, &second_key, 2);
+
+        assert!(files.read().is_ok());
+        assert!(!files.changed());
+
+        write(&key, "not a key", 3);
+
+        assert!(files.read().is_err());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn select_cipher_suites() {
+        let provider = ring::default_provider();
+
+        let names = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let suites = cipher_suites(
+            &provider,
+            &names(&[
+                "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+                "TLS13_AES_256_GCM_SHA384",
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            suites
+                .iter()
+                .map(|suite| suite.suite().as_str().unwrap())
+                .collect::<Vec<_>>(),
+            [
+                "TLS13_AES_256_GCM_SHA384",
+                "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"
+            ]
+        );
+
+        assert_eq!(
+            cipher_suites(
+                &provider,
+                &names(&["TLS13_AES_256_GCM_SHA384", "TLS_RSA_WITH_RC4_128_SHA"])
+            ),
+            Err("TLS_RSA_WITH_RC4_128_SHA".to_string())
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{diagnostics::validation_error, types::ApiKey};
+use common::error::Terminate;
+use log::debug;
+use serde::Deserialize;
+use std::{collections::HashMap, fmt, fs, path::PathBuf};
+
+/// Roles determine which parts of the `/admin` API an admin token
+/// grants access to. Roles are ordered, i.e. each role includes the
+/// permissions of the roles before it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Role {
+    /// Read inventory data that does not expose resource parameters.
+    #[default]
+    #[serde(rename = "read-only")]
+    ReadOnly,
+    /// Additionally trigger configuration reloads.
+    #[serde(rename = "reload")]
+    Reload,
+    /// Additionally read resource catalogs, which may contain secrets.
+    #[serde(rename = "full")]
+    Full,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadOnly => f.write_str("read-only"),
+            Self::Reload => f.write_str("reload"),
+            Self::Full => f.write_str("full"),
+        }
+    }
+}
+
+/// An admin token grants access to the `/admin` API. Admin tokens are
+/// separate from client API keys and are not associated with any
+/// client.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminToken {
+    pub name: String,
+    #[serde(rename(deserialize = "token"))]
+    pub hash: ApiKey,
+    #[serde(default)]
+    pub role: Role,
+}
+
+/// The expected format of the admin token file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AdminTokenFile {
+    #[serde(default)]
+    tokens: Vec<AdminToken>,
+}
+
+impl AdminToken {
+    /// The identity of requests that are received via the local admin
+    /// socket, which are granted every role.
+    pub fn local() -> Self {
+        Self {
+            name: "local".to_string(),
+            hash: ApiKey(String::new()),
+            role: Role::Full,
+        }
+    }
+
+    /// Check if the role of this token includes the permissions of the
+    /// given role.
+    pub fn permits(&self, role: Role) -> bool {
+        self.role >= role
+    }
+
+    /// Parse admin tokens from a TOML file and return them indexed by
+    /// their hash. A missing file is not an error, it simply disables
+    /// the admin API.
+    pub fn load(path: &PathBuf) -> Result<HashMap<ApiKey, AdminToken>, Terminate> {
+        let scope = "validation";
+
+        if !path.is_file() {
+            debug!(
+                scope,
+                source:% = path.display();
+                "admin token file does not exist, admin API is disabled"
+            );
+
+            return Ok(HashMap::new());
+        }
+
+        let contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(error) => {
+                validation_error!(
+                    scope,
+                    source:% = path.display();
+                    "{}",
+                    error
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        let file = match toml::from_str::<AdminTokenFile>(&contents) {
+            Ok(file) => file,
+            Err(error) => {
+                validation_error!(
+                    scope,
+                    source:% = path.display();
+                    "{}",
+                    error.to_string().trim_end()
+                );
+
+                return Err(Terminate);
+            }
+        };
+
+        let mut tokens = HashMap::with_capacity(file.tokens.len());
+
+        for token in file.tokens {
+            let name = token.name.clone();
+
+            if let Some(other) = tokens.insert(token.hash.clone(), token) {
+                validation_error!(
+                    scope,
+                    source:% = path.display();
+                    "admin token hash from `{}` matches that from `{}`, but admin tokens must be unique",
+                    name,
+                    other.name
+                );
+
+                return Err(Terminate);
+            }
+        }
+
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_admin_tokens_with_roles() {
+        let path = std::env::temp_dir().join(format!(
+            "pullconfd-admin-tokens-{}.toml",
+            std::process::id()
+        ));
+
+        let token = |name: &str, digit: char, role: Option<&str>| {
+            format!(
+                "[[tokens]]\nname = \"{}\"\ntoken = \"{}\"\n{}",
+                name,
+                digit.to_string().repeat(64),
+                role.map(|role| format!("role = \"{}\"\n", role))
+                    .unwrap_or_default()
+            )
+        };
+
+        let load = |contents: &str| {
+            fs::write(&path, contents).unwrap();
+            AdminToken::load(&path)
+        };
+
+        let tokens = load(
+            &[
+                token("monitoring", 'a', None),
+                token("deploy", 'b', Some("reload")),
+                token("operator", 'c', Some("full")),
+            ]
+            .join("\n"),
+        )
+        .unwrap();
+
+        let duplicate = load(&[token("a", 'a', None), token("b", 'a', Some("full"))].join("\n"));
+        let unknown = load(&token("a", 'a', Some("admin")));
+
+        fs::remove_file(&path).unwrap();
+
+        let role = |digit: char| tokens[&ApiKey(digit.to_string().repeat(64))].role;
+
+        assert_eq!(role('a'), Role::ReadOnly);
+        assert_eq!(role('b'), Role::Reload);
+        assert_eq!(role('c'), Role::Full);
+
+        // Each role includes the permissions of the roles before it.
+        let deploy = &tokens[&ApiKey("b".repeat(64))];
+
+        assert!(deploy.permits(Role::ReadOnly));
+        assert!(deploy.permits(Role::Reload));
+        assert!(!deploy.permits(Role::Full));
+        assert!(AdminToken::local().permits(Role::Full));
+
+        assert!(duplicate.is_err());
+        assert!(unknown.is_err());
+
+        // A missing file disables the admin API.
+        assert!(AdminToken::load(&path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::lint;
+use common::Hostname;
+use serde::{de::Error, Deserialize, Deserializer};
+use std::{collections::HashMap, fmt, str::FromStr};
+use toml::Value;
+
+/// A glob pattern that grants a client access to every asset whose
+/// path (relative to the asset directory) matches the pattern.
+/// Patterns are matched segment by segment, where `*` matches any
+/// sequence of characters within a single segment, `?` matches
+/// exactly one character and a segment consisting solely of `**`
+/// matches any number of segments (including none).
+/// Patterns may also contain placeholders in the form `${name}`
+/// that are substituted once per client, see `AssetPattern::resolve`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AssetPattern(String);
+
+impl FromStr for AssetPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = match s.strip_prefix('/') {
+            Some(rest) => rest.split('/'),
+            None => return Err(format!("asset pattern `{}` must start with `/`", s)),
+        };
+
+        for segment in segments {
+            if segment.is_empty() {
+                return Err(format!(
+                    "asset pattern `{}` must not contain empty path segments",
+                    s
+                ));
+            }
+
+            if segment == "." || segment == ".." {
+                return Err(format!(
+                    "asset pattern `{}` must not contain relative references such as '.' or '..'",
+                    s
+                ));
+            }
+
+            if segment != "**" && segment.contains("**") {
+                return Err(format!(
+                    "asset pattern `{}` may only use `**` as a whole path segment",
+                    s
+                ));
+            }
+        }
+
+        Ok(Self(s.to_owned()))
+    }
+}
+
+impl<'de> Deserialize<'de> for AssetPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = String::deserialize(deserializer)?;
+
+        Self::from_str(&v).map_err(Error::custom)
+    }
+}
+
+impl fmt::Display for AssetPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AssetPattern {
+    /// Substitute placeholders in the form `${name}` in this pattern.
+    /// `${hostname}` always evaluates to the name of the client, every
+    /// other placeholder must refer to a variable of type string in the
+    /// client's `[variables]` table.
+    pub fn resolve(
+        &self,
+        hostname: &Hostname,
+        variables: &HashMap<String, Value>,
+    ) -> Result<Self, String> {
+        let mut resolved = String::with_capacity(self.0.len());
+        let mut rest = self.0.as_str();
+
+        while let Some(start) = rest.find("${") {
+            resolved.push_str(&rest[..start]);
+
+            let end = rest[start..].find('}').ok_or_else(|| {
+                format!("asset pattern `{}` contains an unclosed placeholder", self)
+            })?;
+
+            let name = &rest[start + 2..start + end];
+
+            if name == "hostname" {
+                resolved.push_str(hostname.as_str());
+            } else {
+                lint::reference_variable(name);
+
+                match variables.get(name).and_then(|value| value.as_str()) {
+                    Some(value) => resolved.push_str(value),
+                    None => {
+                        return Err(format!(
+                            "asset pattern `{}` refers to unknown variable `{}` or the variable is not a string",
+                            self, name
+                        ))
+                    }
+                }
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        resolved.push_str(rest);
+
+        Self::from_str(&resolved)
+    }
+
+    /// Check if an asset path such as `/common/motd` matches this pattern.
+    /// Paths with empty segments or relative references never match, as
+    /// `*` and `**` would match those segments as well, e.g. `/${hostname}/**`
+    /// would grant access to `/my.example.com/../other.example.com/key`.
+    pub fn matches(&self, path: &str) -> bool {
+        let mut segments = path.split('/');
+
+        if segments.next() != Some("")
+            || segments.any(|segment| segment.is_empty() || segment == "." || segment == "..")
+        {
+            return false;
+        }
+
+        let pattern = self.0.split('/').collect::<Vec<&str>>();
+        let path = path.split('/').collect::<Vec<&str>>();
+
+        match_segments(&pattern, &path)
+    }
+}
+
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((other, remainder)) => {
+                match_segment(segment.as_bytes(), other.as_bytes())
+                    && match_segments(rest, remainder)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &[u8], segment: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some((b'*', rest)) => (0..=segment.len()).any(|skip| match_segment(rest, &segment[skip..])),
+        Some((b'?', rest)) => !segment.is_empty() && match_segment(rest, &segment[1..]),
+        Some((c, rest)) => segment
+            .split_first()
+            .is_some_and(|(other, remainder)| c == other && match_segment(rest, remainder)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_asset_pattern() -> Result<(), anyhow::Error> {
+        let pattern = AssetPattern::from_str("/common/**").map_err(anyhow::Error::msg)?;
+
+        assert!(pattern.matches("/common/motd"));
+        assert!(pattern.matches("/common/nginx/sites/default"));
+        assert!(!pattern.matches("/other/motd"));
+
+        let pattern = AssetPattern::from_str("/nginx/*.conf").map_err(anyhow::Error::msg)?;
+
+        assert!(pattern.matches("/nginx/default.conf"));
+        assert!(!pattern.matches("/nginx/sites/default.conf"));
+        assert!(!pattern.matches("/nginx/default.cfg"));
+
+        // Relative references must not escape the directory of a client.
+        let pattern = AssetPattern::from_str("/my.example.com/**").map_err(anyhow::Error::msg)?;
+
+        assert!(!pattern.matches("/my.example.com/../other.example.com/key"));
+        assert!(!pattern.matches("/my.example.com/./key"));
+        assert!(!pattern.matches("/my.example.com//key"));
+        assert!(!pattern.matches("my.example.com/key"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_asset_pattern() -> Result<(), anyhow::Error> {
+        let hostname = Hostname::from_str("my.example.com")?;
+        let variables = HashMap::from([("role".to_string(), Value::from("web"))]);
+
+        let pattern = AssetPattern::from_str("/${hostname}/${role}/**")
+            .and_then(|pattern| pattern.resolve(&hostname, &variables))
+            .map_err(anyhow::Error::msg)?;
+
+        assert!(pattern.matches("/my.example.com/web/index.html"));
+        assert!(AssetPattern::from_str("/${unknown}/**")
+            .and_then(|pattern| pattern.resolve(&hostname, &variables))
+            .is_err());
+        assert!(AssetPattern::from_str("/common/../secret").is_err());
+
+        Ok(())
+    }
+}
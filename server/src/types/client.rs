@@ -1,30 +1,55 @@
-use crate::types::{
-    resources::{
-        apt, cron,
-        deserialize::{Dependency, Resource as DeResource},
-        directory, file, group, host, resolv_conf, symlink, user, Resource,
+use crate::{
+    diagnostics::validation_error,
+    lint,
+    types::{
+        resources::{
+            apt, cron, custom,
+            deserialize::{Dependency, Resource as DeResource},
+            directory, file, group, host, resolv_conf, symlink, user, Resource,
+        },
+        ApiKey, AssetPattern, Group,
     },
-    ApiKey, Group,
 };
 use common::{
     error::Terminate,
     resources::{
         apt::{package::Name as AptPackageName, preference::Name as AptPreferenceName},
         cron::job::Name as CronJobName,
+        custom::Provider,
         group::Name as GroupName,
         user::Name as UserName,
     },
-    Hostname,
+    Hostname, Links, Purge, CATALOG_VERSION,
 };
-use log::error;
+use log::{debug, info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     hash::{Hash, Hasher},
     net::IpAddr,
     path::PathBuf,
+    time::{Duration, Instant},
 };
 use uuid::Uuid;
 
+/// The payload of a resource catalog, which is the envelope of every
+/// successful API response plus the version of the catalog format, so that
+/// clients refuse catalogs that they do not understand.
+#[derive(Serialize)]
+struct Catalog<'a> {
+    links: Links,
+    version: u32,
+    /// Increases whenever the catalog of a client changes, see
+    /// `CatalogSerials`, so that clients refuse older catalogs, e.g. from a
+    /// stale cache. Filtered catalogs are never applied and have no serial.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    serial: Option<u64>,
+    #[serde(skip_serializing_if = "Purge::is_empty")]
+    purge: Purge,
+    data: &'a [&'a Resource],
+}
+
 /// This struct contains temporary helper collections that are
 /// freed after configuration validation has concluded.
 #[derive(Clone, Debug, Default)]
@@ -45,6 +70,13 @@ pub struct ValidationHelpers {
     /// the actual resource metadata of a given dependency is added
     /// to the resource relationship data.
     pub requires: HashMap<Uuid, Vec<Dependency>>,
+    /// The path of the file that each resource was declared in, i.e.
+    /// the client's own file or the file of the group it stems from.
+    pub declarations: HashMap<Uuid, PathBuf>,
+    /// Dependencies that were established due to the `requires`
+    /// meta-parameter as opposed to implicit dependencies. Together with
+    /// `declarations` this is used to explain dependency loops.
+    pub explicit: HashSet<(Uuid, Uuid)>,
     /// Some resources manage filesystem nodes of different types.
     /// This collection helps to ensure during validation that a node
     /// at a given path is not managed by multiple resources of the same
@@ -62,6 +94,7 @@ pub struct ValidationHelpers {
     pub apt_package_names: HashSet<AptPackageName>,
     pub apt_preference_names: HashSet<AptPreferenceName>,
     pub cron_job_names: HashSet<CronJobName>,
+    pub custom_names: HashSet<(Provider, String)>,
 }
 
 impl ValidationHelpers {
@@ -79,11 +112,34 @@ impl ValidationHelpers {
 #[derive(Clone, Debug)]
 pub struct Client {
     pub name: Hostname,
+    /// The path of the file that this client was parsed from.
+    pub source: PathBuf,
     pub api_key: ApiKey,
     pub assigned_groups: Vec<Hostname>,
     pub variables: HashMap<String, toml::Value>,
+    /// Glob patterns that authorize the client to download assets
+    /// in addition to those referenced by its `file` resources.
+    /// These are collected from the client and its groups.
+    pub assets: Vec<AssetPattern>,
     pub temporary: ValidationHelpers,
     pub resources: VecDeque<Resource>,
+    /// Warnings about configuration of this client that is valid,
+    /// but has no effect.
+    pub lints: Vec<String>,
+    /// The number of resources in the catalog that were inherited from
+    /// groups rather than defined by the client itself.
+    pub inherited: usize,
+    /// The time it took to compile and validate the resource catalog.
+    pub compile_time: Duration,
+    /// The interval at which the client should fetch its catalog,
+    /// overriding the server-wide interval.
+    pub poll_interval: Option<Duration>,
+    /// Whether the client is about to be removed, in which case its
+    /// catalog removes what its resources created.
+    pub decommissioned: bool,
+    /// Namespaces in which the client removes what pullconf created, but
+    /// is no longer part of its catalog.
+    pub purge: Purge,
 }
 
 impl Hash for Client {
@@ -130,43 +186,61 @@ impl
     ) -> Result<Self, Self::Error> {
         let scope = "validation";
 
+        let start = Instant::now();
+
         // Initialize the client and validate the client's own configuration,
         // substituting variables in the process.
         // This does not take resources from groups into account.
         let mut client = Self {
             name,
+            source: intermediate.source,
             api_key: intermediate.api_key,
             assigned_groups: intermediate.assigned_groups,
             variables: intermediate.variables,
+            assets: vec![],
             temporary: ValidationHelpers::default(),
             resources: VecDeque::new(),
+            lints: vec![],
+            inherited: 0,
+            compile_time: Duration::ZERO,
+            poll_interval: intermediate.poll_interval.map(Duration::from_secs),
+            decommissioned: intermediate.decommissioned,
+            purge: Purge {
+                cron_jobs: intermediate.purge_cron_jobs,
+            },
         };
 
-        for item in intermediate.resources {
-            let requires = item.requires().to_vec();
+        // Keep track of the variables that are substituted while the
+        // client's own configuration and that of its groups is processed
+        // in order to warn about variables that are never referenced.
+        let (result, referenced) = lint::track_variables(|| {
+            client.process(intermediate.assets, intermediate.resources, groups)
+        });
 
-            // Convert resource from the deserialized to the final form,
-            // substituting variables in the process.
-            let resource = Resource::try_from((&item, &client.variables)).map_err(|error| {
-                error!(
-                    scope,
-                    client:% = client.name,
-                    resource:% = item.kind();
-                    "{}",
-                    error
-                );
-                Terminate
-            })?;
+        result?;
 
-            // Save dependencies as they appear in the deserialized resource.
-            client.temporary.requires.insert(resource.id(), requires);
+        let mut unreferenced = client
+            .variables
+            .keys()
+            .filter(|name| !referenced.contains(*name))
+            .cloned()
+            .collect::<Vec<_>>();
 
-            client.resources.push_back(resource);
-        }
+        unreferenced.sort();
 
-        // Extend the client's resource catalog with resources from groups
-        // that the client is a member of, substituting variables in the process.
-        client.extend_from_groups(groups)?;
+        for name in unreferenced {
+            warn!(
+                scope,
+                client:% = client.name;
+                "variable `{}` is never referenced",
+                name
+            );
+
+            client.lints.push(format!(
+                "client `{}`: variable `{}` is never referenced",
+                client.name, name
+            ));
+        }
 
         client.temporary.file_paths = client
             .resources
@@ -177,17 +251,160 @@ impl
 
         client.validate()?;
 
+        // Resources are converted after validation, so that the catalog
+        // keeps the relationships of the regular catalog.
+        if client.decommissioned {
+            let converted = client
+                .resources
+                .iter_mut()
+                .map(|resource| resource.decommission())
+                .filter(|converted| *converted)
+                .count();
+
+            info!(
+                scope,
+                client:% = client.name;
+                "client is decommissioned, {} of {} resources are removed",
+                converted,
+                client.resources.len()
+            );
+        }
+
+        client.inherited = client.temporary.origins.len();
+
         client.temporary.clear();
 
+        client.compile_time = start.elapsed();
+
+        debug!(
+            scope,
+            client:% = client.name,
+            resources = client.resources.len(),
+            inherited = client.inherited,
+            compile_time_ms = client.compile_time.as_millis();
+            "compiled resource catalog"
+        );
+
         Ok(client)
     }
 }
 
 impl Client {
+    /// Resolve the client's asset patterns and resources and extend
+    /// them with those of its groups, substituting variables in the
+    /// process.
+    fn process(
+        &mut self,
+        assets: Vec<AssetPattern>,
+        resources: Vec<DeResource>,
+        groups: &mut HashMap<Hostname, (Group, usize)>,
+    ) -> Result<(), Terminate> {
+        let scope = "validation";
+
+        for pattern in &assets {
+            let pattern = pattern
+                .resolve(&self.name, &self.variables)
+                .map_err(|error| {
+                    validation_error!(scope, client:% = self.name; "{}", error);
+                    Terminate
+                })?;
+
+            self.assets.push(pattern);
+        }
+
+        for item in resources {
+            let requires = item.requires().to_vec();
+
+            // Convert resource from the deserialized to the final form,
+            // substituting variables in the process.
+            let resource =
+                Resource::try_from((&item, &self.name, &self.variables)).map_err(|error| {
+                    validation_error!(
+                        scope,
+                        client:% = self.name,
+                        resource:% = item.kind();
+                        "{}",
+                        error
+                    );
+                    Terminate
+                })?;
+
+            // Resource IDs are derived from the parameters that uniquely
+            // identify a resource, so duplicates must be rejected before
+            // their IDs are used to track dependencies.
+            if self.resources.contains(&resource) {
+                validation_error!(
+                    scope,
+                    client:% = self.name,
+                    resource:% = resource.kind();
+                    "{} appears multiple times, but resources must be unique",
+                    resource.repr()
+                );
+
+                return Err(Terminate);
+            }
+
+            // Save dependencies as they appear in the deserialized resource.
+            self.temporary.requires.insert(resource.id(), requires);
+            self.temporary
+                .declarations
+                .insert(resource.id(), self.source.clone());
+
+            self.resources.push_back(resource);
+        }
+
+        // Extend the client's resource catalog with resources from groups
+        // that the client is a member of, substituting variables in the process.
+        self.extend_from_groups(groups)
+    }
+
     pub fn name(&self) -> &Hostname {
         &self.name
     }
 
+    /// Serialize the resource catalog of this client into the payload
+    /// that the client receives when requesting its resources and
+    /// compute the etag of the payload.
+    pub fn serialize_catalog(&self, serial: Option<u64>) -> (Vec<u8>, String) {
+        self.serialize(&self.resources.iter().collect::<Vec<_>>(), serial)
+    }
+
+    /// Serialize a subset of this client's resource catalog in the same
+    /// format as the whole catalog and compute the etag of the payload.
+    pub fn serialize_resources(&self, resources: &[&Resource]) -> (Vec<u8>, String) {
+        self.serialize(resources, None)
+    }
+
+    fn serialize(&self, resources: &[&Resource], serial: Option<u64>) -> (Vec<u8>, String) {
+        let response = Catalog {
+            links: Links {
+                this: format!("/api/clients/{}", self.name()),
+                ..Default::default()
+            },
+            version: CATALOG_VERSION,
+            serial,
+            purge: self.purge,
+            data: resources,
+        };
+
+        let bytes = serde_json::to_vec(&response).unwrap();
+
+        let etag = format!("{:x}", Sha256::digest(&bytes));
+
+        (bytes, etag)
+    }
+
+    /// Count the resources in this client's catalog per resource type.
+    pub fn resource_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+
+        for resource in &self.resources {
+            *counts.entry(resource.kind().to_string()).or_default() += 1;
+        }
+
+        counts
+    }
+
     /// Dependencies between resources are stored in a flat structure,
     /// a map of hashsets. Per resource this structure documents
     /// which other resources it depends on.
@@ -195,19 +412,66 @@ impl Client {
     /// we have to scour the dependencies of a resource and the dependencies
     /// of each dependency recursively. If the resource ID turns up
     /// at any point, establishing a new dependency between this resource
-    /// and the starting dependency would introduce a loop.
+    /// and the starting dependency would introduce a loop. In that case
+    /// the chain of resource IDs that leads from the starting dependency
+    /// to the resource is returned.
     /// If the search turns up empty, the relationship can be safely
     /// established.
-    fn dependency_introduces_loop(&self, node: Uuid, target: Uuid) -> bool {
-        match self.temporary.dependencies.get(&node) {
-            Some(ids) => {
-                ids.contains(&target)
-                    || ids
-                        .iter()
-                        .any(|id| self.dependency_introduces_loop(*id, target))
+    fn dependency_introduces_loop(&self, node: Uuid, target: Uuid) -> Option<Vec<Uuid>> {
+        let ids = self.temporary.dependencies.get(&node)?;
+
+        if ids.contains(&target) {
+            return Some(vec![node, target]);
+        }
+
+        ids.iter().find_map(|id| {
+            self.dependency_introduces_loop(*id, target)
+                .map(|mut path| {
+                    path.insert(0, node);
+                    path
+                })
+        })
+    }
+
+    /// Describe a dependency loop that starts and ends with `resource`
+    /// (which is not part of the catalog while it is validated), e.g.
+    /// ``file `/a` → directory `/b` (requires in clients/foo.toml) → file `/a` (implicit)``.
+    /// Every step names the file where the dependency was declared or
+    /// whether it is an implicit dependency.
+    fn describe_loop(&self, resource: &Resource, path: &[Uuid]) -> String {
+        let repr = |id: &Uuid| {
+            if *id == resource.id() {
+                resource.repr()
+            } else {
+                self.resources
+                    .iter()
+                    .find(|other| other.id() == *id)
+                    .map(|other| other.repr())
+                    .unwrap_or_else(|| id.to_string())
             }
-            None => false,
+        };
+
+        let mut description = repr(&resource.id());
+        let mut previous = resource.id();
+
+        for (index, id) in path.iter().enumerate() {
+            // The first step is the dependency that is about to be
+            // established, which is always an explicit one.
+            let origin = if index == 0 || self.temporary.explicit.contains(&(previous, *id)) {
+                match self.temporary.declarations.get(&previous) {
+                    Some(source) => format!("requires in {}", source.display()),
+                    None => "requires".to_string(),
+                }
+            } else {
+                "implicit".to_string()
+            };
+
+            description.push_str(&format!(" → {} ({})", repr(id), origin));
+
+            previous = *id;
         }
+
+        description
     }
 
     /// Return the resource corresponding to a dependency. Most dependencies
@@ -235,6 +499,15 @@ impl Client {
                         .is_some_and(|item| item.parameters.name == *name)
                 })
                 .cloned(),
+            Dependency::Custom { provider, name } => self
+                .resources
+                .iter()
+                .find(|resource| {
+                    resource.as_custom().is_some_and(|item| {
+                        item.parameters.provider == *provider && item.parameters.name == *name
+                    })
+                })
+                .cloned(),
             Dependency::Directory { path } => self
                 .resources
                 .iter()
@@ -315,31 +588,42 @@ impl Client {
 
         for group_name in &self.assigned_groups {
             let (group, count) = groups.get_mut(group_name).ok_or_else(|| {
-                error!(scope, client:% = self.name, group:% = group_name; "unknown group `{}`", group_name);
+                validation_error!(scope, client:% = self.name, group:% = group_name; "unknown group `{}`", group_name);
                 Terminate
             })?;
 
             *count += 1;
 
+            for pattern in &group.assets {
+                let pattern = pattern
+                    .resolve(&self.name, &self.variables)
+                    .map_err(|error| {
+                        validation_error!(scope, client:% = self.name, group:% = group_name; "{}", error);
+                        Terminate
+                    })?;
+
+                if !self.assets.contains(&pattern) {
+                    self.assets.push(pattern);
+                }
+            }
+
             for item in &group.resources {
                 let requires = item.requires().to_vec();
 
                 // Convert resource from the deserialized to the final form,
                 // substituting variables in the process.
-                let resource = Resource::try_from((item, &self.variables)).map_err(|error| {
-                    error!(
-                        scope,
-                        client:% = self.name,
-                        group:% = group_name,
-                        resource:% = item.kind();
-                        "{}",
-                        error
-                    );
-                    Terminate
-                })?;
-
-                // Save dependencies as they appear in the deserialized resource.
-                self.temporary.requires.insert(resource.id(), requires);
+                let resource =
+                    Resource::try_from((item, &self.name, &self.variables)).map_err(|error| {
+                        validation_error!(
+                            scope,
+                            client:% = self.name,
+                            group:% = group_name,
+                            resource:% = item.kind();
+                            "{}",
+                            error
+                        );
+                        Terminate
+                    })?;
 
                 // Check if a similar resource is already present ...
                 if let Some(duplicate) = self.resources.iter().find(|other| **other == resource) {
@@ -348,7 +632,7 @@ impl Client {
                     // because the saved resource originates from the client
                     // and takes precedence.
                     if let Some(origin) = self.temporary.origins.get(&duplicate.id()) {
-                        error!(
+                        validation_error!(
                             scope,
                             client:% = self.name,
                             group:% = group_name,
@@ -365,10 +649,16 @@ impl Client {
                 } else {
                     // If no similar resource is present, save this one into
                     // the catalog and also record that this resource stems from
-                    // a group.
+                    // a group. Dependencies are only saved at this point, as
+                    // a skipped duplicate shares its ID with the resource
+                    // that takes precedence.
                     self.temporary
                         .origins
                         .insert(resource.id(), group_name.clone());
+                    self.temporary.requires.insert(resource.id(), requires);
+                    self.temporary
+                        .declarations
+                        .insert(resource.id(), group.source.clone());
                     self.resources.push_back(resource);
                 }
             }
@@ -408,6 +698,7 @@ impl Client {
                 Resource::AptPackage(ref mut item) => self.validate_apt_package(item)?,
                 Resource::AptPreference(ref mut item) => self.validate_apt_preference(item)?,
                 Resource::CronJob(ref mut item) => self.validate_cron_job(item)?,
+                Resource::Custom(ref mut item) => self.validate_custom(item)?,
                 Resource::Directory(ref mut item) => self.validate_directory(item)?,
                 Resource::File(ref mut item) => self.validate_file(item)?,
                 Resource::Group(ref mut item) => self.validate_group(item)?,
@@ -448,14 +739,17 @@ impl Client {
                             let metadata = resource.metadata();
                             let other_metadata = other_resource.metadata().clone();
 
-                            if self.dependency_introduces_loop(other_metadata.id, metadata.id) {
-                                error!(
+                            if let Some(path) =
+                                self.dependency_introduces_loop(other_metadata.id, metadata.id)
+                            {
+                                validation_error!(
                                     scope,
                                     client:% = self.name,
                                     resource:% = resource.kind();
-                                    "{} cannot depend on {} as it would introduce a dependency loop",
+                                    "{} cannot depend on {} as it would introduce a dependency loop: {}",
                                     resource.repr(),
-                                    other_resource.repr()
+                                    other_resource.repr(),
+                                    self.describe_loop(&resource, &path)
                                 );
 
                                 return Err(Terminate);
@@ -466,10 +760,30 @@ impl Client {
                                 .or_default()
                                 .insert(other_metadata.id)
                             {
+                                self.temporary
+                                    .explicit
+                                    .insert((metadata.id, other_metadata.id));
+
                                 resource.push_requirement(other_metadata.clone());
+                            } else {
+                                warn!(
+                                    scope,
+                                    client:% = self.name,
+                                    resource:% = resource.kind();
+                                    "`requires` entry of {} for {} is redundant as it is already a dependency",
+                                    resource.repr(),
+                                    other_resource.repr()
+                                );
+
+                                self.lints.push(format!(
+                                    "client `{}`: `requires` entry of {} for {} is redundant as it is already a dependency",
+                                    self.name,
+                                    resource.repr(),
+                                    other_resource.repr()
+                                ));
                             }
                         } else {
-                            error!(
+                            validation_error!(
                                 scope,
                                 client:% = self.name,
                                 resource:% = resource.kind();
@@ -482,7 +796,7 @@ impl Client {
                         }
                     }
                     None => {
-                        error!(
+                        validation_error!(
                             scope,
                             client:% = self.name,
                             resource:% = resource.kind();
@@ -513,7 +827,7 @@ impl Client {
             .paths
             .insert(file.parameters.path.to_path_buf())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = file.kind(),
@@ -529,7 +843,7 @@ impl Client {
         // Check if any file conflicts with this file in that regard.
         if let Some(parent) = &file.parameters.path.parent() {
             if self.temporary.file_paths.contains(*parent) {
-                error!(
+                validation_error!(
                     scope,
                     client:% = self.name,
                     resource:% = file.kind(),
@@ -557,7 +871,7 @@ impl Client {
             .cron_job_names
             .insert(job.parameters.name.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = job.kind(),
@@ -570,7 +884,7 @@ impl Client {
         }
 
         if !self.temporary.paths.insert(job.parameters.target.clone()) {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = job.kind(),
@@ -586,6 +900,33 @@ impl Client {
         Ok(())
     }
 
+    fn validate_custom(&mut self, custom: &mut custom::Custom) -> Result<(), Terminate> {
+        let scope = "validation";
+
+        let name = custom.parameters.name.clone();
+
+        // Check for uniqueness of the name parameter among the resources
+        // of the same provider.
+        if !self
+            .temporary
+            .custom_names
+            .insert((custom.parameters.provider.clone(), name.clone()))
+        {
+            validation_error!(
+                scope,
+                client:% = self.name,
+                resource:% = custom.kind(),
+                name;
+                "{} appears multiple times, names must be unique among the resources of a provider",
+                custom.repr()
+            );
+
+            return Err(Terminate);
+        }
+
+        Ok(())
+    }
+
     fn validate_directory(
         &mut self,
         directory: &mut directory::Directory,
@@ -600,7 +941,7 @@ impl Client {
             .paths
             .insert(directory.parameters.path.to_path_buf())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = directory.kind(),
@@ -616,7 +957,7 @@ impl Client {
         // Check if any file conflicts with this directory in that regard.
         if let Some(parent) = &directory.parameters.path.parent() {
             if self.temporary.file_paths.contains(*parent) {
-                error!(
+                validation_error!(
                     scope,
                     client:% = self.name,
                     resource:% = directory.kind(),
@@ -702,7 +1043,7 @@ impl Client {
             .paths
             .insert(symlink.parameters.path.to_path_buf())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = symlink.kind(),
@@ -718,7 +1059,7 @@ impl Client {
         // Check if any file conflicts with this symlink in that regard.
         if let Some(parent) = &symlink.parameters.path.parent() {
             if self.temporary.file_paths.contains(*parent) {
-                error!(
+                validation_error!(
                     scope,
                     client:% = self.name,
                     resource:% = symlink.kind(),
@@ -745,7 +1086,7 @@ impl Client {
             .host_ip_addresses
             .insert(host.parameters.ip_address)
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = host.kind(),
@@ -767,7 +1108,7 @@ impl Client {
             .find(|f| *f.parameters.path == host.parameters.target)
         {
             if file.parameters.content.is_some() || file.parameters.source.is_some() {
-                error!(
+                validation_error!(
                     scope,
                     client:% = self.name,
                     resource:% = host.kind(),
@@ -795,7 +1136,7 @@ impl Client {
             .group_names
             .insert(group.parameters.name.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = group.kind(),
@@ -821,7 +1162,7 @@ impl Client {
             .user_names
             .insert(user.parameters.name.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = user.kind(),
@@ -848,7 +1189,7 @@ impl Client {
             .iter()
             .any(|item| item.as_resolv_conf().is_some())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = resolv_conf.kind();
@@ -869,7 +1210,7 @@ impl Client {
             .find(|f| *f.parameters.path == resolv_conf.parameters.target)
         {
             if file.parameters.content.is_some() || file.parameters.source.is_some() {
-                error!(
+                validation_error!(
                     scope,
                     client:% = self.name,
                     resource:% = resolv_conf.kind();
@@ -899,7 +1240,7 @@ impl Client {
             .apt_package_names
             .insert(package.parameters.name.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = package.kind(),
@@ -928,7 +1269,7 @@ impl Client {
             .apt_preference_names
             .insert(preference.parameters.name.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = preference.kind(),
@@ -945,7 +1286,7 @@ impl Client {
             .paths
             .insert(preference.parameters.target.clone())
         {
-            error!(
+            validation_error!(
                 scope,
                 client:% = self.name,
                 resource:% = preference.kind(),
@@ -964,18 +1305,95 @@ impl Client {
 
 pub mod deserialize {
     use super::*;
+    use schemars::JsonSchema;
     use serde::Deserialize;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Client {
         #[serde(rename(deserialize = "api-key"))]
+        #[schemars(with = "String")]
         pub api_key: ApiKey,
         #[serde(default, rename(deserialize = "groups"))]
+        #[schemars(with = "Vec<String>")]
         pub assigned_groups: Vec<Hostname>,
         #[serde(default)]
+        #[schemars(with = "HashMap<String, serde_json::Value>")]
         pub variables: HashMap<String, toml::Value>,
         #[serde(default)]
+        #[schemars(with = "Vec<String>")]
+        pub assets: Vec<AssetPattern>,
+        #[serde(default)]
         pub resources: Vec<DeResource>,
+        /// The interval in seconds at which the client should fetch
+        /// its catalog.
+        #[serde(default, rename(deserialize = "poll-interval"))]
+        pub poll_interval: Option<u64>,
+        #[serde(default)]
+        pub decommissioned: bool,
+        /// Whether the client removes cron jobs that pullconf created, but
+        /// which are no longer part of its catalog.
+        #[serde(default, rename(deserialize = "purge-cron-jobs"))]
+        pub purge_cron_jobs: bool,
+        #[serde(skip)]
+        pub source: PathBuf,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{configuration::Configuration, diagnostics, AppState};
+    use std::fs;
+
+    #[test]
+    fn describe_dependency_loops() {
+        let directory =
+            std::env::temp_dir().join(format!("pullconfd-loops-{}", std::process::id()));
+
+        AppState::fixture(&directory, &[]);
+
+        // The file below /srv implicitly depends on its parent directory,
+        // which is part of a loop of explicit dependencies.
+        let client = format!(
+            r#"api-key = "{}"
+
+[[resources]]
+type = "file"
+path = "/srv/motd"
+
+[[resources]]
+type = "directory"
+path = "/srv"
+requires = [{{ type = "file", path = "/etc/motd" }}]
+
+[[resources]]
+type = "file"
+path = "/etc/motd"
+requires = [{{ type = "file", path = "/srv/motd" }}]
+"#,
+            "a".repeat(64)
+        );
+
+        let resources = directory.join("resources");
+        let source = resources.join("clients/web.example.com.toml");
+
+        fs::write(&source, client).unwrap();
+
+        let (configuration, diagnostics) =
+            diagnostics::collect(|| Configuration::try_from(&resources));
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(configuration.is_err());
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(
+            diagnostics[0].message,
+            format!(
+                "file `/etc/motd` cannot depend on file `/srv/motd` as it would introduce a dependency loop: \
+                 file `/etc/motd` → file `/srv/motd` (requires in {0}) → directory `/srv` (implicit) → \
+                 file `/etc/motd` (requires in {0})",
+                source.display()
+            )
+        );
     }
 }
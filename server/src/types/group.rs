@@ -1,9 +1,30 @@
-use crate::types::resources::deserialize::Resource;
+use crate::types::{resources::deserialize::Resource, AssetPattern};
+use schemars::JsonSchema;
 use serde::Deserialize;
+use std::{collections::BTreeMap, path::PathBuf};
 
-#[derive(Clone, Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub struct Group {
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub assets: Vec<AssetPattern>,
     #[serde(default)]
     pub resources: Vec<Resource>,
+    /// The path of the file that this group was parsed from.
+    #[serde(skip)]
+    pub source: PathBuf,
+}
+
+impl Group {
+    /// Count the resources defined in this group per resource type.
+    pub fn resource_counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+
+        for resource in &self.resources {
+            *counts.entry(resource.kind().to_string()).or_default() += 1;
+        }
+
+        counts
+    }
 }
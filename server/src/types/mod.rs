@@ -1,14 +1,32 @@
+pub mod admin;
+pub mod asset;
 pub mod client;
 pub mod group;
 pub mod resources;
 
+pub use admin::AdminToken;
+pub use asset::AssetPattern;
 pub use client::Client;
 pub use group::Group;
 
+use common::Links;
+use hmac::{Hmac, Mac};
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use sha2::{Digest, Sha256};
 use std::{ops::Deref, str::FromStr};
 
+/// The envelope of every successful API response.
+#[derive(Serialize)]
+pub struct ApiResponse<T> {
+    pub links: Links,
+    pub data: T,
+}
+
+/// Prefix that marks an API key hash as an HMAC-SHA256 digest keyed
+/// with the server pepper. Hashes without this prefix are plain SHA-256
+/// digests that are still accepted for backwards compatibility.
+const HMAC_SHA256_PREFIX: &str = "hmac-sha256:";
+
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
 pub struct ApiKey(String);
 
@@ -16,11 +34,13 @@ impl FromStr for ApiKey {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() != 64 {
+        let hash = s.strip_prefix(HMAC_SHA256_PREFIX).unwrap_or(s);
+
+        if hash.len() != 64 {
             return Err("API key hash has invalid length, must be a sha256 hash of exactly 64 hexadecimal characters".to_string());
         }
 
-        if let Some(ref c) = s
+        if let Some(ref c) = hash
             .chars()
             .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_hexdigit()))
         {
@@ -54,7 +74,43 @@ impl Deref for ApiKey {
 }
 
 impl ApiKey {
+    /// Compute the legacy, unsalted SHA-256 hash of an API key.
     pub fn encrypt(s: &str) -> Self {
         Self(format!("{:x}", Sha256::digest(s.as_bytes())))
     }
+
+    /// Compute the HMAC-SHA256 hash of an API key using the server
+    /// pepper as the secret key.
+    pub fn hmac(s: &str, pepper: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(pepper.as_bytes())
+            .expect("HMAC accepts keys of any length");
+
+        mac.update(s.as_bytes());
+
+        Self(format!(
+            "{}{:x}",
+            HMAC_SHA256_PREFIX,
+            mac.finalize().into_bytes()
+        ))
+    }
+
+    /// Compute every hash of an API key that may be found in the
+    /// configuration. The HMAC-SHA256 hash comes first if a server
+    /// pepper is configured, followed by the legacy SHA-256 hash.
+    pub fn candidates(s: &str, pepper: Option<&str>) -> Vec<Self> {
+        let mut keys = Vec::with_capacity(2);
+
+        if let Some(pepper) = pepper {
+            keys.push(Self::hmac(s, pepper));
+        }
+
+        keys.push(Self::encrypt(s));
+
+        keys
+    }
+
+    /// Check if this is a legacy, unsalted SHA-256 hash.
+    pub fn is_legacy(&self) -> bool {
+        !self.0.starts_with(HMAC_SHA256_PREFIX)
+    }
 }
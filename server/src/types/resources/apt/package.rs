@@ -4,7 +4,7 @@ use crate::types::resources::{
 };
 use common::{
     resources::apt::package::{Ensure, Parameters, Relationships},
-    ResourceMetadata, ResourceType,
+    Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -56,7 +56,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Package {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::AptPackage,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -100,8 +102,9 @@ impl Package {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -110,6 +113,11 @@ pub mod de {
         pub version: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
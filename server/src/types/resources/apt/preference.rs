@@ -7,7 +7,7 @@ use common::{
         apt::preference::{Parameters, Relationships},
         directory::ChildNode,
     },
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::PathBuf};
@@ -79,7 +79,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Preference {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::AptPreference,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -150,8 +152,9 @@ impl From<&Preference> for ChildNode {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -165,6 +168,11 @@ pub mod de {
         pub pin_priority: VariableOrValue,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
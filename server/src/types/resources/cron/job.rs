@@ -7,7 +7,7 @@ use common::{
         cron::job::{Environment, Parameters, Relationships},
         user::Name as Username,
     },
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::PathBuf};
@@ -92,7 +92,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Job {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::CronJob,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -153,8 +155,9 @@ impl Job {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -167,6 +170,11 @@ pub mod de {
         pub command: VariableOrValue,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
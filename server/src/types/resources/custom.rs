@@ -0,0 +1,161 @@
+use super::{
+    deserialize::{Dependency, VariableOrValue},
+    Resource,
+};
+use common::{
+    resources::custom::{Parameters, Relationships},
+    Ensure, Priority, ResourceMetadata, ResourceType,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use toml::Value;
+use uuid::Uuid;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Custom {
+    #[serde(flatten)]
+    pub metadata: ResourceMetadata,
+    pub parameters: Parameters,
+    pub relationships: Relationships,
+}
+
+impl PartialEq for Custom {
+    fn eq(&self, other: &Self) -> bool {
+        self.parameters.provider == other.parameters.provider
+            && self.parameters.name == other.parameters.name
+    }
+}
+
+impl Eq for Custom {}
+
+impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Custom {
+    type Error = String;
+
+    fn try_from(
+        (parameters, variables): (&de::Parameters, &HashMap<String, Value>),
+    ) -> Result<Self, Self::Error> {
+        let parameters = {
+            let ensure = match &parameters.ensure {
+                Some(parameter) => parameter.resolve("ensure", variables)?,
+                None => Ensure::default(),
+            };
+
+            let provider = parameters.provider.resolve("provider", variables)?;
+
+            let name: String = parameters.name.resolve("name", variables)?;
+
+            if name.is_empty() {
+                return Err("parameter `name` cannot be an empty string".to_string());
+            }
+
+            // Variables are substituted in the top-level values, while
+            // the values themselves are passed on to the provider as they
+            // are.
+            let parameters = match &parameters.parameters {
+                Some(parameter) => parameter
+                    .resolve::<BTreeMap<String, VariableOrValue>>("parameters", variables)?
+                    .into_iter()
+                    .map(|(key, item)| {
+                        let value: Value = item.resolve("parameters", variables)?;
+
+                        serde_json::to_value(value)
+                            .map(|value| (key, value))
+                            .map_err(|error| {
+                                format!(
+                                    "parameter `parameters` contains an invalid value: {}",
+                                    error
+                                )
+                            })
+                    })
+                    .collect::<Result<_, String>>()?,
+                None => Default::default(),
+            };
+
+            Parameters {
+                ensure,
+                provider,
+                name,
+                parameters,
+            }
+        };
+
+        Ok(Self {
+            metadata: ResourceMetadata {
+                kind: ResourceType::Custom,
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
+            },
+            parameters,
+            relationships: Relationships::default(),
+        })
+    }
+}
+
+impl Custom {
+    pub fn kind(&self) -> ResourceType {
+        self.metadata.kind
+    }
+
+    pub fn display(&self) -> String {
+        self.parameters.name.clone()
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.metadata.id
+    }
+
+    pub fn metadata(&self) -> &ResourceMetadata {
+        &self.metadata
+    }
+
+    /// Custom resources are represented by their provider rather than the
+    /// generic `custom` type, e.g. "postgresql::database `app`".
+    pub fn repr(&self) -> String {
+        format!("{} `{}`", self.parameters.provider, self.display())
+    }
+
+    pub fn must_depend_on(&self, _resource: &Resource) -> bool {
+        false
+    }
+
+    pub fn may_depend_on(&self, resource: &Resource) -> bool {
+        match resource {
+            Resource::Custom(custom) => custom != self,
+            _ => true,
+        }
+    }
+
+    pub fn push_requirement(&mut self, metadata: ResourceMetadata) {
+        self.relationships.requires.push(metadata)
+    }
+}
+
+pub mod de {
+    use super::*;
+    use schemars::JsonSchema;
+
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
+    #[serde(deny_unknown_fields)]
+    pub struct Parameters {
+        #[serde(default)]
+        pub ensure: Option<VariableOrValue>,
+        pub provider: VariableOrValue,
+        pub name: VariableOrValue,
+        #[serde(default)]
+        pub parameters: Option<VariableOrValue>,
+        #[serde(default)]
+        pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
+    }
+
+    impl Parameters {
+        pub fn kind(&self) -> ResourceType {
+            ResourceType::Custom
+        }
+    }
+}
@@ -1,11 +1,18 @@
-use super::{apt, cron, directory, file, group, host, resolv_conf, symlink, user};
+use super::{apt, cron, custom, directory, file, group, host, resolv_conf, symlink, user};
+use crate::lint;
 use common::{
     resources::{
         apt::{package::Name as PackageName, preference::Name as PreferenceName},
+        custom::Provider,
         group::Name as Groupname,
         user::Name as Username,
     },
-    ResourceType, SafePathBuf,
+    Priority, ResourceType, SafePathBuf,
+};
+use schemars::{
+    gen::SchemaGenerator,
+    schema::{Metadata, Schema, SchemaObject},
+    JsonSchema,
 };
 use serde::{
     de::{DeserializeOwned, Error as SerdeError, Unexpected},
@@ -14,7 +21,7 @@ use serde::{
 use std::{collections::HashMap, net::IpAddr};
 use toml::Value;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, tag = "type")]
 pub enum Resource {
     #[serde(rename = "apt::package")]
@@ -23,6 +30,8 @@ pub enum Resource {
     AptPreference(apt::preference::de::Parameters),
     #[serde(rename = "cron::job")]
     CronJob(cron::job::de::Parameters),
+    #[serde(rename = "custom")]
+    Custom(custom::de::Parameters),
     #[serde(rename = "directory")]
     Directory(directory::de::Parameters),
     #[serde(rename = "file")]
@@ -45,6 +54,7 @@ impl Resource {
             Self::AptPackage(parameters) => parameters.kind(),
             Self::AptPreference(parameters) => parameters.kind(),
             Self::CronJob(parameters) => parameters.kind(),
+            Self::Custom(parameters) => parameters.kind(),
             Self::Directory(parameters) => parameters.kind(),
             Self::File(parameters) => parameters.kind(),
             Self::Group(parameters) => parameters.kind(),
@@ -60,6 +70,7 @@ impl Resource {
             Self::AptPackage(parameters) => parameters.requires.as_slice(),
             Self::AptPreference(parameters) => parameters.requires.as_slice(),
             Self::CronJob(parameters) => parameters.requires.as_slice(),
+            Self::Custom(parameters) => parameters.requires.as_slice(),
             Self::Directory(parameters) => parameters.requires.as_slice(),
             Self::File(parameters) => parameters.requires.as_slice(),
             Self::Group(parameters) => parameters.requires.as_slice(),
@@ -70,6 +81,38 @@ impl Resource {
         }
     }
 
+    pub fn tags(&self) -> &[String] {
+        match self {
+            Self::AptPackage(parameters) => parameters.tags.as_slice(),
+            Self::AptPreference(parameters) => parameters.tags.as_slice(),
+            Self::CronJob(parameters) => parameters.tags.as_slice(),
+            Self::Custom(parameters) => parameters.tags.as_slice(),
+            Self::Directory(parameters) => parameters.tags.as_slice(),
+            Self::File(parameters) => parameters.tags.as_slice(),
+            Self::Group(parameters) => parameters.tags.as_slice(),
+            Self::Host(parameters) => parameters.tags.as_slice(),
+            Self::ResolvConf(parameters) => parameters.tags.as_slice(),
+            Self::Symlink(parameters) => parameters.tags.as_slice(),
+            Self::User(parameters) => parameters.tags.as_slice(),
+        }
+    }
+
+    pub fn priority(&self) -> Option<Priority> {
+        match self {
+            Self::AptPackage(parameters) => parameters.priority,
+            Self::AptPreference(parameters) => parameters.priority,
+            Self::CronJob(parameters) => parameters.priority,
+            Self::Custom(parameters) => parameters.priority,
+            Self::Directory(parameters) => parameters.priority,
+            Self::File(parameters) => parameters.priority,
+            Self::Group(parameters) => parameters.priority,
+            Self::Host(parameters) => parameters.priority,
+            Self::ResolvConf(parameters) => parameters.priority,
+            Self::Symlink(parameters) => parameters.priority,
+            Self::User(parameters) => parameters.priority,
+        }
+    }
+
     pub fn as_apt_package(&self) -> Option<&apt::package::de::Parameters> {
         match self {
             Self::AptPackage(parameters) => Some(parameters),
@@ -91,6 +134,13 @@ impl Resource {
         }
     }
 
+    pub fn as_custom(&self) -> Option<&custom::de::Parameters> {
+        match self {
+            Self::Custom(parameters) => Some(parameters),
+            _ => None,
+        }
+    }
+
     pub fn as_directory(&self) -> Option<&directory::de::Parameters> {
         match self {
             Self::Directory(parameters) => Some(parameters),
@@ -156,12 +206,16 @@ impl VariableOrValue {
         variables: &HashMap<String, Value>,
     ) -> Result<T, String> {
         let value = match self {
-            VariableOrValue::Variable(variable) => variables.get(variable).ok_or_else(|| {
-                format!(
-                    "parameter `{}` refers to unknown variable `{}`",
-                    label, variable
-                )
-            })?,
+            VariableOrValue::Variable(variable) => {
+                lint::reference_variable(variable);
+
+                variables.get(variable).ok_or_else(|| {
+                    format!(
+                        "parameter `{}` refers to unknown variable `{}`",
+                        label, variable
+                    )
+                })?
+            }
             VariableOrValue::Value(value) => value,
         };
 
@@ -175,6 +229,28 @@ impl VariableOrValue {
     }
 }
 
+// Variables are resolved long after deserialization, so the schema of
+// a parameter cannot be narrowed down beyond "any value".
+impl JsonSchema for VariableOrValue {
+    fn schema_name() -> String {
+        "VariableOrValue".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some(
+                    "Either a literal value or a reference to a variable in the form `$pullconf::<name>`"
+                        .to_string(),
+                ),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
 fn deserialize_variable<'de, D>(deserializer: D) -> Result<String, D::Error>
 where
     D: Deserializer<'de>,
@@ -194,7 +270,7 @@ where
 impl VariableOrValue {
     pub fn as_value(&self) -> Option<&Value> {
         match self {
-            Self::Value(v) => Some(&v),
+            Self::Value(v) => Some(v),
             _ => None,
         }
     }
@@ -215,19 +291,40 @@ impl VariableOrValue {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq)]
 #[serde(deny_unknown_fields, tag = "type")]
 pub enum Dependency {
     #[serde(rename = "apt::package")]
-    AptPackage { name: PackageName },
+    AptPackage {
+        #[schemars(with = "String")]
+        name: PackageName,
+    },
     #[serde(rename = "apt::preference")]
-    AptPreference { name: PreferenceName },
+    AptPreference {
+        #[schemars(with = "String")]
+        name: PreferenceName,
+    },
+    #[serde(rename = "custom")]
+    Custom {
+        #[schemars(with = "String")]
+        provider: Provider,
+        name: String,
+    },
     #[serde(rename = "directory")]
-    Directory { path: SafePathBuf },
+    Directory {
+        #[schemars(with = "String")]
+        path: SafePathBuf,
+    },
     #[serde(rename = "file")]
-    File { path: SafePathBuf },
+    File {
+        #[schemars(with = "String")]
+        path: SafePathBuf,
+    },
     #[serde(rename = "group")]
-    Group { name: Groupname },
+    Group {
+        #[schemars(with = "String")]
+        name: Groupname,
+    },
     #[serde(rename = "host")]
     Host {
         #[serde(rename = "ip-address")]
@@ -236,9 +333,15 @@ pub enum Dependency {
     #[serde(rename = "resolv.conf")]
     ResolvConf,
     #[serde(rename = "symlink")]
-    Symlink { path: SafePathBuf },
+    Symlink {
+        #[schemars(with = "String")]
+        path: SafePathBuf,
+    },
     #[serde(rename = "user")]
-    User { name: Username },
+    User {
+        #[schemars(with = "String")]
+        name: Username,
+    },
 }
 
 impl Dependency {
@@ -246,6 +349,7 @@ impl Dependency {
         match self {
             Self::AptPackage { name } => format!("apt::package `{}`", name),
             Self::AptPreference { name } => format!("apt::preference `{}`", name),
+            Self::Custom { provider, name } => format!("{} `{}`", provider, name),
             Self::Directory { path } => format!("directory `{}`", path.display()),
             Self::File { path } => format!("file `{}`", path.display()),
             Self::Group { name } => format!("group `{}`", name),
@@ -324,4 +428,38 @@ requires = [
 
         Ok(())
     }
+
+    #[test]
+    fn resource_schema() -> Result<(), anyhow::Error> {
+        let schema = serde_json::to_value(schemars::schema_for!(Resource))?;
+
+        let types = schema["oneOf"]
+            .as_array()
+            .map(|variants| {
+                variants
+                    .iter()
+                    .filter_map(|variant| variant["properties"]["type"]["enum"][0].as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        assert_eq!(
+            types,
+            vec![
+                "apt::package",
+                "apt::preference",
+                "cron::job",
+                "custom",
+                "directory",
+                "file",
+                "group",
+                "host",
+                "resolv.conf",
+                "symlink",
+                "user"
+            ]
+        );
+
+        Ok(())
+    }
 }
@@ -7,7 +7,7 @@ use common::{
         directory::{ChildNode, Parameters, Relationships},
         user::Name as Username,
     },
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -71,7 +71,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Directory {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::Directory,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -180,8 +182,9 @@ impl From<&Directory> for ChildNode {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         pub path: VariableOrValue,
@@ -195,6 +198,11 @@ pub mod de {
         pub purge: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
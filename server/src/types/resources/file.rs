@@ -8,7 +8,7 @@ use common::{
         file::{Mode, Parameters, Relationships},
         user::Name as Username,
     },
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -70,6 +70,11 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for File {
                 None => None,
             };
 
+            let sensitive = match &parameters.sensitive {
+                Some(parameter) => parameter.resolve("sensitive", variables)?,
+                None => false,
+            };
+
             // The contents of a file can either be set via the `content` or `source`
             // parameters, but not both. If neither parameter is set, the file contents
             // are not managed at all.
@@ -87,13 +92,16 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for File {
                 group,
                 content,
                 source,
+                sensitive,
             }
         };
 
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::File,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -168,8 +176,9 @@ impl From<&File> for ChildNode {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         pub path: VariableOrValue,
@@ -186,7 +195,14 @@ pub mod de {
         #[serde(default)]
         pub source: Option<VariableOrValue>,
         #[serde(default)]
+        pub sensitive: Option<VariableOrValue>,
+        #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
@@ -4,7 +4,7 @@ use super::{
 };
 use common::{
     resources::group::{Parameters, Relationships},
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -56,7 +56,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Group {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::Group,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -109,8 +111,9 @@ impl Group {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -120,6 +123,11 @@ pub mod de {
         pub system: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
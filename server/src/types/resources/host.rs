@@ -4,7 +4,7 @@ use super::{
 };
 use common::{
     resources::host::{Parameters, Relationships},
-    Ensure, Hostname, ResourceMetadata, ResourceType,
+    Ensure, Hostname, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, path::Path};
@@ -73,7 +73,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Host {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::Host,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -124,8 +126,9 @@ impl Host {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -137,6 +140,11 @@ pub mod de {
         pub aliases: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
@@ -1,5 +1,6 @@
 pub mod apt;
 pub mod cron;
+pub mod custom;
 pub mod deserialize;
 pub mod directory;
 pub mod file;
@@ -12,6 +13,7 @@ pub mod user;
 pub use apt::package::Package as AptPackage;
 pub use apt::preference::Preference as AptPreference;
 pub use cron::job::Job as CronJob;
+pub use custom::Custom;
 pub use directory::Directory;
 pub use file::File;
 pub use group::Group;
@@ -20,19 +22,23 @@ pub use resolv_conf::ResolvConf;
 pub use symlink::Symlink;
 pub use user::User;
 
-use common::{ResourceMetadata, ResourceType};
+use common::{Ensure, Hostname, ResourceMetadata, ResourceType};
 use deserialize::Resource as DeResource;
 use serde::Serialize;
 use std::collections::HashMap;
 use toml::Value;
 use uuid::Uuid;
 
+/// The namespace of the version 5 UUIDs that identify resources.
+const RESOURCE_ID_NAMESPACE: Uuid = Uuid::from_u128(0x5c1f_0d2e_8a4b_4e6f_9b3a_7d2c_1e0f_4a8b);
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum Resource {
     AptPackage(AptPackage),
     AptPreference(AptPreference),
     CronJob(CronJob),
+    Custom(Custom),
     Directory(Directory),
     File(File),
     Group(Group),
@@ -60,6 +66,12 @@ impl From<CronJob> for Resource {
     }
 }
 
+impl From<Custom> for Resource {
+    fn from(custom: Custom) -> Self {
+        Self::Custom(custom)
+    }
+}
+
 impl From<Directory> for Resource {
     fn from(directory: Directory) -> Self {
         Self::Directory(directory)
@@ -108,6 +120,7 @@ impl Resource {
             Self::AptPackage(package) => package.id(),
             Self::AptPreference(preference) => preference.id(),
             Self::CronJob(job) => job.id(),
+            Self::Custom(custom) => custom.id(),
             Self::Directory(directory) => directory.id(),
             Self::File(file) => file.id(),
             Self::Group(group) => group.id(),
@@ -123,6 +136,7 @@ impl Resource {
             Self::AptPackage(package) => package.kind(),
             Self::AptPreference(preference) => preference.kind(),
             Self::CronJob(job) => job.kind(),
+            Self::Custom(custom) => custom.kind(),
             Self::Directory(directory) => directory.kind(),
             Self::File(file) => file.kind(),
             Self::Group(group) => group.kind(),
@@ -138,6 +152,7 @@ impl Resource {
             Self::AptPackage(package) => package.repr(),
             Self::AptPreference(preference) => preference.repr(),
             Self::CronJob(job) => job.repr(),
+            Self::Custom(custom) => custom.repr(),
             Self::Directory(directory) => directory.repr(),
             Self::File(file) => file.repr(),
             Self::Group(group) => group.repr(),
@@ -153,6 +168,7 @@ impl Resource {
             Self::AptPackage(package) => package.metadata(),
             Self::AptPreference(preference) => preference.metadata(),
             Self::CronJob(job) => job.metadata(),
+            Self::Custom(custom) => custom.metadata(),
             Self::Directory(directory) => directory.metadata(),
             Self::File(file) => file.metadata(),
             Self::Group(group) => group.metadata(),
@@ -163,11 +179,28 @@ impl Resource {
         }
     }
 
+    fn metadata_mut(&mut self) -> &mut ResourceMetadata {
+        match self {
+            Self::AptPackage(package) => &mut package.metadata,
+            Self::AptPreference(preference) => &mut preference.metadata,
+            Self::CronJob(job) => &mut job.metadata,
+            Self::Custom(custom) => &mut custom.metadata,
+            Self::Directory(directory) => &mut directory.metadata,
+            Self::File(file) => &mut file.metadata,
+            Self::Group(group) => &mut group.metadata,
+            Self::Host(host) => &mut host.metadata,
+            Self::ResolvConf(resolv_conf) => &mut resolv_conf.metadata,
+            Self::Symlink(symlink) => &mut symlink.metadata,
+            Self::User(user) => &mut user.metadata,
+        }
+    }
+
     pub fn may_depend_on(&self, other: &Self) -> bool {
         match self {
             Self::AptPackage(item) => item.may_depend_on(other),
             Self::AptPreference(item) => item.may_depend_on(other),
             Self::CronJob(item) => item.may_depend_on(other),
+            Self::Custom(item) => item.may_depend_on(other),
             Self::Directory(item) => item.may_depend_on(other),
             Self::File(item) => item.may_depend_on(other),
             Self::Group(item) => item.may_depend_on(other),
@@ -183,6 +216,7 @@ impl Resource {
             Self::AptPackage(item) => item.must_depend_on(other),
             Self::AptPreference(item) => item.must_depend_on(other),
             Self::CronJob(item) => item.must_depend_on(other),
+            Self::Custom(item) => item.must_depend_on(other),
             Self::Directory(item) => item.must_depend_on(other),
             Self::File(item) => item.must_depend_on(other),
             Self::Group(item) => item.must_depend_on(other),
@@ -194,10 +228,19 @@ impl Resource {
     }
 
     pub fn push_requirement(&mut self, metadata: ResourceMetadata) {
+        // Tags and priorities only describe the resource itself and are not
+        // repeated in the relationships of other resources.
+        let metadata = ResourceMetadata {
+            tags: vec![],
+            priority: None,
+            ..metadata
+        };
+
         match self {
             Self::AptPackage(item) => item.push_requirement(metadata),
             Self::AptPreference(item) => item.push_requirement(metadata),
             Self::CronJob(item) => item.push_requirement(metadata),
+            Self::Custom(item) => item.push_requirement(metadata),
             Self::Directory(item) => item.push_requirement(metadata),
             Self::File(item) => item.push_requirement(metadata),
             Self::Group(item) => item.push_requirement(metadata),
@@ -208,6 +251,34 @@ impl Resource {
         }
     }
 
+    /// Convert this resource so that applying it removes what it
+    /// previously created. This is only done for resources whose removal
+    /// can be reverted by applying them again. Packages, users, groups and
+    /// the resolver configuration are left as they are, since removing
+    /// them may break the system or destroy data that pullconf did not
+    /// create. The same applies to custom resources, as it is up to their
+    /// providers what removing them entails. Returns whether the resource
+    /// was converted.
+    pub fn decommission(&mut self) -> bool {
+        let ensure = match self {
+            Self::AptPreference(item) => &mut item.parameters.ensure,
+            Self::CronJob(item) => &mut item.parameters.ensure,
+            Self::Directory(item) => &mut item.parameters.ensure,
+            Self::File(item) => &mut item.parameters.ensure,
+            Self::Host(item) => &mut item.parameters.ensure,
+            Self::Symlink(item) => &mut item.parameters.ensure,
+            Self::AptPackage(_)
+            | Self::Custom(_)
+            | Self::Group(_)
+            | Self::ResolvConf(_)
+            | Self::User(_) => return false,
+        };
+
+        *ensure = Ensure::Absent;
+
+        true
+    }
+
     pub fn as_apt_package(&self) -> Option<&AptPackage> {
         match self {
             Self::AptPackage(item) => Some(item),
@@ -229,6 +300,13 @@ impl Resource {
         }
     }
 
+    pub fn as_custom(&self) -> Option<&Custom> {
+        match self {
+            Self::Custom(item) => Some(item),
+            _ => None,
+        }
+    }
+
     pub fn as_directory(&self) -> Option<&Directory> {
         match self {
             Self::Directory(item) => Some(item),
@@ -279,13 +357,13 @@ impl Resource {
     }
 }
 
-impl TryFrom<(&DeResource, &HashMap<String, Value>)> for Resource {
+impl TryFrom<(&DeResource, &Hostname, &HashMap<String, Value>)> for Resource {
     type Error = String;
 
     fn try_from(
-        (resource, variables): (&DeResource, &HashMap<String, Value>),
+        (item, client, variables): (&DeResource, &Hostname, &HashMap<String, Value>),
     ) -> Result<Self, Self::Error> {
-        let resource = match resource {
+        let mut resource = match item {
             DeResource::AptPackage(item) => {
                 Self::AptPackage(AptPackage::try_from((item, variables))?)
             }
@@ -293,6 +371,7 @@ impl TryFrom<(&DeResource, &HashMap<String, Value>)> for Resource {
                 Self::AptPreference(AptPreference::try_from((item, variables))?)
             }
             DeResource::CronJob(item) => Self::CronJob(CronJob::try_from((item, variables))?),
+            DeResource::Custom(item) => Self::Custom(Custom::try_from((item, variables))?),
             DeResource::Directory(item) => Self::Directory(Directory::try_from((item, variables))?),
             DeResource::File(item) => Self::File(File::try_from((item, variables))?),
             DeResource::Group(item) => Self::Group(Group::try_from((item, variables))?),
@@ -304,6 +383,113 @@ impl TryFrom<(&DeResource, &HashMap<String, Value>)> for Resource {
             DeResource::User(item) => Self::User(User::try_from((item, variables))?),
         };
 
+        // The ID is derived from the client, the resource type and the
+        // parameter that uniquely identifies the resource, so that
+        // unchanged catalogs serialize to identical bytes and keep their
+        // etags across reloads and restarts of the server.
+        resource.metadata_mut().id = Uuid::new_v5(
+            &RESOURCE_ID_NAMESPACE,
+            format!("{}/{}", client, resource.repr()).as_bytes(),
+        );
+
+        resource.metadata_mut().tags = item.tags().to_vec();
+        resource.metadata_mut().priority = item.priority();
+
         Ok(resource)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn deterministic_resource_ids() -> Result<(), anyhow::Error> {
+        let resource: DeResource = toml::from_str(
+            r#"
+type = "file"
+path = "/etc/foo"
+"#,
+        )?;
+
+        let variables = HashMap::new();
+        let foo = Hostname::from_str("foo.example.com").unwrap();
+        let bar = Hostname::from_str("bar.example.com").unwrap();
+
+        let first = Resource::try_from((&resource, &foo, &variables)).unwrap();
+        let second = Resource::try_from((&resource, &foo, &variables)).unwrap();
+        let other = Resource::try_from((&resource, &bar, &variables)).unwrap();
+
+        assert_eq!(first.id(), second.id());
+        assert_ne!(first.id(), other.id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn resource_priority() -> Result<(), anyhow::Error> {
+        let resource: DeResource = toml::from_str(
+            r#"
+type = "apt::package"
+name = "texlive-full"
+priority = "idle"
+"#,
+        )?;
+
+        let variables = HashMap::new();
+        let client = Hostname::from_str("foo.example.com").unwrap();
+        let resource = Resource::try_from((&resource, &client, &variables)).unwrap();
+
+        assert_eq!(resource.metadata().priority, Some(common::Priority::Idle));
+
+        let invalid: Result<DeResource, _> = toml::from_str(
+            r#"
+type = "apt::package"
+name = "texlive-full"
+priority = "lowest"
+"#,
+        );
+
+        assert!(invalid.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decommission_resources() -> Result<(), anyhow::Error> {
+        let file: DeResource = toml::from_str(
+            r#"
+type = "file"
+path = "/etc/foo"
+"#,
+        )?;
+        let package: DeResource = toml::from_str(
+            r#"
+type = "apt::package"
+name = "curl"
+"#,
+        )?;
+
+        let variables = HashMap::new();
+        let client = Hostname::from_str("foo.example.com").unwrap();
+
+        let mut file = Resource::try_from((&file, &client, &variables)).unwrap();
+        let mut package = Resource::try_from((&package, &client, &variables)).unwrap();
+
+        assert!(file.decommission());
+        assert_eq!(
+            file.as_file().unwrap().parameters.ensure,
+            common::Ensure::Absent
+        );
+
+        // Removing packages may break the system, so they are kept.
+        assert!(!package.decommission());
+        assert_eq!(
+            package.as_apt_package().unwrap().parameters.ensure,
+            common::resources::apt::package::Ensure::default()
+        );
+
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@ use super::{
 };
 use common::{
     resources::resolv_conf::{Parameters, Relationships, ResolverOption, SortlistPair},
-    Ensure, Hostname, ResourceMetadata, ResourceType,
+    Ensure, Hostname, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, net::IpAddr, path::Path};
@@ -88,7 +88,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for ResolvConf {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::ResolvConf,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -132,8 +134,9 @@ impl ResolvConf {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -148,6 +151,11 @@ pub mod de {
         pub options: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
@@ -5,7 +5,7 @@ use super::{
 use common::{
     resources::directory::ChildNode,
     resources::symlink::{Parameters, Relationships},
-    Ensure, ResourceMetadata, ResourceType,
+    Ensure, Priority, ResourceMetadata, ResourceType,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -54,7 +54,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for Symlink {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::Symlink,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -133,8 +135,9 @@ impl From<&Symlink> for ChildNode {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -143,6 +146,11 @@ pub mod de {
         pub target: VariableOrValue,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {
@@ -7,7 +7,7 @@ use common::{
         group::Name as Groupname,
         user::{Parameters, Password, Relationships},
     },
-    Ensure, ResourceMetadata, ResourceType, SafePathBuf,
+    Ensure, Priority, ResourceMetadata, ResourceType, SafePathBuf,
 };
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, str::FromStr};
@@ -113,7 +113,9 @@ impl TryFrom<(&de::Parameters, &HashMap<String, Value>)> for User {
         Ok(Self {
             metadata: ResourceMetadata {
                 kind: ResourceType::User,
-                id: Uuid::new_v4(),
+                id: Uuid::nil(),
+                tags: vec![],
+                priority: None,
             },
             parameters,
             relationships: Relationships::default(),
@@ -168,8 +170,9 @@ impl User {
 
 pub mod de {
     use super::*;
+    use schemars::JsonSchema;
 
-    #[derive(Clone, Debug, Deserialize)]
+    #[derive(Clone, Debug, Deserialize, JsonSchema)]
     #[serde(deny_unknown_fields)]
     pub struct Parameters {
         #[serde(default)]
@@ -193,6 +196,11 @@ pub mod de {
         pub groups: Option<VariableOrValue>,
         #[serde(default)]
         pub requires: Vec<Dependency>,
+        #[serde(default)]
+        pub tags: Vec<String>,
+        #[serde(default)]
+        #[schemars(with = "Option<String>")]
+        pub priority: Option<Priority>,
     }
 
     impl Parameters {